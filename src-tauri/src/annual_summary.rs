@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+
+use crate::ipc_types::{AnnualSummary, AnnualSummaryLine, Filing};
+
+fn empty_line(_type: &str) -> AnnualSummaryLine {
+    AnnualSummaryLine {
+        _type: _type.into(),
+        filing_count: 0,
+        gross_income: 0,
+        wht_paid: 0,
+        wht_reclaimable: 0,
+        tax_payable: 0,
+        tax_paid: 0,
+    }
+}
+
+fn get_wht_reclaimable(filing: &Filing) -> i64 {
+    let wht_paid = filing.wht_paid.unwrap_or(0);
+    filing.wht_actual.unwrap_or(wht_paid) - wht_paid
+}
+
+fn add_filing(line: &mut AnnualSummaryLine, filing: &Filing, amended_filing: Option<&Filing>) {
+    let Some(amended_filing) = amended_filing else {
+        line.filing_count += 1;
+        line.gross_income += filing.gross_income.unwrap_or(0);
+        line.wht_paid += filing.wht_paid.unwrap_or(0);
+        line.wht_reclaimable += get_wht_reclaimable(filing);
+        line.tax_payable += filing.tax_payable;
+        if filing.status == "paid" {
+            line.tax_paid += filing.tax_payable;
+        }
+        return;
+    };
+    // An amendment only contributes its difference to the filing it corrects
+    line.gross_income +=
+        filing.gross_income.unwrap_or(0) - amended_filing.gross_income.unwrap_or(0);
+    line.wht_paid += filing.wht_paid.unwrap_or(0) - amended_filing.wht_paid.unwrap_or(0);
+    line.wht_reclaimable += get_wht_reclaimable(filing) - get_wht_reclaimable(amended_filing);
+    let tax_payable_delta = filing.tax_payable - amended_filing.tax_payable;
+    line.tax_payable += tax_payable_delta;
+    if filing.status == "paid" {
+        line.tax_paid += tax_payable_delta;
+    }
+}
+
+/// Totals per income type for filings whose income was realised in the given year
+pub fn get_annual_summary(year: i32, filings: &[Filing]) -> AnnualSummary {
+    let year_prefix = format!("{year}-");
+    let mut lines_by_type: BTreeMap<String, AnnualSummaryLine> = BTreeMap::new();
+    let mut total = empty_line("total");
+    let mut incomplete_filing_count = 0;
+    let mut suspected_duplicate_filing_count = 0;
+    for filing in filings.iter() {
+        let Some(income_date) = &filing.income_date else {
+            // Older filings only know their deadline, which is the best guess for the year
+            if filing.filing_deadline.starts_with(&year_prefix) {
+                incomplete_filing_count += 1;
+            }
+            continue;
+        };
+        if !income_date.starts_with(&year_prefix) {
+            continue;
+        }
+        let amended_filing = filing
+            .amends_filing_id
+            .and_then(|id| filings.iter().find(|f| f.id == id));
+        // Left out until the user confirms that they are not duplicates
+        if filing.suspected_duplicate_of_report_id.is_some()
+            || amended_filing.is_some_and(|f| f.suspected_duplicate_of_report_id.is_some())
+        {
+            suspected_duplicate_filing_count += 1;
+            continue;
+        }
+        add_filing(
+            lines_by_type
+                .entry(filing._type.clone())
+                .or_insert_with(|| empty_line(&filing._type)),
+            filing,
+            amended_filing,
+        );
+        add_filing(&mut total, filing, amended_filing);
+    }
+    AnnualSummary {
+        year,
+        lines: lines_by_type.into_values().collect(),
+        total,
+        incomplete_filing_count,
+        suspected_duplicate_filing_count,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn filing(
+        _type: &str,
+        status: &str,
+        income_date: Option<&str>,
+        gross_income: i64,
+        wht_paid: i64,
+        tax_payable: i64,
+    ) -> Filing {
+        Filing {
+            id: 0,
+            report_id: None,
+            _type: _type.into(),
+            status: status.into(),
+            paying_entity: "BMW".into(),
+            filing_deadline: "2023-12-15".into(),
+            tax_payable,
+            tax_payment_reference: "".into(),
+            income_date: income_date.map(|d| d.into()),
+            gross_income: income_date.map(|_| gross_income),
+            wht_paid: income_date.map(|_| wht_paid),
+            gross_tax_payable: None,
+            income_currency_code: None,
+            income_currency_amount: None,
+            income_exchange_rate: None,
+            wht_currency_code: None,
+            wht_currency_amount: None,
+            wht_exchange_rate: None,
+            amends_filing_id: None,
+            tax_payable_delta: None,
+            late_payment_date: None,
+            late_interest: None,
+            wht_actual: income_date.map(|_| wht_paid),
+            wht_credit_rate: None,
+            taxpayer_profile_id: 1,
+            period: None,
+            suspected_duplicate_of_report_id: None,
+        }
+    }
+
+    #[test]
+    fn test_get_annual_summary() {
+        let filings = vec![
+            filing("dividend", "paid", Some("2023-03-01"), 100000, 10000, 5000),
+            Filing {
+                // 10% treaty rate, 12.5% withheld
+                wht_actual: Some(25000),
+                wht_credit_rate: Some(dec!(10)),
+                ..filing("dividend", "init", Some("2023-11-15"), 200000, 20000, 10000)
+            },
+            filing("interest", "filed", Some("2023-06-30"), 1000, 0, 150),
+            // Different year
+            filing("dividend", "paid", Some("2022-12-30"), 100000, 10000, 5000),
+            // No income breakdown
+            filing("dividend", "paid", None, 0, 0, 7000),
+            Filing {
+                suspected_duplicate_of_report_id: Some(1),
+                ..filing("dividend", "init", Some("2023-03-01"), 100000, 10000, 5000)
+            },
+        ];
+        assert_eq!(
+            get_annual_summary(2023, &filings),
+            AnnualSummary {
+                year: 2023,
+                lines: vec![
+                    AnnualSummaryLine {
+                        _type: "dividend".into(),
+                        filing_count: 2,
+                        gross_income: 300000,
+                        wht_paid: 30000,
+                        wht_reclaimable: 5000,
+                        tax_payable: 15000,
+                        tax_paid: 5000,
+                    },
+                    AnnualSummaryLine {
+                        _type: "interest".into(),
+                        filing_count: 1,
+                        gross_income: 1000,
+                        wht_paid: 0,
+                        wht_reclaimable: 0,
+                        tax_payable: 150,
+                        tax_paid: 0,
+                    },
+                ],
+                total: AnnualSummaryLine {
+                    _type: "total".into(),
+                    filing_count: 3,
+                    gross_income: 301000,
+                    wht_paid: 30000,
+                    wht_reclaimable: 5000,
+                    tax_payable: 15150,
+                    tax_paid: 5000,
+                },
+                incomplete_filing_count: 1,
+                suspected_duplicate_filing_count: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_get_annual_summary_amendment() {
+        let original = Filing {
+            id: 1,
+            ..filing("dividend", "paid", Some("2023-03-01"), 100000, 10000, 5000)
+        };
+        let amendment = Filing {
+            id: 2,
+            amends_filing_id: Some(1),
+            tax_payable_delta: Some(3000),
+            ..filing("dividend", "paid", Some("2023-03-01"), 120000, 10000, 8000)
+        };
+        let summary = get_annual_summary(2023, &[amendment, original]);
+        assert_eq!(
+            summary.total,
+            AnnualSummaryLine {
+                _type: "total".into(),
+                filing_count: 1,
+                gross_income: 120000,
+                wht_paid: 10000,
+                wht_reclaimable: 0,
+                tax_payable: 8000,
+                tax_paid: 8000,
+            }
+        );
+    }
+}
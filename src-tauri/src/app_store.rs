@@ -0,0 +1,160 @@
+use std::{
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use rusqlite::Connection;
+
+use crate::{
+    error::DkaResult,
+    filesystem::get_db_path,
+    secrets::{uses_passphrase, SecretKey},
+};
+
+/// Storage context of the app: the data directory holding the database, reports and filings, a
+/// pool of connections to the database and the key for secrets stored in it. It does not depend
+/// on Tauri, so the pipeline can also run from the CLI and in tests.
+pub struct AppStore {
+    data_dir: PathBuf,
+    // Holds the key file, away from the data directory
+    key_dir: PathBuf,
+    idle_connections: Mutex<Vec<Connection>>,
+    passphrase: Option<String>,
+    secret_key: Mutex<Option<Arc<SecretKey>>>,
+}
+
+impl AppStore {
+    pub fn new(data_dir: PathBuf, key_dir: PathBuf) -> Self {
+        Self {
+            data_dir,
+            key_dir,
+            idle_connections: Mutex::new(vec![]),
+            passphrase: None,
+            secret_key: Mutex::new(None),
+        }
+    }
+
+    /// Derives the secret key from a passphrase instead of using the key file
+    pub fn with_passphrase(mut self, passphrase: String) -> Self {
+        self.passphrase = Some(passphrase);
+        self
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    pub fn key_dir(&self) -> &Path {
+        &self.key_dir
+    }
+
+    /// Loads the secret key on first use, since deriving it from a passphrase is slow
+    pub fn secret_key(&self) -> DkaResult<Arc<SecretKey>> {
+        let mut secret_key = self.secret_key.lock().expect("Secret key poisoned");
+        if let Some(secret_key) = secret_key.as_ref() {
+            return Ok(secret_key.clone());
+        }
+        let loaded_secret_key = Arc::new(SecretKey::load(
+            &self.data_dir,
+            &self.key_dir,
+            self.passphrase.as_deref(),
+        )?);
+        *secret_key = Some(loaded_secret_key.clone());
+        Ok(loaded_secret_key)
+    }
+
+    /// Whether the secrets are protected by a passphrase that has not been entered yet
+    #[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+    pub fn is_secret_key_locked(&self) -> bool {
+        uses_passphrase(&self.data_dir) && self.secret_key().is_err()
+    }
+
+    /// Unlocks the secrets with a passphrase entered in the UI
+    #[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+    pub fn unlock_secret_key(&self, passphrase: &str) -> DkaResult<()> {
+        let secret_key = SecretKey::load(&self.data_dir, &self.key_dir, Some(passphrase))?;
+        self.set_secret_key(secret_key);
+        Ok(())
+    }
+
+    pub fn set_secret_key(&self, secret_key: SecretKey) {
+        *self.secret_key.lock().expect("Secret key poisoned") = Some(Arc::new(secret_key));
+    }
+
+    /// Takes an idle connection from the pool, or opens a new one if there is none. The
+    /// connection goes back to the pool when dropped.
+    pub fn connection(&self) -> DkaResult<PooledConnection<'_>> {
+        let idle_connection = self
+            .idle_connections
+            .lock()
+            .expect("Connection pool poisoned")
+            .pop();
+        let conn = match idle_connection {
+            Some(conn) => conn,
+            None => {
+                let conn = Connection::open(get_db_path(self)?)?;
+                conn.pragma_update(None, "journal_mode", "WAL")?;
+                conn
+            }
+        };
+        Ok(PooledConnection {
+            store: self,
+            conn: Some(conn),
+        })
+    }
+}
+
+pub struct PooledConnection<'a> {
+    store: &'a AppStore,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("Connection already returned")
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().expect("Connection already returned")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let (Some(conn), Ok(mut idle_connections)) =
+            (self.conn.take(), self.store.idle_connections.lock())
+        {
+            idle_connections.push(conn);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_reuse() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = AppStore::new(data_dir.path().to_path_buf(), data_dir.path().to_path_buf());
+        {
+            let conn = store.connection().unwrap();
+            conn.execute("CREATE TABLE t (id INTEGER)", []).unwrap();
+            // A second connection is opened while the first one is in use
+            let conn2 = store.connection().unwrap();
+            conn2.execute("INSERT INTO t (id) VALUES (1)", []).unwrap();
+        }
+        assert_eq!(store.idle_connections.lock().unwrap().len(), 2);
+        let count: i64 = store
+            .connection()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(store.idle_connections.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,418 @@
+use std::{
+    cmp::max,
+    collections::{HashMap, HashSet, VecDeque},
+};
+
+use chrono::{Months, NaiveDate};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+use crate::{
+    error::{DkaError, DkaResult},
+    exchange_rate::{get_exchange_rate, ExchangeRateProvider},
+    money::{apply_rate_rsdc, to_rsdc},
+    report_parser::{ExchangeRateInfo, TradeInfo},
+};
+
+const CAPITAL_GAINS_TAX_RATE: Decimal = dec!(0.15);
+const TAX_EXEMPT_HOLDING_MONTHS: u32 = 120;
+
+/// A disposed quantity of a security, matched to the lot it was acquired in
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisposalInfo {
+    pub isin: String,
+    pub symbol: String,
+    pub quantity: Decimal,
+    pub currency_code: String,
+    pub acquisition_date: NaiveDate,
+    pub acquisition_currency_amount: Decimal, // Including commission
+    pub disposal_date: NaiveDate,
+    pub disposal_currency_amount: Decimal, // Net of commission
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapitalGainInfo {
+    pub isin: String,
+    pub symbol: String,
+    pub quantity: Decimal,
+    pub acquisition_date: NaiveDate,
+    pub disposal_date: NaiveDate,
+    pub acquisition_value_rsdc: i64,
+    pub disposal_value_rsdc: i64,
+    pub capital_gain_rsdc: i64, // Negative for a capital loss
+    pub tax_exempt: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapitalGainsFilingInfo {
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub capital_gain_infos: Vec<CapitalGainInfo>,
+    pub capital_gain_rsdc: i64,
+    pub capital_loss_rsdc: i64,
+    pub tax_payable_rsdc: i64,
+}
+impl CapitalGainsFilingInfo {
+    pub fn new(
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+        capital_gain_infos: Vec<CapitalGainInfo>,
+    ) -> Self {
+        let taxable_infos = capital_gain_infos.iter().filter(|cgi| !cgi.tax_exempt);
+        let capital_gain_rsdc: i64 = taxable_infos
+            .clone()
+            .map(|cgi| max(cgi.capital_gain_rsdc, 0))
+            .sum();
+        let capital_loss_rsdc: i64 = taxable_infos
+            .map(|cgi| max(-cgi.capital_gain_rsdc, 0))
+            .sum();
+        // Losses within the period are offset against gains
+        let tax_payable_rsdc = max(
+            apply_rate_rsdc(
+                capital_gain_rsdc - capital_loss_rsdc,
+                CAPITAL_GAINS_TAX_RATE,
+            ),
+            0,
+        );
+        CapitalGainsFilingInfo {
+            period_start,
+            period_end,
+            capital_gain_infos,
+            capital_gain_rsdc,
+            capital_loss_rsdc,
+            tax_payable_rsdc,
+        }
+    }
+}
+
+struct Lot {
+    acquisition_date: NaiveDate,
+    quantity: Decimal,
+    unit_cost: Decimal,
+}
+
+pub fn get_half_year_bounds(year: i32, half: u32) -> DkaResult<(NaiveDate, NaiveDate)> {
+    let (start, end) = match half {
+        1 => (
+            NaiveDate::from_ymd_opt(year, 1, 1),
+            NaiveDate::from_ymd_opt(year, 6, 30),
+        ),
+        2 => (
+            NaiveDate::from_ymd_opt(year, 7, 1),
+            NaiveDate::from_ymd_opt(year, 12, 31),
+        ),
+        _ => return Err(DkaError::user("Half year must be 1 or 2")),
+    };
+    match (start, end) {
+        (Some(start), Some(end)) => Ok((start, end)),
+        _ => Err(DkaError::user("Invalid year")),
+    }
+}
+
+fn get_missing_acquisitions_error(trade_info: &TradeInfo) -> DkaError {
+    DkaError::User(format!(
+        "Disposal of {} ({}) on {} exceeds the quantity held, import the statements with its earlier acquisitions",
+        trade_info.symbol, trade_info.isin, trade_info.trade_date
+    ))
+}
+
+/// Matches the disposals within the period to acquisitions per ISIN on a first-in, first-out
+/// basis. Trades before the period are needed to establish the acquisition lots, but a security
+/// held from before the first imported statement only fails the periods in which it is disposed of.
+pub fn match_disposals(
+    trade_infos: &[TradeInfo],
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+) -> DkaResult<Vec<DisposalInfo>> {
+    let mut sorted_trade_infos = trade_infos.to_vec();
+    // Acquisitions go before disposals on the same day
+    sorted_trade_infos.sort_by_key(|ti| (ti.trade_date, ti.quantity.is_sign_negative()));
+
+    let mut lots_by_isin: HashMap<String, VecDeque<Lot>> = HashMap::new();
+    // Securities disposed of in larger quantities than the imported trades hold
+    let mut incomplete_isins: HashSet<String> = HashSet::new();
+    let mut disposal_infos: Vec<DisposalInfo> = vec![];
+    for trade_info in sorted_trade_infos.iter() {
+        if trade_info.trade_date > period_end {
+            break;
+        }
+        let in_period = trade_info.trade_date >= period_start;
+        let lots = lots_by_isin.entry(trade_info.isin.clone()).or_default();
+        if trade_info.quantity > Decimal::ZERO {
+            lots.push_back(Lot {
+                acquisition_date: trade_info.trade_date,
+                quantity: trade_info.quantity,
+                unit_cost: (-trade_info.proceeds - trade_info.commission) / trade_info.quantity,
+            });
+            continue;
+        }
+        if in_period && incomplete_isins.contains(&trade_info.isin) {
+            return Err(get_missing_acquisitions_error(trade_info));
+        }
+        let disposal_quantity = -trade_info.quantity;
+        let unit_proceeds = (trade_info.proceeds + trade_info.commission) / disposal_quantity;
+        let mut remaining_quantity = disposal_quantity;
+        while remaining_quantity > Decimal::ZERO {
+            let Some(lot) = lots.front_mut() else {
+                if in_period {
+                    return Err(get_missing_acquisitions_error(trade_info));
+                }
+                incomplete_isins.insert(trade_info.isin.clone());
+                break;
+            };
+            let matched_quantity = remaining_quantity.min(lot.quantity);
+            if in_period {
+                disposal_infos.push(DisposalInfo {
+                    isin: trade_info.isin.clone(),
+                    symbol: trade_info.symbol.clone(),
+                    quantity: matched_quantity,
+                    currency_code: trade_info.currency_code.clone(),
+                    acquisition_date: lot.acquisition_date,
+                    acquisition_currency_amount: matched_quantity * lot.unit_cost,
+                    disposal_date: trade_info.trade_date,
+                    disposal_currency_amount: matched_quantity * unit_proceeds,
+                });
+            }
+            lot.quantity -= matched_quantity;
+            remaining_quantity -= matched_quantity;
+            if lot.quantity.is_zero() {
+                lots.pop_front();
+            }
+        }
+    }
+    Ok(disposal_infos)
+}
+
+fn is_tax_exempt(acquisition_date: &NaiveDate, disposal_date: &NaiveDate) -> bool {
+    match acquisition_date.checked_add_months(Months::new(TAX_EXEMPT_HOLDING_MONTHS)) {
+        Some(exemption_date) => *disposal_date >= exemption_date,
+        None => false,
+    }
+}
+
+pub async fn get_capital_gain_info(
+    disposal_info: &DisposalInfo,
+    exchange_rate_infos: &[ExchangeRateInfo],
+    exchange_rate_provider: &mut impl ExchangeRateProvider,
+) -> DkaResult<CapitalGainInfo> {
+    let acquisition_exchange_rate = get_exchange_rate(
+        &disposal_info.acquisition_date,
+        &disposal_info.currency_code,
+        exchange_rate_infos,
+        exchange_rate_provider,
+    )
+    .await?;
+    let disposal_exchange_rate = get_exchange_rate(
+        &disposal_info.disposal_date,
+        &disposal_info.currency_code,
+        exchange_rate_infos,
+        exchange_rate_provider,
+    )
+    .await?;
+    let acquisition_value_rsdc =
+        to_rsdc(disposal_info.acquisition_currency_amount * acquisition_exchange_rate);
+    let disposal_value_rsdc =
+        to_rsdc(disposal_info.disposal_currency_amount * disposal_exchange_rate);
+    Ok(CapitalGainInfo {
+        isin: disposal_info.isin.clone(),
+        symbol: disposal_info.symbol.clone(),
+        quantity: disposal_info.quantity,
+        acquisition_date: disposal_info.acquisition_date,
+        disposal_date: disposal_info.disposal_date,
+        acquisition_value_rsdc,
+        disposal_value_rsdc,
+        capital_gain_rsdc: disposal_value_rsdc - acquisition_value_rsdc,
+        tax_exempt: is_tax_exempt(
+            &disposal_info.acquisition_date,
+            &disposal_info.disposal_date,
+        ),
+    })
+}
+
+pub async fn get_capital_gains_filing_info(
+    trade_infos: &[TradeInfo],
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+    exchange_rate_infos: &[ExchangeRateInfo],
+    exchange_rate_provider: &mut impl ExchangeRateProvider,
+) -> DkaResult<CapitalGainsFilingInfo> {
+    let mut capital_gain_infos: Vec<CapitalGainInfo> = vec![];
+    for disposal_info in match_disposals(trade_infos, period_start, period_end)?.iter() {
+        capital_gain_infos.push(
+            get_capital_gain_info(disposal_info, exchange_rate_infos, exchange_rate_provider)
+                .await?,
+        );
+    }
+    Ok(CapitalGainsFilingInfo::new(
+        period_start,
+        period_end,
+        capital_gain_infos,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::parse_iso;
+
+    fn trade(date: &str, quantity: Decimal, proceeds: Decimal, commission: Decimal) -> TradeInfo {
+        TradeInfo {
+            isin: "US0378331005".into(),
+            symbol: "AAPL".into(),
+            trade_date: parse_iso(date).unwrap(),
+            currency_code: "USD".into(),
+            quantity,
+            proceeds,
+            commission,
+        }
+    }
+
+    #[test]
+    fn test_match_disposals_fifo() {
+        let trade_infos = vec![
+            trade("2023-03-01", dec!(-15.0), dec!(1800.0), dec!(-3.0)),
+            trade("2023-01-10", dec!(10.0), dec!(-1000.0), dec!(-2.0)),
+            trade("2023-02-10", dec!(10.0), dec!(-1100.0), dec!(-2.0)),
+        ];
+        assert_eq!(
+            match_disposals(
+                &trade_infos,
+                parse_iso("2023-01-01").unwrap(),
+                parse_iso("2023-06-30").unwrap()
+            )
+            .unwrap(),
+            vec![
+                DisposalInfo {
+                    isin: "US0378331005".into(),
+                    symbol: "AAPL".into(),
+                    quantity: dec!(10.0),
+                    currency_code: "USD".into(),
+                    acquisition_date: parse_iso("2023-01-10").unwrap(),
+                    acquisition_currency_amount: dec!(1002.0),
+                    disposal_date: parse_iso("2023-03-01").unwrap(),
+                    disposal_currency_amount: dec!(1198.0),
+                },
+                // Partially matched second lot
+                DisposalInfo {
+                    isin: "US0378331005".into(),
+                    symbol: "AAPL".into(),
+                    quantity: dec!(5.0),
+                    currency_code: "USD".into(),
+                    acquisition_date: parse_iso("2023-02-10").unwrap(),
+                    acquisition_currency_amount: dec!(551.0),
+                    disposal_date: parse_iso("2023-03-01").unwrap(),
+                    disposal_currency_amount: dec!(599.0),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_disposals_short_sale() {
+        let trade_infos = vec![
+            trade("2023-01-10", dec!(10.0), dec!(-1000.0), dec!(-2.0)),
+            trade("2023-03-01", dec!(-15.0), dec!(1800.0), dec!(-3.0)),
+        ];
+        assert!(matches!(
+            match_disposals(
+                &trade_infos,
+                parse_iso("2023-01-01").unwrap(),
+                parse_iso("2023-06-30").unwrap()
+            ),
+            Err(DkaError::User(_))
+        ));
+    }
+
+    #[test]
+    fn test_match_disposals_held_before_first_statement() {
+        let msft_trade = |date: &str, quantity: Decimal, proceeds: Decimal| TradeInfo {
+            isin: "US5949181045".into(),
+            symbol: "MSFT".into(),
+            ..trade(date, quantity, proceeds, dec!(-1.0))
+        };
+        let mut trade_infos = vec![
+            trade("2023-01-10", dec!(10.0), dec!(-1000.0), dec!(-2.0)),
+            // More than the imported statements hold
+            trade("2023-02-01", dec!(-15.0), dec!(1800.0), dec!(-3.0)),
+            msft_trade("2023-03-01", dec!(5.0), dec!(-1150.0)),
+            msft_trade("2023-08-01", dec!(-5.0), dec!(1200.0)),
+            trade("2023-09-01", dec!(10.0), dec!(-1100.0), dec!(-2.0)),
+        ];
+        let first_half = (
+            parse_iso("2023-01-01").unwrap(),
+            parse_iso("2023-06-30").unwrap(),
+        );
+        let second_half = (
+            parse_iso("2023-07-01").unwrap(),
+            parse_iso("2023-12-31").unwrap(),
+        );
+        let DkaError::User(message) =
+            match_disposals(&trade_infos, first_half.0, first_half.1).unwrap_err()
+        else {
+            panic!("Expected a user error");
+        };
+        assert!(message.contains("US0378331005"));
+        // Other securities can still be filed
+        let disposal_infos = match_disposals(&trade_infos, second_half.0, second_half.1).unwrap();
+        assert_eq!(disposal_infos.len(), 1);
+        assert_eq!(disposal_infos[0].isin, "US5949181045");
+        // Until the incomplete security is disposed of again
+        trade_infos.push(trade("2023-10-02", dec!(-5.0), dec!(600.0), dec!(-1.0)));
+        assert!(matches!(
+            match_disposals(&trade_infos, second_half.0, second_half.1),
+            Err(DkaError::User(_))
+        ));
+    }
+
+    #[test]
+    fn test_get_half_year_bounds() {
+        assert_eq!(
+            get_half_year_bounds(2023, 2).unwrap(),
+            (
+                parse_iso("2023-07-01").unwrap(),
+                parse_iso("2023-12-31").unwrap()
+            )
+        );
+        assert!(get_half_year_bounds(2023, 3).is_err());
+    }
+
+    #[test]
+    fn test_is_tax_exempt() {
+        let acquisition_date = parse_iso("2013-03-01").unwrap();
+        assert!(!is_tax_exempt(
+            &acquisition_date,
+            &parse_iso("2023-02-28").unwrap()
+        ));
+        assert!(is_tax_exempt(
+            &acquisition_date,
+            &parse_iso("2023-03-01").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_capital_gains_filing_info_offsets_losses() {
+        let capital_gain_info = |capital_gain_rsdc: i64, tax_exempt: bool| CapitalGainInfo {
+            isin: "US0378331005".into(),
+            symbol: "AAPL".into(),
+            quantity: dec!(1.0),
+            acquisition_date: parse_iso("2023-01-10").unwrap(),
+            disposal_date: parse_iso("2023-03-01").unwrap(),
+            acquisition_value_rsdc: 1000000,
+            disposal_value_rsdc: 1000000 + capital_gain_rsdc,
+            capital_gain_rsdc,
+            tax_exempt,
+        };
+        let cgfi = CapitalGainsFilingInfo::new(
+            parse_iso("2023-01-01").unwrap(),
+            parse_iso("2023-06-30").unwrap(),
+            vec![
+                capital_gain_info(500000, false),
+                capital_gain_info(-200000, false),
+                capital_gain_info(900000, true),
+            ],
+        );
+        assert_eq!(cgfi.capital_gain_rsdc, 500000);
+        assert_eq!(cgfi.capital_loss_rsdc, 200000);
+        assert_eq!(cgfi.tax_payable_rsdc, 45000);
+    }
+}
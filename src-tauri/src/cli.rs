@@ -0,0 +1,526 @@
+use std::{fs, io::Write, path::PathBuf, process::ExitCode, time::Duration};
+
+use clap::{Parser, Subcommand};
+use tokio::sync::Mutex;
+
+use crate::{
+    app_store::AppStore,
+    database::{
+        get_filings, get_importers, migrate_database, rekey_secrets, DEFAULT_TAXPAYER_PROFILE_ID,
+    },
+    drop_folder::DropFolderCache,
+    error::{DkaError, DkaResult},
+    filesystem::{get_filing_content, get_technical_conf, migrate_filesystem},
+    ipc_types::{JobMessage, Report},
+    job_logic::{
+        get_new_dropped_files, import_dropped_files, import_mail_archive, process_reports, run_job,
+        save_new_report,
+    },
+    job_store::JobStore,
+    opo_data::format_rsd_amount,
+    secrets::{get_default_key_dir, PASSPHRASE_ENV_VAR},
+};
+
+// Must match the identifier in tauri.conf.json, so that the CLI shares the desktop app's data
+const APP_IDENTIFIER: &str = "dobkapapp";
+
+const REPORT_TYPES: &[&str] = &[
+    "NativeIncomeJson",
+    "IbkrCsv",
+    "IbkrFlexXml",
+    "Trading212Csv",
+    "RevolutCsv",
+    "DegiroCsv",
+    "GenericCsv",
+];
+
+#[derive(Parser)]
+#[command(
+    name = "dobkapapp-cli",
+    about = "Runs the dobkapapp import and filing pipeline without the UI"
+)]
+struct Cli {
+    /// Data directory, defaults to the one used by the desktop app
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+    /// Directory of the secret key file, defaults to the one used by the desktop app
+    #[arg(long, global = true)]
+    key_dir: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Fetch new reports from the mailbox and create filings for them
+    Sync,
+    Filings {
+        #[command(subcommand)]
+        command: FilingsCommand,
+    },
+    Filing {
+        #[command(subcommand)]
+        command: FilingCommand,
+    },
+    Report {
+        #[command(subcommand)]
+        command: ReportCommand,
+    },
+    MailArchive {
+        #[command(subcommand)]
+        command: MailArchiveCommand,
+    },
+    Secrets {
+        #[command(subcommand)]
+        command: SecretsCommand,
+    },
+    /// Keep importing the report files placed in the drop folder and create filings for them
+    Watch {
+        /// Drop folder, defaults to the one in the technical settings
+        folder: Option<PathBuf>,
+        /// Seconds between checks for new files
+        #[arg(long, default_value_t = 30)]
+        interval: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum FilingsCommand {
+    /// List filings, newest first
+    List {
+        /// Only list filings with this status (init, filed or paid)
+        #[arg(long)]
+        status: Option<String>,
+        /// Only list filings of this taxpayer profile
+        #[arg(long)]
+        taxpayer: Option<i32>,
+    },
+}
+
+#[derive(Subcommand)]
+enum FilingCommand {
+    /// Write the filing XML to a file, or to stdout
+    Export {
+        filing_id: i32,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReportCommand {
+    /// Import a report file and create filings for it
+    Import {
+        file: PathBuf,
+        /// Report type, e.g. IbkrCsv. Defaults to the importer's report type
+        #[arg(long = "type")]
+        report_type: Option<String>,
+        /// Importer whose settings (payment notes, CSV column mapping) apply to the report
+        #[arg(long)]
+        importer: Option<i32>,
+    },
+}
+
+#[derive(Subcommand)]
+enum MailArchiveCommand {
+    /// Import the reports from an .eml file, mbox file or Maildir directory, matched against the
+    /// importers' filters, and create filings for them
+    Import { path: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum SecretsCommand {
+    /// Encrypt the mailbox secrets with a new passphrase, read from standard input
+    SetPassphrase,
+    /// Encrypt the mailbox secrets with the key file instead of a passphrase
+    RemovePassphrase,
+}
+
+fn get_default_data_dir() -> DkaResult<PathBuf> {
+    dirs::data_dir()
+        .map(|data_dir| data_dir.join(APP_IDENTIFIER))
+        .ok_or_else(|| DkaError::user("Cannot determine the data directory, use --data-dir"))
+}
+
+fn migrate(store: &AppStore) -> DkaResult<()> {
+    migrate_filesystem(store)?;
+    migrate_database(store)?;
+    // Fails if a provider is already installed, which is fine
+    let _ =
+        rustls::crypto::CryptoProvider::install_default(rustls::crypto::ring::default_provider());
+    Ok(())
+}
+
+/// Prints the job messages and fails if any of them is an error
+async fn print_job_messages(job_id: i32, job_store_mutex: &Mutex<JobStore>) -> DkaResult<()> {
+    let Some(job) = job_store_mutex.lock().await.get(job_id) else {
+        return Ok(());
+    };
+    let mut error_count = 0;
+    for message in job.messages {
+        match message {
+            JobMessage::Report {
+                from,
+                subject,
+                attachment_name,
+            } => println!("Report {attachment_name} from {from} ({subject})"),
+            JobMessage::Success { message } => println!("{message}"),
+            JobMessage::Warning { message } => eprintln!("Warning: {message}"),
+            JobMessage::Error { message } => {
+                eprintln!("Error: {message}");
+                error_count += 1;
+            }
+        }
+    }
+    if error_count > 0 {
+        return Err(DkaError::Generic(format!("{error_count} errors")));
+    }
+    Ok(())
+}
+
+async fn sync(store: &AppStore) -> DkaResult<()> {
+    let job_store_mutex = Mutex::new(JobStore::new());
+    let job_id = job_store_mutex.lock().await.create();
+    let result = run_job(job_id, &job_store_mutex, store).await;
+    print_job_messages(job_id, &job_store_mutex).await?;
+    result
+}
+
+async fn import_archive(store: &AppStore, path: PathBuf) -> DkaResult<()> {
+    let job_store_mutex = Mutex::new(JobStore::new());
+    let job_id = job_store_mutex.lock().await.create();
+    let result = import_mail_archive(job_id, &job_store_mutex, store, &path).await;
+    print_job_messages(job_id, &job_store_mutex).await?;
+    result
+}
+
+async fn watch(store: &AppStore, folder: Option<PathBuf>, interval: u64) -> DkaResult<()> {
+    let drop_folder = match folder {
+        Some(folder) => folder,
+        None => get_technical_conf(store)?
+            .drop_folder
+            .map(PathBuf::from)
+            .ok_or_else(|| DkaError::user("No drop folder configured, pass one as an argument"))?,
+    };
+    let mut drop_folder_cache = DropFolderCache::default();
+    loop {
+        // Keep watching, e.g. the folder can be on a drive that is not mounted yet
+        match get_new_dropped_files(store, &drop_folder, &mut drop_folder_cache) {
+            Ok(new_dropped_files) if !new_dropped_files.is_empty() => {
+                let job_store_mutex = Mutex::new(JobStore::new());
+                let job_id = job_store_mutex.lock().await.create();
+                let result = match import_dropped_files(
+                    job_id,
+                    &job_store_mutex,
+                    store,
+                    &drop_folder,
+                    &new_dropped_files,
+                )
+                .await
+                {
+                    Ok(()) => process_reports(job_id, &job_store_mutex, store).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = print_job_messages(job_id, &job_store_mutex)
+                    .await
+                    .and(result)
+                {
+                    eprintln!("{e}");
+                }
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("{}: {e}", drop_folder.display()),
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+fn list_filings(
+    store: &AppStore,
+    status: Option<String>,
+    taxpayer_profile_id: Option<i32>,
+) -> DkaResult<()> {
+    for filing in get_filings(store)?
+        .iter()
+        .filter(|f| status.as_ref().is_none_or(|s| &f.status == s))
+        .filter(|f| taxpayer_profile_id.is_none_or(|id| f.taxpayer_profile_id == id))
+    {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            filing.id,
+            filing._type,
+            filing.status,
+            filing.filing_deadline,
+            format_rsd_amount(filing.tax_payable),
+            filing.paying_entity,
+        );
+    }
+    Ok(())
+}
+
+fn export_filing(store: &AppStore, filing_id: i32, output: Option<PathBuf>) -> DkaResult<()> {
+    let filing_content = get_filing_content(store, filing_id)?;
+    match output {
+        Some(output) => fs::write(output, filing_content)?,
+        None => std::io::stdout().write_all(&filing_content)?,
+    };
+    Ok(())
+}
+
+async fn import_report(
+    store: &AppStore,
+    file: PathBuf,
+    report_type: Option<String>,
+    importer_id: Option<i32>,
+) -> DkaResult<()> {
+    let importer = match importer_id {
+        Some(importer_id) => Some(
+            get_importers(store)?
+                .into_iter()
+                .find(|im| im.id == importer_id)
+                .ok_or_else(|| DkaError::user(format!("No importer with id {importer_id}")))?,
+        ),
+        None => None,
+    };
+    let Some(report_type) = report_type.or(importer.as_ref().map(|im| im.report_type.clone()))
+    else {
+        return Err(DkaError::user("Either --type or --importer is required"));
+    };
+    if !REPORT_TYPES.contains(&report_type.as_str()) {
+        return Err(DkaError::User(format!(
+            "Unknown report type {report_type}, expected one of {}",
+            REPORT_TYPES.join(", ")
+        )));
+    }
+    let report_content = fs::read(&file)?;
+    let report_id = save_new_report(
+        store,
+        &Report {
+            id: 0,
+            _type: report_type,
+            importer_id,
+            mailbox_id: None,
+            mailbox_message_id: None,
+            report_name: file
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or("Imported report".into()),
+            status: "init".into(),
+            taxpayer_profile_id: importer
+                .map(|im| im.taxpayer_profile_id)
+                .unwrap_or(DEFAULT_TAXPAYER_PROFILE_ID),
+        },
+        &report_content,
+    )?;
+    println!("Imported report {report_id}");
+
+    let job_store_mutex = Mutex::new(JobStore::new());
+    let job_id = job_store_mutex.lock().await.create();
+    let result = process_reports(job_id, &job_store_mutex, store).await;
+    print_job_messages(job_id, &job_store_mutex).await?;
+    result
+}
+
+fn set_passphrase(store: &AppStore) -> DkaResult<()> {
+    let mut passphrase = String::new();
+    std::io::stdin().read_line(&mut passphrase)?;
+    let passphrase = passphrase.trim_end_matches(['\r', '\n']);
+    if passphrase.is_empty() {
+        return Err(DkaError::user("The passphrase cannot be empty"));
+    }
+    rekey_secrets(store, Some(passphrase))?;
+    println!(
+        "Secrets are now protected by the passphrase, set {PASSPHRASE_ENV_VAR} to it from now on"
+    );
+    Ok(())
+}
+
+async fn run_command(cli: Cli) -> DkaResult<()> {
+    let data_dir = match cli.data_dir {
+        Some(data_dir) => data_dir,
+        None => get_default_data_dir()?,
+    };
+    let key_dir = match cli.key_dir {
+        Some(key_dir) => key_dir,
+        None => get_default_key_dir()?,
+    };
+    let mut store = AppStore::new(data_dir, key_dir);
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV_VAR) {
+        store = store.with_passphrase(passphrase);
+    }
+    migrate(&store)?;
+    match cli.command {
+        Command::Sync => sync(&store).await,
+        Command::Filings {
+            command: FilingsCommand::List { status, taxpayer },
+        } => list_filings(&store, status, taxpayer),
+        Command::Filing {
+            command: FilingCommand::Export { filing_id, output },
+        } => export_filing(&store, filing_id, output),
+        Command::Report {
+            command:
+                ReportCommand::Import {
+                    file,
+                    report_type,
+                    importer,
+                },
+        } => import_report(&store, file, report_type, importer).await,
+        Command::MailArchive {
+            command: MailArchiveCommand::Import { path },
+        } => import_archive(&store, path).await,
+        Command::Secrets {
+            command: SecretsCommand::SetPassphrase,
+        } => set_passphrase(&store),
+        Command::Secrets {
+            command: SecretsCommand::RemovePassphrase,
+        } => rekey_secrets(&store, None),
+        Command::Watch { folder, interval } => watch(&store, folder, interval).await,
+    }
+}
+
+pub fn run_cli() -> ExitCode {
+    let cli = Cli::parse();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to start the async runtime");
+    match runtime.block_on(run_command(cli)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use clap::CommandFactory;
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        database::{save_nbs_exchange_rates, update_taxpayer_profile},
+        exchange_rate::NbsExchangeRate,
+        ipc_types::TaxpayerProfile,
+    };
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_report_import() {
+        let cli = Cli::parse_from([
+            "dobkapapp-cli",
+            "report",
+            "import",
+            "report.csv",
+            "--type",
+            "IbkrCsv",
+            "--data-dir",
+            "/tmp/dobkapapp",
+        ]);
+        assert_eq!(cli.data_dir, Some(PathBuf::from("/tmp/dobkapapp")));
+        match cli.command {
+            Command::Report {
+                command:
+                    ReportCommand::Import {
+                        file,
+                        report_type,
+                        importer,
+                    },
+            } => {
+                assert_eq!(file, PathBuf::from("report.csv"));
+                assert_eq!(report_type.as_deref(), Some("IbkrCsv"));
+                assert_eq!(importer, None);
+            }
+            _ => panic!("Expected report import"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_and_export_report() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = AppStore::new(data_dir.path().to_path_buf(), data_dir.path().to_path_buf());
+        migrate(&store).unwrap();
+        update_taxpayer_profile(
+            &store,
+            &TaxpayerProfile {
+                id: 1,
+                jmbg: "0101990710008".into(),
+                full_name: "Petar Petrovic".into(),
+                street_address: "Glavna 1".into(),
+                opstina_code: "013".into(),
+                phone_number: "0601234567".into(),
+                email_address: "petar@example.com".into(),
+            },
+        )
+        .unwrap();
+        // Seeds the exchange rate cache, so that nothing is fetched from NBS
+        save_nbs_exchange_rates(
+            &store,
+            &[NbsExchangeRate {
+                date: NaiveDate::from_ymd_opt(2020, 7, 16).unwrap(),
+                currency_code: "USD".into(),
+                exchange_rate: dec!(100),
+            }],
+        )
+        .unwrap();
+        let report_path = data_dir.path().join("dividend.json");
+        fs::write(
+            &report_path,
+            r#"{
+                "type": "dividend",
+                "payingEntity": "Apple",
+                "incomeDate": "2020-07-16",
+                "incomeCurrencyCode": "USD",
+                "incomeCurrencyAmount": 100,
+                "whtCurrencyCode": "USD",
+                "whtCurrencyAmount": 10
+            }"#,
+        )
+        .unwrap();
+        let data_dir_arg = data_dir.path().to_str().unwrap();
+        // Keeps the key file of the test out of the user's key directory
+        let key_dir_arg = data_dir_arg;
+
+        run_command(Cli::parse_from([
+            "dobkapapp-cli",
+            "--data-dir",
+            data_dir_arg,
+            "--key-dir",
+            key_dir_arg,
+            "report",
+            "import",
+            report_path.to_str().unwrap(),
+            "--type",
+            "NativeIncomeJson",
+        ]))
+        .await
+        .unwrap();
+        let filings = get_filings(&store).unwrap();
+        assert_eq!(filings.len(), 1);
+        assert_eq!(filings[0].paying_entity, "Apple");
+
+        let output_path = data_dir.path().join("filing.xml");
+        run_command(Cli::parse_from([
+            "dobkapapp-cli",
+            "--data-dir",
+            data_dir_arg,
+            "--key-dir",
+            key_dir_arg,
+            "filing",
+            "export",
+            &filings[0].id.to_string(),
+            "--output",
+            output_path.to_str().unwrap(),
+        ]))
+        .await
+        .unwrap();
+        let filing_content = fs::read_to_string(output_path).unwrap();
+        assert!(filing_content.contains("0101990710008"));
+    }
+}
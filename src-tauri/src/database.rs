@@ -1,21 +1,30 @@
-use rusqlite::{fallible_streaming_iterator::FallibleStreamingIterator, named_params, Connection};
-use tauri::AppHandle;
+use std::collections::HashSet;
+
+use chrono::NaiveDate;
+use rusqlite::{fallible_streaming_iterator::FallibleStreamingIterator, named_params, types::Type};
 
 use crate::{
+    app_store::AppStore,
+    date::{format_iso, parse_iso},
     error::{DkaError, DkaResult},
-    filesystem::get_db_path,
-    ipc_types::{Filing, Importer, Mailbox, MailboxCursor, Report, TaxpayerProfile},
+    exchange_rate::NbsExchangeRate,
+    filesystem::{get_content_hash, get_report_content, get_report_path},
+    ibkr_flex_report_parser::IbkrFlexReportParser,
+    ibkr_report_parser::IbkrReportParser,
+    income_tax::FilingInfo,
+    ipc_types::{
+        Filing, GenericCsvConf, Importer, Mailbox, MailboxCursor, MailboxFolderCursor,
+        MailboxOAuthConf, Report, TaxpayerProfile,
+    },
+    money::{from_real, to_real},
+    report_parser::{
+        get_report_parser, get_trade_hashes, ExchangeRateInfo, FiledIncome, ReportParser, TradeInfo,
+    },
+    secrets::SecretKey,
 };
 
-fn get_connection(app_handle: &AppHandle) -> DkaResult<Connection> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path)?;
-    conn.pragma_update(None, "journal_mode", &"WAL")?;
-    Ok(conn)
-}
-
-pub fn migrate_database(app_handle: &AppHandle) -> DkaResult<()> {
-    let conn = get_connection(app_handle)?;
+pub fn migrate_database(store: &AppStore) -> DkaResult<()> {
+    let conn = store.connection()?;
     let mut stmt = conn.prepare(
         "
         SELECT name FROM sqlite_master WHERE type='table' AND name='dobkapman_migrations'
@@ -215,188 +224,1394 @@ pub fn migrate_database(app_handle: &AppHandle) -> DkaResult<()> {
         )?
         .execute([])?;
     }
-    // Logic for migration 5 and above goes here
-    if db_migration_version > 4 {
-        panic!("Cannot migrate backwards, db_migration_version={db_migration_version}");
+    if db_migration_version < 5 {
+        conn.prepare(
+            "
+            ALTER TABLE importers ADD COLUMN generic_csv_conf TEXT
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (5, 'generic-csv-conf')
+        ",
+        )?
+        .execute([])?;
     }
-    Ok(())
-}
-
-// Mailboxes
-
-pub fn get_mailbox(app_handle: &AppHandle) -> DkaResult<Mailbox> {
-    let conn = get_connection(app_handle)?;
-    let mut stmt = conn.prepare(
-        "
-        select
-            id,
-            email_address,
-            email_password,
-            imap_host,
-            imap_port,
-            cursor
-        from mailboxes
-        where id=1
-    ",
-    )?;
-    let rows: Vec<_> = stmt
-        .query_map([], |row| {
-            Ok(Mailbox {
-                id: row.get::<_, i32>(0)?,
-                email_address: row.get::<_, String>(1)?,
-                email_password: row.get::<_, String>(2)?,
-                imap_host: row.get::<_, String>(3)?,
-                imap_port: row.get::<_, i32>(4)?,
-                cursor: MailboxCursor::from_db_string(&row.get::<_, String>(5)?).unwrap(),
-            })
-        })?
-        .collect();
-    if rows.is_empty() {
-        return Err(DkaError::generic("Mailbox not found in database"));
+    if db_migration_version < 6 {
+        conn.prepare(
+            "
+            CREATE TABLE trades (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                report_id INTEGER NOT NULL REFERENCES reports(id),
+                isin TEXT NOT NULL,
+                symbol TEXT NOT NULL,
+                trade_date TEXT NOT NULL,
+                currency_code TEXT NOT NULL,
+                quantity REAL NOT NULL,
+                proceeds REAL NOT NULL,
+                commission REAL NOT NULL
+            )
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (6, 'trades')
+        ",
+        )?
+        .execute([])?;
     }
-    Ok(rows.into_iter().next().unwrap().unwrap())
-}
-
-pub fn update_mailbox(app_handle: &AppHandle, mailbox: &Mailbox) -> DkaResult<()> {
-    get_connection(app_handle)?
-        .prepare(
+    if db_migration_version < 7 {
+        conn.prepare(
             "
-        update mailboxes set
-            email_address = :email_address,
-            email_password = :email_password,
-            imap_host = :imap_host,
-            imap_port = :imap_port,
-            cursor = :cursor
-        where id=:id
+            ALTER TABLE filings ADD COLUMN income_date TEXT
         ",
         )?
-        .execute(named_params! {
-            ":id": mailbox.id,
-            ":email_address": mailbox.email_address,
-            ":email_password": mailbox.email_password,
-            ":imap_host": mailbox.imap_host,
-            ":imap_port": mailbox.imap_port,
-            ":cursor": mailbox.cursor.to_db_string(),
-        })?;
-    Ok(())
-}
-
-// Taxpayer profiles
-
-pub fn get_taxpayer_profile(app_handle: &AppHandle) -> DkaResult<TaxpayerProfile> {
-    let conn = get_connection(app_handle)?;
-    let mut stmt = conn.prepare(
-        "
-        select
-            id,
-            jmbg,
-            full_name,
-            street_address,
-            opstina_code,
-            phone_number,
-            email_address
-        from taxpayer_profiles
-        where id=1
-    ",
-    )?;
-    let rows: Vec<_> = stmt
-        .query_map([], |row| {
-            Ok(TaxpayerProfile {
-                id: row.get::<_, i32>(0)?,
-                jmbg: row.get::<_, String>(1)?,
-                full_name: row.get::<_, String>(2)?,
-                street_address: row.get::<_, String>(3)?,
-                opstina_code: row.get::<_, String>(4)?,
-                phone_number: row.get::<_, String>(5)?,
-                email_address: row.get::<_, String>(6)?,
-            })
-        })?
-        .collect();
-    if rows.is_empty() {
-        return Err(DkaError::generic("Taxpayer profile not found in database"));
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN gross_income INTEGER
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN wht_paid INTEGER
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (7, 'filing-income')
+        ",
+        )?
+        .execute([])?;
     }
-    Ok(rows.into_iter().next().unwrap().unwrap())
-}
-
-pub fn update_taxpayer_profile(
-    app_handle: &AppHandle,
-    taxpayer_profile: &TaxpayerProfile,
-) -> DkaResult<()> {
-    get_connection(app_handle)?
-        .prepare(
+    if db_migration_version < 8 {
+        conn.prepare(
             "
-            update taxpayer_profiles set
-                jmbg = :jmbg,
-                full_name = :full_name,
-                street_address = :street_address,
-                opstina_code = :opstina_code,
-                phone_number = :phone_number,
-                email_address = :email_address
-            where id=:id
+            ALTER TABLE filings ADD COLUMN gross_tax_payable INTEGER
         ",
         )?
-        .execute(named_params! {
-            ":id": taxpayer_profile.id,
-            ":jmbg": taxpayer_profile.jmbg,
-            ":full_name": taxpayer_profile.full_name,
-            ":street_address": taxpayer_profile.street_address,
-            ":opstina_code": taxpayer_profile.opstina_code,
-            ":phone_number": taxpayer_profile.phone_number,
-            ":email_address": taxpayer_profile.email_address,
-        })?;
-    Ok(())
-}
-
-// Importers
-
-pub fn get_importers(app_handle: &AppHandle) -> DkaResult<Vec<Importer>> {
-    let conn = get_connection(app_handle)?;
-    let mut stmt = conn.prepare(
-        "
-        select
-            id,
-            name,
-            report_type,
-            taxpayer_profile_id,
-            mailbox_id,
-            from_filter,
-            subject_filter,
-            payment_notes,
-            attachment_regex
-        from importers
-        order by id
-    ",
-    )?;
-    let rows: Vec<_> = stmt
-        .query_map([], |row| {
-            Ok(Importer {
-                id: row.get::<_, i32>(0)?,
-                name: row.get::<_, String>(1)?,
-                report_type: row.get::<_, String>(2)?,
-                taxpayer_profile_id: row.get::<_, i32>(3)?,
-                mailbox_id: row.get::<_, i32>(4)?,
-                from_filter: row.get::<_, String>(5)?,
-                subject_filter: row.get::<_, String>(6)?,
-                payment_notes: row.get::<_, String>(7)?,
-                attachment_regex: row.get::<_, String>(8)?,
-            })
-        })?
-        .map(|r| r.unwrap())
-        .collect();
-    Ok(rows)
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN income_currency_code TEXT
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN income_currency_amount REAL
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN income_exchange_rate REAL
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN wht_currency_code TEXT
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN wht_currency_amount REAL
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN wht_exchange_rate REAL
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (8, 'filing-breakdown')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 9 {
+        conn.prepare(
+            "
+            CREATE TABLE nbs_exchange_rates (
+                date TEXT NOT NULL,
+                currency_code TEXT NOT NULL,
+                exchange_rate REAL NOT NULL,
+                PRIMARY KEY (date, currency_code)
+            )
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (9, 'nbs-exchange-rates')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 10 {
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN amends_filing_id INTEGER
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN tax_payable_delta INTEGER
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (10, 'filing-amendment')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 11 {
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN late_payment_date TEXT
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN late_interest INTEGER
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (11, 'late-interest')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 12 {
+        conn.prepare(
+            "
+            ALTER TABLE importers ADD COLUMN group_filings_by_date INTEGER NOT NULL DEFAULT 0
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (12, 'group-filings-by-date')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 13 {
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN wht_actual INTEGER
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN wht_credit_rate REAL
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (13, 'wht-treaty-cap')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 14 {
+        conn.prepare(
+            "
+            ALTER TABLE mailboxes ADD COLUMN auth_method TEXT NOT NULL DEFAULT 'password'
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE mailboxes ADD COLUMN oauth_conf TEXT
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (14, 'mailbox-oauth')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 15 {
+        let secret_key = store.secret_key()?;
+        let mut stmt = conn.prepare(
+            "
+            SELECT id, email_password, oauth_conf FROM mailboxes
+        ",
+        )?;
+        let rows: Vec<(i32, String, Option<String>)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+        for (id, email_password, oauth_conf) in rows {
+            conn.prepare(
+                "
+                UPDATE mailboxes SET email_password = :email_password, oauth_conf = :oauth_conf
+                WHERE id = :id
+            ",
+            )?
+            .execute(named_params! {
+                ":id": id,
+                ":email_password": secret_key.encrypt(&email_password)?,
+                ":oauth_conf": oauth_conf.map(|c| secret_key.encrypt(&c)).transpose()?,
+            })?;
+        }
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (15, 'encrypt-secrets')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 16 {
+        // Existing filings belong to the only profile there was
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN taxpayer_profile_id INTEGER NOT NULL DEFAULT 1
+                REFERENCES taxpayer_profiles(id)
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (16, 'filing-taxpayer-profile')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 17 {
+        conn.prepare(
+            "
+            ALTER TABLE reports ADD COLUMN content_hash TEXT
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            CREATE INDEX reports_content_hash ON reports(content_hash)
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (17, 'report-content-hash')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 18 {
+        // Reports imported before content hashes were kept. Their files are hashed as they are,
+        // a missing file leaves the hash empty.
+        let mut stmt = conn.prepare(
+            "
+            SELECT id FROM reports WHERE content_hash IS NULL
+        ",
+        )?;
+        let report_ids: Vec<i32> = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for report_id in report_ids {
+            let Ok(report_content) = get_report_content(store, report_id) else {
+                continue;
+            };
+            conn.prepare(
+                "
+                UPDATE reports SET content_hash = :content_hash WHERE id = :id
+            ",
+            )?
+            .execute(named_params! {
+                ":id": report_id,
+                ":content_hash": get_content_hash(&report_content),
+            })?;
+        }
+        conn.prepare(
+            "
+            CREATE TABLE income_hashes (
+                report_id INTEGER NOT NULL REFERENCES reports(id),
+                income_hash TEXT NOT NULL,
+                PRIMARY KEY (report_id, income_hash)
+            )
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            CREATE INDEX income_hashes_income_hash ON income_hashes(income_hash)
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (18, 'income-hashes')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 19 {
+        conn.prepare(
+            "
+            ALTER TABLE mailboxes ADD COLUMN folders TEXT NOT NULL DEFAULT '[\"INBOX\"]'
+        ",
+        )?
+        .execute([])?;
+        // UID cursors of mailboxes synced before are not carried over, since their folder's
+        // UIDVALIDITY was never kept. Those folders are searched again from the day of the last
+        // sync, and the messages already imported are recognized by their content.
+        conn.prepare(
+            "
+            CREATE TABLE mailbox_folder_cursors (
+                mailbox_id INTEGER NOT NULL REFERENCES mailboxes(id),
+                folder TEXT NOT NULL,
+                uid_validity INTEGER NOT NULL,
+                last_seen_uid INTEGER NOT NULL,
+                PRIMARY KEY (mailbox_id, folder)
+            )
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (19, 'mailbox-folders')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 20 {
+        conn.prepare(
+            "
+            CREATE TABLE report_exchange_rates (
+                report_id INTEGER NOT NULL REFERENCES reports(id),
+                date TEXT NOT NULL,
+                currency_code TEXT NOT NULL,
+                exchange_rate REAL NOT NULL
+            )
+        ",
+        )?
+        .execute([])?;
+        // Capital gains filings have no report, and are told apart by their half year
+        conn.prepare(
+            "
+            CREATE TABLE filings_new (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                report_id INTEGER REFERENCES reports(id),
+                paying_entity TEXT NOT NULL,
+                filing_deadline TEXT NOT NULL,
+                tax_payable INTEGER NOT NULL,
+                status TEXT NOT NULL DEFAULT 'init',
+                \"type\" TEXT NOT NULL DEFAULT 'dividend',
+                tax_payment_reference TEXT NOT NULL DEFAULT '',
+                income_date TEXT,
+                gross_income INTEGER,
+                wht_paid INTEGER,
+                gross_tax_payable INTEGER,
+                income_currency_code TEXT,
+                income_currency_amount REAL,
+                income_exchange_rate REAL,
+                wht_currency_code TEXT,
+                wht_currency_amount REAL,
+                wht_exchange_rate REAL,
+                amends_filing_id INTEGER,
+                tax_payable_delta INTEGER,
+                late_payment_date TEXT,
+                late_interest INTEGER,
+                wht_actual INTEGER,
+                wht_credit_rate REAL,
+                taxpayer_profile_id INTEGER NOT NULL DEFAULT 1 REFERENCES taxpayer_profiles(id),
+                period TEXT
+            )
+        ",
+        )?
+        .execute([])?;
+        // Capital gains filings were named e.g. 'Capital gains 2024 H1'
+        conn.prepare(
+            "
+            INSERT INTO filings_new
+            SELECT
+                id,
+                CASE WHEN type = 'capital-gain' THEN NULL ELSE report_id END,
+                paying_entity,
+                filing_deadline,
+                tax_payable,
+                status,
+                type,
+                tax_payment_reference,
+                income_date,
+                gross_income,
+                wht_paid,
+                gross_tax_payable,
+                income_currency_code,
+                income_currency_amount,
+                income_exchange_rate,
+                wht_currency_code,
+                wht_currency_amount,
+                wht_exchange_rate,
+                amends_filing_id,
+                tax_payable_delta,
+                late_payment_date,
+                late_interest,
+                wht_actual,
+                wht_credit_rate,
+                taxpayer_profile_id,
+                CASE WHEN type = 'capital-gain'
+                    THEN replace(substr(paying_entity, length('Capital gains ') + 1), ' ', '-')
+                END
+            FROM filings
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            DROP TABLE filings
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE filings_new RENAME TO filings
+        ",
+        )?
+        .execute([])?;
+        // Trades and statement exchange rates were not kept for reports processed before,
+        // so they are read again from the reports that have them
+        let mut stmt = conn.prepare(
+            "
+            SELECT id, type FROM reports
+            WHERE status = 'processed' AND type IN ('IbkrCsv', 'IbkrFlexXml')
+        ",
+        )?;
+        let reports: Vec<(i32, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        for (report_id, report_type) in reports {
+            let report_parser: Box<dyn ReportParser> = match report_type.as_str() {
+                "IbkrCsv" => Box::new(IbkrReportParser::new()),
+                _ => Box::new(IbkrFlexReportParser::new()),
+            };
+            let parsed_report = match report_parser.parse(get_report_path(store, report_id)?) {
+                Ok(parsed_report) => parsed_report,
+                Err(e) => {
+                    eprintln!("Could not read the trades of report {report_id}: {e}");
+                    continue;
+                }
+            };
+            let trade_count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM trades WHERE report_id = ?1",
+                [report_id],
+                |row| row.get(0),
+            )?;
+            // Trade hashes are only added by migration 27
+            if trade_count == 0 {
+                for trade_info in parsed_report.trade_infos.iter() {
+                    conn.prepare(
+                        "
+                        INSERT INTO trades (
+                            report_id, isin, symbol, trade_date, currency_code, quantity, proceeds,
+                            commission
+                        ) VALUES (
+                            :report_id, :isin, :symbol, :trade_date, :currency_code, :quantity,
+                            :proceeds, :commission
+                        )
+                    ",
+                    )?
+                    .execute(named_params! {
+                        ":report_id": report_id,
+                        ":isin": trade_info.isin,
+                        ":symbol": trade_info.symbol,
+                        ":trade_date": format_iso(&trade_info.trade_date),
+                        ":currency_code": trade_info.currency_code,
+                        ":quantity": to_real(trade_info.quantity),
+                        ":proceeds": to_real(trade_info.proceeds),
+                        ":commission": to_real(trade_info.commission),
+                    })?;
+                }
+            }
+            create_report_exchange_rates(store, report_id, &parsed_report.exchange_rate_infos)?;
+        }
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (20, 'capital-gains-filings')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 21 {
+        // Amendments which were not submitted yet have their own status
+        conn.prepare(
+            "
+            UPDATE filings SET status = 'amendment'
+            WHERE status = 'init' AND amends_filing_id IS NOT NULL
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (21, 'amendment-status')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 22 {
+        // The calculation of each income declared in a grouped filing
+        conn.prepare(
+            "
+            CREATE TABLE filing_incomes (
+                filing_id INTEGER NOT NULL REFERENCES filings(id),
+                position INTEGER NOT NULL,
+                type TEXT NOT NULL,
+                paying_entity TEXT NOT NULL,
+                income_date TEXT NOT NULL,
+                income_currency_code TEXT NOT NULL,
+                income_currency_amount REAL NOT NULL,
+                income_exchange_rate REAL NOT NULL,
+                wht_currency_code TEXT NOT NULL,
+                wht_currency_amount REAL NOT NULL,
+                wht_exchange_rate REAL NOT NULL,
+                gross_income INTEGER NOT NULL,
+                wht_actual INTEGER NOT NULL,
+                wht_credit_rate REAL,
+                wht_paid INTEGER NOT NULL,
+                gross_tax_payable INTEGER NOT NULL,
+                tax_payable INTEGER NOT NULL
+            )
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (22, 'filing-incomes')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 23 {
+        // Only reports fetched over IMAP come from a mailbox message. The others were stored with
+        // the first mailbox and message 0, which is never a valid IMAP UID.
+        conn.prepare(
+            "
+            CREATE TABLE reports_new (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                importer_id INTEGER REFERENCES importers(id) ON DELETE SET NULL,
+                mailbox_id INTEGER REFERENCES mailboxes(id),
+                mailbox_message_id INTEGER,
+                report_name TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'init',
+                type TEXT NOT NULL DEFAULT 'IbkrCsv',
+                content_hash TEXT
+            )
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO reports_new
+            SELECT
+                id,
+                importer_id,
+                CASE WHEN mailbox_message_id = 0 THEN NULL ELSE mailbox_id END,
+                CASE WHEN mailbox_message_id = 0 THEN NULL ELSE mailbox_message_id END,
+                report_name,
+                status,
+                type,
+                content_hash
+            FROM reports
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            DROP TABLE reports
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE reports_new RENAME TO reports
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            CREATE UNIQUE INDEX reports_importer_report_name_ui
+            ON reports(importer_id, report_name)
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            CREATE INDEX reports_content_hash ON reports(content_hash)
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (23, 'nullable-report-mailbox')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 24 {
+        // Reports keep the taxpayer profile they were imported for, even if their importer is
+        // moved to another profile or deleted later
+        conn.prepare(
+            "
+            ALTER TABLE reports ADD COLUMN taxpayer_profile_id INTEGER NOT NULL DEFAULT 1
+                REFERENCES taxpayer_profiles(id)
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            UPDATE reports SET taxpayer_profile_id = (
+                SELECT im.taxpayer_profile_id FROM importers im WHERE im.id = reports.importer_id
+            )
+            WHERE importer_id IN (SELECT id FROM importers)
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (24, 'report-taxpayer-profile')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 25 {
+        // Incomes are only recognized as the same within a taxpayer profile and importer, as two
+        // accounts can receive the same dividend on the same day
+        conn.prepare(
+            "
+            ALTER TABLE income_hashes ADD COLUMN taxpayer_profile_id INTEGER NOT NULL DEFAULT 1
+                REFERENCES taxpayer_profiles(id)
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            ALTER TABLE income_hashes ADD COLUMN importer_id INTEGER
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            UPDATE income_hashes SET
+                taxpayer_profile_id = (
+                    SELECT r.taxpayer_profile_id FROM reports r WHERE r.id = income_hashes.report_id
+                ),
+                importer_id = (
+                    SELECT r.importer_id FROM reports r WHERE r.id = income_hashes.report_id
+                )
+            WHERE report_id IN (SELECT id FROM reports)
+        ",
+        )?
+        .execute([])?;
+        // Reports processed before income hashes were kept have filings but no hashes, so their
+        // incomes are read again
+        let mut stmt = conn.prepare(
+            "
+            SELECT r.id, r.type, im.generic_csv_conf
+            FROM reports r
+            LEFT JOIN importers im ON im.id = r.importer_id
+            WHERE r.status = 'processed'
+                AND r.id IN (SELECT report_id FROM filings WHERE report_id IS NOT NULL)
+                AND r.id NOT IN (SELECT report_id FROM income_hashes)
+        ",
+        )?;
+        let reports: Vec<(i32, String, Option<String>)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+        for (report_id, report_type, generic_csv_conf) in reports {
+            let generic_csv_conf =
+                generic_csv_conf.and_then(|conf| GenericCsvConf::from_db_string(&conf).ok());
+            let Some(report_parser) = get_report_parser(&report_type, generic_csv_conf) else {
+                continue;
+            };
+            let Ok(parsed_report) = report_parser.parse(get_report_path(store, report_id)?) else {
+                continue;
+            };
+            let income_hashes: Vec<String> = parsed_report
+                .income_infos
+                .iter()
+                .map(|ii| ii.get_income_hash())
+                .collect();
+            for income_hash in income_hashes {
+                conn.prepare(
+                    "
+                    INSERT OR IGNORE INTO income_hashes
+                        (report_id, income_hash, taxpayer_profile_id, importer_id)
+                    SELECT id, :income_hash, taxpayer_profile_id, importer_id
+                    FROM reports WHERE id = :report_id
+                ",
+                )?
+                .execute(named_params! {
+                    ":report_id": report_id,
+                    ":income_hash": income_hash,
+                })?;
+            }
+        }
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (25, 'scoped-income-hashes')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 26 {
+        // Filings of incomes that another importer of the profile already filed, until the user
+        // confirms they are not duplicates
+        conn.prepare(
+            "
+            ALTER TABLE filings ADD COLUMN suspected_duplicate_of_report_id INTEGER
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (26, 'suspected-duplicate-filings')
+        ",
+        )?
+        .execute([])?;
+    }
+    if db_migration_version < 27 {
+        // The same trades can arrive in several statements, e.g. daily and monthly ones
+        conn.prepare(
+            "
+            ALTER TABLE trades ADD COLUMN trade_hash TEXT NOT NULL DEFAULT ''
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            CREATE INDEX trades_trade_hash ON trades(trade_hash)
+        ",
+        )?
+        .execute([])?;
+        let mut stmt = conn.prepare(
+            "
+            SELECT id, report_id, isin, symbol, trade_date, currency_code, quantity, proceeds,
+                commission
+            FROM trades
+            ORDER BY report_id, id
+        ",
+        )?;
+        let trades: Vec<(i32, i32, TradeInfo)> = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i32>(0)?,
+                    row.get::<_, i32>(1)?,
+                    TradeInfo {
+                        isin: row.get::<_, String>(2)?,
+                        symbol: row.get::<_, String>(3)?,
+                        trade_date: parse_iso(&row.get::<_, String>(4)?).map_err(|e| {
+                            rusqlite::Error::FromSqlConversionFailure(4, Type::Text, Box::new(e))
+                        })?,
+                        currency_code: row.get::<_, String>(5)?,
+                        quantity: from_real(row.get::<_, f64>(6)?),
+                        proceeds: from_real(row.get::<_, f64>(7)?),
+                        commission: from_real(row.get::<_, f64>(8)?),
+                    },
+                ))
+            })?
+            .collect::<Result<_, _>>()?;
+        for report_trades in trades.chunk_by(|a, b| a.1 == b.1) {
+            let trade_infos: Vec<TradeInfo> =
+                report_trades.iter().map(|(_, _, ti)| ti.clone()).collect();
+            for ((trade_id, _, _), trade_hash) in
+                report_trades.iter().zip(get_trade_hashes(&trade_infos))
+            {
+                conn.prepare(
+                    "
+                    UPDATE trades SET trade_hash = :trade_hash WHERE id = :id
+                ",
+                )?
+                .execute(named_params! {
+                    ":id": trade_id,
+                    ":trade_hash": trade_hash,
+                })?;
+            }
+        }
+        // Trades stored twice are only kept from the report imported first
+        conn.prepare(
+            "
+            DELETE FROM trades WHERE id IN (
+                SELECT t.id FROM trades t
+                JOIN reports r ON r.id = t.report_id
+                WHERE EXISTS (
+                    SELECT 1 FROM trades t2
+                    JOIN reports r2 ON r2.id = t2.report_id
+                    WHERE t2.trade_hash = t.trade_hash
+                        AND r2.taxpayer_profile_id = r.taxpayer_profile_id
+                        AND t2.report_id < t.report_id
+                )
+            )
+        ",
+        )?
+        .execute([])?;
+        conn.prepare(
+            "
+            INSERT INTO dobkapman_migrations (id, name) VALUES (27, 'trade-hashes')
+        ",
+        )?
+        .execute([])?;
+    }
+    // Logic for migration 28 and above goes here
+    if db_migration_version > 27 {
+        panic!("Cannot migrate backwards, db_migration_version={db_migration_version}");
+    }
+    Ok(())
+}
+
+// Mailboxes
+
+pub fn get_mailboxes(store: &AppStore) -> DkaResult<Vec<Mailbox>> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        select
+            id,
+            email_address,
+            email_password,
+            imap_host,
+            imap_port,
+            cursor,
+            folders,
+            auth_method,
+            oauth_conf
+        from mailboxes
+        order by id
+    ",
+    )?;
+    let rows: Vec<_> = stmt
+        .query_map([], |row| {
+            // The cursor and folders are parsed, and the secrets decrypted, below
+            Ok((
+                Mailbox {
+                    id: row.get::<_, i32>(0)?,
+                    email_address: row.get::<_, String>(1)?,
+                    email_password: row.get::<_, String>(2)?,
+                    imap_host: row.get::<_, String>(3)?,
+                    imap_port: row.get::<_, i32>(4)?,
+                    cursor: MailboxCursor::Date {
+                        date_string: String::new(),
+                    },
+                    folders: vec![],
+                    auth_method: row.get::<_, String>(7)?,
+                    oauth_conf: None,
+                },
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, Option<String>>(8)?,
+            ))
+        })?
+        .collect::<Result<_, _>>()?;
+    let secret_key = store.secret_key()?;
+    rows.into_iter()
+        .map(|(mut mailbox, cursor, folders, encrypted_oauth_conf)| {
+            mailbox.cursor = MailboxCursor::from_db_string(&cursor)?;
+            mailbox.folders = serde_json::from_str(&folders)
+                .map_err(|e| DkaError::Generic(format!("invalid mailbox folders format: {e}")))?;
+            mailbox.email_password = secret_key.decrypt(&mailbox.email_password)?;
+            mailbox.oauth_conf = encrypted_oauth_conf
+                .map(|s| MailboxOAuthConf::from_db_string(&secret_key.decrypt(&s)?))
+                .transpose()?;
+            Ok(mailbox)
+        })
+        .collect()
+}
+
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn get_mailbox(store: &AppStore, mailbox_id: i32) -> DkaResult<Mailbox> {
+    get_mailboxes(store)?
+        .into_iter()
+        .find(|mb| mb.id == mailbox_id)
+        .ok_or_else(|| DkaError::generic("Mailbox not found in database"))
+}
+
+pub fn update_mailbox(store: &AppStore, mailbox: &Mailbox) -> DkaResult<()> {
+    let secret_key = store.secret_key()?;
+    let encrypted_oauth_conf = mailbox
+        .oauth_conf
+        .as_ref()
+        .map(|c| secret_key.encrypt(&c.to_db_string()))
+        .transpose()?;
+    store
+        .connection()?
+        .prepare(
+            "
+        update mailboxes set
+            email_address = :email_address,
+            email_password = :email_password,
+            imap_host = :imap_host,
+            imap_port = :imap_port,
+            cursor = :cursor,
+            folders = :folders,
+            auth_method = :auth_method,
+            oauth_conf = :oauth_conf
+        where id=:id
+        ",
+        )?
+        .execute(named_params! {
+            ":id": mailbox.id,
+            ":email_address": mailbox.email_address,
+            ":email_password": secret_key.encrypt(&mailbox.email_password)?,
+            ":imap_host": mailbox.imap_host,
+            ":imap_port": mailbox.imap_port,
+            ":cursor": mailbox.cursor.to_db_string(),
+            ":folders": serde_json::to_string(&mailbox.folders).map_err(DkaError::generic)?,
+            ":auth_method": mailbox.auth_method,
+            ":oauth_conf": encrypted_oauth_conf,
+        })?;
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn create_mailbox(store: &AppStore, mailbox: &Mailbox) -> DkaResult<i32> {
+    let secret_key = store.secret_key()?;
+    let encrypted_oauth_conf = mailbox
+        .oauth_conf
+        .as_ref()
+        .map(|c| secret_key.encrypt(&c.to_db_string()))
+        .transpose()?;
+    let conn = store.connection()?;
+    let mailbox_id = conn
+        .prepare(
+            "
+        insert into mailboxes(
+            email_address,
+            email_password,
+            imap_host,
+            imap_port,
+            cursor,
+            folders,
+            auth_method,
+            oauth_conf
+        ) values (
+            :email_address,
+            :email_password,
+            :imap_host,
+            :imap_port,
+            :cursor,
+            :folders,
+            :auth_method,
+            :oauth_conf
+        ) returning id
+    ",
+        )?
+        .query_row(
+            named_params! {
+                ":email_address": mailbox.email_address,
+                ":email_password": secret_key.encrypt(&mailbox.email_password)?,
+                ":imap_host": mailbox.imap_host,
+                ":imap_port": mailbox.imap_port,
+                ":cursor": mailbox.cursor.to_db_string(),
+                ":folders": serde_json::to_string(&mailbox.folders).map_err(DkaError::generic)?,
+                ":auth_method": mailbox.auth_method,
+                ":oauth_conf": encrypted_oauth_conf,
+            },
+            |row| row.get::<_, i32>(0),
+        )?;
+    Ok(mailbox_id)
+}
+
+/// Re-encrypts the mailbox secrets with a new key, derived from the passphrase if there is one and
+/// otherwise kept in the key file
+pub fn rekey_secrets(store: &AppStore, passphrase: Option<&str>) -> DkaResult<()> {
+    let mailboxes = get_mailboxes(store)?;
+    let secret_key = SecretKey::replace(
+        store.data_dir(),
+        store.key_dir(),
+        passphrase,
+        |secret_key| {
+            let mut conn = store.connection()?;
+            let tx = conn.transaction()?;
+            for mailbox in &mailboxes {
+                tx.prepare(
+                    "
+                update mailboxes set
+                    email_password = :email_password,
+                    oauth_conf = :oauth_conf
+                where id=:id
+                ",
+                )?
+                .execute(named_params! {
+                    ":id": mailbox.id,
+                    ":email_password": secret_key.encrypt(&mailbox.email_password)?,
+                    ":oauth_conf": mailbox
+                        .oauth_conf
+                        .as_ref()
+                        .map(|c| secret_key.encrypt(&c.to_db_string()))
+                        .transpose()?,
+                })?;
+            }
+            tx.commit()?;
+            Ok(())
+        },
+    )?;
+    store.set_secret_key(secret_key);
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn delete_mailbox(store: &AppStore, mailbox_id: i32) -> DkaResult<()> {
+    if get_importers(store)?
+        .iter()
+        .any(|im| im.mailbox_id == mailbox_id)
+    {
+        return Err(DkaError::user(
+            "Mailbox is used by importers, move or delete them first",
+        ));
+    }
+    // Reports reference their mailbox
+    if !get_reports_by_mailbox(store, mailbox_id)?.is_empty() {
+        return Err(DkaError::user("Mailbox has reports, delete them first"));
+    }
+    delete_mailbox_folder_cursors(store, mailbox_id)?;
+    store
+        .connection()?
+        .prepare(
+            "
+        delete from mailboxes
+        where id=:id
+    ",
+        )?
+        .execute(named_params! {
+            ":id": mailbox_id,
+        })?;
+    Ok(())
+}
+
+pub fn get_mailbox_folder_cursors(
+    store: &AppStore,
+    mailbox_id: i32,
+) -> DkaResult<Vec<MailboxFolderCursor>> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        select
+            folder,
+            uid_validity,
+            last_seen_uid
+        from mailbox_folder_cursors
+        where mailbox_id=:mailbox_id
+        order by folder
+    ",
+    )?;
+    let rows = stmt
+        .query_map(
+            named_params! {
+                ":mailbox_id": mailbox_id,
+            },
+            |row| {
+                Ok(MailboxFolderCursor {
+                    folder: row.get::<_, String>(0)?,
+                    uid_validity: row.get::<_, u32>(1)?,
+                    last_seen_uid: row.get::<_, u32>(2)?,
+                })
+            },
+        )?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+pub fn update_mailbox_folder_cursor(
+    store: &AppStore,
+    mailbox_id: i32,
+    folder_cursor: &MailboxFolderCursor,
+) -> DkaResult<()> {
+    store
+        .connection()?
+        .prepare(
+            "
+        insert into mailbox_folder_cursors (
+            mailbox_id,
+            folder,
+            uid_validity,
+            last_seen_uid
+        ) values (
+            :mailbox_id,
+            :folder,
+            :uid_validity,
+            :last_seen_uid
+        ) on conflict (mailbox_id, folder) do update set
+            uid_validity = excluded.uid_validity,
+            last_seen_uid = excluded.last_seen_uid
+    ",
+        )?
+        .execute(named_params! {
+            ":mailbox_id": mailbox_id,
+            ":folder": folder_cursor.folder,
+            ":uid_validity": folder_cursor.uid_validity,
+            ":last_seen_uid": folder_cursor.last_seen_uid,
+        })?;
+    Ok(())
+}
+
+/// Makes every folder of the mailbox start over from the mailbox cursor's date
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn delete_mailbox_folder_cursors(store: &AppStore, mailbox_id: i32) -> DkaResult<()> {
+    store
+        .connection()?
+        .prepare(
+            "
+        delete from mailbox_folder_cursors
+        where mailbox_id=:mailbox_id
+    ",
+        )?
+        .execute(named_params! {
+            ":mailbox_id": mailbox_id,
+        })?;
+    Ok(())
+}
+
+// Taxpayer profiles
+
+/// The profile created with the database, which reports without an importer (e.g. manual ones)
+/// are attributed to
+pub const DEFAULT_TAXPAYER_PROFILE_ID: i32 = 1;
+
+pub fn get_taxpayer_profiles(store: &AppStore) -> DkaResult<Vec<TaxpayerProfile>> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        select
+            id,
+            jmbg,
+            full_name,
+            street_address,
+            opstina_code,
+            phone_number,
+            email_address
+        from taxpayer_profiles
+        order by id
+    ",
+    )?;
+    let rows: Vec<_> = stmt
+        .query_map([], |row| {
+            Ok(TaxpayerProfile {
+                id: row.get::<_, i32>(0)?,
+                jmbg: row.get::<_, String>(1)?,
+                full_name: row.get::<_, String>(2)?,
+                street_address: row.get::<_, String>(3)?,
+                opstina_code: row.get::<_, String>(4)?,
+                phone_number: row.get::<_, String>(5)?,
+                email_address: row.get::<_, String>(6)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn get_taxpayer_profile(
+    store: &AppStore,
+    taxpayer_profile_id: i32,
+) -> DkaResult<TaxpayerProfile> {
+    get_taxpayer_profiles(store)?
+        .into_iter()
+        .find(|tp| tp.id == taxpayer_profile_id)
+        .ok_or_else(|| DkaError::generic("Taxpayer profile not found in database"))
+}
+
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn update_taxpayer_profile(
+    store: &AppStore,
+    taxpayer_profile: &TaxpayerProfile,
+) -> DkaResult<()> {
+    store
+        .connection()?
+        .prepare(
+            "
+            update taxpayer_profiles set
+                jmbg = :jmbg,
+                full_name = :full_name,
+                street_address = :street_address,
+                opstina_code = :opstina_code,
+                phone_number = :phone_number,
+                email_address = :email_address
+            where id=:id
+        ",
+        )?
+        .execute(named_params! {
+            ":id": taxpayer_profile.id,
+            ":jmbg": taxpayer_profile.jmbg,
+            ":full_name": taxpayer_profile.full_name,
+            ":street_address": taxpayer_profile.street_address,
+            ":opstina_code": taxpayer_profile.opstina_code,
+            ":phone_number": taxpayer_profile.phone_number,
+            ":email_address": taxpayer_profile.email_address,
+        })?;
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn create_taxpayer_profile(
+    store: &AppStore,
+    taxpayer_profile: &TaxpayerProfile,
+) -> DkaResult<i32> {
+    let taxpayer_profile_id = store
+        .connection()?
+        .prepare(
+            "
+        insert into taxpayer_profiles(
+            jmbg,
+            full_name,
+            street_address,
+            opstina_code,
+            phone_number,
+            email_address
+        ) values (
+            :jmbg,
+            :full_name,
+            :street_address,
+            :opstina_code,
+            :phone_number,
+            :email_address
+        ) returning id
+    ",
+        )?
+        .query_row(
+            named_params! {
+                ":jmbg": taxpayer_profile.jmbg,
+                ":full_name": taxpayer_profile.full_name,
+                ":street_address": taxpayer_profile.street_address,
+                ":opstina_code": taxpayer_profile.opstina_code,
+                ":phone_number": taxpayer_profile.phone_number,
+                ":email_address": taxpayer_profile.email_address,
+            },
+            |row| row.get::<_, i32>(0),
+        )?;
+    Ok(taxpayer_profile_id)
+}
+
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn delete_taxpayer_profile(store: &AppStore, taxpayer_profile_id: i32) -> DkaResult<()> {
+    if taxpayer_profile_id == DEFAULT_TAXPAYER_PROFILE_ID {
+        return Err(DkaError::user(
+            "The first taxpayer profile cannot be deleted",
+        ));
+    }
+    if get_importers(store)?
+        .iter()
+        .any(|im| im.taxpayer_profile_id == taxpayer_profile_id)
+    {
+        return Err(DkaError::user(
+            "Taxpayer profile is used by importers, move or delete them first",
+        ));
+    }
+    if get_reports(store)?
+        .iter()
+        .any(|r| r.taxpayer_profile_id == taxpayer_profile_id)
+    {
+        return Err(DkaError::user(
+            "Taxpayer profile has reports, delete them first",
+        ));
+    }
+    if !get_filings_by_taxpayer_profile(store, taxpayer_profile_id)?.is_empty() {
+        return Err(DkaError::user(
+            "Taxpayer profile has filings, delete them first",
+        ));
+    }
+    store
+        .connection()?
+        .prepare(
+            "
+        delete from taxpayer_profiles
+        where id=:id
+    ",
+        )?
+        .execute(named_params! {
+            ":id": taxpayer_profile_id,
+        })?;
+    Ok(())
+}
+
+// Importers
+
+pub fn get_importers(store: &AppStore) -> DkaResult<Vec<Importer>> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        select
+            id,
+            name,
+            report_type,
+            taxpayer_profile_id,
+            mailbox_id,
+            from_filter,
+            subject_filter,
+            payment_notes,
+            attachment_regex,
+            generic_csv_conf,
+            group_filings_by_date
+        from importers
+        order by id
+    ",
+    )?;
+    let rows: Vec<_> = stmt
+        .query_map([], |row| {
+            // The generic CSV conf is parsed below
+            Ok((
+                Importer {
+                    id: row.get::<_, i32>(0)?,
+                    name: row.get::<_, String>(1)?,
+                    report_type: row.get::<_, String>(2)?,
+                    taxpayer_profile_id: row.get::<_, i32>(3)?,
+                    mailbox_id: row.get::<_, i32>(4)?,
+                    from_filter: row.get::<_, String>(5)?,
+                    subject_filter: row.get::<_, String>(6)?,
+                    payment_notes: row.get::<_, String>(7)?,
+                    attachment_regex: row.get::<_, String>(8)?,
+                    generic_csv_conf: None,
+                    group_filings_by_date: row.get::<_, bool>(10)?,
+                },
+                row.get::<_, Option<String>>(9)?,
+            ))
+        })?
+        .collect::<Result<_, _>>()?;
+    rows.into_iter()
+        .map(|(mut importer, generic_csv_conf)| {
+            importer.generic_csv_conf = generic_csv_conf
+                .map(|s| GenericCsvConf::from_db_string(&s))
+                .transpose()?;
+            Ok(importer)
+        })
+        .collect()
 }
 
-pub fn update_importer(app_handle: &AppHandle, importer: &Importer) -> DkaResult<()> {
-    get_connection(app_handle)?
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn update_importer(store: &AppStore, importer: &Importer) -> DkaResult<()> {
+    store
+        .connection()?
         .prepare(
             "
             update importers set
                 name = :name,
                 report_type = :report_type,
+                taxpayer_profile_id = :taxpayer_profile_id,
+                mailbox_id = :mailbox_id,
                 from_filter = :from_filter,
                 subject_filter = :subject_filter,
                 payment_notes = :payment_notes,
-                attachment_regex = :attachment_regex
+                attachment_regex = :attachment_regex,
+                generic_csv_conf = :generic_csv_conf,
+                group_filings_by_date = :group_filings_by_date
             where id=:id
         ",
         )?
@@ -404,16 +1619,21 @@ pub fn update_importer(app_handle: &AppHandle, importer: &Importer) -> DkaResult
             ":id": importer.id,
             ":name": importer.name,
             ":report_type": importer.report_type,
+            ":taxpayer_profile_id": importer.taxpayer_profile_id,
+            ":mailbox_id": importer.mailbox_id,
             ":from_filter": importer.from_filter,
             ":subject_filter": importer.subject_filter,
             ":payment_notes": importer.payment_notes,
             ":attachment_regex": importer.attachment_regex,
+            ":generic_csv_conf": importer.generic_csv_conf.as_ref().map(|c| c.to_db_string()),
+            ":group_filings_by_date": importer.group_filings_by_date,
         })?;
     Ok(())
 }
 
-pub fn create_importer(app_handle: &AppHandle, importer: &Importer) -> DkaResult<i32> {
-    let conn = get_connection(app_handle)?;
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn create_importer(store: &AppStore, importer: &Importer) -> DkaResult<i32> {
+    let conn = store.connection()?;
     let mut stmt = conn.prepare(
         "
         insert into importers(
@@ -424,16 +1644,20 @@ pub fn create_importer(app_handle: &AppHandle, importer: &Importer) -> DkaResult
             from_filter,
             subject_filter,
             payment_notes,
-            attachment_regex
+            attachment_regex,
+            generic_csv_conf,
+            group_filings_by_date
         ) values (
             :name,
             :report_type,
-            1,
-            1,
+            :taxpayer_profile_id,
+            :mailbox_id,
             :from_filter,
             :subject_filter,
             :payment_notes,
-            :attachment_regex
+            :attachment_regex,
+            :generic_csv_conf,
+            :group_filings_by_date
         ) returning id
     ",
     )?;
@@ -442,10 +1666,14 @@ pub fn create_importer(app_handle: &AppHandle, importer: &Importer) -> DkaResult
             named_params! {
                 ":name": importer.name,
                 ":report_type": importer.report_type,
+                ":taxpayer_profile_id": importer.taxpayer_profile_id,
+                ":mailbox_id": importer.mailbox_id,
                 ":from_filter": importer.from_filter,
                 ":subject_filter": importer.subject_filter,
                 ":payment_notes": importer.payment_notes,
                 ":attachment_regex": importer.attachment_regex,
+                ":generic_csv_conf": importer.generic_csv_conf.as_ref().map(|c| c.to_db_string()),
+                ":group_filings_by_date": importer.group_filings_by_date,
             },
             |row| Ok(row.get::<_, i32>(0)?),
         )?
@@ -457,8 +1685,9 @@ pub fn create_importer(app_handle: &AppHandle, importer: &Importer) -> DkaResult
     return Ok(rows[0]);
 }
 
-pub fn delete_importer(app_handle: &AppHandle, importer_id: i32) -> DkaResult<()> {
-    let conn = get_connection(app_handle)?;
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn delete_importer(store: &AppStore, importer_id: i32) -> DkaResult<()> {
+    let conn = store.connection()?;
     let mut stmt = conn.prepare(
         "
         delete from importers
@@ -473,8 +1702,8 @@ pub fn delete_importer(app_handle: &AppHandle, importer_id: i32) -> DkaResult<()
 
 // Reports
 
-pub fn get_reports_by_mailbox(app_handle: &AppHandle, mailbox_id: i32) -> DkaResult<Vec<Report>> {
-    let conn = get_connection(app_handle)?;
+pub fn get_reports(store: &AppStore) -> DkaResult<Vec<Report>> {
+    let conn = store.connection()?;
     let mut stmt = conn.prepare(
         "
         select
@@ -484,7 +1713,44 @@ pub fn get_reports_by_mailbox(app_handle: &AppHandle, mailbox_id: i32) -> DkaRes
             mailbox_id,
             mailbox_message_id,
             report_name,
-            status
+            status,
+            taxpayer_profile_id
+        from reports
+        order by id
+    ",
+    )?;
+    let rows: Vec<_> = stmt
+        .query_map([], |row| {
+            Ok(Report {
+                id: row.get::<_, i32>(0)?,
+                _type: row.get::<_, String>(1)?,
+                importer_id: row.get::<_, Option<i32>>(2)?,
+                mailbox_id: row.get::<_, Option<i32>>(3)?,
+                mailbox_message_id: row.get::<_, Option<i32>>(4)?,
+                report_name: row.get::<_, String>(5)?,
+                status: row.get::<_, String>(6)?,
+                taxpayer_profile_id: row.get::<_, i32>(7)?,
+            })
+        })?
+        .map(|r| r.unwrap())
+        .collect();
+    Ok(rows)
+}
+
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn get_reports_by_mailbox(store: &AppStore, mailbox_id: i32) -> DkaResult<Vec<Report>> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        select
+            id,
+            type,
+            importer_id,
+            mailbox_id,
+            mailbox_message_id,
+            report_name,
+            status,
+            taxpayer_profile_id
         from reports
         where mailbox_id=:mailbox_id
         order by id
@@ -500,10 +1766,11 @@ pub fn get_reports_by_mailbox(app_handle: &AppHandle, mailbox_id: i32) -> DkaRes
                     id: row.get::<_, i32>(0)?,
                     _type: row.get::<_, String>(1)?,
                     importer_id: row.get::<_, Option<i32>>(2)?,
-                    mailbox_id: row.get::<_, i32>(3)?,
-                    mailbox_message_id: row.get::<_, i32>(4)?,
+                    mailbox_id: row.get::<_, Option<i32>>(3)?,
+                    mailbox_message_id: row.get::<_, Option<i32>>(4)?,
                     report_name: row.get::<_, String>(5)?,
                     status: row.get::<_, String>(6)?,
+                    taxpayer_profile_id: row.get::<_, i32>(7)?,
                 })
             },
         )?
@@ -512,8 +1779,36 @@ pub fn get_reports_by_mailbox(app_handle: &AppHandle, mailbox_id: i32) -> DkaRes
     Ok(rows)
 }
 
-pub fn delete_report(app_handle: &AppHandle, report_id: i32) -> DkaResult<()> {
-    let conn = get_connection(app_handle)?;
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn delete_report(store: &AppStore, report_id: i32) -> DkaResult<()> {
+    let conn = store.connection()?;
+    conn.prepare(
+        "
+        delete from trades
+        where report_id=:report_id
+    ",
+    )?
+    .execute(named_params! {
+        ":report_id": report_id,
+    })?;
+    conn.prepare(
+        "
+        delete from report_exchange_rates
+        where report_id=:report_id
+    ",
+    )?
+    .execute(named_params! {
+        ":report_id": report_id,
+    })?;
+    conn.prepare(
+        "
+        delete from income_hashes
+        where report_id=:report_id
+    ",
+    )?
+    .execute(named_params! {
+        ":report_id": report_id,
+    })?;
     let mut stmt = conn.prepare(
         "
         delete from reports
@@ -526,8 +1821,8 @@ pub fn delete_report(app_handle: &AppHandle, report_id: i32) -> DkaResult<()> {
     Ok(())
 }
 
-pub fn create_report(app_handle: &AppHandle, report: &Report) -> DkaResult<i32> {
-    let conn = get_connection(app_handle)?;
+pub fn create_report(store: &AppStore, report: &Report) -> DkaResult<i32> {
+    let conn = store.connection()?;
     let mut stmt = conn.prepare(
         "
     insert into reports (
@@ -536,14 +1831,16 @@ pub fn create_report(app_handle: &AppHandle, report: &Report) -> DkaResult<i32>
         mailbox_id,
         mailbox_message_id,
         report_name,
-        status
+        status,
+        taxpayer_profile_id
     ) values (
         :type,
         :importer_id,
         :mailbox_id,
         :mailbox_message_id,
         :report_name,
-        :status
+        :status,
+        :taxpayer_profile_id
     ) returning id
     ",
     )?;
@@ -556,6 +1853,7 @@ pub fn create_report(app_handle: &AppHandle, report: &Report) -> DkaResult<i32>
                 ":mailbox_message_id": report.mailbox_message_id,
                 ":report_name": report.report_name,
                 ":status": report.status,
+                ":taxpayer_profile_id": report.taxpayer_profile_id,
             },
             |row| Ok(row.get::<_, i32>(0)?),
         )?
@@ -567,8 +1865,9 @@ pub fn create_report(app_handle: &AppHandle, report: &Report) -> DkaResult<i32>
     return Ok(rows[0]);
 }
 
-pub fn update_report(app_handle: &AppHandle, report: &Report) -> DkaResult<()> {
-    get_connection(app_handle)?
+pub fn update_report(store: &AppStore, report: &Report) -> DkaResult<()> {
+    store
+        .connection()?
         .prepare(
             "
             update reports set
@@ -583,10 +1882,328 @@ pub fn update_report(app_handle: &AppHandle, report: &Report) -> DkaResult<()> {
     Ok(())
 }
 
+/// Importer ID and content hash of the reports that have one
+pub fn get_report_content_hashes(store: &AppStore) -> DkaResult<HashSet<(Option<i32>, String)>> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        select importer_id, content_hash
+        from reports
+        where content_hash is not null
+    ",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, Option<i32>>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+pub fn update_report_content_hash(
+    store: &AppStore,
+    report_id: i32,
+    content_hash: &str,
+) -> DkaResult<()> {
+    store
+        .connection()?
+        .prepare(
+            "
+            update reports set
+                content_hash = :content_hash
+            where id=:id
+        ",
+        )?
+        .execute(named_params! {
+            ":id": report_id,
+            ":content_hash": content_hash,
+        })?;
+    Ok(())
+}
+
+// Income hashes
+
+/// The first report each filed income came from, by income hash
+/// The filed incomes, oldest first
+pub fn get_filed_incomes(store: &AppStore) -> DkaResult<Vec<FiledIncome>> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        select income_hash, report_id, taxpayer_profile_id, importer_id
+        from income_hashes
+        order by report_id
+    ",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(FiledIncome {
+                income_hash: row.get::<_, String>(0)?,
+                report_id: row.get::<_, i32>(1)?,
+                taxpayer_profile_id: row.get::<_, i32>(2)?,
+                importer_id: row.get::<_, Option<i32>>(3)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+pub fn create_income_hashes(
+    store: &AppStore,
+    report: &Report,
+    income_hashes: &[String],
+) -> DkaResult<()> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        insert or ignore into income_hashes (
+            report_id,
+            income_hash,
+            taxpayer_profile_id,
+            importer_id
+        ) values (
+            :report_id,
+            :income_hash,
+            :taxpayer_profile_id,
+            :importer_id
+        )
+    ",
+    )?;
+    for income_hash in income_hashes {
+        stmt.execute(named_params! {
+            ":report_id": report.id,
+            ":income_hash": income_hash,
+            ":taxpayer_profile_id": report.taxpayer_profile_id,
+            ":importer_id": report.importer_id,
+        })?;
+    }
+    Ok(())
+}
+
+// Trades
+
+/// Returns the trades from the reports imported for the taxpayer profile
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn get_trades(store: &AppStore, taxpayer_profile_id: i32) -> DkaResult<Vec<TradeInfo>> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        select
+            t.isin,
+            t.symbol,
+            t.trade_date,
+            t.currency_code,
+            t.quantity,
+            t.proceeds,
+            t.commission
+        from trades t
+        join reports r on r.id = t.report_id
+        where r.taxpayer_profile_id = :taxpayer_profile_id
+        order by t.trade_date, t.id
+    ",
+    )?;
+    let rows: Vec<_> = stmt
+        .query_map(
+            named_params! {
+                ":taxpayer_profile_id": taxpayer_profile_id,
+            },
+            |row| {
+                Ok(TradeInfo {
+                    isin: row.get::<_, String>(0)?,
+                    symbol: row.get::<_, String>(1)?,
+                    trade_date: parse_iso(&row.get::<_, String>(2)?).unwrap(),
+                    currency_code: row.get::<_, String>(3)?,
+                    quantity: from_real(row.get::<_, f64>(4)?),
+                    proceeds: from_real(row.get::<_, f64>(5)?),
+                    commission: from_real(row.get::<_, f64>(6)?),
+                })
+            },
+        )?
+        .map(|r| r.unwrap())
+        .collect();
+    Ok(rows)
+}
+
+/// Stores the trades of the report, skipping those that a report of the same taxpayer profile
+/// already has. Returns the number of skipped trades.
+pub fn create_trades(
+    store: &AppStore,
+    report_id: i32,
+    trade_infos: &[TradeInfo],
+) -> DkaResult<usize> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        insert into trades(
+            report_id,
+            isin,
+            symbol,
+            trade_date,
+            currency_code,
+            quantity,
+            proceeds,
+            commission,
+            trade_hash
+        )
+        select
+            :report_id,
+            :isin,
+            :symbol,
+            :trade_date,
+            :currency_code,
+            :quantity,
+            :proceeds,
+            :commission,
+            :trade_hash
+        where not exists (
+            select 1 from trades t
+            join reports r on r.id = t.report_id
+            where t.trade_hash = :trade_hash
+                and r.taxpayer_profile_id = (
+                    select taxpayer_profile_id from reports where id = :report_id
+                )
+        )
+    ",
+    )?;
+    let mut skipped_trades_counter = 0;
+    for (trade_info, trade_hash) in trade_infos.iter().zip(get_trade_hashes(trade_infos)) {
+        let inserted_row_count = stmt.execute(named_params! {
+            ":report_id": report_id,
+            ":isin": trade_info.isin,
+            ":symbol": trade_info.symbol,
+            ":trade_date": format_iso(&trade_info.trade_date),
+            ":currency_code": trade_info.currency_code,
+            ":quantity": to_real(trade_info.quantity),
+            ":proceeds": to_real(trade_info.proceeds),
+            ":commission": to_real(trade_info.commission),
+            ":trade_hash": trade_hash,
+        })?;
+        if inserted_row_count == 0 {
+            skipped_trades_counter += 1;
+        }
+    }
+    Ok(skipped_trades_counter)
+}
+
+// Report exchange rates
+
+/// The exchange rates that came with the statements, for currencies without an NBS rate
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn get_report_exchange_rates(store: &AppStore) -> DkaResult<Vec<ExchangeRateInfo>> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        select
+            date,
+            currency_code,
+            exchange_rate
+        from report_exchange_rates
+        order by date, report_id
+    ",
+    )?;
+    let rows: Vec<(String, String, f64)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+    rows.into_iter()
+        .map(|(date, currency_code, exchange_rate)| {
+            Ok(ExchangeRateInfo {
+                date: parse_iso(&date)?,
+                currency_code,
+                currency_to_base_currency_rate: from_real(exchange_rate),
+            })
+        })
+        .collect()
+}
+
+pub fn create_report_exchange_rates(
+    store: &AppStore,
+    report_id: i32,
+    exchange_rate_infos: &[ExchangeRateInfo],
+) -> DkaResult<()> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        insert into report_exchange_rates(
+            report_id,
+            date,
+            currency_code,
+            exchange_rate
+        ) values (
+            :report_id,
+            :date,
+            :currency_code,
+            :exchange_rate
+        )
+    ",
+    )?;
+    for exchange_rate_info in exchange_rate_infos {
+        stmt.execute(named_params! {
+            ":report_id": report_id,
+            ":date": format_iso(&exchange_rate_info.date),
+            ":currency_code": exchange_rate_info.currency_code,
+            ":exchange_rate": to_real(exchange_rate_info.currency_to_base_currency_rate),
+        })?;
+    }
+    Ok(())
+}
+
+// NBS exchange rates
+
+pub fn get_nbs_exchange_rates(store: &AppStore) -> DkaResult<Vec<NbsExchangeRate>> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        select
+            date,
+            currency_code,
+            exchange_rate
+        from nbs_exchange_rates
+    ",
+    )?;
+    let rows: Vec<_> = stmt
+        .query_map([], |row| {
+            Ok(NbsExchangeRate {
+                date: parse_iso(&row.get::<_, String>(0)?).unwrap(),
+                currency_code: row.get::<_, String>(1)?,
+                exchange_rate: from_real(row.get::<_, f64>(2)?),
+            })
+        })?
+        .map(|r| r.unwrap())
+        .collect();
+    Ok(rows)
+}
+
+pub fn save_nbs_exchange_rates(
+    store: &AppStore,
+    nbs_exchange_rates: &[NbsExchangeRate],
+) -> DkaResult<()> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        insert or replace into nbs_exchange_rates(
+            date,
+            currency_code,
+            exchange_rate
+        ) values (
+            :date,
+            :currency_code,
+            :exchange_rate
+        )
+    ",
+    )?;
+    for nbs_exchange_rate in nbs_exchange_rates {
+        stmt.execute(named_params! {
+            ":date": format_iso(&nbs_exchange_rate.date),
+            ":currency_code": nbs_exchange_rate.currency_code,
+            ":exchange_rate": to_real(nbs_exchange_rate.exchange_rate),
+        })?;
+    }
+    Ok(())
+}
+
 // Filings
 
-pub fn get_filings(app_handle: &AppHandle) -> DkaResult<Vec<Filing>> {
-    let conn = get_connection(app_handle)?;
+pub fn get_filings(store: &AppStore) -> DkaResult<Vec<Filing>> {
+    let conn = store.connection()?;
     let mut stmt = conn.prepare(
         "
         select
@@ -597,7 +2214,26 @@ pub fn get_filings(app_handle: &AppHandle) -> DkaResult<Vec<Filing>> {
             paying_entity,
             filing_deadline,
             tax_payable,
-            tax_payment_reference
+            tax_payment_reference,
+            income_date,
+            gross_income,
+            wht_paid,
+            gross_tax_payable,
+            income_currency_code,
+            income_currency_amount,
+            income_exchange_rate,
+            wht_currency_code,
+            wht_currency_amount,
+            wht_exchange_rate,
+            amends_filing_id,
+            tax_payable_delta,
+            late_payment_date,
+            late_interest,
+            wht_actual,
+            wht_credit_rate,
+            taxpayer_profile_id,
+            period,
+            suspected_duplicate_of_report_id
         from filings
         order by id desc
     ",
@@ -607,12 +2243,31 @@ pub fn get_filings(app_handle: &AppHandle) -> DkaResult<Vec<Filing>> {
             Ok(Filing {
                 id: row.get::<_, i32>(0)?,
                 _type: row.get::<_, String>(1)?,
-                report_id: row.get::<_, i32>(2)?,
+                report_id: row.get::<_, Option<i32>>(2)?,
                 status: row.get::<_, String>(3)?,
                 paying_entity: row.get::<_, String>(4)?,
                 filing_deadline: row.get::<_, String>(5)?,
                 tax_payable: row.get::<_, i64>(6)?,
                 tax_payment_reference: row.get::<_, String>(7)?,
+                income_date: row.get::<_, Option<String>>(8)?,
+                gross_income: row.get::<_, Option<i64>>(9)?,
+                wht_paid: row.get::<_, Option<i64>>(10)?,
+                gross_tax_payable: row.get::<_, Option<i64>>(11)?,
+                income_currency_code: row.get::<_, Option<String>>(12)?,
+                income_currency_amount: row.get::<_, Option<f64>>(13)?.map(from_real),
+                income_exchange_rate: row.get::<_, Option<f64>>(14)?.map(from_real),
+                wht_currency_code: row.get::<_, Option<String>>(15)?,
+                wht_currency_amount: row.get::<_, Option<f64>>(16)?.map(from_real),
+                wht_exchange_rate: row.get::<_, Option<f64>>(17)?.map(from_real),
+                amends_filing_id: row.get::<_, Option<i32>>(18)?,
+                tax_payable_delta: row.get::<_, Option<i64>>(19)?,
+                late_payment_date: row.get::<_, Option<String>>(20)?,
+                late_interest: row.get::<_, Option<i64>>(21)?,
+                wht_actual: row.get::<_, Option<i64>>(22)?,
+                wht_credit_rate: row.get::<_, Option<f64>>(23)?.map(from_real),
+                taxpayer_profile_id: row.get::<_, i32>(24)?,
+                period: row.get::<_, Option<String>>(25)?,
+                suspected_duplicate_of_report_id: row.get::<_, Option<i32>>(26)?,
             })
         })?
         .map(|r| r.unwrap())
@@ -620,8 +2275,21 @@ pub fn get_filings(app_handle: &AppHandle) -> DkaResult<Vec<Filing>> {
     Ok(rows)
 }
 
-pub fn update_filing(app_handle: &AppHandle, filing: &Filing) -> DkaResult<()> {
-    get_connection(app_handle)?
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn get_filings_by_taxpayer_profile(
+    store: &AppStore,
+    taxpayer_profile_id: i32,
+) -> DkaResult<Vec<Filing>> {
+    Ok(get_filings(store)?
+        .into_iter()
+        .filter(|f| f.taxpayer_profile_id == taxpayer_profile_id)
+        .collect())
+}
+
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn update_filing(store: &AppStore, filing: &Filing) -> DkaResult<()> {
+    store
+        .connection()?
         .prepare(
             "
             update filings set
@@ -638,8 +2306,51 @@ pub fn update_filing(app_handle: &AppHandle, filing: &Filing) -> DkaResult<()> {
     Ok(())
 }
 
-pub fn create_filing(app_handle: &AppHandle, filing: &Filing) -> DkaResult<i32> {
-    let conn = get_connection(app_handle)?;
+/// Keeps a filing that was suspected to duplicate an income filed from another report
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn confirm_filing_not_duplicate(store: &AppStore, filing_id: i32) -> DkaResult<()> {
+    store
+        .connection()?
+        .prepare(
+            "
+            update filings set
+                suspected_duplicate_of_report_id = null
+            where id=:id
+        ",
+        )?
+        .execute(named_params! {
+            ":id": filing_id,
+        })?;
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn update_filing_late_interest(
+    store: &AppStore,
+    filing_id: i32,
+    late_payment_date: Option<String>,
+    late_interest: Option<i64>,
+) -> DkaResult<()> {
+    store
+        .connection()?
+        .prepare(
+            "
+            update filings set
+                late_payment_date = :late_payment_date,
+                late_interest = :late_interest
+            where id=:id
+        ",
+        )?
+        .execute(named_params! {
+            ":id": filing_id,
+            ":late_payment_date": late_payment_date,
+            ":late_interest": late_interest,
+        })?;
+    Ok(())
+}
+
+pub fn create_filing(store: &AppStore, filing: &Filing) -> DkaResult<i32> {
+    let conn = store.connection()?;
     let mut stmt = conn.prepare(
         "
         insert into filings(
@@ -649,7 +2360,26 @@ pub fn create_filing(app_handle: &AppHandle, filing: &Filing) -> DkaResult<i32>
             paying_entity,
             filing_deadline,
             tax_payable,
-            tax_payment_reference
+            tax_payment_reference,
+            income_date,
+            gross_income,
+            wht_paid,
+            gross_tax_payable,
+            income_currency_code,
+            income_currency_amount,
+            income_exchange_rate,
+            wht_currency_code,
+            wht_currency_amount,
+            wht_exchange_rate,
+            amends_filing_id,
+            tax_payable_delta,
+            late_payment_date,
+            late_interest,
+            wht_actual,
+            wht_credit_rate,
+            taxpayer_profile_id,
+            period,
+            suspected_duplicate_of_report_id
         ) values (
             :type,
             :report_id,
@@ -657,7 +2387,26 @@ pub fn create_filing(app_handle: &AppHandle, filing: &Filing) -> DkaResult<i32>
             :paying_entity,
             :filing_deadline,
             :tax_payable,
-            :tax_payment_reference
+            :tax_payment_reference,
+            :income_date,
+            :gross_income,
+            :wht_paid,
+            :gross_tax_payable,
+            :income_currency_code,
+            :income_currency_amount,
+            :income_exchange_rate,
+            :wht_currency_code,
+            :wht_currency_amount,
+            :wht_exchange_rate,
+            :amends_filing_id,
+            :tax_payable_delta,
+            :late_payment_date,
+            :late_interest,
+            :wht_actual,
+            :wht_credit_rate,
+            :taxpayer_profile_id,
+            :period,
+            :suspected_duplicate_of_report_id
         ) returning id
     ",
     )?;
@@ -671,6 +2420,25 @@ pub fn create_filing(app_handle: &AppHandle, filing: &Filing) -> DkaResult<i32>
                 ":filing_deadline": filing.filing_deadline,
                 ":tax_payable": filing.tax_payable,
                 ":tax_payment_reference": filing.tax_payment_reference,
+                ":income_date": filing.income_date,
+                ":gross_income": filing.gross_income,
+                ":wht_paid": filing.wht_paid,
+                ":gross_tax_payable": filing.gross_tax_payable,
+                ":income_currency_code": filing.income_currency_code,
+                ":income_currency_amount": filing.income_currency_amount.map(to_real),
+                ":income_exchange_rate": filing.income_exchange_rate.map(to_real),
+                ":wht_currency_code": filing.wht_currency_code,
+                ":wht_currency_amount": filing.wht_currency_amount.map(to_real),
+                ":wht_exchange_rate": filing.wht_exchange_rate.map(to_real),
+                ":amends_filing_id": filing.amends_filing_id,
+                ":tax_payable_delta": filing.tax_payable_delta,
+                ":late_payment_date": filing.late_payment_date,
+                ":late_interest": filing.late_interest,
+                ":wht_actual": filing.wht_actual,
+                ":wht_credit_rate": filing.wht_credit_rate.map(to_real),
+                ":taxpayer_profile_id": filing.taxpayer_profile_id,
+                ":period": filing.period,
+                ":suspected_duplicate_of_report_id": filing.suspected_duplicate_of_report_id,
             },
             |row| Ok(row.get::<_, i32>(0)?),
         )?
@@ -682,8 +2450,143 @@ pub fn create_filing(app_handle: &AppHandle, filing: &Filing) -> DkaResult<i32>
     return Ok(rows[0]);
 }
 
-pub fn delete_filing(app_handle: &AppHandle, filing_id: i32) -> DkaResult<()> {
-    let conn = get_connection(app_handle)?;
+/// The incomes declared in a grouped filing, empty for filings of a single income
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn get_filing_incomes(store: &AppStore, filing_id: i32) -> DkaResult<Vec<FilingInfo>> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        select
+            type,
+            paying_entity,
+            income_date,
+            income_currency_code,
+            income_currency_amount,
+            income_exchange_rate,
+            wht_currency_code,
+            wht_currency_amount,
+            wht_exchange_rate,
+            gross_income,
+            wht_actual,
+            wht_credit_rate,
+            wht_paid,
+            gross_tax_payable,
+            tax_payable
+        from filing_incomes
+        where filing_id=:filing_id
+        order by position
+    ",
+    )?;
+    let rows: Vec<FilingInfo> = stmt
+        .query_map(named_params! {":filing_id": filing_id}, |row| {
+            let income_date = row.get::<_, String>(2)?;
+            Ok(FilingInfo {
+                _type: row.get::<_, String>(0)?,
+                paying_entity: row.get::<_, String>(1)?,
+                income_date: NaiveDate::parse_from_str(&income_date, "%Y-%m-%d").map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(e))
+                })?,
+                income_currency_code: row.get::<_, String>(3)?,
+                income_currency_amount: from_real(row.get::<_, f64>(4)?),
+                income_exchange_rate: from_real(row.get::<_, f64>(5)?),
+                wht_currency_code: row.get::<_, String>(6)?,
+                wht_currency_amount: from_real(row.get::<_, f64>(7)?),
+                wht_exchange_rate: from_real(row.get::<_, f64>(8)?),
+                gross_income_rsdc: row.get::<_, i64>(9)?,
+                wht_actual_rsdc: row.get::<_, i64>(10)?,
+                wht_credit_rate: row.get::<_, Option<f64>>(11)?.map(from_real),
+                wht_paid_rsdc: row.get::<_, i64>(12)?,
+                gross_tax_payable_rsdc: row.get::<_, i64>(13)?,
+                tax_payable_rsdc: row.get::<_, i64>(14)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+pub fn create_filing_incomes(
+    store: &AppStore,
+    filing_id: i32,
+    filing_infos: &[FilingInfo],
+) -> DkaResult<()> {
+    let conn = store.connection()?;
+    let mut stmt = conn.prepare(
+        "
+        insert into filing_incomes(
+            filing_id,
+            position,
+            type,
+            paying_entity,
+            income_date,
+            income_currency_code,
+            income_currency_amount,
+            income_exchange_rate,
+            wht_currency_code,
+            wht_currency_amount,
+            wht_exchange_rate,
+            gross_income,
+            wht_actual,
+            wht_credit_rate,
+            wht_paid,
+            gross_tax_payable,
+            tax_payable
+        ) values (
+            :filing_id,
+            :position,
+            :type,
+            :paying_entity,
+            :income_date,
+            :income_currency_code,
+            :income_currency_amount,
+            :income_exchange_rate,
+            :wht_currency_code,
+            :wht_currency_amount,
+            :wht_exchange_rate,
+            :gross_income,
+            :wht_actual,
+            :wht_credit_rate,
+            :wht_paid,
+            :gross_tax_payable,
+            :tax_payable
+        )
+    ",
+    )?;
+    for (position, filing_info) in filing_infos.iter().enumerate() {
+        stmt.execute(named_params! {
+            ":filing_id": filing_id,
+            ":position": position as i64,
+            ":type": filing_info._type,
+            ":paying_entity": filing_info.paying_entity,
+            ":income_date": format_iso(&filing_info.income_date),
+            ":income_currency_code": filing_info.income_currency_code,
+            ":income_currency_amount": to_real(filing_info.income_currency_amount),
+            ":income_exchange_rate": to_real(filing_info.income_exchange_rate),
+            ":wht_currency_code": filing_info.wht_currency_code,
+            ":wht_currency_amount": to_real(filing_info.wht_currency_amount),
+            ":wht_exchange_rate": to_real(filing_info.wht_exchange_rate),
+            ":gross_income": filing_info.gross_income_rsdc,
+            ":wht_actual": filing_info.wht_actual_rsdc,
+            ":wht_credit_rate": filing_info.wht_credit_rate.map(to_real),
+            ":wht_paid": filing_info.wht_paid_rsdc,
+            ":gross_tax_payable": filing_info.gross_tax_payable_rsdc,
+            ":tax_payable": filing_info.tax_payable_rsdc,
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn delete_filing(store: &AppStore, filing_id: i32) -> DkaResult<()> {
+    let conn = store.connection()?;
+    conn.prepare(
+        "
+        delete from filing_incomes
+        where filing_id=:filing_id
+    ",
+    )?
+    .execute(named_params! {
+        ":filing_id": filing_id,
+    })?;
     let mut stmt = conn.prepare(
         "
         delete from filings
@@ -695,3 +2598,376 @@ pub fn delete_filing(app_handle: &AppHandle, filing_id: i32) -> DkaResult<()> {
     })?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::migrate_filesystem;
+
+    fn migrated_store(data_dir: &tempfile::TempDir) -> AppStore {
+        let store = AppStore::new(data_dir.path().to_path_buf(), data_dir.path().to_path_buf());
+        migrate_filesystem(&store).unwrap();
+        migrate_database(&store).unwrap();
+        store
+    }
+
+    #[test]
+    fn test_mailbox_secrets_encrypted() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = migrated_store(&data_dir);
+        let mut mailbox = get_mailbox(&store, 1).unwrap();
+        mailbox.email_password = "hunter2".into();
+        update_mailbox(&store, &mailbox).unwrap();
+
+        let stored_password: String = store
+            .connection()
+            .unwrap()
+            .query_row(
+                "SELECT email_password FROM mailboxes WHERE id=1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!stored_password.contains("hunter2"));
+        assert_eq!(get_mailbox(&store, 1).unwrap().email_password, "hunter2");
+
+        // Another passphrase cannot decrypt the password stored with the key file
+        let other_store =
+            AppStore::new(data_dir.path().to_path_buf(), data_dir.path().to_path_buf())
+                .with_passphrase("x".into());
+        assert!(get_mailbox(&other_store, 1).is_err());
+    }
+
+    #[test]
+    fn test_rekey_secrets() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = migrated_store(&data_dir);
+        let mut mailbox = get_mailbox(&store, 1).unwrap();
+        mailbox.email_password = "hunter2".into();
+        update_mailbox(&store, &mailbox).unwrap();
+
+        rekey_secrets(&store, Some("correct horse")).unwrap();
+        assert_eq!(get_mailbox(&store, 1).unwrap().email_password, "hunter2");
+        let other_store =
+            AppStore::new(data_dir.path().to_path_buf(), data_dir.path().to_path_buf());
+        assert!(other_store.is_secret_key_locked());
+        assert!(other_store.unlock_secret_key("battery staple").is_err());
+        other_store.unlock_secret_key("correct horse").unwrap();
+        assert_eq!(
+            get_mailbox(&other_store, 1).unwrap().email_password,
+            "hunter2"
+        );
+
+        rekey_secrets(&other_store, None).unwrap();
+        let other_store =
+            AppStore::new(data_dir.path().to_path_buf(), data_dir.path().to_path_buf());
+        assert!(!other_store.is_secret_key_locked());
+        assert_eq!(
+            get_mailbox(&other_store, 1).unwrap().email_password,
+            "hunter2"
+        );
+    }
+
+    #[test]
+    fn test_create_delete_mailbox() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = migrated_store(&data_dir);
+        let mut mailbox = get_mailbox(&store, 1).unwrap();
+        mailbox.email_address = "joint@example.com".into();
+        mailbox.email_password = "hunter2".into();
+        let mailbox_id = create_mailbox(&store, &mailbox).unwrap();
+        assert_eq!(mailbox_id, 2);
+        let mailboxes = get_mailboxes(&store).unwrap();
+        assert_eq!(mailboxes.len(), 2);
+        assert_eq!(mailboxes[1].email_address, "joint@example.com");
+        assert_eq!(mailboxes[1].email_password, "hunter2");
+        assert_eq!(mailboxes[1].folders, vec!["INBOX".to_string()]);
+
+        let importer_id = create_importer(
+            &store,
+            &Importer {
+                id: 0,
+                name: "Joint IBKR".into(),
+                report_type: "IbkrCsv".into(),
+                taxpayer_profile_id: 1,
+                mailbox_id,
+                from_filter: "".into(),
+                subject_filter: "".into(),
+                payment_notes: "".into(),
+                attachment_regex: "".into(),
+                generic_csv_conf: None,
+                group_filings_by_date: false,
+            },
+        )
+        .unwrap();
+        assert_eq!(get_importers(&store).unwrap()[0].mailbox_id, mailbox_id);
+        assert!(matches!(
+            delete_mailbox(&store, mailbox_id),
+            Err(DkaError::User(_))
+        ));
+        delete_importer(&store, importer_id).unwrap();
+        // Folder cursors go with the mailbox
+        update_mailbox_folder_cursor(
+            &store,
+            mailbox_id,
+            &MailboxFolderCursor {
+                folder: "INBOX".into(),
+                uid_validity: 1,
+                last_seen_uid: 10,
+            },
+        )
+        .unwrap();
+        delete_mailbox(&store, mailbox_id).unwrap();
+        assert_eq!(get_mailboxes(&store).unwrap().len(), 1);
+        assert!(get_mailbox_folder_cursors(&store, mailbox_id)
+            .unwrap()
+            .is_empty());
+
+        // Reports fetched from a mailbox keep it
+        create_report(
+            &store,
+            &Report {
+                id: 0,
+                _type: "NativeIncomeJson".into(),
+                importer_id: None,
+                mailbox_id: Some(1),
+                mailbox_message_id: Some(1),
+                report_name: "Fetched report".into(),
+                status: "init".into(),
+                taxpayer_profile_id: DEFAULT_TAXPAYER_PROFILE_ID,
+            },
+        )
+        .unwrap();
+        assert!(matches!(delete_mailbox(&store, 1), Err(DkaError::User(_))));
+    }
+
+    #[test]
+    fn test_mailbox_folder_cursors() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = migrated_store(&data_dir);
+        let mut mailbox = get_mailbox(&store, 1).unwrap();
+        assert_eq!(mailbox.folders, vec!["INBOX".to_string()]);
+        mailbox.folders = vec!["INBOX".into(), "[Gmail]/Statements, IBKR".into()];
+        update_mailbox(&store, &mailbox).unwrap();
+        assert_eq!(get_mailbox(&store, 1).unwrap().folders, mailbox.folders);
+
+        let folder_cursor =
+            |folder: &str, uid_validity: u32, last_seen_uid: u32| MailboxFolderCursor {
+                folder: folder.into(),
+                uid_validity,
+                last_seen_uid,
+            };
+        update_mailbox_folder_cursor(&store, 1, &folder_cursor("INBOX", 1, 10)).unwrap();
+        update_mailbox_folder_cursor(&store, 1, &folder_cursor("INBOX", 1, 12)).unwrap();
+        update_mailbox_folder_cursor(&store, 1, &folder_cursor("[Gmail]/Statements, IBKR", 5, 3))
+            .unwrap();
+        assert_eq!(
+            get_mailbox_folder_cursors(&store, 1).unwrap(),
+            vec![
+                folder_cursor("INBOX", 1, 12),
+                folder_cursor("[Gmail]/Statements, IBKR", 5, 3),
+            ]
+        );
+        delete_mailbox_folder_cursors(&store, 1).unwrap();
+        assert!(get_mailbox_folder_cursors(&store, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_create_delete_taxpayer_profile() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = migrated_store(&data_dir);
+        let mut taxpayer_profile =
+            get_taxpayer_profile(&store, DEFAULT_TAXPAYER_PROFILE_ID).unwrap();
+        taxpayer_profile.full_name = "Jelena Petrovic".into();
+        let taxpayer_profile_id = create_taxpayer_profile(&store, &taxpayer_profile).unwrap();
+        assert_eq!(taxpayer_profile_id, 2);
+        let taxpayer_profiles = get_taxpayer_profiles(&store).unwrap();
+        assert_eq!(taxpayer_profiles.len(), 2);
+        assert_eq!(taxpayer_profiles[1].full_name, "Jelena Petrovic");
+
+        let importer_id = create_importer(
+            &store,
+            &Importer {
+                id: 0,
+                name: "Jelena IBKR".into(),
+                report_type: "IbkrCsv".into(),
+                taxpayer_profile_id,
+                mailbox_id: 1,
+                from_filter: "".into(),
+                subject_filter: "".into(),
+                payment_notes: "".into(),
+                attachment_regex: "".into(),
+                generic_csv_conf: None,
+                group_filings_by_date: false,
+            },
+        )
+        .unwrap();
+        let report_id = create_report(
+            &store,
+            &Report {
+                id: 0,
+                _type: "IbkrCsv".into(),
+                importer_id: Some(importer_id),
+                mailbox_id: Some(1),
+                mailbox_message_id: Some(1),
+                report_name: "report.csv".into(),
+                status: "init".into(),
+                taxpayer_profile_id,
+            },
+        )
+        .unwrap();
+        create_trades(
+            &store,
+            report_id,
+            &[TradeInfo {
+                isin: "US0378331005".into(),
+                symbol: "AAPL".into(),
+                trade_date: parse_iso("2024-03-01").unwrap(),
+                currency_code: "USD".into(),
+                quantity: 10.into(),
+                proceeds: (-1800).into(),
+                commission: (-1).into(),
+            }],
+        )
+        .unwrap();
+        assert_eq!(get_trades(&store, taxpayer_profile_id).unwrap().len(), 1);
+        assert!(get_trades(&store, DEFAULT_TAXPAYER_PROFILE_ID)
+            .unwrap()
+            .is_empty());
+
+        assert!(matches!(
+            delete_taxpayer_profile(&store, taxpayer_profile_id),
+            Err(DkaError::User(_))
+        ));
+        assert!(matches!(
+            delete_taxpayer_profile(&store, DEFAULT_TAXPAYER_PROFILE_ID),
+            Err(DkaError::User(_))
+        ));
+        delete_importer(&store, importer_id).unwrap();
+        // The report keeps the profile after its importer is gone
+        assert_eq!(get_trades(&store, taxpayer_profile_id).unwrap().len(), 1);
+        assert!(matches!(
+            delete_taxpayer_profile(&store, taxpayer_profile_id),
+            Err(DkaError::User(_))
+        ));
+        delete_report(&store, report_id).unwrap();
+        delete_taxpayer_profile(&store, taxpayer_profile_id).unwrap();
+        assert_eq!(get_taxpayer_profiles(&store).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_get_importers_invalid_generic_csv_conf() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = migrated_store(&data_dir);
+        let importer = Importer {
+            id: 0,
+            name: "Bank".into(),
+            report_type: "GenericCsv".into(),
+            taxpayer_profile_id: DEFAULT_TAXPAYER_PROFILE_ID,
+            mailbox_id: 1,
+            from_filter: "".into(),
+            subject_filter: "".into(),
+            payment_notes: "".into(),
+            attachment_regex: "".into(),
+            generic_csv_conf: None,
+            group_filings_by_date: false,
+        };
+        create_importer(&store, &importer).unwrap();
+        store
+            .connection()
+            .unwrap()
+            .execute("UPDATE importers SET generic_csv_conf = 'not json'", [])
+            .unwrap();
+        assert!(matches!(get_importers(&store), Err(DkaError::Generic(_))));
+    }
+
+    #[test]
+    fn test_report_exchange_rates() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = migrated_store(&data_dir);
+        let report_id = create_report(
+            &store,
+            &Report {
+                id: 0,
+                _type: "IbkrCsv".into(),
+                importer_id: None,
+                mailbox_id: Some(1),
+                mailbox_message_id: Some(1),
+                report_name: "report.csv".into(),
+                status: "init".into(),
+                taxpayer_profile_id: DEFAULT_TAXPAYER_PROFILE_ID,
+            },
+        )
+        .unwrap();
+        let exchange_rate_infos = vec![ExchangeRateInfo {
+            date: parse_iso("2024-03-01").unwrap(),
+            currency_code: "CAD".into(),
+            currency_to_base_currency_rate: rust_decimal_macros::dec!(0.7377),
+        }];
+        create_report_exchange_rates(&store, report_id, &exchange_rate_infos).unwrap();
+        assert_eq!(
+            get_report_exchange_rates(&store).unwrap(),
+            exchange_rate_infos
+        );
+        delete_report(&store, report_id).unwrap();
+        assert!(get_report_exchange_rates(&store).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_create_trades_skips_duplicates() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = migrated_store(&data_dir);
+        let mut taxpayer_profile =
+            get_taxpayer_profile(&store, DEFAULT_TAXPAYER_PROFILE_ID).unwrap();
+        taxpayer_profile.full_name = "Jelena Petrovic".into();
+        let other_taxpayer_profile_id = create_taxpayer_profile(&store, &taxpayer_profile).unwrap();
+        let report = |report_name: &str, taxpayer_profile_id: i32| {
+            create_report(
+                &store,
+                &Report {
+                    id: 0,
+                    _type: "IbkrCsv".into(),
+                    importer_id: None,
+                    mailbox_id: None,
+                    mailbox_message_id: None,
+                    report_name: report_name.into(),
+                    status: "init".into(),
+                    taxpayer_profile_id,
+                },
+            )
+            .unwrap()
+        };
+        let trade_info = |quantity: i64| TradeInfo {
+            isin: "US0378331005".into(),
+            symbol: "AAPL".into(),
+            trade_date: parse_iso("2024-03-01").unwrap(),
+            currency_code: "USD".into(),
+            quantity: quantity.into(),
+            proceeds: (-180 * quantity).into(),
+            commission: (-1).into(),
+        };
+        // Two fills at the same price
+        let daily_trade_infos = vec![trade_info(10), trade_info(10)];
+        let daily_report_id = report("daily.csv", DEFAULT_TAXPAYER_PROFILE_ID);
+        assert_eq!(
+            create_trades(&store, daily_report_id, &daily_trade_infos).unwrap(),
+            0
+        );
+        let monthly_trade_infos = vec![trade_info(10), trade_info(10), trade_info(5)];
+        let monthly_report_id = report("monthly.csv", DEFAULT_TAXPAYER_PROFILE_ID);
+        assert_eq!(
+            create_trades(&store, monthly_report_id, &monthly_trade_infos).unwrap(),
+            2
+        );
+        assert_eq!(
+            get_trades(&store, DEFAULT_TAXPAYER_PROFILE_ID).unwrap(),
+            monthly_trade_infos
+        );
+        // Another profile can make the same trades
+        let other_report_id = report("other.csv", other_taxpayer_profile_id);
+        assert_eq!(
+            create_trades(&store, other_report_id, &daily_trade_infos).unwrap(),
+            0
+        );
+    }
+}
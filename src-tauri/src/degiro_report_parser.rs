@@ -0,0 +1,243 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use chrono::NaiveDate;
+use csv::StringRecord;
+use rust_decimal::Decimal;
+
+use crate::{
+    error::{DkaError, DkaResult},
+    report_parser::{IncomeInfo, ParsedReport, ReportParser},
+};
+
+pub struct DegiroReportParser {}
+impl DegiroReportParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+const DIVIDEND_DESCRIPTIONS: &[&str] = &["Dividend"];
+const WHT_DESCRIPTIONS: &[&str] = &["Dividend Tax", "Dividendbelasting"];
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+struct DividendKey {
+    date: NaiveDate,
+    entity_name: String,
+    entity_isin: String,
+}
+
+struct Columns {
+    value_date: usize,
+    product: usize,
+    isin: usize,
+    description: usize,
+    change_currency: usize,
+    change_amount: usize,
+}
+impl Columns {
+    fn from_header(header: &StringRecord) -> DkaResult<Self> {
+        let require = |name: &str| {
+            header.iter().position(|h| h == name).ok_or_else(|| {
+                DkaError::Generic(format!("Degiro report is missing column: {}", name))
+            })
+        };
+        let change_currency = require("Change")?;
+        Ok(Columns {
+            value_date: require("Value date")?,
+            product: require("Product")?,
+            isin: require("ISIN")?,
+            description: require("Description")?,
+            change_currency,
+            // The amount lives in the unnamed column right after "Change"
+            change_amount: change_currency + 1,
+        })
+    }
+}
+
+fn parse_date(s: &str) -> DkaResult<NaiveDate> {
+    NaiveDate::parse_from_str(s, "%d-%m-%Y")
+        .map_err(|_| DkaError::Generic(format!("Failed to parse date: {}", s)))
+}
+
+fn parse_amount(s: &str) -> DkaResult<Decimal> {
+    // Depending on the account locale, amounts look like "1,234.56" or "1.234,56". Whichever
+    // separator comes last is the decimal one, the other one separates thousands.
+    let amount_str = match (s.rfind('.'), s.rfind(',')) {
+        (Some(point), Some(comma)) if comma > point => s.replace('.', "").replace(',', "."),
+        (None, Some(_)) => s.replace(',', "."),
+        _ => s.replace(',', ""),
+    };
+    amount_str
+        .parse()
+        .map_err(|e| DkaError::Generic(format!("Could not parse number: {}", e)))
+}
+
+fn get_dividend_incomes(
+    columns: &Columns,
+    string_records: &[StringRecord],
+) -> DkaResult<Vec<IncomeInfo>> {
+    let mut dividend_info_map: HashMap<DividendKey, IncomeInfo> = HashMap::new();
+    for row in string_records
+        .iter()
+        .filter(|row| DIVIDEND_DESCRIPTIONS.contains(&&row[columns.description]))
+    {
+        let income_date = parse_date(&row[columns.value_date])?;
+        let paying_entity = row[columns.product].to_string();
+        let paying_entity_isin = row[columns.isin].to_string();
+        let dividend_currency_code = row[columns.change_currency].to_string();
+        let dividend_currency_amount = parse_amount(&row[columns.change_amount])?;
+        match dividend_info_map.get_mut(&DividendKey {
+            date: income_date,
+            entity_name: paying_entity.clone(),
+            entity_isin: paying_entity_isin.clone(),
+        }) {
+            None => {
+                dividend_info_map.insert(
+                    DividendKey {
+                        date: income_date,
+                        entity_name: paying_entity.clone(),
+                        entity_isin: paying_entity_isin.clone(),
+                    },
+                    IncomeInfo {
+                        _type: "dividend".into(),
+                        paying_entity,
+                        isin: Some(paying_entity_isin),
+                        income_date,
+                        income_currency_code: dividend_currency_code.clone(),
+                        income_currency_amount: dividend_currency_amount,
+                        wht_currency_code: dividend_currency_code, // Default value, may be overwritten
+                        wht_currency_amount: Decimal::ZERO, // Default value, may be overwritten
+                    },
+                );
+            }
+            Some(entry) => {
+                if entry.income_currency_code != dividend_currency_code {
+                    return Err(DkaError::generic(
+                        "Duplicate dividends found with different currencies",
+                    ));
+                }
+                entry.income_currency_amount += dividend_currency_amount;
+            }
+        };
+    }
+
+    for row in string_records
+        .iter()
+        .filter(|row| WHT_DESCRIPTIONS.contains(&&row[columns.description]))
+    {
+        let wht_currency_code = row[columns.change_currency].to_string();
+        let wht_currency_amount = -parse_amount(&row[columns.change_amount])?;
+        match dividend_info_map.get_mut(&DividendKey {
+            date: parse_date(&row[columns.value_date])?,
+            entity_name: row[columns.product].to_string(),
+            entity_isin: row[columns.isin].to_string(),
+        }) {
+            None => {
+                return Err(DkaError::generic("Could not match wht to dividend"));
+            }
+            Some(pii) => {
+                if pii.wht_currency_amount != Decimal::ZERO
+                    && pii.wht_currency_code != wht_currency_code
+                {
+                    return Err(DkaError::generic(
+                        "Two WHT payments for the same dividend payment have different currencies",
+                    ));
+                }
+                pii.wht_currency_code = wht_currency_code;
+                pii.wht_currency_amount += wht_currency_amount;
+            }
+        };
+    }
+
+    let mut dividend_infos: Vec<_> = dividend_info_map.into_values().collect();
+    dividend_infos.sort_by_key(|pii| (pii.income_date, pii.paying_entity.clone())); // Deterministic order
+    Ok(dividend_infos)
+}
+
+impl ReportParser for DegiroReportParser {
+    fn parse(&self, report_path: PathBuf) -> DkaResult<ParsedReport> {
+        let file = std::fs::File::open(report_path)?;
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(file);
+        let header = rdr
+            .headers()
+            .map_err(|e| DkaError::Generic(format!("CSV parse error {}", e)))?
+            .clone();
+        let columns = Columns::from_header(&header)?;
+        let mut string_records: Vec<StringRecord> = vec![];
+        for result in rdr.records() {
+            let string_record = match result {
+                Ok(string_record) => string_record,
+                Err(e) => {
+                    return Err(DkaError::Generic(format!("CSV parse error {}", e)));
+                }
+            };
+            string_records.push(string_record);
+        }
+        Ok(ParsedReport {
+            income_infos: get_dividend_incomes(&columns, &string_records)?,
+            trade_infos: vec![],
+            exchange_rate_infos: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use rust_decimal_macros::dec;
+
+    use crate::{
+        date::parse_iso,
+        degiro_report_parser::{parse_amount, DegiroReportParser},
+        report_parser::{IncomeInfo, ReportParser},
+    };
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("2.30").unwrap(), dec!(2.3));
+        assert_eq!(parse_amount("-0,22").unwrap(), dec!(-0.22));
+        assert_eq!(parse_amount("1,234.56").unwrap(), dec!(1234.56));
+        assert_eq!(parse_amount("1.234,56").unwrap(), dec!(1234.56));
+        assert_eq!(parse_amount("-1,234,567.8").unwrap(), dec!(-1234567.8));
+        assert_eq!(parse_amount("-1.234.567,8").unwrap(), dec!(-1234567.8));
+        assert!(parse_amount("abc").is_err());
+    }
+
+    #[test]
+    fn test_degiro_full() {
+        let csv_content = include_bytes!("../tests/data/degiro-1.csv");
+        let mut tempfile = tempfile::NamedTempFile::new().unwrap();
+        tempfile.write_all(csv_content).unwrap();
+        let report_parser = DegiroReportParser::new();
+        let parsed_report = report_parser.parse(tempfile.path().to_path_buf()).unwrap();
+        assert_eq!(
+            parsed_report.income_infos,
+            vec![
+                IncomeInfo {
+                    _type: "dividend".into(),
+                    income_currency_code: "USD".into(),
+                    paying_entity: "APPLE INC".into(),
+                    isin: Some("US0378331005".into()),
+                    income_currency_amount: dec!(2.3),
+                    wht_currency_code: "USD".into(),
+                    wht_currency_amount: dec!(0.35),
+                    income_date: parse_iso("2023-01-12").unwrap(),
+                },
+                // Amounts with a decimal comma
+                IncomeInfo {
+                    _type: "dividend".into(),
+                    income_currency_code: "EUR".into(),
+                    paying_entity: "ASML HOLDING".into(),
+                    isin: Some("NL0010273215".into()),
+                    income_currency_amount: dec!(1.45),
+                    wht_currency_code: "EUR".into(),
+                    wht_currency_amount: dec!(0.22),
+                    income_date: parse_iso("2023-01-12").unwrap(),
+                },
+            ],
+        );
+    }
+}
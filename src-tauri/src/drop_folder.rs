@@ -0,0 +1,138 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::error::{DkaError, DkaResult};
+
+/// Extensions of files that are still being downloaded or written
+const PARTIAL_FILE_EXTENSIONS: [&str; 4] = ["part", "partial", "crdownload", "tmp"];
+
+/// A file found in the drop folder. Its content is only read once it is known to be needed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DroppedFile {
+    pub file_name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl DroppedFile {
+    pub fn read_content(&self) -> DkaResult<Vec<u8>> {
+        Ok(fs::read(&self.path)?)
+    }
+}
+
+/// The dropped files that were read and turned out to be imported already, by path, size and
+/// modification time. Polling the drop folder skips them unless they change.
+#[derive(Debug, Default)]
+pub struct DropFolderCache {
+    known_files: HashSet<(PathBuf, u64, SystemTime)>,
+}
+
+impl DropFolderCache {
+    pub fn contains(&self, dropped_file: &DroppedFile) -> bool {
+        self.known_files.contains(&Self::key(dropped_file))
+    }
+
+    pub fn insert(&mut self, dropped_file: &DroppedFile) {
+        self.known_files.insert(Self::key(dropped_file));
+    }
+
+    fn key(dropped_file: &DroppedFile) -> (PathBuf, u64, SystemTime) {
+        (
+            dropped_file.path.clone(),
+            dropped_file.size,
+            dropped_file.modified,
+        )
+    }
+}
+
+/// Lists the files placed directly in the drop folder, sorted by name. Hidden files and files
+/// that look like unfinished downloads are left alone until they are complete.
+pub fn list_drop_folder(path: &Path) -> DkaResult<Vec<DroppedFile>> {
+    if !path.is_dir() {
+        return Err(DkaError::User(format!(
+            "Drop folder {} does not exist",
+            path.display()
+        )));
+    }
+    let mut file_paths: Vec<_> = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    file_paths.retain(|p| p.is_file() && !is_ignored_file(p));
+    file_paths.sort();
+    let mut dropped_files = vec![];
+    for file_path in file_paths {
+        let Some(file_name) = file_path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let metadata = fs::metadata(&file_path)?;
+        dropped_files.push(DroppedFile {
+            file_name: file_name.into(),
+            path: file_path.clone(),
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        });
+    }
+    Ok(dropped_files)
+}
+
+fn is_ignored_file(path: &Path) -> bool {
+    let is_hidden = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .is_none_or(|n| n.starts_with('.') || n.starts_with('~'));
+    let is_partial = path.extension().is_some_and(|ext| {
+        PARTIAL_FILE_EXTENSIONS
+            .iter()
+            .any(|partial_ext| ext.eq_ignore_ascii_case(partial_ext))
+    });
+    is_hidden || is_partial
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_drop_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("b.csv"), "bb").unwrap();
+        fs::write(dir.path().join("a.xml"), "a").unwrap();
+        fs::write(dir.path().join(".DS_Store"), "hidden").unwrap();
+        fs::write(dir.path().join("c.csv.crdownload"), "partial").unwrap();
+        fs::create_dir(dir.path().join("archive")).unwrap();
+        fs::write(dir.path().join("archive/d.csv"), "nested").unwrap();
+        let dropped_files = list_drop_folder(dir.path()).unwrap();
+        assert_eq!(
+            dropped_files
+                .iter()
+                .map(|df| (df.file_name.as_str(), df.size))
+                .collect::<Vec<_>>(),
+            vec![("a.xml", 1), ("b.csv", 2)]
+        );
+        assert_eq!(dropped_files[1].read_content().unwrap(), b"bb");
+        assert!(matches!(
+            list_drop_folder(&dir.path().join("missing")),
+            Err(DkaError::User(_))
+        ));
+    }
+
+    #[test]
+    fn test_drop_folder_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.csv"), "a").unwrap();
+        let mut drop_folder_cache = DropFolderCache::default();
+        let dropped_file = list_drop_folder(dir.path()).unwrap().remove(0);
+        assert!(!drop_folder_cache.contains(&dropped_file));
+        drop_folder_cache.insert(&dropped_file);
+        assert!(drop_folder_cache.contains(&dropped_file));
+        // A file overwritten with new content is read again
+        fs::write(dir.path().join("a.csv"), "changed").unwrap();
+        let dropped_file = list_drop_folder(dir.path()).unwrap().remove(0);
+        assert!(!drop_folder_cache.contains(&dropped_file));
+    }
+}
@@ -1,4 +1,5 @@
 use std::io;
+#[cfg(feature = "desktop")]
 use tauri::ipc::InvokeError;
 use thiserror::Error;
 
@@ -10,6 +11,7 @@ pub enum DkaError {
     Generic(String),
     #[error("User: {0}")]
     User(String),
+    #[cfg(feature = "desktop")]
     #[error("Tauri: {0}")]
     Tauri(#[from] tauri::Error),
     #[error("Rusqlite: {0}")]
@@ -25,6 +27,7 @@ impl DkaError {
         Self::User(s.to_string())
     }
 }
+#[cfg(feature = "desktop")]
 impl Into<InvokeError> for DkaError {
     fn into(self) -> InvokeError {
         InvokeError::from_anyhow(self.into())
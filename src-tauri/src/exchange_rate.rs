@@ -1,5 +1,8 @@
+use std::collections::HashMap;
+
 use chrono::NaiveDate;
 use regex::Regex;
+use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -11,10 +14,11 @@ use crate::{
 struct Item {
     #[serde(rename = "Currency")]
     currency: String,
+    // Kept as text so that the rates are parsed exactly
     #[serde(rename = "Unit")]
-    scale_factor: f64,
+    scale_factor: String,
     #[serde(rename = "Middle_Rate")]
-    scaled_exchange_rate: f64,
+    scaled_exchange_rate: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -28,98 +32,313 @@ const NBS_CURRENCIES: &[&'static str] = &[
     "SEK", "TRY",
 ];
 
-pub async fn get_nbs_exchange_rate(date: &NaiveDate, currency_code: &str) -> DkaResult<f64> {
-    let url1 = format!(
-        "https://webappcenter.nbs.rs/ExchangeRateWebApp/ExchangeRate/IndexByDate?isSearchExecuted=true&Date={}&ExchangeRateListTypeID=3",
-        date.format("%d.%m.%Y"),
-    );
-    let Ok(result1) = reqwest::get(url1).await else {
-        return Err(DkaError::generic("Error fetching from NBS URL"));
-    };
-    let Ok(body1) = result1.bytes().await else {
-        return Err(DkaError::generic("Error fetching body from NBS URL"));
-    };
+// NBS quotes its middle rates with four decimal places
+const CROSS_RATE_DECIMAL_PLACES: u32 = 4;
 
-    let body1_str = String::from_utf8_lossy(&body1);
+const NBS_BASE_URL: &str = "https://webappcenter.nbs.rs";
 
-    let url2_regex = Regex::new("/ExchangeRateWebApp/ExchangeRate/Download\\?ExchangeRateListID=(?:[0-9a-f\\-]{36})&.*&Format=xml").unwrap();
-    let Some(url2_matches) = url2_regex.captures(&body1_str) else {
-        return Err(DkaError::generic("NBS XML URL not found"));
-    };
+#[derive(Debug, Clone, PartialEq)]
+pub struct NbsExchangeRate {
+    pub date: NaiveDate,
+    pub currency_code: String,
+    pub exchange_rate: Decimal,
+}
 
-    let url2 = format!("https://webappcenter.nbs.rs{}", &url2_matches[0]);
-    let Ok(result2) = reqwest::get(url2).await else {
-        return Err(DkaError::generic("Error fetching from NBS XML URL"));
-    };
+/// Source of NBS middle exchange rate lists. A list covers every currency for its date.
+pub trait ExchangeRateProvider {
+    async fn get_nbs_exchange_rates(&mut self, date: &NaiveDate)
+        -> DkaResult<Vec<NbsExchangeRate>>;
+}
 
-    let Ok(body2) = result2.bytes().await else {
-        return Err(DkaError::generic("Error fetching body from NBS XML URL"));
-    };
+/// Scrapes exchange rate lists off the NBS public website
+pub struct NbsExchangeRateProvider {
+    base_url: String,
+}
+impl NbsExchangeRateProvider {
+    pub fn new() -> Self {
+        Self::with_base_url(NBS_BASE_URL)
+    }
+    pub fn with_base_url(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+        }
+    }
+}
+impl ExchangeRateProvider for NbsExchangeRateProvider {
+    async fn get_nbs_exchange_rates(
+        &mut self,
+        date: &NaiveDate,
+    ) -> DkaResult<Vec<NbsExchangeRate>> {
+        let url1 = format!(
+            "{}/ExchangeRateWebApp/ExchangeRate/IndexByDate?isSearchExecuted=true&Date={}&ExchangeRateListTypeID=3",
+            self.base_url,
+            date.format("%d.%m.%Y"),
+        );
+        let Ok(result1) = reqwest::get(url1).await else {
+            return Err(DkaError::generic("Error fetching from NBS URL"));
+        };
+        let Ok(body1) = result1.bytes().await else {
+            return Err(DkaError::generic("Error fetching body from NBS URL"));
+        };
+
+        let body1_str = String::from_utf8_lossy(&body1);
+
+        let url2_regex = Regex::new("/ExchangeRateWebApp/ExchangeRate/Download\\?ExchangeRateListID=(?:[0-9a-f\\-]{36})&.*&Format=xml").unwrap();
+        let Some(url2_matches) = url2_regex.captures(&body1_str) else {
+            return Err(DkaError::generic("NBS XML URL not found"));
+        };
 
-    let body2_str = String::from_utf8_lossy(&body2);
+        let url2 = format!("{}{}", self.base_url, &url2_matches[0]);
+        let Ok(result2) = reqwest::get(url2).await else {
+            return Err(DkaError::generic("Error fetching from NBS XML URL"));
+        };
 
-    let erl: ExchangeRatesList = serde_xml_rs::from_str(&body2_str)
-        .map_err(|e| DkaError::Generic(format!("Could not parse NBS XML: {}", e)))?;
+        let Ok(body2) = result2.bytes().await else {
+            return Err(DkaError::generic("Error fetching body from NBS XML URL"));
+        };
 
-    let Some(exchange_rate) = erl
-        .items
+        let body2_str = String::from_utf8_lossy(&body2);
+
+        let erl: ExchangeRatesList = serde_xml_rs::from_str(&body2_str)
+            .map_err(|e| DkaError::Generic(format!("Could not parse NBS XML: {}", e)))?;
+
+        erl.items
+            .iter()
+            .map(|item| {
+                let (Ok(scaled_exchange_rate), Ok(scale_factor)) = (
+                    item.scaled_exchange_rate.parse::<Decimal>(),
+                    item.scale_factor.parse::<Decimal>(),
+                ) else {
+                    return Err(DkaError::Generic(format!(
+                        "Could not parse NBS rate for {}",
+                        item.currency
+                    )));
+                };
+                Ok(NbsExchangeRate {
+                    date: *date,
+                    currency_code: item.currency.clone(),
+                    exchange_rate: scaled_exchange_rate / scale_factor,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Serves lists that were downloaded before, so each date is only ever fetched once
+pub struct CachedExchangeRateProvider<P: ExchangeRateProvider> {
+    inner: P,
+    rates_by_date: HashMap<NaiveDate, Vec<NbsExchangeRate>>,
+    unsaved_rates: Vec<NbsExchangeRate>,
+}
+impl<P: ExchangeRateProvider> CachedExchangeRateProvider<P> {
+    pub fn new(inner: P, saved_rates: Vec<NbsExchangeRate>) -> Self {
+        let mut rates_by_date: HashMap<NaiveDate, Vec<NbsExchangeRate>> = HashMap::new();
+        for nbs_exchange_rate in saved_rates {
+            rates_by_date
+                .entry(nbs_exchange_rate.date)
+                .or_default()
+                .push(nbs_exchange_rate);
+        }
+        Self {
+            inner,
+            rates_by_date,
+            unsaved_rates: vec![],
+        }
+    }
+    /// Rates downloaded since the last call, to be persisted by the caller
+    pub fn take_unsaved_rates(&mut self) -> Vec<NbsExchangeRate> {
+        std::mem::take(&mut self.unsaved_rates)
+    }
+}
+impl<P: ExchangeRateProvider> ExchangeRateProvider for CachedExchangeRateProvider<P> {
+    async fn get_nbs_exchange_rates(
+        &mut self,
+        date: &NaiveDate,
+    ) -> DkaResult<Vec<NbsExchangeRate>> {
+        if let Some(nbs_exchange_rates) = self.rates_by_date.get(date) {
+            return Ok(nbs_exchange_rates.clone());
+        }
+        let nbs_exchange_rates = self.inner.get_nbs_exchange_rates(date).await?;
+        self.unsaved_rates
+            .extend(nbs_exchange_rates.iter().cloned());
+        self.rates_by_date.insert(*date, nbs_exchange_rates.clone());
+        Ok(nbs_exchange_rates)
+    }
+}
+
+/// Serves a fixed set of rates, for tests
+#[cfg(test)]
+pub struct FixtureExchangeRateProvider {
+    nbs_exchange_rates: Vec<NbsExchangeRate>,
+}
+#[cfg(test)]
+impl FixtureExchangeRateProvider {
+    pub fn new(nbs_exchange_rates: Vec<NbsExchangeRate>) -> Self {
+        Self { nbs_exchange_rates }
+    }
+}
+#[cfg(test)]
+impl ExchangeRateProvider for FixtureExchangeRateProvider {
+    async fn get_nbs_exchange_rates(
+        &mut self,
+        date: &NaiveDate,
+    ) -> DkaResult<Vec<NbsExchangeRate>> {
+        let nbs_exchange_rates: Vec<_> = self
+            .nbs_exchange_rates
+            .iter()
+            .filter(|ner| ner.date == *date)
+            .cloned()
+            .collect();
+        if nbs_exchange_rates.is_empty() {
+            return Err(DkaError::Generic(format!(
+                "No fixture exchange rates for {}",
+                date
+            )));
+        }
+        Ok(nbs_exchange_rates)
+    }
+}
+
+pub async fn get_nbs_exchange_rate(
+    date: &NaiveDate,
+    currency_code: &str,
+    exchange_rate_provider: &mut impl ExchangeRateProvider,
+) -> DkaResult<Decimal> {
+    let Some(exchange_rate) = exchange_rate_provider
+        .get_nbs_exchange_rates(date)
+        .await?
         .iter()
-        .find(|item| item.currency == currency_code)
-        .map(|item| item.scaled_exchange_rate / item.scale_factor)
+        .find(|ner| ner.currency_code == currency_code)
+        .map(|ner| ner.exchange_rate)
     else {
         return Err(DkaError::generic("Could not find exchange rate"));
     };
     Ok(exchange_rate)
 }
 
+/// The statement rate of the currency closest to the date, preferring those on or before it. Rates
+/// can come from several statements, e.g. for the trades of a capital gains filing.
+fn find_statement_rate(
+    date: &NaiveDate,
+    currency_code: &str,
+    exchange_rate_infos: &[ExchangeRateInfo],
+) -> Option<Decimal> {
+    exchange_rate_infos
+        .iter()
+        .filter(|eri| eri.currency_code == currency_code)
+        .min_by_key(|eri| (eri.date > *date, (eri.date - *date).num_days().abs()))
+        .map(|eri| eri.currency_to_base_currency_rate)
+}
+
 pub async fn get_exchange_rate(
     date: &NaiveDate,
     currency_code: &str,
     exchange_rate_infos: &[ExchangeRateInfo],
-) -> DkaResult<f64> {
+    exchange_rate_provider: &mut impl ExchangeRateProvider,
+) -> DkaResult<Decimal> {
     if NBS_CURRENCIES.contains(&currency_code) {
-        return get_nbs_exchange_rate(date, currency_code).await;
+        return get_nbs_exchange_rate(date, currency_code, exchange_rate_provider).await;
     }
-    let usdrsd = get_nbs_exchange_rate(date, "USD").await?;
+    let usdrsd = get_nbs_exchange_rate(date, "USD", exchange_rate_provider).await?;
 
-    let Some(curbase) = exchange_rate_infos
-        .iter()
-        .find(|eri| eri.currency_code == currency_code)
-        .map(|eri| eri.currency_to_base_currency_rate)
-    else {
+    let Some(curbase) = find_statement_rate(date, currency_code, exchange_rate_infos) else {
         return Err(DkaError::generic(
             "Could not find exchange rate in statement",
         ));
     };
-    let Some(usdbase) = exchange_rate_infos
-        .iter()
-        .find(|eri| eri.currency_code == "USD")
-        .map(|eri| eri.currency_to_base_currency_rate)
-    else {
+    let Some(usdbase) = find_statement_rate(date, "USD", exchange_rate_infos) else {
         return Err(DkaError::generic(
             "Could not find USD exchange rate in statement",
         ));
     };
-    println!("DBGG {curbase} {usdbase} {usdrsd}");
-    return Ok(curbase / usdbase * usdrsd);
+    // Rounded like an NBS rate, so that it is exact when stored as REAL and when recomputed
+    Ok((curbase / usdbase * usdrsd).round_dp_with_strategy(
+        CROSS_RATE_DECIMAL_PLACES,
+        RoundingStrategy::MidpointAwayFromZero,
+    ))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::date::parse_iso;
+    use crate::{date::parse_iso, mock_http_server::MockHttpServer};
 
     use super::*;
+    use rust_decimal_macros::dec;
+
+    fn nbs_exchange_rate(
+        date: &str,
+        currency_code: &str,
+        exchange_rate: Decimal,
+    ) -> NbsExchangeRate {
+        NbsExchangeRate {
+            date: parse_iso(date).unwrap(),
+            currency_code: currency_code.into(),
+            exchange_rate,
+        }
+    }
+
+    /// Serves NBS responses recorded for 2020-07-16
+    fn start_nbs_mock_server() -> MockHttpServer {
+        MockHttpServer::start(vec![
+            (
+                "/ExchangeRateWebApp/ExchangeRate/IndexByDate?isSearchExecuted=true&Date=16.07.2020&",
+                include_bytes!("../tests/data/nbs-index-2020-07-16.html").to_vec(),
+            ),
+            (
+                "/ExchangeRateWebApp/ExchangeRate/Download?ExchangeRateListID=",
+                include_bytes!("../tests/data/nbs-list-2020-07-16.xml").to_vec(),
+            ),
+        ])
+    }
 
-    // Live test against NBS site
     #[tokio::test]
     async fn test_get_nbs_exchange_rate() {
-        let rate = get_nbs_exchange_rate(&parse_iso("2020-07-16").unwrap(), "EUR")
-            .await
-            .unwrap();
-        assert!(rate > 117.5945 && rate < 117.5955);
+        let nbs_mock_server = start_nbs_mock_server();
+        let rate = get_nbs_exchange_rate(
+            &parse_iso("2020-07-16").unwrap(),
+            "EUR",
+            &mut NbsExchangeRateProvider::with_base_url(&nbs_mock_server.base_url),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rate, dec!(117.5950));
+        // Scaled rates are divided by their unit
+        let rate = get_nbs_exchange_rate(
+            &parse_iso("2020-07-16").unwrap(),
+            "JPY",
+            &mut NbsExchangeRateProvider::with_base_url(&nbs_mock_server.base_url),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rate, dec!(0.920242));
+    }
+
+    #[tokio::test]
+    async fn test_get_nbs_exchange_rate_missing_date() {
+        let nbs_mock_server = start_nbs_mock_server();
+        assert!(get_nbs_exchange_rate(
+            &parse_iso("2020-07-17").unwrap(),
+            "EUR",
+            &mut NbsExchangeRateProvider::with_base_url(&nbs_mock_server.base_url),
+        )
+        .await
+        .is_err());
     }
 
     // Live test against NBS site
+    #[tokio::test]
+    #[ignore]
+    async fn test_get_nbs_exchange_rate_live() {
+        let rate = get_nbs_exchange_rate(
+            &parse_iso("2020-07-16").unwrap(),
+            "EUR",
+            &mut NbsExchangeRateProvider::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rate, dec!(117.5950));
+    }
+
     #[tokio::test]
     async fn test_get_exchange_rate() {
         let rate = get_exchange_rate(
@@ -129,17 +348,90 @@ mod tests {
                 ExchangeRateInfo {
                     date: parse_iso("2025-11-06").unwrap(),
                     currency_code: "MXN".into(),
-                    currency_to_base_currency_rate: 0.0465,
+                    currency_to_base_currency_rate: dec!(0.0465),
                 },
                 ExchangeRateInfo {
                     date: parse_iso("2025-11-06").unwrap(),
                     currency_code: "USD".into(),
-                    currency_to_base_currency_rate: 0.8646,
+                    currency_to_base_currency_rate: dec!(0.8646),
                 },
             ],
+            &mut FixtureExchangeRateProvider::new(vec![nbs_exchange_rate(
+                "2025-11-06",
+                "USD",
+                dec!(101.8911),
+            )]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rate, dec!(5.4799));
+    }
+
+    #[test]
+    fn test_find_statement_rate() {
+        let exchange_rate_info = |date: &str, rate: Decimal| ExchangeRateInfo {
+            date: parse_iso(date).unwrap(),
+            currency_code: "MXN".into(),
+            currency_to_base_currency_rate: rate,
+        };
+        let exchange_rate_infos = [
+            exchange_rate_info("2025-01-31", dec!(0.041)),
+            exchange_rate_info("2025-02-28", dec!(0.043)),
+            exchange_rate_info("2025-03-31", dec!(0.045)),
+        ];
+        let find = |date: &str, currency_code: &str| {
+            find_statement_rate(
+                &parse_iso(date).unwrap(),
+                currency_code,
+                &exchange_rate_infos,
+            )
+        };
+        assert_eq!(find("2025-02-28", "MXN"), Some(dec!(0.043)));
+        assert_eq!(find("2025-03-30", "MXN"), Some(dec!(0.043)));
+        assert_eq!(find("2025-01-02", "MXN"), Some(dec!(0.041)));
+        assert_eq!(find("2025-02-28", "USD"), None);
+    }
+
+    #[tokio::test]
+    async fn test_cached_exchange_rate_provider() {
+        let mut exchange_rate_provider = CachedExchangeRateProvider::new(
+            FixtureExchangeRateProvider::new(vec![nbs_exchange_rate(
+                "2020-07-17",
+                "EUR",
+                dec!(117.5951),
+            )]),
+            vec![nbs_exchange_rate("2020-07-16", "EUR", dec!(117.595))],
+        );
+        // Served from the saved rates
+        let rate = get_nbs_exchange_rate(
+            &parse_iso("2020-07-16").unwrap(),
+            "EUR",
+            &mut exchange_rate_provider,
+        )
+        .await
+        .unwrap();
+        assert_eq!(rate, dec!(117.595));
+        // The list for a saved date is complete, so a missing currency is not refetched
+        assert!(get_nbs_exchange_rate(
+            &parse_iso("2020-07-16").unwrap(),
+            "USD",
+            &mut exchange_rate_provider
+        )
+        .await
+        .is_err());
+        assert!(exchange_rate_provider.take_unsaved_rates().is_empty());
+        // Served by the inner provider and recorded for saving
+        let rate = get_nbs_exchange_rate(
+            &parse_iso("2020-07-17").unwrap(),
+            "EUR",
+            &mut exchange_rate_provider,
         )
         .await
         .unwrap();
-        assert!(rate > 5.475 && rate < 5.485, "{rate}");
+        assert_eq!(rate, dec!(117.5951));
+        assert_eq!(
+            exchange_rate_provider.take_unsaved_rates(),
+            vec![nbs_exchange_rate("2020-07-17", "EUR", dec!(117.5951))]
+        );
     }
 }
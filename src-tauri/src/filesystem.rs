@@ -1,52 +1,55 @@
+use ring::digest;
+use rust_decimal_macros::dec;
 use std::fs;
 use std::fs::File;
 use std::io::Read;
 use std::io::Write;
 use std::path::PathBuf;
-use tauri::AppHandle;
-use tauri::Manager;
 
+use crate::app_store::AppStore;
 use crate::error::DkaError;
 use crate::error::DkaResult;
 use crate::ipc_types::HolidayConf;
+use crate::ipc_types::ReferenceRate;
 use crate::ipc_types::TechnicalConf;
+use crate::ipc_types::WhtTreatyRate;
 
-pub fn migrate_filesystem(app_handle: &AppHandle) -> DkaResult<()> {
-    let reports_dir = app_handle.path().app_data_dir()?.join("reports");
+pub fn migrate_filesystem(store: &AppStore) -> DkaResult<()> {
+    let reports_dir = store.data_dir().join("reports");
     std::fs::create_dir_all(reports_dir)?;
-    let filings_dir = app_handle.path().app_data_dir()?.join("filings");
+    let filings_dir = store.data_dir().join("filings");
     std::fs::create_dir_all(filings_dir)?;
-    match fs::exists(get_technical_conf_path(app_handle)?) {
+    match fs::exists(get_technical_conf_path(store)?) {
         Ok(true) => {}
         _ => {
-            update_technical_conf(app_handle, get_default_technical_conf())?;
+            update_technical_conf(store, get_default_technical_conf())?;
         }
     };
     Ok(())
 }
 
-pub fn get_technical_conf_path(app_handle: &AppHandle) -> DkaResult<PathBuf> {
-    let app_data_dir = app_handle.path().app_data_dir()?;
+pub fn get_technical_conf_path(store: &AppStore) -> DkaResult<PathBuf> {
+    let app_data_dir = store.data_dir();
     Ok(app_data_dir.join(PathBuf::from("technical-conf.json")))
 }
 
-pub fn get_technical_conf_temp_path(app_handle: &AppHandle) -> DkaResult<PathBuf> {
-    let app_data_dir = app_handle.path().app_data_dir()?;
+pub fn get_technical_conf_temp_path(store: &AppStore) -> DkaResult<PathBuf> {
+    let app_data_dir = store.data_dir();
     Ok(app_data_dir.join(PathBuf::from("technical-conf.json.tmp")))
 }
 
-pub fn get_filings_dir(app_handle: &AppHandle) -> DkaResult<PathBuf> {
-    let app_data_dir = app_handle.path().app_data_dir()?;
+pub fn get_filings_dir(store: &AppStore) -> DkaResult<PathBuf> {
+    let app_data_dir = store.data_dir();
     Ok(app_data_dir.join(PathBuf::from("filings")))
 }
 
-pub fn get_reports_dir(app_handle: &AppHandle) -> DkaResult<PathBuf> {
-    let app_data_dir = app_handle.path().app_data_dir()?;
+pub fn get_reports_dir(store: &AppStore) -> DkaResult<PathBuf> {
+    let app_data_dir = store.data_dir();
     Ok(app_data_dir.join(PathBuf::from("reports")))
 }
 
-pub fn get_technical_conf(app_handle: &AppHandle) -> DkaResult<TechnicalConf> {
-    let mut file = File::open(get_technical_conf_path(app_handle)?)?;
+pub fn get_technical_conf(store: &AppStore) -> DkaResult<TechnicalConf> {
+    let mut file = File::open(get_technical_conf_path(store)?)?;
     let mut file_content = String::new();
     file.read_to_string(&mut file_content)?;
     let conf: TechnicalConf = serde_json::from_str(&file_content)
@@ -54,17 +57,14 @@ pub fn get_technical_conf(app_handle: &AppHandle) -> DkaResult<TechnicalConf> {
     Ok(conf)
 }
 
-pub fn get_db_path(app_handle: &AppHandle) -> DkaResult<PathBuf> {
-    let app_data_dir = app_handle.path().app_data_dir()?;
+pub fn get_db_path(store: &AppStore) -> DkaResult<PathBuf> {
+    let app_data_dir = store.data_dir();
     Ok(app_data_dir.join(PathBuf::from("db.sqlite")))
 }
 
-pub fn update_technical_conf(
-    app_handle: &AppHandle,
-    technical_conf: TechnicalConf,
-) -> DkaResult<()> {
-    let temp_path = get_technical_conf_temp_path(app_handle)?;
-    let final_path = get_technical_conf_path(app_handle)?;
+pub fn update_technical_conf(store: &AppStore, technical_conf: TechnicalConf) -> DkaResult<()> {
+    let temp_path = get_technical_conf_temp_path(store)?;
+    let final_path = get_technical_conf_path(store)?;
     let mut file = File::create(&temp_path)?;
     let file_content = serde_json::to_string(&technical_conf).map_err(DkaError::generic)?;
     file.write_all(file_content.as_bytes())?;
@@ -155,11 +155,128 @@ fn get_default_technical_conf() -> TechnicalConf {
                 "2026-11-11".into(),
             ],
         },
+        reference_rates: get_default_reference_rates(),
+        wht_treaty_rates: get_default_wht_treaty_rates(),
+        drop_folder: None,
     }
 }
 
-pub fn get_filing_content(app_handle: &AppHandle, filing_id: i32) -> DkaResult<Vec<u8>> {
-    let path = get_filing_path(app_handle, filing_id)?;
+// Portfolio dividend rates. Countries without an entry are credited in full, up to the Serbian tax.
+pub fn get_default_wht_treaty_rates() -> Vec<WhtTreatyRate> {
+    [
+        ("AT", dec!(15)),
+        ("CA", dec!(15)),
+        ("CH", dec!(15)),
+        ("DE", dec!(15)),
+        ("FR", dec!(15)),
+        ("GB", dec!(10)),
+        ("IE", dec!(10)),
+        ("IT", dec!(10)),
+        ("NL", dec!(15)),
+    ]
+    .into_iter()
+    .map(|(country_code, rate)| WhtTreatyRate {
+        country_code: country_code.into(),
+        rate,
+    })
+    .collect()
+}
+
+pub fn get_default_reference_rates() -> Vec<ReferenceRate> {
+    vec![
+        ReferenceRate {
+            valid_from: "2019-11-07".into(),
+            rate: dec!(2.25),
+        },
+        ReferenceRate {
+            valid_from: "2020-03-12".into(),
+            rate: dec!(1.75),
+        },
+        ReferenceRate {
+            valid_from: "2020-04-09".into(),
+            rate: dec!(1.5),
+        },
+        ReferenceRate {
+            valid_from: "2020-06-11".into(),
+            rate: dec!(1.25),
+        },
+        ReferenceRate {
+            valid_from: "2020-12-10".into(),
+            rate: dec!(1.0),
+        },
+        ReferenceRate {
+            valid_from: "2022-04-07".into(),
+            rate: dec!(1.5),
+        },
+        ReferenceRate {
+            valid_from: "2022-05-12".into(),
+            rate: dec!(2.0),
+        },
+        ReferenceRate {
+            valid_from: "2022-06-09".into(),
+            rate: dec!(2.5),
+        },
+        ReferenceRate {
+            valid_from: "2022-07-07".into(),
+            rate: dec!(2.75),
+        },
+        ReferenceRate {
+            valid_from: "2022-08-11".into(),
+            rate: dec!(3.0),
+        },
+        ReferenceRate {
+            valid_from: "2022-09-08".into(),
+            rate: dec!(3.5),
+        },
+        ReferenceRate {
+            valid_from: "2022-10-06".into(),
+            rate: dec!(4.0),
+        },
+        ReferenceRate {
+            valid_from: "2022-11-10".into(),
+            rate: dec!(4.5),
+        },
+        ReferenceRate {
+            valid_from: "2022-12-08".into(),
+            rate: dec!(5.0),
+        },
+        ReferenceRate {
+            valid_from: "2023-01-12".into(),
+            rate: dec!(5.25),
+        },
+        ReferenceRate {
+            valid_from: "2023-02-09".into(),
+            rate: dec!(5.5),
+        },
+        ReferenceRate {
+            valid_from: "2023-03-09".into(),
+            rate: dec!(5.75),
+        },
+        ReferenceRate {
+            valid_from: "2023-04-06".into(),
+            rate: dec!(6.0),
+        },
+        ReferenceRate {
+            valid_from: "2023-07-13".into(),
+            rate: dec!(6.5),
+        },
+        ReferenceRate {
+            valid_from: "2024-06-13".into(),
+            rate: dec!(6.25),
+        },
+        ReferenceRate {
+            valid_from: "2024-07-11".into(),
+            rate: dec!(6.0),
+        },
+        ReferenceRate {
+            valid_from: "2024-09-12".into(),
+            rate: dec!(5.75),
+        },
+    ]
+}
+
+pub fn get_filing_content(store: &AppStore, filing_id: i32) -> DkaResult<Vec<u8>> {
+    let path = get_filing_path(store, filing_id)?;
     let mut file = File::open(path)?;
     let mut file_content: Vec<u8> = vec![];
     file.read_to_end(&mut file_content)?;
@@ -167,38 +284,64 @@ pub fn get_filing_content(app_handle: &AppHandle, filing_id: i32) -> DkaResult<V
 }
 
 pub fn save_filing_content(
-    app_handle: &AppHandle,
+    store: &AppStore,
     filing_id: i32,
     filing_content: &[u8],
 ) -> DkaResult<()> {
-    let mut file = File::create_new(get_filing_path(app_handle, filing_id)?)?;
+    let mut file = File::create_new(get_filing_path(store, filing_id)?)?;
     file.write_all(filing_content)?;
     Ok(())
 }
 
-pub fn get_report_path(app_handle: &AppHandle, report_id: i32) -> DkaResult<PathBuf> {
-    Ok(get_reports_dir(app_handle)?.join(PathBuf::from(format!("{}.csv", report_id))))
+/// Overwrites the content of an existing filing. The new content is written to a temporary file
+/// first, so the old filing stays intact if writing fails.
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub fn replace_filing_content(
+    store: &AppStore,
+    filing_id: i32,
+    filing_content: &[u8],
+) -> DkaResult<()> {
+    let filing_path = get_filing_path(store, filing_id)?;
+    let tmp_path = filing_path.with_extension("xml.tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(filing_content)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, &filing_path)?;
+    Ok(())
 }
 
-pub fn get_filing_path(app_handle: &AppHandle, filing_id: i32) -> DkaResult<PathBuf> {
-    Ok(get_filings_dir(app_handle)?.join(PathBuf::from(format!("{}.xml", filing_id))))
+pub fn get_report_path(store: &AppStore, report_id: i32) -> DkaResult<PathBuf> {
+    Ok(get_reports_dir(store)?.join(PathBuf::from(format!("{}.csv", report_id))))
 }
 
-pub fn get_report_content(app_handle: &AppHandle, report_id: i32) -> DkaResult<Vec<u8>> {
-    let mut file = File::open(get_report_path(app_handle, report_id)?)?;
+pub fn get_filing_path(store: &AppStore, filing_id: i32) -> DkaResult<PathBuf> {
+    Ok(get_filings_dir(store)?.join(PathBuf::from(format!("{}.xml", filing_id))))
+}
+
+pub fn get_report_content(store: &AppStore, report_id: i32) -> DkaResult<Vec<u8>> {
+    let mut file = File::open(get_report_path(store, report_id)?)?;
     let mut file_content: Vec<u8> = vec![];
     file.read_to_end(&mut file_content)?;
     Ok(file_content)
 }
 
 pub fn save_report_content(
-    app_handle: &AppHandle,
+    store: &AppStore,
     report_id: i32,
     report_content: &[u8],
 ) -> DkaResult<()> {
-    let mut file = File::create_new(get_report_path(app_handle, report_id)?)?;
+    let mut file = File::create_new(get_report_path(store, report_id)?)?;
     file.write_all(report_content)?;
     Ok(())
 }
 
+/// Hex encoded SHA-256 of a report's content, to recognize a report that was already imported
+pub fn get_content_hash(content: &[u8]) -> String {
+    digest::digest(&digest::SHA256, content)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
 // TODO: "unit test" to write technical conf to file
@@ -0,0 +1,281 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use chrono::NaiveDate;
+use csv::StringRecord;
+use regex::Regex;
+use rust_decimal::Decimal;
+
+use crate::{
+    error::{DkaError, DkaResult},
+    ipc_types::GenericCsvConf,
+    report_parser::{IncomeInfo, ParsedReport, ReportParser},
+};
+
+pub struct GenericCsvReportParser {
+    conf: GenericCsvConf,
+}
+impl GenericCsvReportParser {
+    pub fn new(conf: GenericCsvConf) -> Self {
+        Self { conf }
+    }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+struct IncomeKey {
+    _type: String,
+    date: NaiveDate,
+    entity_name: String,
+    currency_code: String,
+}
+
+struct Columns {
+    date: usize,
+    _type: usize,
+    payer: usize,
+    currency: usize,
+    amount: usize,
+    wht: Option<usize>,
+    row_filters: Vec<(usize, Regex)>,
+}
+impl Columns {
+    fn new(header: &StringRecord, conf: &GenericCsvConf) -> DkaResult<Self> {
+        let require = |name: &str| {
+            header.iter().position(|h| h.trim() == name).ok_or_else(|| {
+                DkaError::User(format!("Generic CSV report is missing column: {}", name))
+            })
+        };
+        let mut row_filters: Vec<(usize, Regex)> = vec![];
+        for row_filter in conf.row_filters.iter() {
+            let regex = Regex::new(&row_filter.regex)
+                .map_err(|e| DkaError::User(format!("Bad row filter regex: {}", e)))?;
+            row_filters.push((require(&row_filter.column)?, regex));
+        }
+        Ok(Columns {
+            date: require(&conf.date_column)?,
+            _type: require(&conf.type_column)?,
+            payer: require(&conf.payer_column)?,
+            currency: require(&conf.currency_column)?,
+            amount: require(&conf.amount_column)?,
+            wht: if conf.wht_column.is_empty() {
+                None
+            } else {
+                Some(require(&conf.wht_column)?)
+            },
+            row_filters,
+        })
+    }
+}
+
+fn parse_amount(s: &str, decimal_separator: &str) -> DkaResult<Decimal> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(Decimal::ZERO);
+    }
+    // Drop thousands separators, then normalize the decimal separator
+    let amount_str: String = match decimal_separator {
+        "," => s.replace(['.', ' '], "").replace(',', "."),
+        _ => s.replace([',', ' '], ""),
+    };
+    amount_str
+        .parse()
+        .map_err(|e| DkaError::Generic(format!("Could not parse number: {}", e)))
+}
+
+fn get_incomes(
+    conf: &GenericCsvConf,
+    columns: &Columns,
+    string_records: &[StringRecord],
+) -> DkaResult<Vec<IncomeInfo>> {
+    let dividend_type_regex = Regex::new(&conf.dividend_type_regex)
+        .map_err(|e| DkaError::User(format!("Bad dividend type regex: {}", e)))?;
+    let interest_type_regex = Regex::new(&conf.interest_type_regex)
+        .map_err(|e| DkaError::User(format!("Bad interest type regex: {}", e)))?;
+    let mut income_info_map: HashMap<IncomeKey, IncomeInfo> = HashMap::new();
+    for row in string_records.iter() {
+        if !columns
+            .row_filters
+            .iter()
+            .all(|(column, regex)| regex.is_match(&row[*column]))
+        {
+            continue;
+        }
+        let row_type = &row[columns._type];
+        let income_type = if !conf.dividend_type_regex.is_empty()
+            && dividend_type_regex.is_match(row_type)
+        {
+            "dividend"
+        } else if !conf.interest_type_regex.is_empty() && interest_type_regex.is_match(row_type) {
+            "interest"
+        } else {
+            continue;
+        };
+        let income_date = NaiveDate::parse_from_str(row[columns.date].trim(), &conf.date_format)
+            .map_err(|_| {
+                DkaError::Generic(format!("Failed to parse date: {}", &row[columns.date]))
+            })?;
+        let currency_code = row[columns.currency].trim().to_string();
+        let income_currency_amount = parse_amount(&row[columns.amount], &conf.decimal_separator)?;
+        let wht_currency_amount = match columns.wht {
+            Some(wht) => parse_amount(&row[wht], &conf.decimal_separator)?.abs(),
+            None => Decimal::ZERO,
+        };
+        income_info_map
+            .entry(IncomeKey {
+                _type: income_type.into(),
+                date: income_date,
+                entity_name: row[columns.payer].trim().to_string(),
+                currency_code: currency_code.clone(),
+            })
+            .and_modify(|e| {
+                e.income_currency_amount += income_currency_amount;
+                e.wht_currency_amount += wht_currency_amount;
+            })
+            .or_insert(IncomeInfo {
+                _type: income_type.into(),
+                paying_entity: row[columns.payer].trim().to_string(),
+                isin: None,
+                income_date,
+                income_currency_code: currency_code.clone(),
+                income_currency_amount,
+                wht_currency_code: currency_code,
+                wht_currency_amount,
+            });
+    }
+
+    let mut income_infos: Vec<_> = income_info_map
+        .into_values()
+        .filter(|pii| pii.income_currency_amount > Decimal::ZERO)
+        .collect();
+    income_infos.sort_by_key(|pii| {
+        (
+            pii.income_date,
+            pii._type.clone(),
+            pii.paying_entity.clone(),
+            pii.income_currency_code.clone(),
+        )
+    }); // Deterministic order
+    Ok(income_infos)
+}
+
+impl ReportParser for GenericCsvReportParser {
+    fn parse(&self, report_path: PathBuf) -> DkaResult<ParsedReport> {
+        let delimiter = match self.conf.delimiter.as_bytes() {
+            [] => b',',
+            [d] => *d,
+            _ => {
+                return Err(DkaError::user(
+                    "Generic CSV delimiter must be a single character",
+                ))
+            }
+        };
+        let file = std::fs::File::open(report_path)?;
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_reader(file);
+        let header = rdr
+            .headers()
+            .map_err(|e| DkaError::Generic(format!("CSV parse error {}", e)))?
+            .clone();
+        let columns = Columns::new(&header, &self.conf)?;
+        let mut string_records: Vec<StringRecord> = vec![];
+        for result in rdr.records() {
+            let string_record = match result {
+                Ok(string_record) => string_record,
+                Err(e) => {
+                    return Err(DkaError::Generic(format!("CSV parse error {}", e)));
+                }
+            };
+            // Skip trailing summary rows and blank lines
+            if string_record.len() < header.len() {
+                continue;
+            }
+            string_records.push(string_record);
+        }
+        Ok(ParsedReport {
+            income_infos: get_incomes(&self.conf, &columns, &string_records)?,
+            trade_infos: vec![],
+            exchange_rate_infos: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use rust_decimal_macros::dec;
+
+    use crate::{
+        date::parse_iso,
+        generic_csv_report_parser::GenericCsvReportParser,
+        ipc_types::{GenericCsvConf, GenericCsvRowFilter},
+        report_parser::{IncomeInfo, ReportParser},
+    };
+
+    fn get_conf() -> GenericCsvConf {
+        GenericCsvConf {
+            delimiter: ";".into(),
+            date_column: "Datum".into(),
+            date_format: "%d.%m.%Y".into(),
+            type_column: "Opis".into(),
+            dividend_type_regex: "^Dividenda".into(),
+            interest_type_regex: "^Kamata".into(),
+            payer_column: "Isplatilac".into(),
+            currency_column: "Valuta".into(),
+            amount_column: "Iznos".into(),
+            wht_column: "Porez".into(),
+            decimal_separator: ",".into(),
+            row_filters: vec![GenericCsvRowFilter {
+                column: "Status".into(),
+                regex: "^Izvrseno$".into(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_generic_csv_full() {
+        let csv_content = include_bytes!("../tests/data/generic-1.csv");
+        let mut tempfile = tempfile::NamedTempFile::new().unwrap();
+        tempfile.write_all(csv_content).unwrap();
+        let report_parser = GenericCsvReportParser::new(get_conf());
+        let parsed_report = report_parser.parse(tempfile.path().to_path_buf()).unwrap();
+        assert_eq!(
+            parsed_report.income_infos,
+            vec![
+                IncomeInfo {
+                    _type: "dividend".into(),
+                    income_currency_code: "EUR".into(),
+                    paying_entity: "ABC AG".into(),
+                    isin: None,
+                    income_currency_amount: dec!(1250.5), // Thousands separator
+                    wht_currency_code: "EUR".into(),
+                    wht_currency_amount: dec!(125.05),
+                    income_date: parse_iso("2023-01-12").unwrap(),
+                },
+                IncomeInfo {
+                    _type: "interest".into(),
+                    income_currency_code: "EUR".into(),
+                    paying_entity: "Banka".into(),
+                    isin: None,
+                    income_currency_amount: dec!(3.2),
+                    wht_currency_code: "EUR".into(),
+                    wht_currency_amount: dec!(0.0),
+                    income_date: parse_iso("2023-01-31").unwrap(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn test_generic_csv_missing_column() {
+        let csv_content = include_bytes!("../tests/data/generic-1.csv");
+        let mut tempfile = tempfile::NamedTempFile::new().unwrap();
+        tempfile.write_all(csv_content).unwrap();
+        let mut conf = get_conf();
+        conf.amount_column = "Amount".into();
+        let report_parser = GenericCsvReportParser::new(conf);
+        assert!(report_parser.parse(tempfile.path().to_path_buf()).is_err());
+    }
+}
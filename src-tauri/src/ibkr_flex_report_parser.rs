@@ -0,0 +1,422 @@
+use std::{collections::HashMap, fs::File, io::Read, path::PathBuf};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{
+    error::{DkaError, DkaResult},
+    report_parser::{ExchangeRateInfo, IncomeInfo, ParsedReport, ReportParser},
+};
+
+pub struct IbkrFlexReportParser {}
+impl IbkrFlexReportParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FlexQueryResponse {
+    #[serde(rename = "FlexStatements")]
+    flex_statements: FlexStatements,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FlexStatements {
+    #[serde(rename = "FlexStatement", default)]
+    flex_statements: Vec<FlexStatement>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FlexStatement {
+    #[serde(rename = "AccountInformation")]
+    account_information: Option<AccountInformation>,
+    #[serde(rename = "CashTransactions")]
+    cash_transactions: Option<CashTransactions>,
+    #[serde(rename = "ConversionRates")]
+    conversion_rates: Option<ConversionRates>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct AccountInformation {
+    #[serde(rename = "@currency")]
+    currency: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CashTransactions {
+    #[serde(rename = "CashTransaction", default)]
+    cash_transactions: Vec<CashTransaction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CashTransaction {
+    #[serde(rename = "@type")]
+    _type: String,
+    #[serde(rename = "@currency")]
+    currency: String,
+    #[serde(rename = "@fxRateToBase", default)]
+    fx_rate_to_base: String,
+    #[serde(rename = "@symbol", default)]
+    symbol: String,
+    #[serde(rename = "@isin", default)]
+    isin: String,
+    #[serde(rename = "@settleDate")]
+    settle_date: String,
+    #[serde(rename = "@reportDate")]
+    report_date: String,
+    #[serde(rename = "@amount")]
+    amount: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ConversionRates {
+    #[serde(rename = "ConversionRate", default)]
+    conversion_rates: Vec<ConversionRate>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ConversionRate {
+    #[serde(rename = "@reportDate")]
+    report_date: String,
+    #[serde(rename = "@fromCurrency")]
+    from_currency: String,
+    #[serde(rename = "@toCurrency")]
+    to_currency: String,
+    #[serde(rename = "@rate")]
+    rate: String,
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+struct DividendKey {
+    date: NaiveDate,
+    entity_name: String,
+    entity_isin: String,
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+struct InterestKey {
+    date: NaiveDate,
+    currency_code: String,
+}
+
+fn parse_flex_date(s: &str) -> DkaResult<NaiveDate> {
+    // Flex queries can be configured to append a time to dates, e.g. "20230112;103000"
+    let date_str = s.split([';', ',', ' ']).next().unwrap_or("");
+    NaiveDate::parse_from_str(date_str, "%Y%m%d")
+        .or_else(|_| NaiveDate::parse_from_str(date_str, "%Y-%m-%d"))
+        .map_err(|_| DkaError::Generic(format!("Failed to parse date: {}", s)))
+}
+
+fn parse_flex_amount(s: &str) -> DkaResult<Decimal> {
+    s.parse()
+        .map_err(|e| DkaError::Generic(format!("Could not parse number: {}", e)))
+}
+
+fn get_dividend_incomes(cash_transactions: &[&CashTransaction]) -> DkaResult<Vec<IncomeInfo>> {
+    let mut dividend_info_map: HashMap<DividendKey, IncomeInfo> = HashMap::new();
+    for ct in cash_transactions
+        .iter()
+        .filter(|ct| ct._type == "Dividends")
+    {
+        let income_date = parse_flex_date(&ct.settle_date)?;
+        let dividend_currency_amount = parse_flex_amount(&ct.amount)?;
+        let key = DividendKey {
+            date: income_date,
+            entity_name: ct.symbol.clone(),
+            entity_isin: ct.isin.clone(),
+        };
+        match dividend_info_map.get_mut(&key) {
+            None => {
+                dividend_info_map.insert(
+                    key,
+                    IncomeInfo {
+                        _type: "dividend".into(),
+                        paying_entity: ct.symbol.clone(),
+                        isin: Some(ct.isin.clone()).filter(|isin| !isin.is_empty()),
+                        income_date,
+                        income_currency_code: ct.currency.clone(),
+                        income_currency_amount: dividend_currency_amount,
+                        wht_currency_code: ct.currency.clone(), // Default value, may be overwritten
+                        wht_currency_amount: Decimal::ZERO,     // Default value, may be overwritten
+                    },
+                );
+            }
+            Some(entry) => {
+                if entry.income_currency_code != ct.currency {
+                    return Err(DkaError::generic(
+                        "Duplicate dividends found with different currencies",
+                    ));
+                }
+                entry.income_currency_amount += dividend_currency_amount;
+            }
+        };
+    }
+
+    for ct in cash_transactions
+        .iter()
+        .filter(|ct| ct._type == "Withholding Tax")
+    {
+        let wht_currency_amount = -parse_flex_amount(&ct.amount)?;
+        match dividend_info_map.get_mut(&DividendKey {
+            date: parse_flex_date(&ct.settle_date)?,
+            entity_name: ct.symbol.clone(),
+            entity_isin: ct.isin.clone(),
+        }) {
+            None => {
+                return Err(DkaError::generic("Could not match wht to dividend"));
+            }
+            Some(pii) => {
+                if pii.wht_currency_amount != Decimal::ZERO && pii.wht_currency_code != ct.currency
+                {
+                    return Err(DkaError::generic(
+                        "Two WHT payments for the same dividend payment have different currencies",
+                    ));
+                }
+                pii.wht_currency_code = ct.currency.clone();
+                pii.wht_currency_amount += wht_currency_amount;
+            }
+        };
+    }
+
+    let mut dividend_infos: Vec<_> = dividend_info_map.into_values().collect();
+    dividend_infos.sort_by_key(|pii| (pii.paying_entity.clone(), pii.income_date)); // Deterministic order
+    Ok(dividend_infos)
+}
+
+fn get_interest_incomes(cash_transactions: &[&CashTransaction]) -> DkaResult<Vec<IncomeInfo>> {
+    let mut interest_info_map: HashMap<InterestKey, IncomeInfo> = HashMap::new();
+    for ct in cash_transactions
+        .iter()
+        .filter(|ct| ct._type == "Broker Interest Received" || ct._type == "Broker Interest Paid")
+    {
+        let income_date = parse_flex_date(&ct.settle_date)?;
+        let interest_currency_amount = parse_flex_amount(&ct.amount)?;
+        interest_info_map
+            .entry(InterestKey {
+                date: income_date,
+                currency_code: ct.currency.clone(),
+            })
+            .and_modify(|e| e.income_currency_amount += interest_currency_amount)
+            .or_insert(IncomeInfo {
+                _type: "interest".into(),
+                paying_entity: "Interactive Brokers".into(),
+                isin: None,
+                income_date,
+                income_currency_code: ct.currency.clone(),
+                income_currency_amount: interest_currency_amount,
+                wht_currency_code: ct.currency.clone(),
+                wht_currency_amount: Decimal::ZERO,
+            });
+    }
+
+    let mut interest_infos: Vec<_> = interest_info_map
+        .into_values()
+        .filter(|pii| pii.income_currency_amount > Decimal::ZERO)
+        .collect();
+    interest_infos.sort_by_key(|pii| (pii.income_date, pii.income_currency_code.clone())); // Deterministic order
+    Ok(interest_infos)
+}
+
+fn get_exchange_rate_infos(
+    base_currency_code: &str,
+    cash_transactions: &[&CashTransaction],
+    conversion_rates: &[&ConversionRate],
+) -> DkaResult<Vec<ExchangeRateInfo>> {
+    let mut exchange_rate_infos: Vec<ExchangeRateInfo> = vec![];
+    for cr in conversion_rates
+        .iter()
+        .filter(|cr| cr.to_currency == base_currency_code)
+    {
+        exchange_rate_infos.push(ExchangeRateInfo {
+            date: parse_flex_date(&cr.report_date)?,
+            currency_code: cr.from_currency.clone(),
+            currency_to_base_currency_rate: parse_flex_amount(&cr.rate)?,
+        });
+    }
+    // Fall back to the rates attached to cash transactions for currencies missing from the
+    // ConversionRates section
+    for ct in cash_transactions.iter() {
+        if ct.fx_rate_to_base.is_empty()
+            || exchange_rate_infos
+                .iter()
+                .any(|eri| eri.currency_code == ct.currency)
+        {
+            continue;
+        }
+        exchange_rate_infos.push(ExchangeRateInfo {
+            date: parse_flex_date(&ct.report_date)?,
+            currency_code: ct.currency.clone(),
+            currency_to_base_currency_rate: parse_flex_amount(&ct.fx_rate_to_base)?,
+        });
+    }
+    if !exchange_rate_infos
+        .iter()
+        .any(|eri| eri.currency_code == base_currency_code)
+    {
+        let Some(date) = exchange_rate_infos.iter().map(|eri| eri.date).max() else {
+            return Ok(exchange_rate_infos);
+        };
+        exchange_rate_infos.push(ExchangeRateInfo {
+            date,
+            currency_code: base_currency_code.into(),
+            currency_to_base_currency_rate: Decimal::ONE,
+        });
+    }
+    Ok(exchange_rate_infos)
+}
+
+impl ReportParser for IbkrFlexReportParser {
+    fn parse(&self, report_path: PathBuf) -> DkaResult<ParsedReport> {
+        let mut file_content = String::new();
+        let mut file = File::open(report_path)?;
+        file.read_to_string(&mut file_content)?;
+        let flex_query_response: FlexQueryResponse = serde_xml_rs::from_str(&file_content)
+            .map_err(|e| DkaError::Generic(format!("Could not parse Flex XML: {}", e)))?;
+        let flex_statements = flex_query_response.flex_statements.flex_statements;
+
+        let mut base_currency_code: Option<String> = None;
+        for flex_statement in flex_statements.iter() {
+            let Some(account_information) = &flex_statement.account_information else {
+                return Err(DkaError::generic(
+                    "Flex statement is missing the AccountInformation section",
+                ));
+            };
+            match &base_currency_code {
+                None => base_currency_code = Some(account_information.currency.clone()),
+                Some(c) if *c != account_information.currency => {
+                    return Err(DkaError::generic(
+                        "Flex statements with different base currencies are not supported",
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+        let Some(base_currency_code) = base_currency_code else {
+            return Err(DkaError::generic("Flex report contains no statements"));
+        };
+
+        let cash_transactions: Vec<&CashTransaction> = flex_statements
+            .iter()
+            .filter_map(|fs| fs.cash_transactions.as_ref())
+            .flat_map(|cts| cts.cash_transactions.iter())
+            .collect();
+        let conversion_rates: Vec<&ConversionRate> = flex_statements
+            .iter()
+            .filter_map(|fs| fs.conversion_rates.as_ref())
+            .flat_map(|crs| crs.conversion_rates.iter())
+            .collect();
+
+        let mut income_infos: Vec<IncomeInfo> = vec![];
+        income_infos.append(&mut get_dividend_incomes(&cash_transactions)?);
+        income_infos.append(&mut get_interest_incomes(&cash_transactions)?);
+        Ok(ParsedReport {
+            income_infos,
+            trade_infos: vec![],
+            exchange_rate_infos: get_exchange_rate_infos(
+                &base_currency_code,
+                &cash_transactions,
+                &conversion_rates,
+            )?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use rust_decimal_macros::dec;
+
+    use crate::{
+        date::parse_iso,
+        ibkr_flex_report_parser::IbkrFlexReportParser,
+        report_parser::{ExchangeRateInfo, IncomeInfo, ReportParser},
+    };
+
+    #[test]
+    fn test_ibkr_flex_full() {
+        let xml_content = include_bytes!("../tests/data/ibkr-flex1.xml");
+        let mut tempfile = tempfile::NamedTempFile::new().unwrap();
+        tempfile.write_all(xml_content).unwrap();
+        let report_parser = IbkrFlexReportParser::new();
+        let parsed_report = report_parser.parse(tempfile.path().to_path_buf()).unwrap();
+        assert_eq!(
+            parsed_report.income_infos,
+            vec![
+                IncomeInfo {
+                    _type: "dividend".into(),
+                    income_currency_code: "EUR".into(),
+                    paying_entity: "ABC".into(),
+                    isin: Some("SG0000123456".into()),
+                    income_currency_amount: dec!(60.0),
+                    wht_currency_code: "EUR".into(),
+                    wht_currency_amount: dec!(6.0),
+                    income_date: parse_iso("2023-01-12").unwrap(),
+                },
+                // Two dividends from DEF in CAD, merged together
+                IncomeInfo {
+                    _type: "dividend".into(),
+                    income_currency_code: "CAD".into(),
+                    paying_entity: "DEF".into(),
+                    isin: Some("CA0000654321".into()),
+                    income_currency_amount: dec!(25.0),
+                    wht_currency_code: "CAD".into(),
+                    wht_currency_amount: dec!(0.0),
+                    income_date: parse_iso("2023-01-12").unwrap(),
+                },
+                IncomeInfo {
+                    _type: "interest".into(),
+                    income_currency_code: "EUR".into(),
+                    paying_entity: "Interactive Brokers".into(),
+                    isin: None,
+                    income_currency_amount: dec!(12.34), // debit interest deducted from credit interest
+                    wht_currency_code: "EUR".into(),
+                    wht_currency_amount: dec!(0.0),
+                    income_date: parse_iso("2023-01-03").unwrap(),
+                },
+            ],
+        );
+        assert_eq!(
+            parsed_report.exchange_rate_infos,
+            vec![
+                ExchangeRateInfo {
+                    date: parse_iso("2023-01-13").unwrap(),
+                    currency_code: "CAD".into(),
+                    currency_to_base_currency_rate: dec!(0.68907),
+                },
+                ExchangeRateInfo {
+                    date: parse_iso("2023-01-13").unwrap(),
+                    currency_code: "USD".into(),
+                    currency_to_base_currency_rate: dec!(0.92448),
+                },
+                ExchangeRateInfo {
+                    date: parse_iso("2023-01-12").unwrap(),
+                    currency_code: "EUR".into(),
+                    currency_to_base_currency_rate: dec!(1.0),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ibkr_flex_unmatched_wht() {
+        let xml_content = r#"
+            <FlexQueryResponse queryName="Dobkapapp" type="AF">
+            <FlexStatements count="1">
+            <FlexStatement accountId="U1234567">
+            <AccountInformation accountId="U1234567" currency="USD" />
+            <CashTransactions>
+            <CashTransaction currency="USD" fxRateToBase="1" symbol="XYZ" isin="US0000000001" settleDate="20230112" reportDate="20230112" amount="-1.5" type="Withholding Tax" />
+            </CashTransactions>
+            </FlexStatement>
+            </FlexStatements>
+            </FlexQueryResponse>
+        "#;
+        let mut tempfile = tempfile::NamedTempFile::new().unwrap();
+        tempfile.write_all(xml_content.as_bytes()).unwrap();
+        let report_parser = IbkrFlexReportParser::new();
+        assert!(report_parser.parse(tempfile.path().to_path_buf()).is_err());
+    }
+}
@@ -1,13 +1,14 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{cmp::max, collections::HashMap, path::PathBuf};
 
 use chrono::NaiveDate;
 use csv::StringRecord;
 use regex::Regex;
+use rust_decimal::Decimal;
 
 use crate::{
     date::parse_iso,
     error::{DkaError, DkaResult},
-    report_parser::{ExchangeRateInfo, IncomeInfo, ParsedReport, ReportParser},
+    report_parser::{ExchangeRateInfo, IncomeInfo, ParsedReport, ReportParser, TradeInfo},
 };
 
 pub struct IbkrReportParser {}
@@ -100,9 +101,9 @@ fn get_dividend_incomes(string_records: &[StringRecord]) -> DkaResult<Vec<Income
         };
         let paying_entity = parsed_entity_name[1].to_string();
         let paying_entity_isin = parsed_entity_name[2].to_string();
-        let dividend_currency_amount: f64 = row[5]
+        let dividend_currency_amount: Decimal = row[5]
             .parse()
-            .map_err(|e| DkaError::Generic(format!("Could not parse float: {}", e)))?;
+            .map_err(|e| DkaError::Generic(format!("Could not parse number: {}", e)))?;
         match dividend_info_map.get_mut(&DividendKey {
             date_str: payment_date_str.clone(),
             entity_name: paying_entity.clone(),
@@ -118,11 +119,12 @@ fn get_dividend_incomes(string_records: &[StringRecord]) -> DkaResult<Vec<Income
                     IncomeInfo {
                         _type: "dividend".into(),
                         paying_entity: paying_entity.clone(),
+                        isin: Some(paying_entity_isin.clone()),
                         income_date: parse_iso(&payment_date_str).unwrap(),
                         income_currency_code: dividend_currency_code.clone(),
                         income_currency_amount: dividend_currency_amount,
                         wht_currency_code: dividend_currency_code, // Default value, may be overwritten
-                        wht_currency_amount: 0.0, // Default value, may be overwritten
+                        wht_currency_amount: Decimal::ZERO, // Default value, may be overwritten
                     },
                 );
             }
@@ -171,10 +173,10 @@ fn get_dividend_incomes(string_records: &[StringRecord]) -> DkaResult<Vec<Income
             };
             let paying_entity = parsed_entity_name[1].to_string();
             let paying_entity_isin = parsed_entity_name[2].to_string();
-            let wht_currency_amount: f64 = row[5]
+            let wht_currency_amount: Decimal = row[5]
                 .parse()
-                .map(|x: f64| -x)
-                .map_err(|e| DkaError::Generic(format!("Could not parse float: {}", e)))?;
+                .map(|x: Decimal| -x)
+                .map_err(|e| DkaError::Generic(format!("Could not parse number: {}", e)))?;
             match dividend_info_map.get_mut(&DividendKey {
                 date_str: payment_date_str,
                 entity_name: paying_entity,
@@ -184,7 +186,8 @@ fn get_dividend_incomes(string_records: &[StringRecord]) -> DkaResult<Vec<Income
                     return Err(DkaError::generic("Could not match wht to dividend"));
                 }
                 Some(pii) => {
-                    if pii.wht_currency_amount != 0.0 && pii.wht_currency_code != wht_currency_code
+                    if pii.wht_currency_amount != Decimal::ZERO
+                        && pii.wht_currency_code != wht_currency_code
                     {
                         return Err(DkaError::generic("Two WHT payments for the same dividend payment have different currencies"));
                     }
@@ -234,9 +237,9 @@ fn get_interest_incomes(string_records: &[StringRecord]) -> DkaResult<Vec<Income
         {
             continue;
         }
-        let interest_currency_amount: f64 = row[5]
+        let interest_currency_amount: Decimal = row[5]
             .parse()
-            .map_err(|e| DkaError::Generic(format!("Could not parse float: {}", e)))?;
+            .map_err(|e| DkaError::Generic(format!("Could not parse number: {}", e)))?;
         interest_info_map
             .entry(InterestKey {
                 date_str: payment_date_str.clone(),
@@ -246,22 +249,134 @@ fn get_interest_incomes(string_records: &[StringRecord]) -> DkaResult<Vec<Income
             .or_insert(IncomeInfo {
                 _type: "interest".into(),
                 paying_entity: "Interactive Brokers".into(),
+                isin: None,
                 income_date: parse_iso(&payment_date_str).unwrap(),
                 income_currency_code: interest_currency_code.clone(),
                 income_currency_amount: interest_currency_amount,
                 wht_currency_code: interest_currency_code,
-                wht_currency_amount: 0.0,
+                wht_currency_amount: Decimal::ZERO,
             });
     }
 
     let mut interest_infos: Vec<_> = interest_info_map
         .into_values()
-        .filter(|pii| pii.income_currency_amount > 0.0)
+        .filter(|pii| pii.income_currency_amount > Decimal::ZERO)
         .collect();
     interest_infos.sort_by_key(|pii| (pii.income_date.clone(), pii.income_currency_code.clone())); // Deterministic order
     Ok(interest_infos)
 }
 
+fn get_section_column(header_row: &StringRecord, column_name: &str) -> DkaResult<usize> {
+    header_row
+        .iter()
+        .position(|h| h == column_name)
+        .ok_or_else(|| DkaError::Generic(format!("Could not find column: {}", column_name)))
+}
+
+fn parse_quantity(s: &str) -> DkaResult<Decimal> {
+    s.replace(',', "")
+        .parse()
+        .map_err(|e| DkaError::Generic(format!("Could not parse number: {}", e)))
+}
+
+fn get_isins_by_symbol(string_records: &[StringRecord]) -> DkaResult<HashMap<String, String>> {
+    let mut isins_by_symbol: HashMap<String, String> = HashMap::new();
+    let Some(header_row) = string_records.iter().find(|sr| {
+        sr.len() >= 2 && &sr[0] == "Financial Instrument Information" && &sr[1] == "Header"
+    }) else {
+        return Ok(isins_by_symbol);
+    };
+    let symbol_column = get_section_column(header_row, "Symbol")?;
+    let security_id_column = get_section_column(header_row, "Security ID")?;
+    for row in string_records.iter().filter(|sr| {
+        sr.len() > max(symbol_column, security_id_column)
+            && &sr[0] == "Financial Instrument Information"
+            && &sr[1] == "Data"
+    }) {
+        // Renamed instruments list all of their symbols, e.g. "ABC, ABC.OLD"
+        for symbol in row[symbol_column].split(',') {
+            isins_by_symbol.insert(
+                symbol.trim().to_string(),
+                row[security_id_column].to_string(),
+            );
+        }
+    }
+    Ok(isins_by_symbol)
+}
+
+/// Every asset category has a Trades header of its own, e.g. Forex has "Comm in EUR" instead of
+/// "Comm/Fee", so each header is read together with the rows below it
+fn get_trades(string_records: &[StringRecord]) -> DkaResult<Vec<TradeInfo>> {
+    let header_indexes: Vec<usize> = string_records
+        .iter()
+        .enumerate()
+        .filter(|(_, sr)| sr.len() >= 2 && &sr[0] == "Trades" && &sr[1] == "Header")
+        .map(|(i, _)| i)
+        .collect();
+    let isins_by_symbol = get_isins_by_symbol(string_records)?;
+    let mut trade_infos: Vec<TradeInfo> = vec![];
+    for (i, &header_index) in header_indexes.iter().enumerate() {
+        let section_end_index = header_indexes
+            .get(i + 1)
+            .copied()
+            .unwrap_or(string_records.len());
+        trade_infos.append(&mut get_section_trades(
+            &string_records[header_index],
+            &string_records[header_index + 1..section_end_index],
+            &isins_by_symbol,
+        )?);
+    }
+    Ok(trade_infos)
+}
+
+fn get_section_trades(
+    header_row: &StringRecord,
+    section_rows: &[StringRecord],
+    isins_by_symbol: &HashMap<String, String>,
+) -> DkaResult<Vec<TradeInfo>> {
+    let discriminator_column = get_section_column(header_row, "DataDiscriminator")?;
+    let asset_category_column = get_section_column(header_row, "Asset Category")?;
+    let order_rows: Vec<&StringRecord> = section_rows
+        .iter()
+        .filter(|sr| {
+            sr.len() == header_row.len()
+                && &sr[0] == "Trades"
+                && &sr[1] == "Data"
+                && &sr[discriminator_column] == "Order"
+                && &sr[asset_category_column] == "Stocks"
+        })
+        .collect();
+    if order_rows.is_empty() {
+        return Ok(vec![]);
+    }
+    let currency_column = get_section_column(header_row, "Currency")?;
+    let symbol_column = get_section_column(header_row, "Symbol")?;
+    let date_time_column = get_section_column(header_row, "Date/Time")?;
+    let quantity_column = get_section_column(header_row, "Quantity")?;
+    let proceeds_column = get_section_column(header_row, "Proceeds")?;
+    let commission_column = get_section_column(header_row, "Comm/Fee")?;
+
+    let mut trade_infos: Vec<TradeInfo> = vec![];
+    for row in order_rows {
+        // Date/Time looks like "2023-01-03, 09:30:00"
+        let trade_date_str = row[date_time_column].split(',').next().unwrap_or("");
+        let symbol = row[symbol_column].to_string();
+        trade_infos.push(TradeInfo {
+            isin: isins_by_symbol
+                .get(&symbol)
+                .cloned()
+                .unwrap_or(symbol.clone()),
+            symbol,
+            trade_date: parse_iso(trade_date_str)?,
+            currency_code: row[currency_column].to_string(),
+            quantity: parse_quantity(&row[quantity_column])?,
+            proceeds: parse_quantity(&row[proceeds_column])?,
+            commission: parse_quantity(&row[commission_column])?,
+        });
+    }
+    Ok(trade_infos)
+}
+
 fn get_exchange_rate_infos(string_records: &[StringRecord]) -> DkaResult<Vec<ExchangeRateInfo>> {
     let Some(statement_period_index) = string_records.iter().position(|sr| {
         sr.len() >= 4 && &sr[0] == "Statement" && &sr[1] == "Data" && &sr[2] == "Period"
@@ -297,9 +412,9 @@ fn get_exchange_rate_infos(string_records: &[StringRecord]) -> DkaResult<Vec<Exc
         if currency_code == "USD" {
             saw_usd_rate = true;
         }
-        let currency_to_base_currency_rate: f64 = row[3]
+        let currency_to_base_currency_rate: Decimal = row[3]
             .parse()
-            .map_err(|e| DkaError::Generic(format!("Could not parse float: {}", e)))?;
+            .map_err(|e| DkaError::Generic(format!("Could not parse number: {}", e)))?;
         exchange_rate_infos.push(ExchangeRateInfo {
             date: statement_day.clone(),
             currency_code,
@@ -311,7 +426,7 @@ fn get_exchange_rate_infos(string_records: &[StringRecord]) -> DkaResult<Vec<Exc
         exchange_rate_infos.push(ExchangeRateInfo {
             date: statement_day.clone(),
             currency_code: "USD".into(),
-            currency_to_base_currency_rate: 1.0,
+            currency_to_base_currency_rate: Decimal::ONE,
         });
     }
     Ok(exchange_rate_infos)
@@ -339,6 +454,7 @@ impl ReportParser for IbkrReportParser {
         income_infos.append(&mut get_interest_incomes(&string_records)?);
         Ok(ParsedReport {
             income_infos,
+            trade_infos: get_trades(&string_records)?,
             exchange_rate_infos: get_exchange_rate_infos(&string_records)?,
         })
     }
@@ -348,10 +464,12 @@ impl ReportParser for IbkrReportParser {
 mod tests {
     use std::{io::Write, vec};
 
+    use rust_decimal_macros::dec;
+
     use crate::{
         date::parse_iso,
         ibkr_report_parser::IbkrReportParser,
-        report_parser::{ExchangeRateInfo, IncomeInfo, ReportParser},
+        report_parser::{ExchangeRateInfo, IncomeInfo, ReportParser, TradeInfo},
     };
 
     #[test]
@@ -367,9 +485,10 @@ mod tests {
                 _type: "dividend".into(),
                 income_currency_code: "EUR".into(),
                 paying_entity: "ABC".into(),
-                income_currency_amount: 60.0,
+                isin: Some("SG0000123456".into()),
+                income_currency_amount: dec!(60.0),
                 wht_currency_code: "EUR".into(),
-                wht_currency_amount: 6.0,
+                wht_currency_amount: dec!(6.0),
                 income_date: parse_iso("2023-01-12").unwrap(),
             }],
         );
@@ -390,9 +509,10 @@ mod tests {
                     _type: "dividend".into(),
                     income_currency_code: "EUR".into(),
                     paying_entity: "ABC".into(),
-                    income_currency_amount: 60.0,
+                    isin: Some("FR0000000001".into()),
+                    income_currency_amount: dec!(60.0),
                     wht_currency_code: "EUR".into(),
-                    wht_currency_amount: 0.0,
+                    wht_currency_amount: dec!(0.0),
                     income_date: parse_iso("2023-01-12").unwrap(),
                 },
                 // One dividend from DEF1 in GBP
@@ -400,9 +520,10 @@ mod tests {
                     _type: "dividend".into(),
                     income_currency_code: "GBP".into(),
                     paying_entity: "DEF1".into(),
-                    income_currency_amount: 70.0,
+                    isin: Some("GB0000000001".into()),
+                    income_currency_amount: dec!(70.0),
                     wht_currency_code: "GBP".into(),
-                    wht_currency_amount: 0.0,
+                    wht_currency_amount: dec!(0.0),
                     income_date: parse_iso("2023-01-12").unwrap(),
                 },
                 // Two dividends from DEF2 in GBP, merged together
@@ -410,9 +531,10 @@ mod tests {
                     _type: "dividend".into(),
                     income_currency_code: "GBP".into(),
                     paying_entity: "DEF2".into(),
-                    income_currency_amount: 143.0, // Sum of amounts: 71 + 72
+                    isin: Some("GB0000000002".into()),
+                    income_currency_amount: dec!(143.0), // Sum of amounts: 71 + 72
                     wht_currency_code: "GBP".into(),
-                    wht_currency_amount: 0.0,
+                    wht_currency_amount: dec!(0.0),
                     income_date: parse_iso("2023-01-12").unwrap(),
                 },
             ],
@@ -432,9 +554,10 @@ mod tests {
                 _type: "interest".into(),
                 income_currency_code: "EUR".into(),
                 paying_entity: "Interactive Brokers".into(),
-                income_currency_amount: 12.34,
+                isin: None,
+                income_currency_amount: dec!(12.34),
                 wht_currency_code: "EUR".into(),
-                wht_currency_amount: 0.0,
+                wht_currency_amount: dec!(0.0),
                 income_date: parse_iso("2023-02-03").unwrap(),
             },],
         );
@@ -453,14 +576,71 @@ mod tests {
                 _type: "interest".into(),
                 income_currency_code: "EUR".into(),
                 paying_entity: "Interactive Brokers".into(),
-                income_currency_amount: 10.00, // debit interest deducted from credit interest in same currency
+                isin: None,
+                income_currency_amount: dec!(10.00), // debit interest deducted from credit interest in same currency
                 wht_currency_code: "EUR".into(),
-                wht_currency_amount: 0.0,
+                wht_currency_amount: dec!(0.0),
                 income_date: parse_iso("2023-01-05").unwrap(),
             },],
         );
     }
 
+    #[test]
+    fn test_ibkr_trades_1() {
+        let csv_content = include_bytes!("../tests/data/ibkr-trades1.csv");
+        let mut tempfile = tempfile::NamedTempFile::new().unwrap();
+        tempfile.write_all(csv_content).unwrap();
+        let report_parser = IbkrReportParser::new();
+        let parsed_report = report_parser.parse(tempfile.path().to_path_buf()).unwrap();
+        assert_eq!(
+            parsed_report.trade_infos,
+            vec![
+                TradeInfo {
+                    isin: "US0378331005".into(),
+                    symbol: "AAPL".into(),
+                    trade_date: parse_iso("2023-01-13").unwrap(),
+                    currency_code: "USD".into(),
+                    quantity: dec!(1000.0), // Thousands separator
+                    proceeds: dec!(-125070.0),
+                    commission: dec!(-5.0),
+                },
+                TradeInfo {
+                    isin: "US5949181045".into(),
+                    symbol: "MSFT".into(),
+                    trade_date: parse_iso("2023-01-13").unwrap(),
+                    currency_code: "USD".into(),
+                    quantity: dec!(-5.0),
+                    proceeds: dec!(1200.5),
+                    commission: dec!(-1.0),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn test_ibkr_trades_2() {
+        // Only currency conversions, whose section has no Comm/Fee column
+        let csv_content = include_bytes!("../tests/data/ibkr-trades2.csv");
+        let mut tempfile = tempfile::NamedTempFile::new().unwrap();
+        tempfile.write_all(csv_content).unwrap();
+        let report_parser = IbkrReportParser::new();
+        let parsed_report = report_parser.parse(tempfile.path().to_path_buf()).unwrap();
+        assert_eq!(parsed_report.trade_infos, vec![]);
+        assert_eq!(
+            parsed_report.income_infos,
+            vec![IncomeInfo {
+                _type: "dividend".into(),
+                income_currency_code: "EUR".into(),
+                paying_entity: "ABC".into(),
+                isin: Some("SG0000123456".into()),
+                income_currency_amount: dec!(60.0),
+                wht_currency_code: "EUR".into(),
+                wht_currency_amount: dec!(6.0),
+                income_date: parse_iso("2023-01-12").unwrap(),
+            }],
+        );
+    }
+
     #[test]
     fn test_full() {
         let csv_content = include_bytes!("../tests/data/ibkr-full1.csv");
@@ -475,18 +655,20 @@ mod tests {
                     _type: "dividend".into(),
                     income_currency_code: "EUR".into(),
                     paying_entity: "ABC".into(),
-                    income_currency_amount: 60.00,
+                    isin: Some("SG0000123456".into()),
+                    income_currency_amount: dec!(60.00),
                     wht_currency_code: "EUR".into(),
-                    wht_currency_amount: 6.0,
+                    wht_currency_amount: dec!(6.0),
                     income_date: parse_iso("2023-01-12").unwrap(),
                 },
                 IncomeInfo {
                     _type: "interest".into(),
                     income_currency_code: "EUR".into(),
                     paying_entity: "Interactive Brokers".into(),
-                    income_currency_amount: 12.34,
+                    isin: None,
+                    income_currency_amount: dec!(12.34),
                     wht_currency_code: "EUR".into(),
-                    wht_currency_amount: 0.0,
+                    wht_currency_amount: dec!(0.0),
                     income_date: parse_iso("2023-01-03").unwrap(),
                 },
             ],
@@ -496,7 +678,7 @@ mod tests {
             .contains(&ExchangeRateInfo {
                 date: parse_iso("2023-01-13").unwrap(),
                 currency_code: "CAD".into(),
-                currency_to_base_currency_rate: 0.689070,
+                currency_to_base_currency_rate: dec!(0.689070),
             }))
     }
 }
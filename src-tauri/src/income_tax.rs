@@ -1,12 +1,15 @@
-use std::cmp::max;
+use std::cmp::{max, min};
 
 use chrono::{Datelike, Days, NaiveDate, Weekday};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
 
 use crate::{
-    date::format_iso,
+    date::{format_iso, parse_iso},
     error::{DkaError, DkaResult},
-    exchange_rate::get_exchange_rate,
-    ipc_types::HolidayConf,
+    exchange_rate::{get_exchange_rate, ExchangeRateProvider},
+    ipc_types::{Filing, HolidayConf, WhtTreatyRate},
+    money::{apply_rate_rsdc, to_rsdc},
     report_parser::{ExchangeRateInfo, IncomeInfo},
 };
 
@@ -15,46 +18,181 @@ pub struct FilingInfo {
     pub _type: String,
     pub paying_entity: String,
     pub income_date: NaiveDate,
+    pub income_currency_code: String,
+    pub income_currency_amount: Decimal,
+    pub income_exchange_rate: Decimal,
+    pub wht_currency_code: String,
+    pub wht_currency_amount: Decimal,
+    pub wht_exchange_rate: Decimal,
     pub gross_income_rsdc: i64,
-    pub wht_paid_rsdc: i64,
+    pub wht_actual_rsdc: i64,
+    pub wht_credit_rate: Option<Decimal>, // Treaty rate in percent, None if the credit is not capped
+    pub wht_paid_rsdc: i64,               // Credited part of the actual withholding tax
     pub gross_tax_payable_rsdc: i64,
     pub tax_payable_rsdc: i64,
 }
+impl FilingInfo {
+    /// Rebuilds the calculation stored alongside a filing, without re-parsing its report
+    #[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+    pub fn from_filing(filing: &Filing) -> DkaResult<Self> {
+        let (
+            Some(income_date),
+            Some(income_currency_code),
+            Some(income_currency_amount),
+            Some(income_exchange_rate),
+            Some(wht_currency_code),
+            Some(wht_currency_amount),
+            Some(wht_exchange_rate),
+            Some(gross_income_rsdc),
+            Some(wht_paid_rsdc),
+            Some(gross_tax_payable_rsdc),
+        ) = (
+            &filing.income_date,
+            &filing.income_currency_code,
+            filing.income_currency_amount,
+            filing.income_exchange_rate,
+            &filing.wht_currency_code,
+            filing.wht_currency_amount,
+            filing.wht_exchange_rate,
+            filing.gross_income,
+            filing.wht_paid,
+            filing.gross_tax_payable,
+        )
+        else {
+            return Err(DkaError::user(
+                "Filing declares several incomes, or was created before its calculation was recorded",
+            ));
+        };
+        Ok(FilingInfo {
+            _type: filing._type.clone(),
+            paying_entity: filing.paying_entity.clone(),
+            income_date: parse_iso(income_date)?,
+            income_currency_code: income_currency_code.clone(),
+            income_currency_amount,
+            income_exchange_rate,
+            wht_currency_code: wht_currency_code.clone(),
+            wht_currency_amount,
+            wht_exchange_rate,
+            gross_income_rsdc,
+            wht_actual_rsdc: filing.wht_actual.unwrap_or(wht_paid_rsdc),
+            wht_credit_rate: filing.wht_credit_rate,
+            wht_paid_rsdc,
+            gross_tax_payable_rsdc,
+            tax_payable_rsdc: filing.tax_payable,
+        })
+    }
+
+    /// Recalculates the tax for corrected amounts, keeping the original exchange rates
+    #[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+    pub fn with_corrected_amounts(
+        &self,
+        income_currency_amount: Decimal,
+        wht_currency_amount: Decimal,
+    ) -> Self {
+        let mut corrected = FilingInfo {
+            income_currency_amount,
+            wht_currency_amount,
+            ..self.clone()
+        };
+        corrected.calculate_rsd_amounts();
+        corrected
+    }
+
+    fn calculate_rsd_amounts(&mut self) {
+        self.gross_income_rsdc = to_rsdc(self.income_currency_amount * self.income_exchange_rate);
+        self.wht_actual_rsdc = to_rsdc(self.wht_currency_amount * self.wht_exchange_rate);
+        self.wht_paid_rsdc = match self.wht_credit_rate {
+            Some(rate) => min(
+                self.wht_actual_rsdc,
+                apply_rate_rsdc(self.gross_income_rsdc, rate / dec!(100)),
+            ),
+            None => self.wht_actual_rsdc,
+        };
+        self.gross_tax_payable_rsdc =
+            apply_rate_rsdc(self.gross_income_rsdc, PASSIVE_INCOME_TAX_RATE);
+        self.tax_payable_rsdc = max(self.gross_tax_payable_rsdc - self.wht_paid_rsdc, 0);
+    }
+}
 
-const PASSIVE_INCOME_TAX_RATE: f64 = 0.15;
+const PASSIVE_INCOME_TAX_RATE: Decimal = dec!(0.15);
+
+/// Treaty rate for the country of the security's ISIN, if the country has one configured
+/// The treaty rates are dividend rates, so other incomes are credited in full
+fn get_wht_credit_rate(
+    income_info: &IncomeInfo,
+    wht_treaty_rates: &[WhtTreatyRate],
+) -> Option<Decimal> {
+    if income_info._type != "dividend" {
+        return None;
+    }
+    let country_code = income_info.isin.as_ref()?.get(0..2)?.to_uppercase();
+    wht_treaty_rates
+        .iter()
+        .find(|wtr| wtr.country_code.to_uppercase() == country_code)
+        .map(|wtr| wtr.rate)
+}
 
 pub async fn get_filing_info(
     income_info: &IncomeInfo,
     exchange_rate_infos: &[ExchangeRateInfo],
+    wht_treaty_rates: &[WhtTreatyRate],
+    exchange_rate_provider: &mut impl ExchangeRateProvider,
 ) -> DkaResult<FilingInfo> {
     let income_exchange_rate = get_exchange_rate(
         &income_info.income_date,
         &income_info.income_currency_code,
         exchange_rate_infos,
+        exchange_rate_provider,
     )
     .await?;
-    let gross_income_rsdc =
-        (income_info.income_currency_amount * income_exchange_rate * 100f64).round() as i64;
     let wht_exchange_rate = get_exchange_rate(
         &income_info.income_date,
         &income_info.wht_currency_code,
         exchange_rate_infos,
+        exchange_rate_provider,
     )
     .await?;
-    let wht_paid_rsdc =
-        (income_info.wht_currency_amount * wht_exchange_rate * 100f64).round() as i64;
-    let gross_tax_payable_rsdc =
-        (gross_income_rsdc as f64 * PASSIVE_INCOME_TAX_RATE).round() as i64;
-    let tax_payable_rsdc = max(gross_tax_payable_rsdc - wht_paid_rsdc, 0);
-    Ok(FilingInfo {
+    let mut filing_info = FilingInfo {
         _type: income_info._type.clone(),
         paying_entity: income_info.paying_entity.clone(),
         income_date: income_info.income_date,
-        gross_income_rsdc,
-        wht_paid_rsdc,
-        gross_tax_payable_rsdc,
-        tax_payable_rsdc,
-    })
+        income_currency_code: income_info.income_currency_code.clone(),
+        income_currency_amount: income_info.income_currency_amount,
+        income_exchange_rate,
+        wht_currency_code: income_info.wht_currency_code.clone(),
+        wht_currency_amount: income_info.wht_currency_amount,
+        wht_exchange_rate,
+        gross_income_rsdc: 0,
+        wht_actual_rsdc: 0,
+        wht_credit_rate: get_wht_credit_rate(income_info, wht_treaty_rates),
+        wht_paid_rsdc: 0,
+        gross_tax_payable_rsdc: 0,
+        tax_payable_rsdc: 0,
+    };
+    filing_info.calculate_rsd_amounts();
+    Ok(filing_info)
+}
+
+/// Groups incomes of the same type and income date, so that each group can be declared in one
+/// PP-OPO. Groups are ordered by their first income. The grouped items can carry data along with
+/// their filing info.
+pub fn group_filing_infos_by_date<T>(
+    items: Vec<T>,
+    get_filing_info: impl Fn(&T) -> &FilingInfo,
+) -> Vec<Vec<T>> {
+    let mut groups: Vec<Vec<T>> = vec![];
+    for item in items {
+        let filing_info = get_filing_info(&item);
+        match groups.iter_mut().find(|g| {
+            let first_filing_info = get_filing_info(&g[0]);
+            first_filing_info.income_date == filing_info.income_date
+                && first_filing_info._type == filing_info._type
+        }) {
+            Some(group) => group.push(item),
+            None => groups.push(vec![item]),
+        }
+    }
+    groups
 }
 
 pub fn get_filing_deadline(
@@ -84,26 +222,48 @@ pub fn get_filing_deadline(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::date::parse_iso;
+    use crate::{
+        date::parse_iso,
+        exchange_rate::{FixtureExchangeRateProvider, NbsExchangeRate},
+    };
+
+    fn get_exchange_rate_provider() -> FixtureExchangeRateProvider {
+        FixtureExchangeRateProvider::new(vec![NbsExchangeRate {
+            date: parse_iso("2025-10-01").unwrap(),
+            currency_code: "EUR".into(),
+            exchange_rate: dec!(117.1697),
+        }])
+    }
 
     #[tokio::test]
     async fn test_get_filing_info_some_tax() {
         let x = IncomeInfo {
             _type: "dividend".into(),
             paying_entity: "BMW".into(),
+            isin: None,
             income_date: parse_iso("2025-10-01").unwrap(),
             income_currency_code: "EUR".into(),
-            income_currency_amount: 100.0,
+            income_currency_amount: dec!(100.0),
             wht_currency_code: "EUR".into(),
-            wht_currency_amount: 10.0,
+            wht_currency_amount: dec!(10.0),
         };
         assert_eq!(
-            get_filing_info(&x, &[]).await.unwrap(),
+            get_filing_info(&x, &[], &[], &mut get_exchange_rate_provider())
+                .await
+                .unwrap(),
             FilingInfo {
                 _type: "dividend".into(),
                 paying_entity: "BMW".into(),
                 income_date: parse_iso("2025-10-01").unwrap(),
+                income_currency_code: "EUR".into(),
+                income_currency_amount: dec!(100.0),
+                income_exchange_rate: dec!(117.1697),
+                wht_currency_code: "EUR".into(),
+                wht_currency_amount: dec!(10.0),
+                wht_exchange_rate: dec!(117.1697),
                 gross_income_rsdc: 1171697,
+                wht_actual_rsdc: 117170,
+                wht_credit_rate: None,
                 wht_paid_rsdc: 117170,
                 gross_tax_payable_rsdc: 175755,
                 tax_payable_rsdc: 58585,
@@ -116,19 +276,30 @@ mod tests {
         let x = IncomeInfo {
             _type: "dividend".into(),
             paying_entity: "BMW".into(),
+            isin: None,
             income_date: parse_iso("2025-10-01").unwrap(),
             income_currency_code: "EUR".into(),
-            income_currency_amount: 100.0,
+            income_currency_amount: dec!(100.0),
             wht_currency_code: "EUR".into(),
-            wht_currency_amount: 20.0,
+            wht_currency_amount: dec!(20.0),
         };
         assert_eq!(
-            get_filing_info(&x, &[]).await.unwrap(),
+            get_filing_info(&x, &[], &[], &mut get_exchange_rate_provider())
+                .await
+                .unwrap(),
             FilingInfo {
                 _type: "dividend".into(),
                 paying_entity: "BMW".into(),
                 income_date: parse_iso("2025-10-01").unwrap(),
+                income_currency_code: "EUR".into(),
+                income_currency_amount: dec!(100.0),
+                income_exchange_rate: dec!(117.1697),
+                wht_currency_code: "EUR".into(),
+                wht_currency_amount: dec!(20.0),
+                wht_exchange_rate: dec!(117.1697),
                 gross_income_rsdc: 1171697,
+                wht_actual_rsdc: 234339,
+                wht_credit_rate: None,
                 wht_paid_rsdc: 234339,
                 gross_tax_payable_rsdc: 175755,
                 tax_payable_rsdc: 0,
@@ -136,6 +307,210 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_get_filing_info_treaty_cap() {
+        let x = IncomeInfo {
+            _type: "dividend".into(),
+            paying_entity: "BP".into(),
+            isin: Some("GB0007980591".into()),
+            income_date: parse_iso("2025-10-01").unwrap(),
+            income_currency_code: "EUR".into(),
+            income_currency_amount: dec!(100.0),
+            wht_currency_code: "EUR".into(),
+            wht_currency_amount: dec!(15.0),
+        };
+        let wht_treaty_rates = vec![WhtTreatyRate {
+            country_code: "GB".into(),
+            rate: dec!(10.0),
+        }];
+        let filing_info = get_filing_info(
+            &x,
+            &[],
+            &wht_treaty_rates,
+            &mut get_exchange_rate_provider(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(filing_info.wht_credit_rate, Some(dec!(10.0)));
+        assert_eq!(filing_info.wht_actual_rsdc, 175755);
+        assert_eq!(filing_info.wht_paid_rsdc, 117170);
+        assert_eq!(filing_info.tax_payable_rsdc, 58585);
+
+        // Countries without a treaty rate are credited in full
+        let filing_info = get_filing_info(
+            &IncomeInfo {
+                isin: Some("US0378331005".into()),
+                wht_currency_amount: dec!(10.0),
+                ..x
+            },
+            &[],
+            &wht_treaty_rates,
+            &mut get_exchange_rate_provider(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(filing_info.wht_credit_rate, None);
+        assert_eq!(filing_info.wht_paid_rsdc, 117170);
+        assert_eq!(filing_info.tax_payable_rsdc, 58585);
+    }
+
+    #[tokio::test]
+    async fn test_get_filing_info_interest_not_capped() {
+        let wht_treaty_rates = vec![WhtTreatyRate {
+            country_code: "GB".into(),
+            rate: dec!(10.0),
+        }];
+        let filing_info = get_filing_info(
+            &IncomeInfo {
+                _type: "interest".into(),
+                paying_entity: "UK Gilt".into(),
+                isin: Some("GB00BL68HJ26".into()),
+                income_date: parse_iso("2025-10-01").unwrap(),
+                income_currency_code: "EUR".into(),
+                income_currency_amount: dec!(100.0),
+                wht_currency_code: "EUR".into(),
+                wht_currency_amount: dec!(15.0),
+            },
+            &[],
+            &wht_treaty_rates,
+            &mut get_exchange_rate_provider(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(filing_info.wht_credit_rate, None);
+        assert_eq!(filing_info.wht_paid_rsdc, 175755);
+        assert_eq!(filing_info.tax_payable_rsdc, 0);
+    }
+
+    #[test]
+    fn test_filing_info_from_filing() {
+        let filing = Filing {
+            id: 1,
+            report_id: Some(1),
+            _type: "dividend".into(),
+            status: "init".into(),
+            paying_entity: "BMW".into(),
+            filing_deadline: "2025-10-31".into(),
+            tax_payable: 58585,
+            tax_payment_reference: "".into(),
+            income_date: Some("2025-10-01".into()),
+            gross_income: Some(1171697),
+            wht_paid: Some(117170),
+            gross_tax_payable: Some(175755),
+            income_currency_code: Some("EUR".into()),
+            income_currency_amount: Some(dec!(100.0)),
+            income_exchange_rate: Some(dec!(117.1697)),
+            wht_currency_code: Some("EUR".into()),
+            wht_currency_amount: Some(dec!(10.0)),
+            wht_exchange_rate: Some(dec!(117.1697)),
+            amends_filing_id: None,
+            tax_payable_delta: None,
+            late_payment_date: None,
+            late_interest: None,
+            wht_actual: None,
+            wht_credit_rate: None,
+            taxpayer_profile_id: 1,
+            period: None,
+            suspected_duplicate_of_report_id: None,
+        };
+        assert_eq!(
+            FilingInfo::from_filing(&filing).unwrap(),
+            FilingInfo {
+                _type: "dividend".into(),
+                paying_entity: "BMW".into(),
+                income_date: parse_iso("2025-10-01").unwrap(),
+                income_currency_code: "EUR".into(),
+                income_currency_amount: dec!(100.0),
+                income_exchange_rate: dec!(117.1697),
+                wht_currency_code: "EUR".into(),
+                wht_currency_amount: dec!(10.0),
+                wht_exchange_rate: dec!(117.1697),
+                gross_income_rsdc: 1171697,
+                wht_actual_rsdc: 117170,
+                wht_credit_rate: None,
+                wht_paid_rsdc: 117170,
+                gross_tax_payable_rsdc: 175755,
+                tax_payable_rsdc: 58585,
+            }
+        );
+        let legacy_filing = Filing {
+            income_exchange_rate: None,
+            ..filing
+        };
+        assert!(FilingInfo::from_filing(&legacy_filing).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_filing_info_with_corrected_amounts() {
+        let x = IncomeInfo {
+            _type: "dividend".into(),
+            paying_entity: "BMW".into(),
+            isin: None,
+            income_date: parse_iso("2025-10-01").unwrap(),
+            income_currency_code: "EUR".into(),
+            income_currency_amount: dec!(100.0),
+            wht_currency_code: "EUR".into(),
+            wht_currency_amount: dec!(10.0),
+        };
+        let filing_info = get_filing_info(&x, &[], &[], &mut get_exchange_rate_provider())
+            .await
+            .unwrap();
+        let corrected_filing_info = get_filing_info(
+            &IncomeInfo {
+                income_currency_amount: dec!(120.0),
+                wht_currency_amount: dec!(5.0),
+                ..x
+            },
+            &[],
+            &[],
+            &mut get_exchange_rate_provider(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            filing_info.with_corrected_amounts(dec!(120.0), dec!(5.0)),
+            corrected_filing_info
+        );
+    }
+
+    #[test]
+    fn test_group_filing_infos_by_date() {
+        let filing_info = |_type: &str, paying_entity: &str, income_date: &str| FilingInfo {
+            _type: _type.into(),
+            paying_entity: paying_entity.into(),
+            income_date: parse_iso(income_date).unwrap(),
+            income_currency_code: "EUR".into(),
+            income_currency_amount: dec!(100.0),
+            income_exchange_rate: dec!(117.1697),
+            wht_currency_code: "EUR".into(),
+            wht_currency_amount: dec!(10.0),
+            wht_exchange_rate: dec!(117.1697),
+            gross_income_rsdc: 1171697,
+            wht_actual_rsdc: 117170,
+            wht_credit_rate: None,
+            wht_paid_rsdc: 117170,
+            gross_tax_payable_rsdc: 175755,
+            tax_payable_rsdc: 58585,
+        };
+        let groups = group_filing_infos_by_date(
+            vec![
+                filing_info("dividend", "BMW", "2025-10-01"),
+                filing_info("interest", "IBKR", "2025-10-01"),
+                filing_info("dividend", "Siemens", "2025-10-02"),
+                filing_info("dividend", "BASF", "2025-10-01"),
+            ],
+            |fi| fi,
+        );
+        let paying_entities: Vec<Vec<&str>> = groups
+            .iter()
+            .map(|g| g.iter().map(|fi| fi.paying_entity.as_str()).collect())
+            .collect();
+        assert_eq!(
+            paying_entities,
+            vec![vec!["BMW", "BASF"], vec!["IBKR"], vec!["Siemens"]]
+        );
+    }
+
     #[test]
     fn test_get_filing_deadline_basic() {
         let holiday_conf = HolidayConf {
@@ -1,33 +1,55 @@
+use std::cmp::max;
 use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
 use std::{fs::File, sync::OnceLock};
 
-use tauri::AppHandle;
+use rust_decimal::Decimal;
+use tauri::{AppHandle, Manager, State};
 use tauri_plugin_dialog::{DialogExt, FilePath};
 use tokio::sync::Mutex;
 
-use crate::database::{create_report, migrate_database};
+use crate::app_store::AppStore;
+use crate::capital_gains::{get_capital_gains_filing_info, get_half_year_bounds};
+use crate::database::{create_filing, migrate_database, DEFAULT_TAXPAYER_PROFILE_ID};
+use crate::date::{format_iso, parse_iso};
+use crate::drop_folder::DropFolderCache;
 use crate::error::{DkaError, DkaResult};
-use crate::filesystem::{get_filing_content, get_report_content, save_report_content};
-use crate::ipc_types::{Filing, Importer, Job, JobMessage, Report, TaxpayerProfile};
-use crate::job_logic::run_job;
+use crate::exchange_rate::{CachedExchangeRateProvider, NbsExchangeRateProvider};
+use crate::filesystem::{
+    get_filing_content, get_report_content, replace_filing_content, save_filing_content,
+};
+use crate::income_tax::FilingInfo;
+use crate::ipc_types::{
+    AnnualSummary, Filing, FilingIncome, Importer, Job, JobMessage, MailboxSecrets, Report,
+    SecretsStatus, TaxpayerProfile,
+};
+use crate::job_logic::{
+    create_opo_filing, get_new_dropped_files, get_opo_filing, import_dropped_files,
+    import_mail_archive, process_reports, run_job, save_new_report,
+};
 use crate::job_store::JobStore;
+use crate::late_interest::get_late_interest_info;
+use crate::opo_data::OpoData;
+use crate::pdg_data::PdgData;
+use crate::secrets::uses_passphrase;
 use crate::{
     filesystem::migrate_filesystem,
     ipc_types::{HolidayConf, Mailbox, TechnicalConf},
 };
 use lazy_static::lazy_static;
 
-fn migrate_app(app_handle: &AppHandle) -> DkaResult<()> {
-    migrate_filesystem(app_handle)?;
-    migrate_database(app_handle)?;
+fn migrate_app(store: &AppStore) -> DkaResult<()> {
+    migrate_filesystem(store)?;
+    migrate_database(store)?;
     rustls::crypto::CryptoProvider::install_default(rustls::crypto::ring::default_provider())
         .unwrap();
     Ok(())
 }
 
 static INIT_LOCK: OnceLock<Option<DkaError>> = OnceLock::new();
-fn ensure_app_migrated(app_handle: &AppHandle) -> DkaResult<()> {
-    let err = INIT_LOCK.get_or_init(|| migrate_app(app_handle).err());
+fn ensure_app_migrated(store: &AppStore) -> DkaResult<()> {
+    let err = INIT_LOCK.get_or_init(|| migrate_app(store).err());
     match err {
         None => Ok(()),
         Some(e) => Err(DkaError::Generic(format!(
@@ -39,38 +61,118 @@ fn ensure_app_migrated(app_handle: &AppHandle) -> DkaResult<()> {
 
 lazy_static! {
     static ref JOB_STORE: Mutex<JobStore> = Mutex::new(JobStore::new());
+    /// Held while a job runs, jobs would otherwise race to import and process the same reports
+    static ref JOB_RUN_LOCK: Mutex<()> = Mutex::new(());
 }
 
+const DROP_FOLDER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
 #[tauri::command]
-pub fn get_technical_conf(app_handle: AppHandle) -> DkaResult<TechnicalConf> {
-    ensure_app_migrated(&app_handle)?;
-    crate::filesystem::get_technical_conf(&app_handle)
+pub fn get_technical_conf(store: State<'_, AppStore>) -> DkaResult<TechnicalConf> {
+    ensure_app_migrated(&store)?;
+    crate::filesystem::get_technical_conf(&store)
 }
 
 #[tauri::command]
 pub fn update_technical_conf(
-    app_handle: AppHandle,
+    store: State<'_, AppStore>,
     technical_conf: TechnicalConf,
 ) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
-    crate::filesystem::update_technical_conf(&app_handle, technical_conf)
+    ensure_app_migrated(&store)?;
+    crate::filesystem::update_technical_conf(&store, technical_conf)
+}
+
+#[tauri::command]
+pub fn get_mailboxes(store: State<'_, AppStore>) -> DkaResult<Vec<Mailbox>> {
+    ensure_app_migrated(&store)?;
+    Ok(crate::database::get_mailboxes(&store)?
+        .iter()
+        .map(|mb| mb.redacted())
+        .collect())
+}
+
+/// Trims the folder names, so that the saved folders match what IMAP is asked for
+fn validate_mailbox_folders(mailbox: &mut Mailbox) -> DkaResult<()> {
+    mailbox.folders = mailbox
+        .folders
+        .iter()
+        .map(|f| f.trim().to_string())
+        .collect();
+    if mailbox.folders.is_empty() {
+        return Err(DkaError::user(
+            "Mailbox needs at least one folder, e.g. INBOX",
+        ));
+    }
+    if mailbox.folders.iter().any(|f| f.is_empty()) {
+        return Err(DkaError::user("Mailbox folder names cannot be blank"));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn create_mailbox(store: State<'_, AppStore>, mut mailbox: Mailbox) -> DkaResult<i32> {
+    ensure_app_migrated(&store)?;
+    validate_mailbox_folders(&mut mailbox)?;
+    crate::database::create_mailbox(&store, &mailbox)
+}
+
+#[tauri::command]
+pub fn delete_mailbox(store: State<'_, AppStore>, mailbox_id: i32) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
+    crate::database::delete_mailbox(&store, mailbox_id)
+}
+
+#[tauri::command]
+pub fn update_mailbox(
+    store: State<'_, AppStore>,
+    mut mailbox: Mailbox,
+    secrets: MailboxSecrets,
+) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
+    validate_mailbox_folders(&mut mailbox)?;
+    let stored_mailbox = crate::database::get_mailbox(&store, mailbox.id)?;
+    // A date picked in the UI applies to every folder
+    if mailbox.cursor != stored_mailbox.cursor {
+        crate::database::delete_mailbox_folder_cursors(&store, mailbox.id)?;
+    }
+    crate::database::update_mailbox(
+        &store,
+        &mailbox.with_stored_secrets(&stored_mailbox, &secrets),
+    )
 }
 
 #[tauri::command]
-pub fn get_mailbox(app_handle: AppHandle) -> DkaResult<Mailbox> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::get_mailbox(&app_handle)
+pub fn get_secrets_status(store: State<'_, AppStore>) -> DkaResult<SecretsStatus> {
+    Ok(SecretsStatus {
+        uses_passphrase: uses_passphrase(store.data_dir()),
+        locked: store.is_secret_key_locked(),
+    })
 }
 
 #[tauri::command]
-pub fn update_mailbox(app_handle: AppHandle, mailbox: Mailbox) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::update_mailbox(&app_handle, &mailbox)
+pub fn unlock_secrets(store: State<'_, AppStore>, passphrase: String) -> DkaResult<()> {
+    store.unlock_secret_key(&passphrase)
+}
+
+/// Switches to a new passphrase, or back to the key file if there is none
+#[tauri::command]
+pub fn change_secrets_passphrase(
+    store: State<'_, AppStore>,
+    passphrase: Option<String>,
+) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
+    if passphrase.as_ref().is_some_and(|p| p.is_empty()) {
+        return Err(DkaError::user("The passphrase cannot be empty"));
+    }
+    crate::database::rekey_secrets(&store, passphrase.as_deref())
 }
 
 #[tauri::command]
-pub async fn import_holiday_conf(app_handle: AppHandle) -> DkaResult<HolidayConf> {
-    ensure_app_migrated(&app_handle)?;
+pub async fn import_holiday_conf(
+    app_handle: AppHandle,
+    store: State<'_, AppStore>,
+) -> DkaResult<HolidayConf> {
+    ensure_app_migrated(&store)?;
     let file_path = app_handle.dialog().file().blocking_pick_file();
     let Some(file_path) = file_path.clone() else {
         return Err(DkaError::user("No file chosen"));
@@ -85,80 +187,104 @@ pub async fn import_holiday_conf(app_handle: AppHandle) -> DkaResult<HolidayConf
 }
 
 #[tauri::command]
-pub fn get_taxpayer_profile(app_handle: AppHandle) -> DkaResult<TaxpayerProfile> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::get_taxpayer_profile(&app_handle)
+pub fn get_taxpayer_profiles(store: State<'_, AppStore>) -> DkaResult<Vec<TaxpayerProfile>> {
+    ensure_app_migrated(&store)?;
+    crate::database::get_taxpayer_profiles(&store)
+}
+
+#[tauri::command]
+pub fn create_taxpayer_profile(
+    store: State<'_, AppStore>,
+    taxpayer_profile: TaxpayerProfile,
+) -> DkaResult<i32> {
+    ensure_app_migrated(&store)?;
+    crate::database::create_taxpayer_profile(&store, &taxpayer_profile)
+}
+
+#[tauri::command]
+pub fn delete_taxpayer_profile(
+    store: State<'_, AppStore>,
+    taxpayer_profile_id: i32,
+) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
+    crate::database::delete_taxpayer_profile(&store, taxpayer_profile_id)
 }
 
 #[tauri::command]
 pub fn update_taxpayer_profile(
-    app_handle: AppHandle,
+    store: State<'_, AppStore>,
     taxpayer_profile: TaxpayerProfile,
 ) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::update_taxpayer_profile(&app_handle, &taxpayer_profile)
+    ensure_app_migrated(&store)?;
+    crate::database::update_taxpayer_profile(&store, &taxpayer_profile)
 }
 
 #[tauri::command]
-pub fn get_importers(app_handle: AppHandle) -> DkaResult<Vec<Importer>> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::get_importers(&app_handle)
+pub fn get_importers(store: State<'_, AppStore>) -> DkaResult<Vec<Importer>> {
+    ensure_app_migrated(&store)?;
+    crate::database::get_importers(&store)
 }
 
 #[tauri::command]
-pub fn update_importer(app_handle: AppHandle, importer: Importer) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::update_importer(&app_handle, &importer)
+pub fn update_importer(store: State<'_, AppStore>, importer: Importer) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
+    crate::database::update_importer(&store, &importer)
 }
 
 #[tauri::command]
-pub fn create_importer(app_handle: AppHandle, importer: Importer) -> DkaResult<i32> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::create_importer(&app_handle, &importer)
+pub fn create_importer(store: State<'_, AppStore>, importer: Importer) -> DkaResult<i32> {
+    ensure_app_migrated(&store)?;
+    crate::database::create_importer(&store, &importer)
 }
 
 #[tauri::command]
-pub fn delete_importer(app_handle: AppHandle, importer_id: i32) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::delete_importer(&app_handle, importer_id)
+pub fn delete_importer(store: State<'_, AppStore>, importer_id: i32) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
+    crate::database::delete_importer(&store, importer_id)
 }
 
 #[tauri::command]
-pub async fn import_trivial_report(app_handle: AppHandle, report_content: String) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
+pub async fn import_trivial_report(
+    store: State<'_, AppStore>,
+    report_content: String,
+) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
     let report = Report {
         id: 0,
         _type: "NativeIncomeJson".into(),
         importer_id: None,
-        mailbox_id: 1,         // Should really be NULL
-        mailbox_message_id: 0, // Should really be NULL
+        mailbox_id: None,
+        mailbox_message_id: None,
         report_name: "Manual report".into(),
         status: "init".into(),
+        taxpayer_profile_id: DEFAULT_TAXPAYER_PROFILE_ID,
     };
-    let report_id = create_report(&app_handle, &report)?;
-    let report_content: Vec<u8> = report_content.into_bytes().to_vec();
-    save_report_content(&app_handle, report_id, &report_content)?;
+    save_new_report(&store, &report, report_content.as_bytes())?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_reports(app_handle: AppHandle) -> DkaResult<Vec<Report>> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::get_reports_by_mailbox(&app_handle, 1).map(|mut v| {
+pub fn get_reports(store: State<'_, AppStore>) -> DkaResult<Vec<Report>> {
+    ensure_app_migrated(&store)?;
+    crate::database::get_reports(&store).map(|mut v| {
         v.reverse();
         v
     })
 }
 
 #[tauri::command]
-pub fn delete_report(app_handle: AppHandle, report_id: i32) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::delete_report(&app_handle, report_id)
+pub fn delete_report(store: State<'_, AppStore>, report_id: i32) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
+    crate::database::delete_report(&store, report_id)
 }
 
 #[tauri::command]
-pub async fn export_report(app_handle: AppHandle, report_id: i32) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
+pub async fn export_report(
+    app_handle: AppHandle,
+    store: State<'_, AppStore>,
+    report_id: i32,
+) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
     let save_path = app_handle
         .dialog()
         .file()
@@ -170,7 +296,7 @@ pub async fn export_report(app_handle: AppHandle, report_id: i32) -> DkaResult<(
     let FilePath::Path(save_path) = save_path else {
         return Err(DkaError::user("Only file paths are supported"));
     };
-    let report_content = get_report_content(&app_handle, report_id)?;
+    let report_content = get_report_content(&store, report_id)?;
     let mut file = File::create_new(save_path).map_err(DkaError::Io)?;
     file.write_all(&report_content).map_err(DkaError::Io)?;
     file.flush().map_err(DkaError::Io)?;
@@ -178,26 +304,356 @@ pub async fn export_report(app_handle: AppHandle, report_id: i32) -> DkaResult<(
 }
 
 #[tauri::command]
-pub fn get_filings(app_handle: AppHandle) -> DkaResult<Vec<Filing>> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::get_filings(&app_handle)
+pub fn get_filings(
+    store: State<'_, AppStore>,
+    taxpayer_profile_id: Option<i32>,
+) -> DkaResult<Vec<Filing>> {
+    ensure_app_migrated(&store)?;
+    match taxpayer_profile_id {
+        Some(taxpayer_profile_id) => {
+            crate::database::get_filings_by_taxpayer_profile(&store, taxpayer_profile_id)
+        }
+        None => crate::database::get_filings(&store),
+    }
+}
+
+#[tauri::command]
+pub fn update_filing(
+    store: State<'_, AppStore>,
+    filing: Filing,
+    late_payment_date: Option<String>,
+) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
+    // Amendments start out as "amendment" rather than "init", the other statuses are shared
+    let initial_status = if filing.amends_filing_id.is_some() {
+        "amendment"
+    } else {
+        "init"
+    };
+    if !["filed", "paid", initial_status].contains(&filing.status.as_str()) {
+        return Err(DkaError::user(format!(
+            "Invalid status {} for this filing",
+            filing.status
+        )));
+    }
+    let Some(stored_filing) = crate::database::get_filings(&store)?
+        .into_iter()
+        .find(|f| f.id == filing.id)
+    else {
+        return Err(DkaError::user("Filing not found"));
+    };
+    // Late interest runs until the tax is paid, so it is recomputed for the actual payment date
+    if filing.status == "paid"
+        && stored_filing.status != "paid"
+        && stored_filing.late_payment_date.is_some()
+    {
+        let Some(late_payment_date) = late_payment_date else {
+            return Err(DkaError::user(
+                "Enter the date the tax was paid to calculate the late interest",
+            ));
+        };
+        if Some(&late_payment_date) != stored_filing.late_payment_date.as_ref() {
+            regenerate_filing_content(&store, filing.id, Some(late_payment_date))?;
+        }
+    }
+    crate::database::update_filing(&store, &filing)
+}
+
+#[tauri::command]
+pub fn delete_filing(store: State<'_, AppStore>, filing_id: i32) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
+    crate::database::delete_filing(&store, filing_id)
+}
+
+#[tauri::command]
+pub fn confirm_filing_not_duplicate(store: State<'_, AppStore>, filing_id: i32) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
+    crate::database::confirm_filing_not_duplicate(&store, filing_id)
+}
+
+#[tauri::command]
+pub async fn create_capital_gains_filing(
+    store: State<'_, AppStore>,
+    taxpayer_profile_id: i32,
+    year: i32,
+    half: u32,
+) -> DkaResult<i32> {
+    ensure_app_migrated(&store)?;
+    let taxpayer_profile = crate::database::get_taxpayer_profile(&store, taxpayer_profile_id)?;
+    let (period_start, period_end) = get_half_year_bounds(year, half)?;
+    let period = format!("{}-H{}", year, half);
+    if crate::database::get_filings_by_taxpayer_profile(&store, taxpayer_profile_id)?
+        .iter()
+        .any(|f| f._type == "capital-gain" && f.period.as_ref() == Some(&period))
+    {
+        return Err(DkaError::user(
+            "A capital gains filing for this period already exists",
+        ));
+    }
+    let trade_infos = crate::database::get_trades(&store, taxpayer_profile_id)?;
+    let mut exchange_rate_provider = CachedExchangeRateProvider::new(
+        NbsExchangeRateProvider::new(),
+        crate::database::get_nbs_exchange_rates(&store)?,
+    );
+    let cgfi = get_capital_gains_filing_info(
+        &trade_infos,
+        period_start,
+        period_end,
+        &crate::database::get_report_exchange_rates(&store)?,
+        &mut exchange_rate_provider,
+    )
+    .await;
+    crate::database::save_nbs_exchange_rates(&store, &exchange_rate_provider.take_unsaved_rates())?;
+    let cgfi = cgfi?;
+    if cgfi.capital_gain_infos.is_empty() {
+        return Err(DkaError::user("No disposals found in this period"));
+    }
+    let technical_conf = crate::filesystem::get_technical_conf(&store)?;
+    let pdg_data = PdgData::new(&cgfi, &taxpayer_profile, &technical_conf.holiday_conf)?;
+    let filing_id = create_filing(
+        &store,
+        &Filing {
+            id: 0,
+            report_id: None,
+            _type: "capital-gain".into(),
+            status: "init".into(),
+            paying_entity: format!("Capital gains {} H{}", year, half),
+            filing_deadline: format_iso(&pdg_data.filing_deadline),
+            tax_payable: cgfi.tax_payable_rsdc,
+            tax_payment_reference: "".into(),
+            income_date: Some(format_iso(&period_end)),
+            gross_income: Some(max(cgfi.capital_gain_rsdc - cgfi.capital_loss_rsdc, 0)),
+            wht_paid: Some(0),
+            gross_tax_payable: Some(cgfi.tax_payable_rsdc),
+            income_currency_code: None,
+            income_currency_amount: None,
+            income_exchange_rate: None,
+            wht_currency_code: None,
+            wht_currency_amount: None,
+            wht_exchange_rate: None,
+            amends_filing_id: None,
+            tax_payable_delta: None,
+            late_payment_date: None,
+            late_interest: None,
+            wht_actual: Some(0),
+            wht_credit_rate: None,
+            taxpayer_profile_id,
+            period: Some(period),
+            suspected_duplicate_of_report_id: None,
+        },
+    )?;
+    save_filing_content(&store, filing_id, pdg_data.fill()?.as_bytes())?;
+    Ok(filing_id)
+}
+
+#[tauri::command]
+pub fn get_annual_summary(
+    store: State<'_, AppStore>,
+    taxpayer_profile_id: i32,
+    year: i32,
+) -> DkaResult<AnnualSummary> {
+    ensure_app_migrated(&store)?;
+    let filings = crate::database::get_filings_by_taxpayer_profile(&store, taxpayer_profile_id)?;
+    Ok(crate::annual_summary::get_annual_summary(year, &filings))
+}
+
+fn get_report_payment_notes(store: &AppStore, report_id: Option<i32>) -> DkaResult<String> {
+    let importer_id = crate::database::get_reports(store)?
+        .into_iter()
+        .find(|r| Some(r.id) == report_id)
+        .and_then(|r| r.importer_id);
+    Ok(crate::database::get_importers(store)?
+        .into_iter()
+        .find(|im| Some(im.id) == importer_id)
+        .map(|im| im.payment_notes)
+        .unwrap_or("".to_string()))
+}
+
+/// The incomes declared in a filing, as calculated when it was created
+fn get_filing_infos(store: &AppStore, filing: &Filing) -> DkaResult<Vec<FilingInfo>> {
+    let filing_infos = crate::database::get_filing_incomes(store, filing.id)?;
+    if filing_infos.is_empty() {
+        return Ok(vec![FilingInfo::from_filing(filing)?]);
+    }
+    Ok(filing_infos)
+}
+
+/// The reference under which the tax administration registered a submitted filing, which its
+/// amended returns point to
+fn get_amended_filing_reference(amended_filing: &Filing) -> DkaResult<String> {
+    if amended_filing.tax_payment_reference.trim().is_empty() {
+        return Err(DkaError::user(
+            "Enter the reference of the submitted filing before amending it",
+        ));
+    }
+    Ok(amended_filing.tax_payment_reference.clone())
 }
 
+/// Rewrites the filing XML from its stored calculation. Passing a late payment date switches the
+/// filing to late mode, otherwise any previously chosen late payment date is kept.
 #[tauri::command]
-pub fn update_filing(app_handle: AppHandle, filing: Filing) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::update_filing(&app_handle, &filing)
+pub fn regenerate_filing(
+    store: State<'_, AppStore>,
+    filing_id: i32,
+    late_payment_date: Option<String>,
+) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
+    regenerate_filing_content(&store, filing_id, late_payment_date)
+}
+
+fn regenerate_filing_content(
+    store: &AppStore,
+    filing_id: i32,
+    late_payment_date: Option<String>,
+) -> DkaResult<()> {
+    let filings = crate::database::get_filings(store)?;
+    let Some(filing) = filings.iter().find(|f| f.id == filing_id).cloned() else {
+        return Err(DkaError::user("Filing not found"));
+    };
+    let amended_filing_reference = match filing.amends_filing_id {
+        Some(amends_filing_id) => {
+            let Some(amended_filing) = filings.iter().find(|f| f.id == amends_filing_id) else {
+                return Err(DkaError::user("The amended filing was deleted"));
+            };
+            Some(get_amended_filing_reference(amended_filing)?)
+        }
+        None => None,
+    };
+    let filing_infos = get_filing_infos(store, &filing)?;
+    let payment_notes = get_report_payment_notes(store, filing.report_id)?;
+    let technical_conf = crate::filesystem::get_technical_conf(store)?;
+    let taxpayer_profile =
+        crate::database::get_taxpayer_profile(store, filing.taxpayer_profile_id)?;
+    let mut opo_data = OpoData::new_grouped(
+        &filing_infos,
+        &payment_notes,
+        &taxpayer_profile,
+        &technical_conf.holiday_conf,
+    )?;
+    opo_data.amended_filing_reference = amended_filing_reference;
+    let late_payment_date = late_payment_date.or(filing.late_payment_date);
+    if let Some(late_payment_date) = &late_payment_date {
+        opo_data.late_interest_info = Some(get_late_interest_info(
+            opo_data.get_tax_payable_rsdc(),
+            &opo_data.filing_deadline,
+            &parse_iso(late_payment_date)?,
+            &technical_conf.reference_rates,
+        )?);
+    }
+    let opo_filing_content = opo_data.fill()?;
+    crate::database::update_filing_late_interest(
+        store,
+        filing_id,
+        late_payment_date,
+        opo_data
+            .late_interest_info
+            .as_ref()
+            .map(|lii| lii.interest_rsdc),
+    )?;
+    replace_filing_content(store, filing_id, opo_filing_content.as_bytes())
 }
 
 #[tauri::command]
-pub fn delete_filing(app_handle: AppHandle, filing_id: i32) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
-    crate::database::delete_filing(&app_handle, filing_id)
+pub fn create_amended_filing(
+    store: State<'_, AppStore>,
+    filing_id: i32,
+    income_currency_amounts: Vec<Decimal>,
+    wht_currency_amounts: Vec<Decimal>,
+) -> DkaResult<i32> {
+    ensure_app_migrated(&store)?;
+    let filings = crate::database::get_filings(&store)?;
+    let Some(original_filing) = filings.iter().find(|f| f.id == filing_id) else {
+        return Err(DkaError::user("Filing not found"));
+    };
+    if original_filing.status == "init" || original_filing.status == "amendment" {
+        return Err(DkaError::user(
+            "Only a filing which has already been submitted can be amended",
+        ));
+    }
+    if filings
+        .iter()
+        .any(|f| f.amends_filing_id == Some(filing_id))
+    {
+        return Err(DkaError::user(
+            "This filing has already been amended, amend the latest amendment instead",
+        ));
+    }
+    let filing_infos = get_filing_infos(&store, original_filing)?;
+    if income_currency_amounts.len() != filing_infos.len()
+        || wht_currency_amounts.len() != filing_infos.len()
+    {
+        return Err(DkaError::user(
+            "Corrected amounts are needed for each income of the filing",
+        ));
+    }
+    let filing_infos: Vec<FilingInfo> = filing_infos
+        .iter()
+        .zip(
+            income_currency_amounts
+                .into_iter()
+                .zip(wht_currency_amounts),
+        )
+        .map(|(fi, (income_currency_amount, wht_currency_amount))| {
+            fi.with_corrected_amounts(income_currency_amount, wht_currency_amount)
+        })
+        .collect();
+    let amended_filing_reference = get_amended_filing_reference(original_filing)?;
+    let payment_notes = get_report_payment_notes(&store, original_filing.report_id)?;
+    let technical_conf = crate::filesystem::get_technical_conf(&store)?;
+    let taxpayer_profile =
+        crate::database::get_taxpayer_profile(&store, original_filing.taxpayer_profile_id)?;
+    let mut opo_data = OpoData::new_grouped(
+        &filing_infos,
+        &payment_notes,
+        &taxpayer_profile,
+        &technical_conf.holiday_conf,
+    )?;
+    opo_data.amended_filing_reference = Some(amended_filing_reference);
+    let opo_filing_content = opo_data.fill()?;
+    let amended_filing = Filing {
+        status: "amendment".into(),
+        amends_filing_id: Some(filing_id),
+        tax_payable_delta: Some(opo_data.get_tax_payable_rsdc() - original_filing.tax_payable),
+        ..get_opo_filing(
+            original_filing.report_id,
+            original_filing.taxpayer_profile_id,
+            &opo_data,
+        )
+    };
+    create_opo_filing(&store, &amended_filing, &opo_data, &opo_filing_content)
 }
 
 #[tauri::command]
-pub async fn export_filing(app_handle: AppHandle, filing_id: i32) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
+pub fn get_filing_incomes(
+    store: State<'_, AppStore>,
+    filing_id: i32,
+) -> DkaResult<Vec<FilingIncome>> {
+    ensure_app_migrated(&store)?;
+    let Some(filing) = crate::database::get_filings(&store)?
+        .into_iter()
+        .find(|f| f.id == filing_id)
+    else {
+        return Err(DkaError::user("Filing not found"));
+    };
+    Ok(get_filing_infos(&store, &filing)?
+        .into_iter()
+        .map(|fi| FilingIncome {
+            paying_entity: fi.paying_entity,
+            income_currency_code: fi.income_currency_code,
+            income_currency_amount: fi.income_currency_amount,
+            wht_currency_code: fi.wht_currency_code,
+            wht_currency_amount: fi.wht_currency_amount,
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn export_filing(
+    app_handle: AppHandle,
+    store: State<'_, AppStore>,
+    filing_id: i32,
+) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
     let save_path = app_handle
         .dialog()
         .file()
@@ -209,7 +665,7 @@ pub async fn export_filing(app_handle: AppHandle, filing_id: i32) -> DkaResult<(
     let FilePath::Path(save_path) = save_path else {
         return Err(DkaError::user("Only file paths are supported"));
     };
-    let filing_content = get_filing_content(&app_handle, filing_id)?;
+    let filing_content = get_filing_content(&store, filing_id)?;
     let mut file = File::create_new(save_path).map_err(DkaError::Io)?;
     file.write_all(&filing_content).map_err(DkaError::Io)?;
     file.flush().map_err(DkaError::Io)?;
@@ -217,42 +673,112 @@ pub async fn export_filing(app_handle: AppHandle, filing_id: i32) -> DkaResult<(
 }
 
 #[tauri::command]
-pub async fn get_job(app_handle: AppHandle, job_id: i32) -> DkaResult<Option<Job>> {
-    ensure_app_migrated(&app_handle)?;
+pub async fn get_job(store: State<'_, AppStore>, job_id: i32) -> DkaResult<Option<Job>> {
+    ensure_app_migrated(&store)?;
     let job_store = JOB_STORE.lock().await;
     Ok(job_store.get(job_id))
 }
 
+async fn complete_job(job_id: i32, result: DkaResult<()>) {
+    let mut job_store_guard = JOB_STORE.lock().await;
+    if let Err(e) = result {
+        job_store_guard.add_message(
+            job_id,
+            JobMessage::Error {
+                message: e.to_string(),
+            },
+        );
+    }
+    job_store_guard.set_completed(job_id);
+}
+
 #[tauri::command]
-pub async fn create_job(app_handle: AppHandle) -> DkaResult<i32> {
-    ensure_app_migrated(&app_handle)?;
+pub async fn create_job(app_handle: AppHandle, store: State<'_, AppStore>) -> DkaResult<i32> {
+    ensure_app_migrated(&store)?;
     let mut job_store_guard = JOB_STORE.lock().await;
     let job_id = job_store_guard.create();
     // Fire and forget the job
     tokio::spawn(async move {
-        match run_job(job_id, &JOB_STORE, &app_handle).await {
-            Err(e) => {
-                let mut job_store_guard = JOB_STORE.lock().await;
-                job_store_guard.add_message(
-                    job_id,
-                    JobMessage::Error {
-                        message: e.to_string(),
-                    },
-                );
-                job_store_guard.set_completed(job_id);
-            }
-            Ok(_) => {
-                let mut job_store_guard = JOB_STORE.lock().await;
-                job_store_guard.set_completed(job_id);
-            }
-        }
+        let store = app_handle.state::<AppStore>();
+        let _job_run_guard = JOB_RUN_LOCK.lock().await;
+        let result = run_job(job_id, &JOB_STORE, &store).await;
+        complete_job(job_id, result).await;
     });
     Ok(job_id)
 }
 
+/// Starts a job importing reports from a local mail archive. A Maildir or a directory of .eml
+/// files is picked as a folder, an .eml or mbox file as a file.
+#[tauri::command]
+pub async fn create_mail_archive_job(
+    app_handle: AppHandle,
+    store: State<'_, AppStore>,
+    pick_folder: bool,
+) -> DkaResult<i32> {
+    ensure_app_migrated(&store)?;
+    let archive_path = if pick_folder {
+        app_handle.dialog().file().blocking_pick_folder()
+    } else {
+        app_handle.dialog().file().blocking_pick_file()
+    };
+    let Some(archive_path) = archive_path else {
+        return Err(DkaError::user("No mail archive chosen"));
+    };
+    let FilePath::Path(archive_path) = archive_path else {
+        return Err(DkaError::user("Only file paths are supported"));
+    };
+    let mut job_store_guard = JOB_STORE.lock().await;
+    let job_id = job_store_guard.create();
+    // Fire and forget the job
+    tokio::spawn(async move {
+        let store = app_handle.state::<AppStore>();
+        let _job_run_guard = JOB_RUN_LOCK.lock().await;
+        let result = import_mail_archive(job_id, &JOB_STORE, &store, &archive_path).await;
+        complete_job(job_id, result).await;
+    });
+    Ok(job_id)
+}
+
+/// Polls the drop folder for as long as the app runs, starting a job whenever new report files
+/// show up in it
+pub async fn watch_drop_folder(app_handle: AppHandle) {
+    let mut drop_folder_cache = DropFolderCache::default();
+    loop {
+        tokio::time::sleep(DROP_FOLDER_POLL_INTERVAL).await;
+        let store = app_handle.state::<AppStore>();
+        if ensure_app_migrated(&store).is_err() {
+            continue;
+        }
+        let Ok(TechnicalConf {
+            drop_folder: Some(drop_folder),
+            ..
+        }) = crate::filesystem::get_technical_conf(&store)
+        else {
+            continue;
+        };
+        let drop_folder = Path::new(&drop_folder);
+        let _job_run_guard = JOB_RUN_LOCK.lock().await;
+        // A missing folder or a bad regex is reported by the next sync
+        let new_dropped_files =
+            match get_new_dropped_files(&store, drop_folder, &mut drop_folder_cache) {
+                Ok(new_dropped_files) if !new_dropped_files.is_empty() => new_dropped_files,
+                _ => continue,
+            };
+        let job_id = JOB_STORE.lock().await.create();
+        let result =
+            match import_dropped_files(job_id, &JOB_STORE, &store, drop_folder, &new_dropped_files)
+                .await
+            {
+                Ok(()) => process_reports(job_id, &JOB_STORE, &store).await,
+                Err(e) => Err(e),
+            };
+        complete_job(job_id, result).await;
+    }
+}
+
 #[tauri::command]
-pub async fn cancel_job(app_handle: AppHandle, job_id: i32) -> DkaResult<()> {
-    ensure_app_migrated(&app_handle)?;
+pub async fn cancel_job(store: State<'_, AppStore>, job_id: i32) -> DkaResult<()> {
+    ensure_app_migrated(&store)?;
     let mut job_store = JOB_STORE.lock().await;
     job_store.cancel(job_id);
     Ok(())
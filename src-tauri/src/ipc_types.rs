@@ -1,4 +1,7 @@
+use crate::date::format_iso;
 use crate::error::{DkaError, DkaResult};
+use chrono::DateTime;
+use rust_decimal::Decimal;
 use serde::{self, Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,10 +13,41 @@ pub struct HolidayConf {
     pub holidays: Vec<String>,
 }
 
+/// NBS reference (key policy) rate in percent, in effect from the given date until the next entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReferenceRate {
+    #[serde(rename = "validFrom")]
+    pub valid_from: String,
+    pub rate: Decimal,
+}
+
+/// Withholding tax rate in percent on dividends, as limited by the double tax treaty with the
+/// source country. Foreign tax above this rate is not credited against Serbian tax.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WhtTreatyRate {
+    #[serde(rename = "countryCode")]
+    pub country_code: String,
+    pub rate: Decimal,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TechnicalConf {
     #[serde(rename = "holidayConf")]
     pub holiday_conf: HolidayConf,
+    // Missing from technical conf files written by older versions
+    #[serde(
+        rename = "referenceRates",
+        default = "crate::filesystem::get_default_reference_rates"
+    )]
+    pub reference_rates: Vec<ReferenceRate>,
+    #[serde(
+        rename = "whtTreatyRates",
+        default = "crate::filesystem::get_default_wht_treaty_rates"
+    )]
+    pub wht_treaty_rates: Vec<WhtTreatyRate>,
+    /// Folder watched for report files, e.g. the browser's download folder
+    #[serde(rename = "dropFolder", default)]
+    pub drop_folder: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -24,6 +58,7 @@ pub enum MailboxCursor {
         #[serde(rename = "dateString")]
         date_string: String,
     },
+    // The UID is that of the folder synced last, each folder's own is in its MailboxFolderCursor
     #[serde(rename = "uid")]
     Uid {
         #[serde(rename = "lastSeenUid")]
@@ -66,6 +101,44 @@ impl MailboxCursor {
             _ => Err(DkaError::Generic("invalid mailbox cursor format".into())),
         }
     }
+
+    /// Where the search of a folder starts. The folder's own UID cursor applies for as long as its
+    /// UIDVALIDITY is unchanged, otherwise the folder is searched from the day of the last sync.
+    pub fn for_folder(
+        &self,
+        folder_cursor: Option<&MailboxFolderCursor>,
+        uid_validity: u32,
+    ) -> MailboxCursor {
+        let last_seen_datetime = match self {
+            MailboxCursor::Date { .. } => None,
+            MailboxCursor::Uid {
+                last_seen_datetime, ..
+            } => Some(*last_seen_datetime),
+        };
+        match (folder_cursor, last_seen_datetime) {
+            (Some(folder_cursor), _) if folder_cursor.uid_validity == uid_validity => {
+                MailboxCursor::Uid {
+                    last_seen_uid: folder_cursor.last_seen_uid as i64,
+                    last_seen_datetime: last_seen_datetime.unwrap_or(0),
+                }
+            }
+            (_, Some(last_seen_datetime)) => MailboxCursor::Date {
+                date_string: DateTime::from_timestamp_millis(last_seen_datetime)
+                    .map(|dt| format_iso(&dt.date_naive()))
+                    .unwrap_or("1970-01-01".into()),
+            },
+            (_, None) => self.clone(),
+        }
+    }
+}
+
+/// How far the sync of one IMAP folder has got. UIDs are only comparable within a folder, and
+/// only for as long as the folder's UIDVALIDITY stays the same.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MailboxFolderCursor {
+    pub folder: String,
+    pub uid_validity: u32,
+    pub last_seen_uid: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -82,6 +155,117 @@ pub struct Mailbox {
     pub imap_port: i32,
     #[serde(rename = "cursor")]
     pub cursor: MailboxCursor,
+    // Gmail labels are folders too
+    #[serde(rename = "folders")]
+    pub folders: Vec<String>,
+    // "password" or "oauth2"
+    #[serde(rename = "authMethod")]
+    pub auth_method: String,
+    #[serde(rename = "oauthConf")]
+    pub oauth_conf: Option<MailboxOAuthConf>,
+}
+
+impl Mailbox {
+    /// The mailbox as sent to the frontend, without the password and OAuth secrets
+    #[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+    pub fn redacted(&self) -> Mailbox {
+        Mailbox {
+            email_password: "".into(),
+            oauth_conf: self.oauth_conf.as_ref().map(|c| MailboxOAuthConf {
+                client_secret: "".into(),
+                refresh_token: "".into(),
+                access_token: "".into(),
+                access_token_expiry: 0,
+                ..c.clone()
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// Takes the secrets entered in the frontend, keeping the stored ones that were left alone
+    #[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+    pub fn with_stored_secrets(mut self, stored: &Mailbox, secrets: &MailboxSecrets) -> Mailbox {
+        self.email_password = secrets
+            .email_password
+            .clone()
+            .unwrap_or_else(|| stored.email_password.clone());
+        if let Some(oauth_conf) = self.oauth_conf.as_mut() {
+            let stored_oauth_conf = stored.oauth_conf.as_ref();
+            oauth_conf.client_secret = secrets
+                .client_secret
+                .clone()
+                .or_else(|| stored_oauth_conf.map(|c| c.client_secret.clone()))
+                .unwrap_or_default();
+            match (&secrets.refresh_token, stored_oauth_conf) {
+                (Some(refresh_token), _) => {
+                    // The stored access token belongs to the old refresh token
+                    oauth_conf.refresh_token = refresh_token.clone();
+                    oauth_conf.access_token = "".into();
+                    oauth_conf.access_token_expiry = 0;
+                }
+                (None, Some(stored_oauth_conf)) => {
+                    oauth_conf.refresh_token = stored_oauth_conf.refresh_token.clone();
+                    oauth_conf.access_token = stored_oauth_conf.access_token.clone();
+                    oauth_conf.access_token_expiry = stored_oauth_conf.access_token_expiry;
+                }
+                (None, None) => {}
+            }
+        }
+        self
+    }
+}
+
+/// Secrets entered in the mailbox form. None keeps the stored secret, and an empty string clears
+/// it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub struct MailboxSecrets {
+    #[serde(rename = "emailPassword")]
+    pub email_password: Option<String>,
+    #[serde(rename = "clientSecret")]
+    pub client_secret: Option<String>,
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: Option<String>,
+}
+
+/// Whether the secrets stored in the database are protected by a passphrase, and whether it still
+/// has to be entered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub struct SecretsStatus {
+    #[serde(rename = "usesPassphrase")]
+    pub uses_passphrase: bool,
+    #[serde(rename = "locked")]
+    pub locked: bool,
+}
+
+/// OAuth2 client and tokens for XOAUTH2 login. The access token is refreshed through the refresh
+/// token grant shortly before it expires.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MailboxOAuthConf {
+    #[serde(rename = "tokenUrl")]
+    pub token_url: String,
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    #[serde(rename = "clientSecret")]
+    pub client_secret: String,
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
+    #[serde(rename = "accessToken", default)]
+    pub access_token: String,
+    // Seconds since the Unix epoch
+    #[serde(rename = "accessTokenExpiry", default)]
+    pub access_token_expiry: i64,
+}
+
+impl MailboxOAuthConf {
+    pub fn to_db_string(&self) -> String {
+        serde_json::to_string(self).expect("mailbox OAuth conf should serialize to JSON")
+    }
+    pub fn from_db_string(s: &str) -> DkaResult<Self> {
+        serde_json::from_str(s)
+            .map_err(|e| DkaError::Generic(format!("invalid mailbox OAuth conf format: {}", e)))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -102,6 +286,53 @@ pub struct TaxpayerProfile {
     pub email_address: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenericCsvRowFilter {
+    #[serde(rename = "column")]
+    pub column: String,
+    #[serde(rename = "regex")]
+    pub regex: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenericCsvConf {
+    #[serde(rename = "delimiter")]
+    pub delimiter: String,
+    #[serde(rename = "dateColumn")]
+    pub date_column: String,
+    #[serde(rename = "dateFormat")]
+    pub date_format: String,
+    #[serde(rename = "typeColumn")]
+    pub type_column: String,
+    #[serde(rename = "dividendTypeRegex")]
+    pub dividend_type_regex: String,
+    #[serde(rename = "interestTypeRegex")]
+    pub interest_type_regex: String,
+    #[serde(rename = "payerColumn")]
+    pub payer_column: String,
+    #[serde(rename = "currencyColumn")]
+    pub currency_column: String,
+    #[serde(rename = "amountColumn")]
+    pub amount_column: String,
+    #[serde(rename = "whtColumn")]
+    pub wht_column: String,
+    #[serde(rename = "decimalSeparator")]
+    pub decimal_separator: String,
+    #[serde(rename = "rowFilters")]
+    pub row_filters: Vec<GenericCsvRowFilter>,
+}
+
+impl GenericCsvConf {
+    #[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+    pub fn to_db_string(&self) -> String {
+        serde_json::to_string(self).expect("generic CSV conf should serialize to JSON")
+    }
+    pub fn from_db_string(s: &str) -> DkaResult<Self> {
+        serde_json::from_str(s)
+            .map_err(|e| DkaError::Generic(format!("invalid generic CSV conf format: {}", e)))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Importer {
     #[serde(rename = "id")]
@@ -122,6 +353,10 @@ pub struct Importer {
     pub payment_notes: String,
     #[serde(rename = "attachmentRegex")]
     pub attachment_regex: String,
+    #[serde(rename = "genericCsvConf")]
+    pub generic_csv_conf: Option<GenericCsvConf>,
+    #[serde(rename = "groupFilingsByDate")]
+    pub group_filings_by_date: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -133,21 +368,25 @@ pub struct Report {
     #[serde(rename = "importerId")]
     pub importer_id: Option<i32>,
     #[serde(rename = "mailboxId")]
-    pub mailbox_id: i32,
+    pub mailbox_id: Option<i32>,
     #[serde(rename = "mailboxMessageId")]
-    pub mailbox_message_id: i32,
+    pub mailbox_message_id: Option<i32>,
     #[serde(rename = "reportName")]
     pub report_name: String,
     #[serde(rename = "status")]
     pub status: String,
+    // Taken from the importer when the report is imported
+    #[serde(rename = "taxpayerProfileId")]
+    pub taxpayer_profile_id: i32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Filing {
     #[serde(rename = "id")]
     pub id: i32,
+    // Capital gains filings span many reports and have none
     #[serde(rename = "reportId")]
-    pub report_id: i32,
+    pub report_id: Option<i32>,
     #[serde(rename = "type")]
     pub _type: String,
     #[serde(rename = "status")]
@@ -160,6 +399,102 @@ pub struct Filing {
     pub tax_payable: i64,
     #[serde(rename = "taxPaymentReference")]
     pub tax_payment_reference: String,
+    // The calculation breakdown is missing for filings created by older versions
+    #[serde(rename = "incomeDate")]
+    pub income_date: Option<String>,
+    #[serde(rename = "grossIncome")]
+    pub gross_income: Option<i64>,
+    #[serde(rename = "whtPaid")]
+    pub wht_paid: Option<i64>,
+    #[serde(rename = "grossTaxPayable")]
+    pub gross_tax_payable: Option<i64>,
+    #[serde(rename = "incomeCurrencyCode")]
+    pub income_currency_code: Option<String>,
+    #[serde(rename = "incomeCurrencyAmount")]
+    pub income_currency_amount: Option<Decimal>,
+    #[serde(rename = "incomeExchangeRate")]
+    pub income_exchange_rate: Option<Decimal>,
+    #[serde(rename = "whtCurrencyCode")]
+    pub wht_currency_code: Option<String>,
+    #[serde(rename = "whtCurrencyAmount")]
+    pub wht_currency_amount: Option<Decimal>,
+    #[serde(rename = "whtExchangeRate")]
+    pub wht_exchange_rate: Option<Decimal>,
+    // Set on amended filings (VrstaPrijave 2) only
+    #[serde(rename = "amendsFilingId")]
+    pub amends_filing_id: Option<i32>,
+    #[serde(rename = "taxPayableDelta")]
+    pub tax_payable_delta: Option<i64>,
+    // Set on filings submitted after their deadline
+    #[serde(rename = "latePaymentDate")]
+    pub late_payment_date: Option<String>,
+    #[serde(rename = "lateInterest")]
+    pub late_interest: Option<i64>,
+    // Foreign tax actually withheld, of which only up to the treaty rate is credited in wht_paid
+    #[serde(rename = "whtActual")]
+    pub wht_actual: Option<i64>,
+    #[serde(rename = "whtCreditRate")]
+    pub wht_credit_rate: Option<Decimal>,
+    #[serde(rename = "taxpayerProfileId")]
+    pub taxpayer_profile_id: i32,
+    // Half year of a capital gains filing, e.g. 2024-H1
+    #[serde(rename = "period")]
+    pub period: Option<String>,
+    // Set when another importer of the taxpayer profile already filed one of the incomes, until
+    // the user confirms the filing
+    #[serde(rename = "suspectedDuplicateOfReportId")]
+    pub suspected_duplicate_of_report_id: Option<i32>,
+}
+
+/// One income declared in a filing, with the amounts an amendment can correct
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub struct FilingIncome {
+    #[serde(rename = "payingEntity")]
+    pub paying_entity: String,
+    #[serde(rename = "incomeCurrencyCode")]
+    pub income_currency_code: String,
+    #[serde(rename = "incomeCurrencyAmount")]
+    pub income_currency_amount: Decimal,
+    #[serde(rename = "whtCurrencyCode")]
+    pub wht_currency_code: String,
+    #[serde(rename = "whtCurrencyAmount")]
+    pub wht_currency_amount: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub struct AnnualSummaryLine {
+    #[serde(rename = "type")]
+    pub _type: String,
+    #[serde(rename = "filingCount")]
+    pub filing_count: i32,
+    #[serde(rename = "grossIncome")]
+    pub gross_income: i64,
+    #[serde(rename = "whtPaid")]
+    pub wht_paid: i64,
+    // Foreign tax withheld above the treaty rate, to be reclaimed abroad
+    #[serde(rename = "whtReclaimable")]
+    pub wht_reclaimable: i64,
+    #[serde(rename = "taxPayable")]
+    pub tax_payable: i64,
+    #[serde(rename = "taxPaid")]
+    pub tax_paid: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+pub struct AnnualSummary {
+    #[serde(rename = "year")]
+    pub year: i32,
+    #[serde(rename = "lines")]
+    pub lines: Vec<AnnualSummaryLine>,
+    #[serde(rename = "total")]
+    pub total: AnnualSummaryLine,
+    #[serde(rename = "incompleteFilingCount")]
+    pub incomplete_filing_count: i32,
+    #[serde(rename = "suspectedDuplicateFilingCount")]
+    pub suspected_duplicate_filing_count: i32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -184,6 +519,11 @@ pub enum JobMessage {
         #[serde(rename = "message")]
         message: String,
     },
+    #[serde(rename = "warning")]
+    Warning {
+        #[serde(rename = "message")]
+        message: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -202,6 +542,79 @@ pub struct Job {
 mod tests {
     use super::*;
 
+    fn mailbox() -> Mailbox {
+        Mailbox {
+            id: 1,
+            email_address: "someone@gmail.com".into(),
+            email_password: "hunter2".into(),
+            imap_host: "imap.gmail.com".into(),
+            imap_port: 993,
+            cursor: MailboxCursor::Date {
+                date_string: "2025-10-14".into(),
+            },
+            folders: vec!["INBOX".into()],
+            auth_method: "oauth2".into(),
+            oauth_conf: Some(MailboxOAuthConf {
+                token_url: "https://oauth2.googleapis.com/token".into(),
+                client_id: "client-id".into(),
+                client_secret: "client-secret".into(),
+                refresh_token: "refresh-token".into(),
+                access_token: "access-token".into(),
+                access_token_expiry: 1000,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_mailbox_redacted() {
+        let redacted = mailbox().redacted();
+        assert_eq!(redacted.email_password, "");
+        let oauth_conf = redacted.oauth_conf.unwrap();
+        assert_eq!(oauth_conf.client_id, "client-id");
+        assert_eq!(oauth_conf.client_secret, "");
+        assert_eq!(oauth_conf.refresh_token, "");
+        assert_eq!(oauth_conf.access_token, "");
+    }
+
+    #[test]
+    fn test_mailbox_with_stored_secrets() {
+        let stored = mailbox();
+        let updated = stored
+            .redacted()
+            .with_stored_secrets(&stored, &MailboxSecrets::default());
+        assert_eq!(updated.email_password, "hunter2");
+        assert_eq!(updated.oauth_conf, stored.oauth_conf);
+
+        let updated = stored.redacted().with_stored_secrets(
+            &stored,
+            &MailboxSecrets {
+                email_password: Some("hunter3".into()),
+                client_secret: None,
+                refresh_token: Some("new-refresh-token".into()),
+            },
+        );
+        assert_eq!(updated.email_password, "hunter3");
+        let oauth_conf = updated.oauth_conf.unwrap();
+        assert_eq!(oauth_conf.client_secret, "client-secret");
+        assert_eq!(oauth_conf.refresh_token, "new-refresh-token");
+        // The stored access token belongs to the old refresh token
+        assert_eq!(oauth_conf.access_token, "");
+
+        // Secrets can be cleared
+        let updated = stored.redacted().with_stored_secrets(
+            &stored,
+            &MailboxSecrets {
+                email_password: Some("".into()),
+                client_secret: Some("".into()),
+                refresh_token: None,
+            },
+        );
+        assert_eq!(updated.email_password, "");
+        let oauth_conf = updated.oauth_conf.unwrap();
+        assert_eq!(oauth_conf.client_secret, "");
+        assert_eq!(oauth_conf.refresh_token, "refresh-token");
+    }
+
     #[test]
     fn test_mailbox_cursor_date_to_db_string() {
         let cursor = MailboxCursor::Date {
@@ -242,6 +655,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_mailbox_cursor_for_folder() {
+        let folder_cursor = MailboxFolderCursor {
+            folder: "Statements".into(),
+            uid_validity: 7,
+            last_seen_uid: 42,
+        };
+        let date_cursor = MailboxCursor::Date {
+            date_string: "2025-10-14".into(),
+        };
+        let uid_cursor = MailboxCursor::Uid {
+            last_seen_uid: 1000,
+            last_seen_datetime: 1760486400000, // 2025-10-15
+        };
+        assert_eq!(
+            uid_cursor.for_folder(Some(&folder_cursor), 7),
+            MailboxCursor::Uid {
+                last_seen_uid: 42,
+                last_seen_datetime: 1760486400000
+            }
+        );
+        // A new folder, or one whose UIDs were reset
+        for folder_cursor in [None, Some(&folder_cursor)] {
+            assert_eq!(
+                uid_cursor.for_folder(folder_cursor, 8),
+                MailboxCursor::Date {
+                    date_string: "2025-10-15".into()
+                }
+            );
+            assert_eq!(date_cursor.for_folder(folder_cursor, 8), date_cursor);
+        }
+    }
+
+    #[test]
+    fn test_generic_csv_conf_db_string_roundtrip() {
+        let conf = GenericCsvConf {
+            delimiter: ";".into(),
+            date_column: "Datum".into(),
+            date_format: "%d.%m.%Y".into(),
+            type_column: "Vrsta".into(),
+            dividend_type_regex: "^Dividenda$".into(),
+            interest_type_regex: "^Kamata$".into(),
+            payer_column: "Isplatilac".into(),
+            currency_column: "Valuta".into(),
+            amount_column: "Iznos".into(),
+            wht_column: "Porez".into(),
+            decimal_separator: ",".into(),
+            row_filters: vec![GenericCsvRowFilter {
+                column: "Status".into(),
+                regex: "^Izvrseno$".into(),
+            }],
+        };
+        assert_eq!(
+            GenericCsvConf::from_db_string(&conf.to_db_string()).unwrap(),
+            conf
+        );
+        assert!(GenericCsvConf::from_db_string("not json").is_err());
+    }
+
     #[test]
     fn test_mailbox_cursor_invalid_from_db_string() {
         let s = "bad,format,here,extra";
@@ -1,34 +1,143 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::net::TcpStream;
+use std::path::Path;
 
+use chrono::Local;
 use mail_parser::{MessageParser, MimeHeaders};
 use regex::Regex;
 use rustls_connector::RustlsConnector;
 use std::time::{SystemTime, UNIX_EPOCH};
-use tauri::AppHandle;
 use tokio::sync::Mutex;
 
+use crate::app_store::AppStore;
 use crate::database::{
-    create_filing, create_report, get_reports_by_mailbox, get_taxpayer_profile, update_mailbox,
-    update_report,
+    create_filing, create_filing_incomes, create_income_hashes, create_report,
+    create_report_exchange_rates, create_trades, get_filed_incomes, get_mailbox_folder_cursors,
+    get_nbs_exchange_rates, get_report_content_hashes, get_reports, get_taxpayer_profiles,
+    save_nbs_exchange_rates, update_mailbox, update_mailbox_folder_cursor, update_report,
+    update_report_content_hash,
 };
 use crate::date::format_iso;
+use crate::drop_folder::{list_drop_folder, DropFolderCache};
 use crate::error::DkaError;
+use crate::exchange_rate::{CachedExchangeRateProvider, NbsExchangeRateProvider};
 use crate::filesystem::{
-    get_report_path, get_technical_conf, save_filing_content, save_report_content,
+    get_content_hash, get_report_path, get_technical_conf, save_filing_content, save_report_content,
 };
-use crate::ibkr_report_parser::IbkrReportParser;
-use crate::ipc_types::{Filing, Importer, Mailbox, Report};
+use crate::income_tax::{group_filing_infos_by_date, FilingInfo};
+use crate::ipc_types::{
+    Filing, Importer, Mailbox, MailboxFolderCursor, Report, TaxpayerProfile, TechnicalConf,
+};
+use crate::late_interest::get_late_interest_info;
+use crate::mail_archive::{message_matches_importer, read_mail_archive};
+use crate::oauth::{refresh_access_token, XOAuth2};
 use crate::opo_data::OpoData;
-use crate::report_parser::ReportParser;
-use crate::trivial_report_parser::TrivialReportParser;
+use crate::report_parser::get_report_parser;
 use crate::{
-    database::{get_importers, get_mailbox},
+    database::{get_importers, get_mailboxes},
     error::DkaResult,
     ipc_types::{JobMessage, MailboxCursor},
     job_store::JobStore,
 };
 
+/// The PP-OPO for one or more incomes. Income found after its deadline can only be filed late, so
+/// it gets interest up to today. That interest is provisional, it is recomputed for the actual
+/// payment date when the filing is marked paid. Returns the data with its XML, or the reason the
+/// filing does not pass validation.
+fn fill_opo_data(
+    filing_infos: &[FilingInfo],
+    payment_notes: &str,
+    taxpayer_profile: &TaxpayerProfile,
+    technical_conf: &TechnicalConf,
+) -> DkaResult<(OpoData, String)> {
+    let mut opo_data = OpoData::new_grouped(
+        filing_infos,
+        payment_notes,
+        taxpayer_profile,
+        &technical_conf.holiday_conf,
+    )?;
+    let today = Local::now().date_naive();
+    if opo_data.filing_deadline < today && opo_data.get_tax_payable_rsdc() > 0 {
+        opo_data.late_interest_info = Some(get_late_interest_info(
+            opo_data.get_tax_payable_rsdc(),
+            &opo_data.filing_deadline,
+            &today,
+            &technical_conf.reference_rates,
+        )?);
+    }
+    let opo_filing_content = opo_data.fill()?;
+    Ok((opo_data, opo_filing_content))
+}
+
+/// A filing for one or more incomes. The currency breakdown is only kept on single-income filings,
+/// grouped ones keep it per income in create_opo_filing.
+pub fn get_opo_filing(
+    report_id: Option<i32>,
+    taxpayer_profile_id: i32,
+    opo_data: &OpoData,
+) -> Filing {
+    let single_filing_info = match opo_data.filing_infos.as_slice() {
+        [fi] => Some(fi),
+        _ => None,
+    };
+    let paying_entities: Vec<&str> = opo_data
+        .filing_infos
+        .iter()
+        .map(|fi| fi.paying_entity.as_str())
+        .collect();
+    Filing {
+        id: 0,
+        report_id,
+        _type: opo_data.filing_infos[0]._type.clone(),
+        status: "init".to_string(),
+        paying_entity: paying_entities.join(", "),
+        filing_deadline: format_iso(&opo_data.filing_deadline),
+        tax_payable: opo_data.get_tax_payable_rsdc(),
+        tax_payment_reference: "".to_string(),
+        income_date: Some(format_iso(&opo_data.get_income_date())),
+        gross_income: Some(opo_data.get_gross_income_rsdc()),
+        wht_paid: Some(opo_data.get_wht_paid_rsdc()),
+        gross_tax_payable: Some(opo_data.get_gross_tax_payable_rsdc()),
+        income_currency_code: single_filing_info.map(|fi| fi.income_currency_code.clone()),
+        income_currency_amount: single_filing_info.map(|fi| fi.income_currency_amount),
+        income_exchange_rate: single_filing_info.map(|fi| fi.income_exchange_rate),
+        wht_currency_code: single_filing_info.map(|fi| fi.wht_currency_code.clone()),
+        wht_currency_amount: single_filing_info.map(|fi| fi.wht_currency_amount),
+        wht_exchange_rate: single_filing_info.map(|fi| fi.wht_exchange_rate),
+        amends_filing_id: None,
+        tax_payable_delta: None,
+        late_payment_date: opo_data
+            .late_interest_info
+            .as_ref()
+            .map(|lii| format_iso(&lii.payment_date)),
+        late_interest: opo_data
+            .late_interest_info
+            .as_ref()
+            .map(|lii| lii.interest_rsdc),
+        wht_actual: Some(opo_data.get_wht_actual_rsdc()),
+        wht_credit_rate: single_filing_info.and_then(|fi| fi.wht_credit_rate),
+        taxpayer_profile_id,
+        period: None,
+        suspected_duplicate_of_report_id: None,
+    }
+}
+
+/// Saves a PP-OPO filing with its XML. The incomes of a grouped filing are kept one by one, so
+/// that it can be regenerated and amended like a filing of a single income.
+pub fn create_opo_filing(
+    store: &AppStore,
+    filing: &Filing,
+    opo_data: &OpoData,
+    opo_filing_content: &str,
+) -> DkaResult<i32> {
+    let filing_id = create_filing(store, filing)?;
+    if opo_data.filing_infos.len() > 1 {
+        create_filing_incomes(store, filing_id, &opo_data.filing_infos)?;
+    }
+    save_filing_content(store, filing_id, opo_filing_content.as_bytes())?;
+    Ok(filing_id)
+}
+
 fn format_date_string_for_imap(date_string: &str) -> DkaResult<String> {
     let fragments: Vec<_> = date_string.split("-").collect();
     if fragments.len() != 3 {
@@ -73,52 +182,155 @@ fn get_search_string(
     Ok(fragments.join(" "))
 }
 
-async fn process_email_message(
-    u: u32,
-    message_importer_ids: &[i32],
-    mailbox: &Mailbox,
-    importers: &[Importer],
-    job_id: i32,
-    job_store_mutex: &Mutex<JobStore>,
-    imap_session: &mut imap::Session<
-        rustls::StreamOwned<rustls_connector::rustls::ClientConnection, TcpStream>,
-    >,
-    app_handle: &AppHandle,
-) -> DkaResult<()> {
+/// Marks the job as completed if it has been cancelled, in which case the caller should stop
+async fn check_cancelled(job_id: i32, job_store_mutex: &Mutex<JobStore>) -> bool {
     let mut job_store_guard = job_store_mutex.lock().await;
-    if job_store_guard.is_cancelled(job_id) {
-        job_store_guard.add_message(
-            job_id,
-            JobMessage::Error {
-                message: "cancelled".into(),
-            },
-        );
-        job_store_guard.set_completed(job_id);
-        return Ok(());
+    if !job_store_guard.is_cancelled(job_id) {
+        return false;
     }
-    drop(job_store_guard);
-    let messages = imap_session.uid_fetch(u.to_string(), "RFC822")?;
-    let Some(message) = messages.iter().find(|m| m.uid == Some(u)) else {
-        return Ok(());
-    };
-    let Some(message_body) = message.body() else {
-        return Ok(());
-    };
+    job_store_guard.add_message(
+        job_id,
+        JobMessage::Error {
+            message: "cancelled".into(),
+        },
+    );
+    job_store_guard.set_completed(job_id);
+    true
+}
 
-    let Some(parsed_message) = MessageParser::default().parse(message_body) else {
-        return Ok(());
-    };
+/// The reports imported so far. An importer cannot have two reports with the same name, nor two
+/// with the same content, e.g. from a statement that was forwarded.
+struct ImportedReports {
+    names: HashSet<(i32, String)>,
+    content_hashes: HashSet<(Option<i32>, String)>,
+}
+impl ImportedReports {
+    fn new(store: &AppStore) -> DkaResult<Self> {
+        let names = get_reports(store)?
+            .into_iter()
+            .filter_map(|r| {
+                r.importer_id
+                    .map(|importer_id| (importer_id, r.report_name))
+            })
+            .collect();
+        Ok(Self {
+            names,
+            content_hashes: get_report_content_hashes(store)?,
+        })
+    }
+
+    /// Records a report about to be imported, false if the importer already has it
+    fn insert(&mut self, importer_id: i32, report_name: &str, content_hash: &str) -> bool {
+        let name_key = (importer_id, report_name.to_string());
+        let content_hash_key = (Some(importer_id), content_hash.to_string());
+        if self.names.contains(&name_key) || self.content_hashes.contains(&content_hash_key) {
+            return false;
+        }
+        self.names.insert(name_key);
+        self.content_hashes.insert(content_hash_key);
+        true
+    }
+}
+
+/// The incomes filed so far, by their income hash. The same income in another report of the
+/// importer is a duplicate, while from another importer of the taxpayer profile it may just as
+/// well be a second account receiving the same dividend.
+struct FiledIncomes {
+    // By taxpayer profile, importer and income hash
+    report_ids: HashMap<(i32, Option<i32>, String), i32>,
+    // By taxpayer profile and income hash, for any importer
+    profile_report_ids: HashMap<(i32, String), i32>,
+}
+impl FiledIncomes {
+    fn new(store: &AppStore) -> DkaResult<Self> {
+        let mut filed_incomes = Self {
+            report_ids: HashMap::new(),
+            profile_report_ids: HashMap::new(),
+        };
+        // The first report that filed an income is kept
+        for filed_income in get_filed_incomes(store)? {
+            filed_incomes
+                .report_ids
+                .entry((
+                    filed_income.taxpayer_profile_id,
+                    filed_income.importer_id,
+                    filed_income.income_hash.clone(),
+                ))
+                .or_insert(filed_income.report_id);
+            filed_incomes
+                .profile_report_ids
+                .entry((filed_income.taxpayer_profile_id, filed_income.income_hash))
+                .or_insert(filed_income.report_id);
+        }
+        Ok(filed_incomes)
+    }
+
+    fn insert(&mut self, report: &Report, income_hash: &str) {
+        self.report_ids
+            .entry((
+                report.taxpayer_profile_id,
+                report.importer_id,
+                income_hash.to_string(),
+            ))
+            .or_insert(report.id);
+        self.profile_report_ids
+            .entry((report.taxpayer_profile_id, income_hash.to_string()))
+            .or_insert(report.id);
+    }
+
+    /// The report of the same importer that filed the income
+    fn get_report_id(&self, report: &Report, income_hash: &str) -> Option<i32> {
+        self.report_ids
+            .get(&(
+                report.taxpayer_profile_id,
+                report.importer_id,
+                income_hash.to_string(),
+            ))
+            .copied()
+    }
+
+    /// The report of another importer of the taxpayer profile that filed the income
+    fn get_other_report_id(&self, report: &Report, income_hash: &str) -> Option<i32> {
+        self.profile_report_ids
+            .get(&(report.taxpayer_profile_id, income_hash.to_string()))
+            .copied()
+            .filter(|report_id| *report_id != report.id)
+    }
+}
+
+/// Creates a report and saves its content, unless the report's importer already has a report
+/// with the same content
+pub fn save_new_report(store: &AppStore, report: &Report, report_content: &[u8]) -> DkaResult<i32> {
+    let content_hash = get_content_hash(report_content);
+    if get_report_content_hashes(store)?.contains(&(report.importer_id, content_hash.clone())) {
+        return Err(DkaError::user("This report has already been imported"));
+    }
+    let report_id = create_report(store, report)?;
+    save_report_content(store, report_id, report_content)?;
+    update_report_content_hash(store, report_id, &content_hash)?;
+    Ok(report_id)
+}
+
+/// Saves the attachments matching each importer's attachment regex as new reports. The mailbox and
+/// message are only known for messages fetched over IMAP.
+#[allow(clippy::too_many_arguments)]
+async fn import_message_attachments(
+    parsed_message: &mail_parser::Message<'_>,
+    message_importers: &[&Importer],
+    mailbox_id: Option<i32>,
+    mailbox_message_id: Option<i32>,
+    imported_reports: &mut ImportedReports,
+    job_id: i32,
+    job_store_mutex: &Mutex<JobStore>,
+    store: &AppStore,
+) -> DkaResult<()> {
     let from = parsed_message
         .from()
         .and_then(|a| a.iter().next())
         .and_then(|a| a.address())
         .unwrap_or("");
     let subject = parsed_message.subject().unwrap_or("");
-    for importer_id in message_importer_ids.iter() {
-        let importer = importers
-            .iter()
-            .find(|im| im.id == *importer_id)
-            .expect("importer should be present in the list of all importers");
+    for importer in message_importers.iter() {
         let attachment_regex = Regex::new(&importer.attachment_regex)
             .map_err(|e| DkaError::Generic(format!("Bad attachment regex: {}", e)))?;
         for attachment in parsed_message.attachments() {
@@ -128,19 +340,24 @@ async fn process_email_message(
             if !attachment_regex.is_match(attachment_name) {
                 continue;
             }
-            let report_id = create_report(
-                app_handle,
+            let content_hash = get_content_hash(attachment.contents());
+            if !imported_reports.insert(importer.id, attachment_name, &content_hash) {
+                continue;
+            }
+            save_new_report(
+                store,
                 &Report {
                     id: 0,
                     _type: importer.report_type.clone(),
-                    importer_id: Some(*importer_id),
-                    mailbox_id: mailbox.id,
-                    mailbox_message_id: u as i32,
+                    importer_id: Some(importer.id),
+                    mailbox_id,
+                    mailbox_message_id,
                     report_name: attachment_name.into(),
                     status: "init".into(),
+                    taxpayer_profile_id: importer.taxpayer_profile_id,
                 },
+                attachment.contents(),
             )?;
-            save_report_content(app_handle, report_id, attachment.contents())?;
             job_store_mutex.lock().await.add_message(
                 job_id,
                 JobMessage::Report {
@@ -151,92 +368,286 @@ async fn process_email_message(
             );
         }
     }
-    let mut new_mailbox = mailbox.clone();
-    new_mailbox.cursor = MailboxCursor::Uid {
-        last_seen_uid: u as i64,
-        last_seen_datetime: SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("System clock is before epoch")
-            .as_millis() as i64,
-    };
-    update_mailbox(app_handle, &new_mailbox)?;
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
+async fn process_email_message(
+    u: u32,
+    message_importer_ids: &[i32],
+    mailbox: &Mailbox,
+    importers: &[Importer],
+    imported_reports: &mut ImportedReports,
+    job_id: i32,
+    job_store_mutex: &Mutex<JobStore>,
+    imap_session: &mut imap::Session<
+        rustls::StreamOwned<rustls_connector::rustls::ClientConnection, TcpStream>,
+    >,
+    store: &AppStore,
+) -> DkaResult<()> {
+    let messages = imap_session.uid_fetch(u.to_string(), "RFC822")?;
+    let Some(message) = messages.iter().find(|m| m.uid == Some(u)) else {
+        return Ok(());
+    };
+    let Some(message_body) = message.body() else {
+        return Ok(());
+    };
+
+    let Some(parsed_message) = MessageParser::default().parse(message_body) else {
+        return Ok(());
+    };
+    let message_importers: Vec<&Importer> = message_importer_ids
+        .iter()
+        .map(|importer_id| {
+            importers
+                .iter()
+                .find(|im| im.id == *importer_id)
+                .expect("importer should be present in the list of all importers")
+        })
+        .collect();
+    import_message_attachments(
+        &parsed_message,
+        &message_importers,
+        Some(mailbox.id),
+        Some(u as i32),
+        imported_reports,
+        job_id,
+        job_store_mutex,
+        store,
+    )
+    .await
+}
+
+/// Syncs every mailbox that has importers bound to it and imports the files in the drop folder,
+/// then processes the new reports
 pub async fn run_job(
     job_id: i32,
     job_store_mutex: &Mutex<JobStore>,
-    app_handle: &AppHandle,
+    store: &AppStore,
+) -> DkaResult<()> {
+    let importers = get_importers(store)?;
+    for mailbox in get_mailboxes(store)? {
+        let mailbox_importers: Vec<Importer> = importers
+            .iter()
+            .filter(|im| im.mailbox_id == mailbox.id)
+            .cloned()
+            .collect();
+        if mailbox_importers.is_empty() {
+            continue;
+        }
+        let email_address = mailbox.email_address.clone();
+        // One unreachable mailbox should not hold up the others
+        if let Err(e) =
+            sync_mailbox(job_id, job_store_mutex, store, mailbox, &mailbox_importers).await
+        {
+            job_store_mutex.lock().await.add_message(
+                job_id,
+                JobMessage::Error {
+                    message: format!("{email_address}: {e}"),
+                },
+            );
+        }
+    }
+    if let Some(drop_folder) = get_technical_conf(store)?.drop_folder {
+        if let Err(e) =
+            import_drop_folder(job_id, job_store_mutex, store, Path::new(&drop_folder)).await
+        {
+            job_store_mutex.lock().await.add_message(
+                job_id,
+                JobMessage::Error {
+                    message: format!("{drop_folder}: {e}"),
+                },
+            );
+        }
+    }
+
+    process_reports(job_id, job_store_mutex, store).await
+}
+
+async fn sync_mailbox(
+    job_id: i32,
+    job_store_mutex: &Mutex<JobStore>,
+    store: &AppStore,
+    mut mailbox: Mailbox,
+    importers: &[Importer],
 ) -> DkaResult<()> {
-    let mailbox = get_mailbox(app_handle)?;
-    let importers = get_importers(app_handle)?;
+    let authenticator = match mailbox.auth_method.as_str() {
+        "oauth2" => {
+            let Some(oauth_conf) = mailbox.oauth_conf.as_ref() else {
+                return Err(DkaError::user("Mailbox has no OAuth2 settings"));
+            };
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("System time before Unix epoch")
+                .as_secs() as i64;
+            let oauth_conf = refresh_access_token(oauth_conf, now).await?;
+            if mailbox.oauth_conf.as_ref() != Some(&oauth_conf) {
+                mailbox.oauth_conf = Some(oauth_conf.clone());
+                update_mailbox(store, &mailbox)?;
+            }
+            Some(XOAuth2 {
+                user: mailbox.email_address.clone(),
+                access_token: oauth_conf.access_token,
+            })
+        }
+        _ => None,
+    };
     let stream = TcpStream::connect((mailbox.imap_host.as_ref(), mailbox.imap_port as u16))?;
     let tls = RustlsConnector::new_with_native_certs()?;
     let tlsstream = tls
         .connect(mailbox.imap_host.as_ref(), stream)
         .map_err(|_| DkaError::generic("TLS handshake error"))?;
     let client = imap::Client::new(tlsstream);
-    let mut imap_session = client
-        .login(&mailbox.email_address, &mailbox.email_password)
-        .map_err(|e| e.0)?;
-    let inbox = imap_session.select("INBOX")?;
-    if inbox.uid_validity.is_none() {
-        return Err(DkaError::generic(
-            "Mailbox does not support persistent UIDs",
-        ));
-    }
-    let mut importers_by_message_uid: HashMap<u32, Vec<i32>> = HashMap::new();
-    for importer in importers.iter() {
-        let search_string = get_search_string(
-            &importer.from_filter,
-            &importer.subject_filter,
-            &mailbox.cursor,
-        )?;
-        let search_result = imap_session.uid_search(search_string)?;
-        for uid in search_result {
-            importers_by_message_uid
-                .entry(uid)
-                .and_modify(|v| {
-                    v.push(importer.id);
-                })
-                .or_insert(vec![importer.id]);
+    let mut imap_session = match authenticator {
+        Some(authenticator) => client
+            .authenticate("XOAUTH2", &authenticator)
+            .map_err(|e| e.0)?,
+        None => client
+            .login(&mailbox.email_address, &mailbox.email_password)
+            .map_err(|e| e.0)?,
+    };
+    let folder_cursors = get_mailbox_folder_cursors(store, mailbox.id)?;
+    let mut imported_reports = ImportedReports::new(store)?;
+    let mut processed_messages_counter = 0;
+    for folder in mailbox.folders.clone() {
+        // One missing folder should not hold up the others
+        let selected_folder = match imap_session.select(&folder) {
+            Ok(selected_folder) => selected_folder,
+            Err(e) => {
+                job_store_mutex.lock().await.add_message(
+                    job_id,
+                    JobMessage::Error {
+                        message: format!("{}/{folder}: {e}", mailbox.email_address),
+                    },
+                );
+                continue;
+            }
+        };
+        let Some(uid_validity) = selected_folder.uid_validity else {
+            job_store_mutex.lock().await.add_message(
+                job_id,
+                JobMessage::Error {
+                    message: format!(
+                        "{}/{folder}: Folder does not support persistent UIDs",
+                        mailbox.email_address
+                    ),
+                },
+            );
+            continue;
+        };
+        let folder_cursor = folder_cursors.iter().find(|fc| fc.folder == folder);
+        // The mailbox cursor is left as configured, so new folders start from the same date
+        let search_cursor = mailbox.cursor.for_folder(folder_cursor, uid_validity);
+        // Oldest first, so that the cursor only moves forward
+        let mut importers_by_message_uid: BTreeMap<u32, Vec<i32>> = BTreeMap::new();
+        for importer in importers.iter() {
+            let search_string = get_search_string(
+                &importer.from_filter,
+                &importer.subject_filter,
+                &search_cursor,
+            )?;
+            let search_result = imap_session.uid_search(search_string)?;
+            for uid in search_result {
+                importers_by_message_uid
+                    .entry(uid)
+                    .or_default()
+                    .push(importer.id);
+            }
         }
-    }
-    let database_message_uids: HashSet<u32> = get_reports_by_mailbox(app_handle, 1)?
-        .iter()
-        .map(|r| r.mailbox_message_id as u32)
-        .collect();
 
-    let uuids_to_process: Vec<_> = importers_by_message_uid
-        .keys()
-        .filter(|&u| {
+        for (&u, message_importer_ids) in importers_by_message_uid.iter() {
             // IMAP will always return the last message, even if it outside the UID range.
             // https://stackoverflow.com/questions/34706633/imap-search-by-a-uid-range-on-exchange-server-seems-to-be-broken
-            if let MailboxCursor::Uid { last_seen_uid, .. } = mailbox.cursor {
-                if *u <= (last_seen_uid as u32) {
-                    return false;
+            if let MailboxCursor::Uid { last_seen_uid, .. } = search_cursor {
+                if u <= (last_seen_uid as u32) {
+                    continue;
                 }
             }
-            if database_message_uids.contains(u) {
-                return false;
+            if check_cancelled(job_id, job_store_mutex).await {
+                return Ok(());
             }
-            return true;
-        })
-        .collect();
+            if let Err(e) = process_email_message(
+                u,
+                message_importer_ids,
+                &mailbox,
+                importers,
+                &mut imported_reports,
+                job_id,
+                job_store_mutex,
+                &mut imap_session,
+                store,
+            )
+            .await
+            {
+                job_store_mutex.lock().await.add_message(
+                    job_id,
+                    JobMessage::Error {
+                        message: e.to_string(),
+                    },
+                );
+                continue;
+            };
+            processed_messages_counter += 1;
+            update_mailbox_folder_cursor(
+                store,
+                mailbox.id,
+                &MailboxFolderCursor {
+                    folder: folder.clone(),
+                    uid_validity,
+                    last_seen_uid: u,
+                },
+            )?;
+        }
+    }
+    job_store_mutex.lock().await.add_message(
+        job_id,
+        JobMessage::Success {
+            message: format!(
+                "Processed {processed_messages_counter} emails from {}",
+                mailbox.email_address
+            ),
+        },
+    );
+    Ok(())
+}
 
-    for &u in uuids_to_process.iter() {
-        let Some(message_importer_ids) = importers_by_message_uid.get(&u) else {
+/// Imports the reports found in a local mail archive, matching the messages against every
+/// importer's filters like the IMAP search does, then processes the new reports
+pub async fn import_mail_archive(
+    job_id: i32,
+    job_store_mutex: &Mutex<JobStore>,
+    store: &AppStore,
+    path: &Path,
+) -> DkaResult<()> {
+    let importers = get_importers(store)?;
+    let messages = read_mail_archive(path)?;
+    let mut imported_reports = ImportedReports::new(store)?;
+    let mut matched_messages_counter = 0;
+    for message in messages {
+        if check_cancelled(job_id, job_store_mutex).await {
+            return Ok(());
+        }
+        let message = message?;
+        let Some(parsed_message) = MessageParser::default().parse(&message) else {
             continue;
         };
-        if let Err(e) = process_email_message(
-            *u,
-            message_importer_ids,
-            &mailbox,
-            &importers,
+        let message_importers: Vec<&Importer> = importers
+            .iter()
+            .filter(|im| message_matches_importer(&parsed_message, im))
+            .collect();
+        if message_importers.is_empty() {
+            continue;
+        }
+        matched_messages_counter += 1;
+        if let Err(e) = import_message_attachments(
+            &parsed_message,
+            &message_importers,
+            None,
+            None,
+            &mut imported_reports,
             job_id,
             job_store_mutex,
-            &mut imap_session,
-            app_handle,
+            store,
         )
         .await
         {
@@ -246,35 +657,202 @@ pub async fn run_job(
                     message: e.to_string(),
                 },
             );
-        };
+        }
+    }
+    job_store_mutex.lock().await.add_message(
+        job_id,
+        JobMessage::Success {
+            message: format!(
+                "Processed {matched_messages_counter} emails from {}",
+                path.display()
+            ),
+        },
+    );
+
+    process_reports(job_id, job_store_mutex, store).await
+}
+
+/// A file in the drop folder that has not been imported yet
+pub struct NewDroppedFile {
+    pub file_name: String,
+    pub content: Vec<u8>,
+    // The importers whose attachment regex matches the file name
+    pub importers: Vec<Importer>,
+}
+
+/// The files in the drop folder that have not been imported yet. Importers without an attachment
+/// regex are left out, since nothing else tells one broker's files from another's. Only the files
+/// whose name matches an importer are read, and the cache skips the ones read before that were
+/// imported already.
+pub fn get_new_dropped_files(
+    store: &AppStore,
+    drop_folder: &Path,
+    drop_folder_cache: &mut DropFolderCache,
+) -> DkaResult<Vec<NewDroppedFile>> {
+    let mut importer_regexes = vec![];
+    for importer in get_importers(store)? {
+        if importer.attachment_regex.is_empty() {
+            continue;
+        }
+        let attachment_regex = Regex::new(&importer.attachment_regex)
+            .map_err(|e| DkaError::Generic(format!("Bad attachment regex: {}", e)))?;
+        importer_regexes.push((importer, attachment_regex));
+    }
+    // Any importer's, since the same file can be dropped twice under different names
+    let mut imported_content_hashes: HashSet<String> = get_report_content_hashes(store)?
+        .into_iter()
+        .map(|(_, content_hash)| content_hash)
+        .collect();
+    let mut new_dropped_files = vec![];
+    for dropped_file in list_drop_folder(drop_folder)? {
+        if drop_folder_cache.contains(&dropped_file) {
+            continue;
+        }
+        let file_importers: Vec<Importer> = importer_regexes
+            .iter()
+            .filter(|(_, attachment_regex)| attachment_regex.is_match(&dropped_file.file_name))
+            .map(|(importer, _)| importer.clone())
+            .collect();
+        if file_importers.is_empty() {
+            continue;
+        }
+        let content = dropped_file.read_content()?;
+        if !imported_content_hashes.insert(get_content_hash(&content)) {
+            drop_folder_cache.insert(&dropped_file);
+            continue;
+        }
+        new_dropped_files.push(NewDroppedFile {
+            file_name: dropped_file.file_name,
+            content,
+            importers: file_importers,
+        });
+    }
+    Ok(new_dropped_files)
+}
+
+/// Imports the new files in the drop folder as reports. Files are recognized by their content,
+/// so a file that stays in the folder is only imported once.
+pub async fn import_drop_folder(
+    job_id: i32,
+    job_store_mutex: &Mutex<JobStore>,
+    store: &AppStore,
+    drop_folder: &Path,
+) -> DkaResult<()> {
+    let new_dropped_files =
+        get_new_dropped_files(store, drop_folder, &mut DropFolderCache::default())?;
+    import_dropped_files(
+        job_id,
+        job_store_mutex,
+        store,
+        drop_folder,
+        &new_dropped_files,
+    )
+    .await
+}
+
+/// Saves the new files found in the drop folder as reports of their importers
+pub async fn import_dropped_files(
+    job_id: i32,
+    job_store_mutex: &Mutex<JobStore>,
+    store: &AppStore,
+    drop_folder: &Path,
+    new_dropped_files: &[NewDroppedFile],
+) -> DkaResult<()> {
+    let mut imported_reports = ImportedReports::new(store)?;
+    for dropped_file in new_dropped_files {
+        if check_cancelled(job_id, job_store_mutex).await {
+            return Ok(());
+        }
+        let content_hash = get_content_hash(&dropped_file.content);
+        for importer in dropped_file.importers.iter() {
+            // Brokers reuse file names, e.g. for every month's statement
+            let mut report_name = dropped_file.file_name.clone();
+            if imported_reports
+                .names
+                .contains(&(importer.id, report_name.clone()))
+            {
+                report_name = format!("{} ({})", dropped_file.file_name, &content_hash[..8]);
+            }
+            if !imported_reports.insert(importer.id, &report_name, &content_hash) {
+                continue;
+            }
+            save_new_report(
+                store,
+                &Report {
+                    id: 0,
+                    _type: importer.report_type.clone(),
+                    importer_id: Some(importer.id),
+                    mailbox_id: None,
+                    mailbox_message_id: None,
+                    report_name: report_name.clone(),
+                    status: "init".into(),
+                    taxpayer_profile_id: importer.taxpayer_profile_id,
+                },
+                &dropped_file.content,
+            )?;
+            job_store_mutex.lock().await.add_message(
+                job_id,
+                JobMessage::Report {
+                    from: drop_folder.display().to_string(),
+                    subject: "".into(),
+                    attachment_name: report_name,
+                },
+            );
+        }
     }
     job_store_mutex.lock().await.add_message(
         job_id,
         JobMessage::Success {
-            message: format!("Processed {} emails", uuids_to_process.len()),
+            message: format!(
+                "Imported {} files from {}",
+                new_dropped_files.len(),
+                drop_folder.display()
+            ),
         },
     );
+    Ok(())
+}
 
-    let technical_conf = get_technical_conf(app_handle)?;
-    let taxpayer_profile = get_taxpayer_profile(app_handle)?;
+/// Parses the reports that have not been processed yet and creates their filings
+pub async fn process_reports(
+    job_id: i32,
+    job_store_mutex: &Mutex<JobStore>,
+    store: &AppStore,
+) -> DkaResult<()> {
+    let importers = get_importers(store)?;
+    let technical_conf = get_technical_conf(store)?;
+    let taxpayer_profiles = get_taxpayer_profiles(store)?;
 
     // Process reports
-    let unprocessed_reports: Vec<_> = get_reports_by_mailbox(app_handle, 1)?
+    let unprocessed_reports: Vec<_> = get_reports(store)?
         .into_iter()
         .filter(|r| r.status != "processed")
         .collect();
 
+    let mut exchange_rate_provider = CachedExchangeRateProvider::new(
+        NbsExchangeRateProvider::new(),
+        get_nbs_exchange_rates(store)?,
+    );
+    let mut filed_incomes = FiledIncomes::new(store)?;
     let mut processed_reports_counter = 0;
     let mut processed_passive_incomes_counter = 0;
     for report in unprocessed_reports.iter() {
-        let report_parser: Box<dyn ReportParser> = match report._type.as_str() {
-            "NativeIncomeJson" => Box::new(TrivialReportParser::new()),
-            "IbkrCsv" => Box::new(IbkrReportParser::new()),
-            _ => {
-                continue;
+        let generic_csv_conf = importers
+            .iter()
+            .find(|im| Some(im.id) == report.importer_id)
+            .and_then(|im| im.generic_csv_conf.clone());
+        let Some(report_parser) = get_report_parser(&report._type, generic_csv_conf) else {
+            if report._type == "GenericCsv" {
+                job_store_mutex.lock().await.add_message(
+                    job_id,
+                    JobMessage::Error {
+                        message: format!("Report {} has no generic CSV column mapping", report.id),
+                    },
+                );
             }
+            continue;
         };
-        let report_path = get_report_path(app_handle, report.id)?;
+        let report_path = get_report_path(store, report.id)?;
         let parse_result = report_parser.parse(report_path);
         drop(report_parser);
         match parse_result {
@@ -287,40 +865,167 @@ pub async fn run_job(
                 );
                 continue;
             }
-            Ok(parsed_report) => {
-                let filing_infos = parsed_report.get_filing_infos().await?;
-                for filing_info in filing_infos {
-                    let payment_notes = importers
-                        .iter()
-                        .find(|im| Some(im.id) == report.importer_id)
-                        .map(|im| im.payment_notes.clone())
-                        .unwrap_or("".to_string());
-                    let opo_data = OpoData::new(
-                        &filing_info,
+            Ok(mut parsed_report) => {
+                // The same income can come in another report, e.g. when a statement is both
+                // imported manually and received by email
+                let mut income_hashes = vec![];
+                let mut income_infos = vec![];
+                for income_info in parsed_report.income_infos {
+                    let income_hash = income_info.get_income_hash();
+                    match filed_incomes.get_report_id(report, &income_hash) {
+                        // Filed before a filing of this report failed, and the report was retried
+                        Some(filed_report_id) if filed_report_id == report.id => {}
+                        Some(filed_report_id) => {
+                            job_store_mutex.lock().await.add_message(
+                                job_id,
+                                JobMessage::Warning {
+                                    message: format!(
+                                        "Skipped {} from {} on {} in report {}, it was already filed from report {}",
+                                        income_info._type,
+                                        income_info.paying_entity,
+                                        format_iso(&income_info.income_date),
+                                        report.id,
+                                        filed_report_id
+                                    ),
+                                },
+                            );
+                        }
+                        None => {
+                            // Filed anyway, for the user to confirm or delete
+                            let suspected_duplicate_of_report_id =
+                                filed_incomes.get_other_report_id(report, &income_hash);
+                            if let Some(filed_report_id) = suspected_duplicate_of_report_id {
+                                job_store_mutex.lock().await.add_message(
+                                    job_id,
+                                    JobMessage::Warning {
+                                        message: format!(
+                                            "{} from {} on {} in report {} may have been filed from report {} already, confirm or delete its filing",
+                                            income_info._type,
+                                            income_info.paying_entity,
+                                            format_iso(&income_info.income_date),
+                                            report.id,
+                                            filed_report_id
+                                        ),
+                                    },
+                                );
+                            }
+                            income_hashes.push((income_hash, suspected_duplicate_of_report_id));
+                            income_infos.push(income_info);
+                        }
+                    }
+                }
+                parsed_report.income_infos = income_infos;
+                let filing_infos = parsed_report
+                    .get_filing_infos(
+                        &technical_conf.wht_treaty_rates,
+                        &mut exchange_rate_provider,
+                    )
+                    .await;
+                // Keep the downloaded rates even if some income could not be converted
+                save_nbs_exchange_rates(store, &exchange_rate_provider.take_unsaved_rates())?;
+                let filing_infos = filing_infos?;
+                let importer = importers
+                    .iter()
+                    .find(|im| Some(im.id) == report.importer_id);
+                let taxpayer_profile_id = report.taxpayer_profile_id;
+                let Some(taxpayer_profile) = taxpayer_profiles
+                    .iter()
+                    .find(|tp| tp.id == taxpayer_profile_id)
+                else {
+                    job_store_mutex.lock().await.add_message(
+                        job_id,
+                        JobMessage::Error {
+                            message: format!(
+                                "Taxpayer profile {taxpayer_profile_id} of report {} not found",
+                                report.id
+                            ),
+                        },
+                    );
+                    continue;
+                };
+                let payment_notes = importer
+                    .map(|im| im.payment_notes.clone())
+                    .unwrap_or("".to_string());
+                let hashed_filing_infos: Vec<((String, Option<i32>), FilingInfo)> =
+                    income_hashes.into_iter().zip(filing_infos).collect();
+                let filing_info_groups = if importer.is_some_and(|im| im.group_filings_by_date) {
+                    group_filing_infos_by_date(hashed_filing_infos, |(_, fi)| fi)
+                } else {
+                    hashed_filing_infos
+                        .into_iter()
+                        .map(|hfi| vec![hfi])
+                        .collect()
+                };
+                let mut filed_hashes = vec![];
+                let mut failed_filings_counter = 0;
+                for filing_info_group in filing_info_groups {
+                    let (group_hashes, filing_info_group): (
+                        Vec<(String, Option<i32>)>,
+                        Vec<FilingInfo>,
+                    ) = filing_info_group.into_iter().unzip();
+                    let (opo_data, opo_filing_content) = match fill_opo_data(
+                        &filing_info_group,
                         &payment_notes,
-                        &taxpayer_profile,
-                        &technical_conf.holiday_conf,
-                    )?;
-                    let opo_filing_content = opo_data.fill();
-                    let filing_id = create_filing(
-                        app_handle,
-                        &Filing {
-                            id: 0,
-                            report_id: report.id,
-                            _type: filing_info._type,
-                            status: "init".to_string(),
-                            paying_entity: filing_info.paying_entity,
-                            filing_deadline: format_iso(&opo_data.filing_deadline),
-                            tax_payable: filing_info.tax_payable_rsdc,
-                            tax_payment_reference: "".to_string(),
+                        taxpayer_profile,
+                        &technical_conf,
+                    ) {
+                        Ok(filled_opo_data) => filled_opo_data,
+                        Err(e) => {
+                            let paying_entities: Vec<&str> = filing_info_group
+                                .iter()
+                                .map(|fi| fi.paying_entity.as_str())
+                                .collect();
+                            job_store_mutex.lock().await.add_message(
+                                job_id,
+                                JobMessage::Error {
+                                    message: format!(
+                                        "Could not file {} from {} on {} in report {}: {}",
+                                        filing_info_group[0]._type,
+                                        paying_entities.join(", "),
+                                        format_iso(&filing_info_group[0].income_date),
+                                        report.id,
+                                        e
+                                    ),
+                                },
+                            );
+                            failed_filings_counter += 1;
+                            continue;
+                        }
+                    };
+                    let mut opo_filing =
+                        get_opo_filing(Some(report.id), taxpayer_profile_id, &opo_data);
+                    opo_filing.suspected_duplicate_of_report_id =
+                        group_hashes.iter().find_map(|(_, report_id)| *report_id);
+                    create_opo_filing(store, &opo_filing, &opo_data, &opo_filing_content)?;
+                    processed_passive_incomes_counter += filing_info_group.len();
+                    filed_hashes
+                        .extend(group_hashes.into_iter().map(|(income_hash, _)| income_hash));
+                }
+                create_income_hashes(store, report, &filed_hashes)?;
+                for income_hash in filed_hashes.iter() {
+                    filed_incomes.insert(report, income_hash);
+                }
+                // The report is retried on the next run, e.g. after the taxpayer profile is fixed
+                if failed_filings_counter > 0 {
+                    continue;
+                }
+                let skipped_trades_counter =
+                    create_trades(store, report.id, &parsed_report.trade_infos)?;
+                if skipped_trades_counter > 0 {
+                    job_store_mutex.lock().await.add_message(
+                        job_id,
+                        JobMessage::Warning {
+                            message: format!(
+                                "Skipped {skipped_trades_counter} trades in report {}, they were already imported from another report",
+                                report.id
+                            ),
                         },
-                    )?;
-                    save_filing_content(app_handle, filing_id, opo_filing_content.as_bytes())?;
-                    processed_passive_incomes_counter += 1;
+                    );
                 }
+                create_report_exchange_rates(store, report.id, &parsed_report.exchange_rate_infos)?;
                 let mut updated_report = report.clone();
                 updated_report.status = "processed".into();
-                update_report(app_handle, &updated_report)?;
+                update_report(store, &updated_report)?;
                 processed_reports_counter += 1;
             }
         };
@@ -348,7 +1053,20 @@ pub async fn run_job(
 
 #[cfg(test)]
 mod tests {
+    use chrono::NaiveDate;
+    use rust_decimal_macros::dec;
+
     use super::*;
+    use crate::{
+        database::{
+            confirm_filing_not_duplicate, create_importer, create_taxpayer_profile, delete_filing,
+            get_filing_incomes, get_filings, migrate_database, update_importer,
+            update_taxpayer_profile, DEFAULT_TAXPAYER_PROFILE_ID,
+        },
+        exchange_rate::NbsExchangeRate,
+        filesystem::{get_filing_content, get_report_content, migrate_filesystem},
+        ipc_types::TaxpayerProfile,
+    };
 
     #[test]
     fn test_format_date_string_for_imap() {
@@ -389,4 +1107,570 @@ mod tests {
             "FROM \"abc\" SUBJECT \"def\" UID 124:*".to_string()
         );
     }
+
+    fn taxpayer_profile(jmbg: &str) -> TaxpayerProfile {
+        TaxpayerProfile {
+            id: 1,
+            jmbg: jmbg.into(),
+            full_name: "Petar Petrovic".into(),
+            street_address: "Glavna 1".into(),
+            opstina_code: "013".into(),
+            phone_number: "0601234567".into(),
+            email_address: "petar@example.com".into(),
+        }
+    }
+
+    /// A migrated store with a valid taxpayer profile and the exchange rate cache seeded, so that
+    /// nothing is fetched from NBS
+    fn seeded_store(data_dir: &tempfile::TempDir) -> AppStore {
+        let store = AppStore::new(data_dir.path().to_path_buf(), data_dir.path().to_path_buf());
+        migrate_filesystem(&store).unwrap();
+        migrate_database(&store).unwrap();
+        update_taxpayer_profile(&store, &taxpayer_profile("0101990710008")).unwrap();
+        save_nbs_exchange_rates(
+            &store,
+            &[NbsExchangeRate {
+                date: NaiveDate::from_ymd_opt(2020, 7, 16).unwrap(),
+                currency_code: "USD".into(),
+                exchange_rate: dec!(100),
+            }],
+        )
+        .unwrap();
+        store
+    }
+
+    fn create_dividend_report(
+        store: &AppStore,
+        importer_id: Option<i32>,
+        taxpayer_profile_id: i32,
+    ) -> i32 {
+        save_new_report(
+            store,
+            &Report {
+                id: 0,
+                _type: "NativeIncomeJson".into(),
+                importer_id,
+                mailbox_id: None,
+                mailbox_message_id: None,
+                report_name: "manual".into(),
+                status: "init".into(),
+                taxpayer_profile_id,
+            },
+            br#"{
+                "type": "dividend",
+                "payingEntity": "Apple",
+                "incomeDate": "2020-07-16",
+                "incomeCurrencyCode": "USD",
+                "incomeCurrencyAmount": 100,
+                "whtCurrencyCode": "USD",
+                "whtCurrencyAmount": 10
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_process_reports() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = seeded_store(&data_dir);
+        let report_id = create_dividend_report(&store, None, DEFAULT_TAXPAYER_PROFILE_ID);
+
+        let job_store_mutex = Mutex::new(JobStore::new());
+        let job_id = job_store_mutex.lock().await.create();
+        process_reports(job_id, &job_store_mutex, &store)
+            .await
+            .unwrap();
+
+        let job = job_store_mutex.lock().await.get(job_id).unwrap();
+        assert!(!job
+            .messages
+            .iter()
+            .any(|m| matches!(m, JobMessage::Error { .. })));
+        let filings = get_filings(&store).unwrap();
+        assert_eq!(filings.len(), 1);
+        assert_eq!(filings[0].report_id, Some(report_id));
+        assert_eq!(filings[0].gross_income, Some(1000000));
+        assert_eq!(filings[0].wht_paid, Some(100000));
+        assert_eq!(filings[0].tax_payable, 50000);
+        assert_eq!(filings[0].taxpayer_profile_id, DEFAULT_TAXPAYER_PROFILE_ID);
+        assert!(!get_filing_content(&store, filings[0].id)
+            .unwrap()
+            .is_empty());
+        assert!(get_reports(&store)
+            .unwrap()
+            .iter()
+            .all(|r| r.status == "processed"));
+    }
+
+    #[tokio::test]
+    async fn test_process_reports_taxpayer_profile() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = seeded_store(&data_dir);
+        let taxpayer_profile_id =
+            create_taxpayer_profile(&store, &taxpayer_profile("0202985715000")).unwrap();
+        let mut importer = Importer {
+            id: 0,
+            name: "Spouse".into(),
+            report_type: "NativeIncomeJson".into(),
+            taxpayer_profile_id,
+            mailbox_id: 1,
+            from_filter: "".into(),
+            subject_filter: "".into(),
+            payment_notes: "".into(),
+            attachment_regex: "".into(),
+            generic_csv_conf: None,
+            group_filings_by_date: false,
+        };
+        importer.id = create_importer(&store, &importer).unwrap();
+        let report_id = create_dividend_report(&store, Some(importer.id), taxpayer_profile_id);
+        // Moving the importer afterwards does not move the reports it already imported
+        importer.taxpayer_profile_id = DEFAULT_TAXPAYER_PROFILE_ID;
+        update_importer(&store, &importer).unwrap();
+
+        let job_store_mutex = Mutex::new(JobStore::new());
+        let job_id = job_store_mutex.lock().await.create();
+        process_reports(job_id, &job_store_mutex, &store)
+            .await
+            .unwrap();
+
+        let filings = get_filings(&store).unwrap();
+        assert_eq!(filings.len(), 1);
+        assert_eq!(filings[0].report_id, Some(report_id));
+        assert_eq!(filings[0].taxpayer_profile_id, taxpayer_profile_id);
+        let filing_content =
+            String::from_utf8(get_filing_content(&store, filings[0].id).unwrap()).unwrap();
+        assert!(filing_content.contains("0202985715000"));
+        assert!(!filing_content.contains("0101990710008"));
+    }
+
+    #[tokio::test]
+    async fn test_process_reports_missing_taxpayer_profile() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = seeded_store(&data_dir);
+        let report_id = create_dividend_report(&store, None, DEFAULT_TAXPAYER_PROFILE_ID);
+        // A copy of the report, moved to a profile that does not exist
+        let report_content = get_report_content(&store, report_id).unwrap();
+        let orphaned_report_id = create_report(
+            &store,
+            &Report {
+                id: 0,
+                _type: "NativeIncomeJson".into(),
+                importer_id: None,
+                mailbox_id: None,
+                mailbox_message_id: None,
+                report_name: "orphaned".into(),
+                status: "init".into(),
+                taxpayer_profile_id: DEFAULT_TAXPAYER_PROFILE_ID,
+            },
+        )
+        .unwrap();
+        save_report_content(&store, orphaned_report_id, &report_content).unwrap();
+        let conn = store.connection().unwrap();
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute(
+            "UPDATE reports SET taxpayer_profile_id = 2 WHERE id = ?1",
+            [orphaned_report_id],
+        )
+        .unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        drop(conn);
+
+        let job_store_mutex = Mutex::new(JobStore::new());
+        let job_id = job_store_mutex.lock().await.create();
+        process_reports(job_id, &job_store_mutex, &store)
+            .await
+            .unwrap();
+
+        let job = job_store_mutex.lock().await.get(job_id).unwrap();
+        assert!(job.messages.iter().any(|m| matches!(
+            m,
+            JobMessage::Error { message } if message.contains("Taxpayer profile 2")
+        )));
+        let filings = get_filings(&store).unwrap();
+        assert_eq!(filings.len(), 1);
+        assert_eq!(filings[0].report_id, Some(report_id));
+    }
+
+    #[test]
+    fn test_create_opo_filing_grouped() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = seeded_store(&data_dir);
+        let filing_info = |paying_entity: &str, income_currency_amount| {
+            FilingInfo {
+                _type: "dividend".into(),
+                paying_entity: paying_entity.into(),
+                income_date: NaiveDate::from_ymd_opt(2020, 7, 16).unwrap(),
+                income_currency_code: "USD".into(),
+                income_currency_amount,
+                income_exchange_rate: dec!(100),
+                wht_currency_code: "USD".into(),
+                wht_currency_amount: dec!(0),
+                wht_exchange_rate: dec!(100),
+                gross_income_rsdc: 0,
+                wht_actual_rsdc: 0,
+                wht_credit_rate: None,
+                wht_paid_rsdc: 0,
+                gross_tax_payable_rsdc: 0,
+                tax_payable_rsdc: 0,
+            }
+            .with_corrected_amounts(income_currency_amount, dec!(0))
+        };
+        let filing_infos = vec![
+            filing_info("Apple", dec!(100)),
+            filing_info("IBM", dec!(50)),
+        ];
+        let opo_data = OpoData::new_grouped(
+            &filing_infos,
+            "",
+            &taxpayer_profile("0101990710008"),
+            &get_technical_conf(&store).unwrap().holiday_conf,
+        )
+        .unwrap();
+        let filing_id = create_opo_filing(
+            &store,
+            &get_opo_filing(None, DEFAULT_TAXPAYER_PROFILE_ID, &opo_data),
+            &opo_data,
+            &opo_data.fill().unwrap(),
+        )
+        .unwrap();
+
+        let filings = get_filings(&store).unwrap();
+        assert_eq!(filings[0].paying_entity, "Apple, IBM");
+        assert_eq!(filings[0].income_currency_amount, None);
+        assert_eq!(get_filing_incomes(&store, filing_id).unwrap(), filing_infos);
+        delete_filing(&store, filing_id).unwrap();
+        assert!(get_filing_incomes(&store, filing_id).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_process_reports_invalid_filing() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = seeded_store(&data_dir);
+        let mut invalid_taxpayer_profile = taxpayer_profile("0101990710008");
+        invalid_taxpayer_profile.email_address = "petar".into();
+        update_taxpayer_profile(&store, &invalid_taxpayer_profile).unwrap();
+        create_dividend_report(&store, None, DEFAULT_TAXPAYER_PROFILE_ID);
+
+        let job_store_mutex = Mutex::new(JobStore::new());
+        let job_id = job_store_mutex.lock().await.create();
+        process_reports(job_id, &job_store_mutex, &store)
+            .await
+            .unwrap();
+
+        let job = job_store_mutex.lock().await.get(job_id).unwrap();
+        assert!(job.messages.iter().any(|m| matches!(
+            m,
+            JobMessage::Error { message } if message.contains("ElektronskaPosta")
+        )));
+        assert!(get_filings(&store).unwrap().is_empty());
+        assert!(get_reports(&store)
+            .unwrap()
+            .iter()
+            .all(|r| r.status != "processed"));
+
+        // The report is filed once the profile is fixed
+        update_taxpayer_profile(&store, &taxpayer_profile("0101990710008")).unwrap();
+        process_reports(job_id, &job_store_mutex, &store)
+            .await
+            .unwrap();
+        assert_eq!(get_filings(&store).unwrap().len(), 1);
+        assert!(get_reports(&store)
+            .unwrap()
+            .iter()
+            .all(|r| r.status == "processed"));
+    }
+
+    #[tokio::test]
+    async fn test_process_reports_duplicate_income() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = seeded_store(&data_dir);
+        let report_id = create_dividend_report(&store, None, DEFAULT_TAXPAYER_PROFILE_ID);
+        let report = |report_name: &str| Report {
+            id: 0,
+            _type: "NativeIncomeJson".into(),
+            importer_id: None,
+            mailbox_id: None,
+            mailbox_message_id: None,
+            report_name: report_name.into(),
+            status: "init".into(),
+            taxpayer_profile_id: DEFAULT_TAXPAYER_PROFILE_ID,
+        };
+        let report_content = get_report_content(&store, report_id).unwrap();
+        assert!(matches!(
+            save_new_report(&store, &report("copy"), &report_content),
+            Err(DkaError::User(_))
+        ));
+        // The same income, formatted differently
+        let reformatted_report_content = String::from_utf8(report_content)
+            .unwrap()
+            .replace("\"Apple\"", "\"APPLE \"");
+        let duplicate_report_id = save_new_report(
+            &store,
+            &report("reformatted"),
+            reformatted_report_content.as_bytes(),
+        )
+        .unwrap();
+
+        let job_store_mutex = Mutex::new(JobStore::new());
+        let job_id = job_store_mutex.lock().await.create();
+        process_reports(job_id, &job_store_mutex, &store)
+            .await
+            .unwrap();
+
+        let job = job_store_mutex.lock().await.get(job_id).unwrap();
+        let warnings: Vec<_> = job
+            .messages
+            .iter()
+            .filter_map(|m| match m {
+                JobMessage::Warning { message } => Some(message),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains(&format!("in report {duplicate_report_id}")));
+        let filings = get_filings(&store).unwrap();
+        assert_eq!(filings.len(), 1);
+        assert_eq!(filings[0].report_id, Some(report_id));
+        assert!(get_reports(&store)
+            .unwrap()
+            .iter()
+            .all(|r| r.status == "processed"));
+    }
+
+    #[tokio::test]
+    async fn test_process_reports_suspected_duplicate_income() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = seeded_store(&data_dir);
+        let spouse_taxpayer_profile_id =
+            create_taxpayer_profile(&store, &taxpayer_profile("0202985715000")).unwrap();
+        let importer = |name: &str, taxpayer_profile_id| Importer {
+            id: 0,
+            name: name.into(),
+            report_type: "NativeIncomeJson".into(),
+            taxpayer_profile_id,
+            mailbox_id: 1,
+            from_filter: "".into(),
+            subject_filter: "".into(),
+            payment_notes: "".into(),
+            attachment_regex: "".into(),
+            generic_csv_conf: None,
+            group_filings_by_date: false,
+        };
+        let first_importer_id =
+            create_importer(&store, &importer("Broker", DEFAULT_TAXPAYER_PROFILE_ID)).unwrap();
+        let second_importer_id =
+            create_importer(&store, &importer("Bank", DEFAULT_TAXPAYER_PROFILE_ID)).unwrap();
+        let spouse_importer_id =
+            create_importer(&store, &importer("Spouse", spouse_taxpayer_profile_id)).unwrap();
+        let first_report_id =
+            create_dividend_report(&store, Some(first_importer_id), DEFAULT_TAXPAYER_PROFILE_ID);
+        let second_report_id = create_dividend_report(
+            &store,
+            Some(second_importer_id),
+            DEFAULT_TAXPAYER_PROFILE_ID,
+        );
+        let spouse_report_id =
+            create_dividend_report(&store, Some(spouse_importer_id), spouse_taxpayer_profile_id);
+
+        let job_store_mutex = Mutex::new(JobStore::new());
+        let job_id = job_store_mutex.lock().await.create();
+        process_reports(job_id, &job_store_mutex, &store)
+            .await
+            .unwrap();
+
+        // The same income from another account of the taxpayer is filed, but flagged
+        let job = job_store_mutex.lock().await.get(job_id).unwrap();
+        let warnings: Vec<_> = job
+            .messages
+            .iter()
+            .filter_map(|m| match m {
+                JobMessage::Warning { message } => Some(message),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains(&format!("from report {first_report_id}")));
+        let filings = get_filings(&store).unwrap();
+        assert_eq!(filings.len(), 3);
+        let filing = |report_id| {
+            filings
+                .iter()
+                .find(|f| f.report_id == Some(report_id))
+                .unwrap()
+        };
+        assert_eq!(
+            filing(first_report_id).suspected_duplicate_of_report_id,
+            None
+        );
+        assert_eq!(
+            filing(second_report_id).suspected_duplicate_of_report_id,
+            Some(first_report_id)
+        );
+        assert_eq!(
+            filing(spouse_report_id).suspected_duplicate_of_report_id,
+            None
+        );
+
+        confirm_filing_not_duplicate(&store, filing(second_report_id).id).unwrap();
+        assert!(get_filings(&store)
+            .unwrap()
+            .iter()
+            .all(|f| f.suspected_duplicate_of_report_id.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_import_mail_archive() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = seeded_store(&data_dir);
+        create_importer(
+            &store,
+            &Importer {
+                id: 0,
+                name: "Broker".into(),
+                report_type: "NativeIncomeJson".into(),
+                taxpayer_profile_id: DEFAULT_TAXPAYER_PROFILE_ID,
+                mailbox_id: 1,
+                from_filter: "statements@broker.example".into(),
+                subject_filter: "".into(),
+                payment_notes: "".into(),
+                attachment_regex: "\\.json$".into(),
+                generic_csv_conf: None,
+                group_filings_by_date: false,
+            },
+        )
+        .unwrap();
+        let message = |from: &str| {
+            format!(
+                "From: Broker <{from}>\r
+Subject: Daily statement\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"b\"\r
+\r
+--b\r
+Content-Type: text/plain\r
+\r
+See attached\r
+--b\r
+Content-Type: application/json\r
+Content-Disposition: attachment; filename=\"income.json\"\r
+\r
+{{\"type\": \"dividend\", \"payingEntity\": \"Apple\", \"incomeDate\": \"2020-07-16\",
+\"incomeCurrencyCode\": \"USD\", \"incomeCurrencyAmount\": 100,
+\"whtCurrencyCode\": \"USD\", \"whtCurrencyAmount\": 10}}\r
+--b--\r
+"
+            )
+        };
+        let archive_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            archive_dir.path().join("1.eml"),
+            message("statements@broker.example"),
+        )
+        .unwrap();
+        std::fs::write(
+            archive_dir.path().join("2.eml"),
+            message("someone@else.example"),
+        )
+        .unwrap();
+
+        for _ in 0..2 {
+            let job_store_mutex = Mutex::new(JobStore::new());
+            let job_id = job_store_mutex.lock().await.create();
+            import_mail_archive(job_id, &job_store_mutex, &store, archive_dir.path())
+                .await
+                .unwrap();
+            let job = job_store_mutex.lock().await.get(job_id).unwrap();
+            assert!(!job
+                .messages
+                .iter()
+                .any(|m| matches!(m, JobMessage::Error { .. })));
+        }
+
+        // Importing the archive again does not duplicate the report
+        let reports = get_reports(&store).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].report_name, "income.json");
+        assert_eq!(reports[0].status, "processed");
+        assert_eq!(get_filings(&store).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_import_drop_folder() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = seeded_store(&data_dir);
+        create_importer(
+            &store,
+            &Importer {
+                id: 0,
+                name: "Broker".into(),
+                report_type: "NativeIncomeJson".into(),
+                taxpayer_profile_id: DEFAULT_TAXPAYER_PROFILE_ID,
+                mailbox_id: 1,
+                from_filter: "".into(),
+                subject_filter: "".into(),
+                payment_notes: "".into(),
+                attachment_regex: "\\.json$".into(),
+                generic_csv_conf: None,
+                group_filings_by_date: false,
+            },
+        )
+        .unwrap();
+        let income = |amount: i32| {
+            format!(
+                r#"{{"type": "dividend", "payingEntity": "Apple", "incomeDate": "2020-07-16",
+                "incomeCurrencyCode": "USD", "incomeCurrencyAmount": {amount},
+                "whtCurrencyCode": "USD", "whtCurrencyAmount": 10}}"#
+            )
+        };
+        let drop_dir = tempfile::tempdir().unwrap();
+        std::fs::write(drop_dir.path().join("income.json"), income(100)).unwrap();
+        std::fs::write(drop_dir.path().join("notes.txt"), "not a report").unwrap();
+
+        let import = || async {
+            let job_store_mutex = Mutex::new(JobStore::new());
+            let job_id = job_store_mutex.lock().await.create();
+            import_drop_folder(job_id, &job_store_mutex, &store, drop_dir.path())
+                .await
+                .unwrap();
+            process_reports(job_id, &job_store_mutex, &store)
+                .await
+                .unwrap();
+            let job = job_store_mutex.lock().await.get(job_id).unwrap();
+            assert!(!job
+                .messages
+                .iter()
+                .any(|m| matches!(m, JobMessage::Error { .. })));
+        };
+        import().await;
+        // Neither the file staying in the folder nor a renamed copy are imported again
+        std::fs::copy(
+            drop_dir.path().join("income.json"),
+            drop_dir.path().join("income (1).json"),
+        )
+        .unwrap();
+        import().await;
+        let reports = get_reports(&store).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].report_name, "income.json");
+        assert_eq!(get_filings(&store).unwrap().len(), 1);
+
+        // A new file under a name that was used before gets its own report
+        std::fs::remove_file(drop_dir.path().join("income (1).json")).unwrap();
+        std::fs::write(drop_dir.path().join("income.json"), income(200)).unwrap();
+        let mut drop_folder_cache = DropFolderCache::default();
+        let new_dropped_files =
+            get_new_dropped_files(&store, drop_dir.path(), &mut drop_folder_cache).unwrap();
+        assert_eq!(new_dropped_files.len(), 1);
+        assert_eq!(new_dropped_files[0].importers.len(), 1);
+        import().await;
+        let reports = get_reports(&store).unwrap();
+        assert_eq!(reports.len(), 2);
+        assert!(reports[1].report_name.starts_with("income.json ("));
+        assert_eq!(get_filings(&store).unwrap().len(), 2);
+        assert!(
+            get_new_dropped_files(&store, drop_dir.path(), &mut drop_folder_cache)
+                .unwrap()
+                .is_empty()
+        );
+    }
 }
@@ -28,6 +28,7 @@ impl JobStore {
         self.jobs.insert(job_id, job);
         job_id
     }
+    #[cfg_attr(not(feature = "desktop"), allow(dead_code))]
     pub fn cancel(&mut self, job_id: i32) {
         self.jobs.get_mut(&job_id).map(|j| {
             j.canceled = true;
@@ -0,0 +1,116 @@
+use chrono::{Days, NaiveDate};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+use crate::{
+    date::format_iso,
+    error::{DkaError, DkaResult},
+    ipc_types::ReferenceRate,
+    money::apply_rate_rsdc,
+};
+
+// Interest on late tax payments accrues at the NBS reference rate plus 10 percentage points
+const LATE_INTEREST_MARGIN_PERCENT: Decimal = dec!(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LateInterestInfo {
+    pub payment_date: NaiveDate,
+    pub days_late: i64,
+    pub interest_rsdc: i64,
+}
+
+fn get_reference_rate(date: &NaiveDate, reference_rates: &[ReferenceRate]) -> DkaResult<Decimal> {
+    let date_string = format_iso(date);
+    reference_rates
+        .iter()
+        .filter(|rr| rr.valid_from <= date_string)
+        .max_by(|a, b| a.valid_from.cmp(&b.valid_from))
+        .map(|rr| rr.rate)
+        .ok_or_else(|| DkaError::user(format!("No reference rate configured for {date_string}")))
+}
+
+/// Simple interest for each day after the deadline, up to and including the payment date
+pub fn get_late_interest_info(
+    tax_payable_rsdc: i64,
+    filing_deadline: &NaiveDate,
+    payment_date: &NaiveDate,
+    reference_rates: &[ReferenceRate],
+) -> DkaResult<LateInterestInfo> {
+    if payment_date <= filing_deadline {
+        return Err(DkaError::user(
+            "Payment date is not after the filing deadline",
+        ));
+    }
+    // Sum of the daily rates, applied to the tax once so that rounding happens only at the end
+    let mut interest_rate = Decimal::ZERO;
+    let mut date = filing_deadline.checked_add_days(Days::new(1)).unwrap();
+    while date <= *payment_date {
+        let annual_rate_percent =
+            get_reference_rate(&date, reference_rates)? + LATE_INTEREST_MARGIN_PERCENT;
+        interest_rate += annual_rate_percent / dec!(100) / dec!(365);
+        date = date.checked_add_days(Days::new(1)).unwrap();
+    }
+    Ok(LateInterestInfo {
+        payment_date: *payment_date,
+        days_late: (*payment_date - *filing_deadline).num_days(),
+        interest_rsdc: apply_rate_rsdc(tax_payable_rsdc, interest_rate),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::parse_iso;
+
+    fn reference_rates() -> Vec<ReferenceRate> {
+        vec![
+            ReferenceRate {
+                valid_from: "2024-06-13".into(),
+                rate: dec!(6.25),
+            },
+            ReferenceRate {
+                valid_from: "2024-07-11".into(),
+                rate: dec!(6.0),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_get_late_interest_info() {
+        // 10 days at 16.25% and 20 days at 16%
+        assert_eq!(
+            get_late_interest_info(
+                1000000,
+                &parse_iso("2024-06-30").unwrap(),
+                &parse_iso("2024-07-30").unwrap(),
+                &reference_rates(),
+            )
+            .unwrap(),
+            LateInterestInfo {
+                payment_date: parse_iso("2024-07-30").unwrap(),
+                days_late: 30,
+                interest_rsdc: 13219,
+            }
+        );
+    }
+
+    #[test]
+    fn test_get_late_interest_info_errors() {
+        // Not late
+        assert!(get_late_interest_info(
+            1000000,
+            &parse_iso("2024-06-30").unwrap(),
+            &parse_iso("2024-06-30").unwrap(),
+            &reference_rates(),
+        )
+        .is_err());
+        // Before the first configured reference rate
+        assert!(get_late_interest_info(
+            1000000,
+            &parse_iso("2024-05-31").unwrap(),
+            &parse_iso("2024-06-30").unwrap(),
+            &reference_rates(),
+        )
+        .is_err());
+    }
+}
@@ -1,38 +1,94 @@
+#[cfg(feature = "desktop")]
+use tauri::Manager;
+
+#[cfg(feature = "desktop")]
+use crate::app_store::AppStore;
+#[cfg(feature = "desktop")]
 use crate::ipc_handlers::{
-    cancel_job, create_importer, create_job, delete_filing, delete_importer, delete_report,
-    export_filing, export_report, get_filings, get_importers, get_job, get_mailbox, get_reports,
-    get_taxpayer_profile, get_technical_conf, import_holiday_conf, import_trivial_report,
-    update_filing, update_importer, update_mailbox, update_taxpayer_profile, update_technical_conf,
+    cancel_job, change_secrets_passphrase, confirm_filing_not_duplicate, create_amended_filing,
+    create_capital_gains_filing, create_importer, create_job, create_mail_archive_job,
+    create_mailbox, create_taxpayer_profile, delete_filing, delete_importer, delete_mailbox,
+    delete_report, delete_taxpayer_profile, export_filing, export_report, get_annual_summary,
+    get_filing_incomes, get_filings, get_importers, get_job, get_mailboxes, get_reports,
+    get_secrets_status, get_taxpayer_profiles, get_technical_conf, import_holiday_conf,
+    import_trivial_report, regenerate_filing, unlock_secrets, update_filing, update_importer,
+    update_mailbox, update_taxpayer_profile, update_technical_conf, watch_drop_folder,
 };
+#[cfg(feature = "desktop")]
+use crate::secrets::{get_default_key_dir, PASSPHRASE_ENV_VAR};
 
+#[cfg(feature = "desktop")]
+mod annual_summary;
+mod app_store;
+#[cfg(feature = "desktop")]
+mod capital_gains;
+mod cli;
 mod database;
 mod date;
+mod degiro_report_parser;
+mod drop_folder;
 mod error;
 mod exchange_rate;
 mod filesystem;
+mod generic_csv_report_parser;
+mod ibkr_flex_report_parser;
 mod ibkr_report_parser;
 mod income_tax;
+#[cfg(feature = "desktop")]
 mod ipc_handlers;
 mod ipc_types;
 mod job_logic;
 mod job_store;
+mod late_interest;
+mod mail_archive;
+#[cfg(test)]
+mod mock_http_server;
+mod money;
+mod oauth;
 mod opo_data;
+mod opo_xml;
+#[cfg(feature = "desktop")]
+mod pdg_data;
+#[cfg(feature = "desktop")]
+mod pdg_xml;
 mod report_parser;
+mod revolut_report_parser;
+mod secrets;
+mod trading212_report_parser;
 mod trivial_report_parser;
 
+pub use cli::run_cli;
+
+#[cfg(feature = "desktop")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            let mut store = AppStore::new(app.path().app_data_dir()?, get_default_key_dir()?);
+            if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV_VAR) {
+                store = store.with_passphrase(passphrase);
+            }
+            app.manage(store);
+            tauri::async_runtime::spawn(watch_drop_folder(app.handle().clone()));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             get_technical_conf,
             update_technical_conf,
             import_holiday_conf,
-            get_mailbox,
+            get_mailboxes,
+            create_mailbox,
             update_mailbox,
-            get_taxpayer_profile,
+            get_secrets_status,
+            unlock_secrets,
+            change_secrets_passphrase,
+            delete_mailbox,
+            get_taxpayer_profiles,
+            create_taxpayer_profile,
             update_taxpayer_profile,
+            delete_taxpayer_profile,
             get_importers,
             update_importer,
             create_importer,
@@ -42,10 +98,17 @@ pub fn run() {
             export_report,
             import_trivial_report,
             get_filings,
+            get_annual_summary,
             update_filing,
             delete_filing,
+            confirm_filing_not_duplicate,
+            create_capital_gains_filing,
+            regenerate_filing,
+            create_amended_filing,
+            get_filing_incomes,
             export_filing,
             create_job,
+            create_mail_archive_job,
             get_job,
             cancel_job,
         ])
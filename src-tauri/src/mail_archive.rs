@@ -0,0 +1,201 @@
+use std::{
+    fs,
+    io::BufReader,
+    iter,
+    path::{Path, PathBuf},
+};
+
+use mail_parser::{mailbox::mbox, Message};
+
+use crate::{
+    error::{DkaError, DkaResult},
+    ipc_types::Importer,
+};
+
+/// Raw messages of a mail archive, read one at a time so that a large archive is never held in
+/// memory as a whole
+pub type MailArchiveMessages = Box<dyn Iterator<Item = DkaResult<Vec<u8>>> + Send>;
+
+/// Reads the raw messages from a local mail archive: a single `.eml` file, an mbox file, a
+/// Maildir directory (including Maildir++ subfolders) or a directory of `.eml` files
+pub fn read_mail_archive(path: &Path) -> DkaResult<MailArchiveMessages> {
+    if path.is_dir() {
+        let message_paths = if is_maildir(path) {
+            get_maildir_message_paths(path)?
+        } else {
+            get_eml_paths(path)?
+        };
+        return Ok(Box::new(
+            message_paths.into_iter().map(|p| Ok(fs::read(p)?)),
+        ));
+    }
+    if is_eml_file(path) {
+        return Ok(Box::new(iter::once(Ok(fs::read(path)?))));
+    }
+    let file = fs::File::open(path)?;
+    let mut messages = mbox::MessageIterator::new(BufReader::new(file)).peekable();
+    if messages.peek().is_none() {
+        return Err(DkaError::User(format!(
+            "{} is not an .eml, mbox or Maildir archive",
+            path.display()
+        )));
+    }
+    Ok(Box::new(messages.map(|m| Ok(m?.unwrap_contents()))))
+}
+
+fn is_eml_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("eml"))
+}
+
+fn is_maildir(path: &Path) -> bool {
+    path.join("cur").is_dir() && path.join("new").is_dir()
+}
+
+/// The message files of the Maildir and its Maildir++ subfolders, oldest first like IMAP UIDs
+fn get_maildir_message_paths(path: &Path) -> DkaResult<Vec<PathBuf>> {
+    let mut folders = vec![path.to_path_buf()];
+    for entry in fs::read_dir(path)? {
+        let folder = entry?.path();
+        if is_hidden(&folder) && is_maildir(&folder) {
+            folders.push(folder);
+        }
+    }
+    let mut messages = vec![];
+    for folder in folders {
+        for sub_dir in ["cur", "new"] {
+            for entry in fs::read_dir(folder.join(sub_dir))? {
+                let message_path = entry?.path();
+                if !message_path.is_file() || is_hidden(&message_path) {
+                    continue;
+                }
+                let modified = fs::metadata(&message_path)?.modified()?;
+                messages.push((modified, message_path));
+            }
+        }
+    }
+    messages.sort();
+    Ok(messages.into_iter().map(|(_, p)| p).collect())
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+fn get_eml_paths(path: &Path) -> DkaResult<Vec<PathBuf>> {
+    let mut eml_paths: Vec<_> = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    eml_paths.retain(|p| p.is_file() && is_eml_file(p));
+    eml_paths.sort();
+    if eml_paths.is_empty() {
+        return Err(DkaError::User(format!(
+            "{} is neither a Maildir nor a directory of .eml files",
+            path.display()
+        )));
+    }
+    Ok(eml_paths)
+}
+
+/// Applies the importer's sender and subject filters the way the IMAP search does
+pub fn message_matches_importer(message: &Message, importer: &Importer) -> bool {
+    let from = message
+        .from()
+        .map(|addresses| {
+            addresses
+                .iter()
+                .map(|a| format!("{} <{}>", a.name().unwrap_or(""), a.address().unwrap_or("")))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+    matches_filter(&importer.from_filter, &from)
+        && matches_filter(&importer.subject_filter, message.subject().unwrap_or(""))
+}
+
+/// Case-insensitive substring match, like IMAP SEARCH. An empty filter matches everything.
+fn matches_filter(filter: &str, value: &str) -> bool {
+    value.to_lowercase().contains(&filter.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_messages(path: &Path) -> DkaResult<Vec<Vec<u8>>> {
+        read_mail_archive(path)?.collect()
+    }
+
+    const MESSAGE: &[u8] = b"From: Interactive Brokers <donotreply@interactivebrokers.com>\r\n\
+Subject: Activity Statement\r\n\
+\r\n\
+Hello\r\n";
+
+    #[test]
+    fn test_read_eml() {
+        let dir = tempfile::tempdir().unwrap();
+        let eml_path = dir.path().join("statement.EML");
+        fs::write(&eml_path, MESSAGE).unwrap();
+        assert_eq!(read_messages(&eml_path).unwrap(), vec![MESSAGE.to_vec()]);
+        // A directory of .eml files, other files are ignored
+        fs::write(dir.path().join("notes.txt"), "not a message").unwrap();
+        fs::write(dir.path().join("another.eml"), MESSAGE).unwrap();
+        assert_eq!(read_messages(dir.path()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_read_mbox() {
+        let dir = tempfile::tempdir().unwrap();
+        let mbox_path = dir.path().join("export.mbox");
+        let mut mbox_content = vec![];
+        for _ in 0..3 {
+            mbox_content.extend_from_slice(
+                b"From donotreply@interactivebrokers.com Mon Jan  6 10:00:00 2025\n",
+            );
+            mbox_content.extend_from_slice(MESSAGE);
+            mbox_content.extend_from_slice(b"\n");
+        }
+        fs::write(&mbox_path, mbox_content).unwrap();
+        let messages = read_messages(&mbox_path).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].starts_with(b"From: Interactive Brokers"));
+
+        fs::write(&mbox_path, "not an archive").unwrap();
+        assert!(matches!(
+            read_mail_archive(&mbox_path).map(|_| ()),
+            Err(DkaError::User(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_maildir() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub_dir in ["cur", "new", "tmp", ".Statements/cur", ".Statements/new"] {
+            fs::create_dir_all(dir.path().join(sub_dir)).unwrap();
+        }
+        fs::write(dir.path().join("cur/1700000000.1.host:2,S"), MESSAGE).unwrap();
+        fs::write(dir.path().join("new/1700000001.2.host"), MESSAGE).unwrap();
+        fs::write(
+            dir.path().join(".Statements/cur/1700000002.3.host:2,S"),
+            MESSAGE,
+        )
+        .unwrap();
+        // Hidden files, e.g. left behind by mail clients, are not messages
+        fs::write(dir.path().join("cur/.index"), "not a message").unwrap();
+        assert_eq!(read_messages(dir.path()).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_matches_filter() {
+        assert!(matches_filter("", "anything"));
+        assert!(matches_filter(
+            "interactivebrokers.com",
+            "Interactive Brokers <donotreply@InteractiveBrokers.com>"
+        ));
+        assert!(!matches_filter(
+            "trading212",
+            "donotreply@interactivebrokers.com"
+        ));
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// Minimal local HTTP server for tests. Each request is answered with the body of the first
+/// route whose prefix matches the request target, or 404. Request bodies are recorded.
+pub struct MockHttpServer {
+    pub base_url: String,
+    request_bodies: Arc<Mutex<Vec<String>>>,
+}
+impl MockHttpServer {
+    pub fn start(routes: Vec<(&'static str, Vec<u8>)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("mock server should bind");
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let request_bodies = Arc::new(Mutex::new(vec![]));
+        let thread_request_bodies = request_bodies.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                let mut content_length = 0;
+                loop {
+                    let mut header_line = String::new();
+                    match reader.read_line(&mut header_line) {
+                        Ok(n) if n > 2 => {
+                            if let Some((name, value)) = header_line.split_once(':') {
+                                if name.eq_ignore_ascii_case("content-length") {
+                                    content_length = value.trim().parse().unwrap_or(0);
+                                }
+                            }
+                        }
+                        _ => break,
+                    }
+                }
+                let mut body = vec![0; content_length];
+                if reader.read_exact(&mut body).is_err() {
+                    continue;
+                }
+                if !body.is_empty() {
+                    thread_request_bodies
+                        .lock()
+                        .unwrap()
+                        .push(String::from_utf8_lossy(&body).to_string());
+                }
+                let target = request_line.split(' ').nth(1).unwrap_or("");
+                let (status, body): (&str, &[u8]) =
+                    match routes.iter().find(|(prefix, _)| target.starts_with(prefix)) {
+                        Some((_, body)) => ("200 OK", body),
+                        None => ("404 Not Found", b""),
+                    };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = stream.write_all(body);
+            }
+        });
+        Self {
+            base_url,
+            request_bodies,
+        }
+    }
+
+    pub fn request_bodies(&self) -> Vec<String> {
+        self.request_bodies.lock().unwrap().clone()
+    }
+}
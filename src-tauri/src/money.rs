@@ -0,0 +1,69 @@
+use rust_decimal::{
+    prelude::{FromPrimitive, ToPrimitive},
+    Decimal, RoundingStrategy,
+};
+use rust_decimal_macros::dec;
+
+fn round_to_para(amount_rsdc: Decimal) -> i64 {
+    amount_rsdc
+        .round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+        .to_i64()
+        .expect("RSD amount out of range")
+}
+
+/// Converts an RSD amount to paras, rounding half away from zero
+pub fn to_rsdc(amount_rsd: Decimal) -> i64 {
+    round_to_para(amount_rsd * dec!(100))
+}
+
+/// Applies a rate (e.g. a tax rate) to an amount in paras, rounding half away from zero
+pub fn apply_rate_rsdc(amount_rsdc: i64, rate: Decimal) -> i64 {
+    round_to_para(Decimal::from(amount_rsdc) * rate)
+}
+
+// SQLite has no decimal type, so amounts and rates are stored as REAL. A decimal with at most 15
+// significant digits survives the round trip exactly. Amounts are parsed from statements and rates
+// come from NBS or are cross rates rounded to four decimal places, so they stay well within that.
+pub fn to_real(value: Decimal) -> f64 {
+    value.to_f64().expect("Decimal out of f64 range")
+}
+
+pub fn from_real(value: f64) -> Decimal {
+    Decimal::from_f64(value)
+        .expect("Non-finite REAL value")
+        .normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_rsdc() {
+        assert_eq!(to_rsdc(dec!(11716.97)), 1171697);
+        assert_eq!(to_rsdc(dec!(0.005)), 1);
+        assert_eq!(to_rsdc(dec!(-0.005)), -1);
+        assert_eq!(to_rsdc(dec!(0.0049999)), 0);
+        // 1.005 is 1.00499999999999989... as an f64, which rounds down
+        assert_eq!(to_rsdc(dec!(1.005)), 101);
+    }
+
+    #[test]
+    fn test_apply_rate_rsdc() {
+        assert_eq!(apply_rate_rsdc(1171697, dec!(0.15)), 175755);
+        assert_eq!(apply_rate_rsdc(10, dec!(0.15)), 2); // 1.5 paras
+    }
+
+    #[test]
+    fn test_real_round_trip() {
+        for value in [
+            dec!(117.1697),
+            dec!(0.1),
+            dec!(3.45),
+            dec!(-1234.5678),
+            dec!(0),
+        ] {
+            assert_eq!(from_real(to_real(value)), value);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use serde::Deserialize;
+
+use crate::{
+    error::{DkaError, DkaResult},
+    ipc_types::MailboxOAuthConf,
+};
+
+// Refresh a bit early, so that the token cannot expire between the check and the IMAP login
+const ACCESS_TOKEN_EXPIRY_MARGIN_SECONDS: i64 = 60;
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+    // Only sent by providers that rotate refresh tokens
+    refresh_token: Option<String>,
+}
+
+/// SASL XOAUTH2 mechanism, as supported by Gmail and Outlook
+pub struct XOAuth2 {
+    pub user: String,
+    pub access_token: String,
+}
+impl imap::Authenticator for XOAuth2 {
+    type Response = String;
+    fn process(&self, challenge: &[u8]) -> String {
+        // A non-empty challenge carries the error details, and must be answered with an empty
+        // response for the server to complete the failed exchange
+        if !challenge.is_empty() {
+            return String::new();
+        }
+        format!(
+            "user={}\x01auth=Bearer {}\x01\x01",
+            self.user, self.access_token
+        )
+    }
+}
+
+/// Returns the conf with an access token valid at `now` (seconds since the Unix epoch), using the
+/// refresh token grant if the stored one is missing or about to expire
+pub async fn refresh_access_token(
+    oauth_conf: &MailboxOAuthConf,
+    now: i64,
+) -> DkaResult<MailboxOAuthConf> {
+    if !oauth_conf.access_token.is_empty()
+        && oauth_conf.access_token_expiry - now > ACCESS_TOKEN_EXPIRY_MARGIN_SECONDS
+    {
+        return Ok(oauth_conf.clone());
+    }
+    let mut form = vec![
+        ("grant_type", "refresh_token"),
+        ("refresh_token", &oauth_conf.refresh_token),
+        ("client_id", &oauth_conf.client_id),
+    ];
+    if !oauth_conf.client_secret.is_empty() {
+        form.push(("client_secret", &oauth_conf.client_secret));
+    }
+    let Ok(response) = reqwest::Client::new()
+        .post(&oauth_conf.token_url)
+        .form(&form)
+        .send()
+        .await
+    else {
+        return Err(DkaError::generic("Error fetching from OAuth token URL"));
+    };
+    let status = response.status();
+    let Ok(body) = response.bytes().await else {
+        return Err(DkaError::generic(
+            "Error fetching body from OAuth token URL",
+        ));
+    };
+    if !status.is_success() {
+        return Err(DkaError::User(format!(
+            "OAuth token refresh failed with {}: {}",
+            status,
+            String::from_utf8_lossy(&body)
+        )));
+    }
+    let token_response: TokenResponse = serde_json::from_slice(&body)
+        .map_err(|e| DkaError::Generic(format!("Could not parse OAuth token response: {}", e)))?;
+    Ok(MailboxOAuthConf {
+        refresh_token: token_response
+            .refresh_token
+            .unwrap_or(oauth_conf.refresh_token.clone()),
+        access_token: token_response.access_token,
+        access_token_expiry: now + token_response.expires_in,
+        ..oauth_conf.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use imap::Authenticator;
+
+    use super::*;
+    use crate::mock_http_server::MockHttpServer;
+
+    fn oauth_conf(token_url: &str) -> MailboxOAuthConf {
+        MailboxOAuthConf {
+            token_url: token_url.into(),
+            client_id: "client-id".into(),
+            client_secret: "client-secret".into(),
+            refresh_token: "refresh-token".into(),
+            access_token: "".into(),
+            access_token_expiry: 0,
+        }
+    }
+
+    #[test]
+    fn test_xoauth2_response() {
+        let xoauth2 = XOAuth2 {
+            user: "someone@gmail.com".into(),
+            access_token: "ya29.token".into(),
+        };
+        assert_eq!(
+            xoauth2.process(b""),
+            "user=someone@gmail.com\x01auth=Bearer ya29.token\x01\x01"
+        );
+        assert_eq!(xoauth2.process(b"{\"status\":\"401\"}"), "");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_access_token() {
+        let server = MockHttpServer::start(vec![(
+            "/token",
+            br#"{"access_token":"new-access-token","expires_in":3599,"token_type":"Bearer"}"#
+                .to_vec(),
+        )]);
+        let refreshed =
+            refresh_access_token(&oauth_conf(&format!("{}/token", server.base_url)), 1000)
+                .await
+                .unwrap();
+        assert_eq!(refreshed.access_token, "new-access-token");
+        assert_eq!(refreshed.access_token_expiry, 4599);
+        assert_eq!(refreshed.refresh_token, "refresh-token");
+        assert_eq!(
+            server.request_bodies(),
+            vec![
+                "grant_type=refresh_token&refresh_token=refresh-token&client_id=client-id&client_secret=client-secret"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_access_token_rotated() {
+        let token_response = r#"{
+            "access_token": "new-access-token",
+            "expires_in": 3600,
+            "refresh_token": "new-refresh-token"
+        }"#;
+        let server = MockHttpServer::start(vec![("/token", token_response.as_bytes().to_vec())]);
+        let refreshed = refresh_access_token(&oauth_conf(&format!("{}/token", server.base_url)), 0)
+            .await
+            .unwrap();
+        assert_eq!(refreshed.refresh_token, "new-refresh-token");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_access_token_still_valid() {
+        let mut conf = oauth_conf("http://127.0.0.1:1/token");
+        conf.access_token = "access-token".into();
+        conf.access_token_expiry = 1000;
+        // Valid for long enough, so the unreachable token URL is not used
+        assert_eq!(refresh_access_token(&conf, 500).await.unwrap(), conf);
+        // About to expire
+        assert!(refresh_access_token(&conf, 950).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_access_token_rejected() {
+        let server = MockHttpServer::start(vec![]);
+        let result =
+            refresh_access_token(&oauth_conf(&format!("{}/token", server.base_url)), 0).await;
+        assert!(matches!(result, Err(DkaError::User(_))));
+    }
+}
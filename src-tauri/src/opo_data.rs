@@ -2,15 +2,21 @@ use chrono::NaiveDate;
 
 use crate::{
     date::format_iso,
-    error::DkaResult,
+    error::{DkaError, DkaResult},
     income_tax::{get_filing_deadline, FilingInfo},
     ipc_types::{HolidayConf, TaxpayerProfile},
+    late_interest::LateInterestInfo,
+    opo_xml::{
+        DeklarisaniPodaciOVrstamaPrihoda, Kamata, PodaciODodatnojKamati, PodaciOKamati,
+        PodaciONacinuOstvarivanjaPrihoda, PodaciOPoreskomObvezniku, PodaciOPrijavi,
+        PodaciOVrstamaPrihoda, PodaciPoreskeDeklaracije, Ukupno,
+    },
 };
 
 const SVP_INTEREST: &str = "111401000";
 const SVP_DIVIDEND: &str = "111402000";
 
-fn format_rsd_amount(amount_rsdc: i64) -> String {
+pub fn format_rsd_amount(amount_rsdc: i64) -> String {
     let whole = format!("{}", amount_rsdc / 100);
     let frac = format!("{:0>2}", amount_rsdc % 100);
     format!("{whole}.{frac}")
@@ -27,16 +33,41 @@ pub struct OpoData {
     pub email: String,
     pub realization_method: String,
     pub filing_deadline: NaiveDate,
-    pub filing_info: FilingInfo,
+    /// One declared income line each, all with the same income date
+    pub filing_infos: Vec<FilingInfo>,
+    /// Set on amended returns, which restate the full corrected amounts of the previously
+    /// submitted filing with this reference
+    pub amended_filing_reference: Option<String>,
+    /// Set when filing after the deadline, to declare the interest owed on the late tax
+    pub late_interest_info: Option<LateInterestInfo>,
 }
+fn get_svp(_type: &str) -> &'static str {
+    if _type == "dividend" {
+        SVP_DIVIDEND
+    } else {
+        SVP_INTEREST
+    }
+}
+
 impl OpoData {
-    pub fn new(
-        pifi: &FilingInfo,
+    pub fn new_grouped(
+        pifis: &[FilingInfo],
         payment_notes: &str,
         taxpayer_profile: &TaxpayerProfile,
         holiday_conf: &HolidayConf,
     ) -> DkaResult<Self> {
-        let filing_deadline = get_filing_deadline(&pifi.income_date, holiday_conf)?;
+        let Some(first_pifi) = pifis.first() else {
+            return Err(DkaError::generic("A filing needs at least one income"));
+        };
+        if pifis
+            .iter()
+            .any(|pifi| pifi.income_date != first_pifi.income_date)
+        {
+            return Err(DkaError::generic(
+                "Incomes in one filing must share an income date",
+            ));
+        }
+        let filing_deadline = get_filing_deadline(&first_pifi.income_date, holiday_conf)?;
         Ok(OpoData {
             jmbg: taxpayer_profile.jmbg.clone(),
             full_name: taxpayer_profile.full_name.clone(),
@@ -47,101 +78,256 @@ impl OpoData {
             email: taxpayer_profile.email_address.clone(),
             realization_method: payment_notes.to_string(),
             filing_deadline,
-            filing_info: pifi.clone(),
+            filing_infos: pifis.to_vec(),
+            amended_filing_reference: None,
+            late_interest_info: None,
         })
     }
-    pub fn fill(&self) -> String {
-        format!(
-            "
-            <ns1:PodaciPoreskeDeklaracije
-                xmlns:xsi='http://www.w3.org/2001/XMLSchema-instance'
-                xmlns:ns1='http://pid.purs.gov.rs'>
-                <ns1:PodaciOPrijavi>
-                    <ns1:VrstaPrijave>1</ns1:VrstaPrijave>
-                    <ns1:ObracunskiPeriod>{}</ns1:ObracunskiPeriod>
-                    <ns1:DatumOstvarivanjaPrihoda>{}</ns1:DatumOstvarivanjaPrihoda>
-                    <ns1:Rok>1</ns1:Rok>
-                    <ns1:DatumDospelostiObaveze>{}</ns1:DatumDospelostiObaveze>
-                    </ns1:PodaciOPrijavi>
-                    <ns1:PodaciOPoreskomObvezniku>
-                        <ns1:PoreskiIdentifikacioniBroj>{}</ns1:PoreskiIdentifikacioniBroj>
-                    <ns1:ImePrezimeObveznika>
-                        <![CDATA[{}]]>
-                    </ns1:ImePrezimeObveznika>
-                    <ns1:UlicaBrojPoreskogObveznika>
-                        <![CDATA[{}]]>
-                    </ns1:UlicaBrojPoreskogObveznika>
-                    <ns1:PrebivalisteOpstina>{}</ns1:PrebivalisteOpstina>
-                    <ns1:JMBGPodnosiocaPrijave>{}</ns1:JMBGPodnosiocaPrijave>
-                    <ns1:TelefonKontaktOsobe>{}</ns1:TelefonKontaktOsobe>
-                    <ns1:ElektronskaPosta>{}</ns1:ElektronskaPosta>
-                    </ns1:PodaciOPoreskomObvezniku>
-                    <ns1:PodaciONacinuOstvarivanjaPrihoda>
-                        <ns1:NacinIsplate>3</ns1:NacinIsplate>
-                    <ns1:Ostalo>{}</ns1:Ostalo>
-                    </ns1:PodaciONacinuOstvarivanjaPrihoda>
-                    <ns1:DeklarisaniPodaciOVrstamaPrihoda>
-                        <ns1:PodaciOVrstamaPrihoda>
-                            <ns1:RedniBroj>1</ns1:RedniBroj>
-                            <ns1:SifraVrstePrihoda>{}</ns1:SifraVrstePrihoda>
-                            <ns1:BrutoPrihod>{}</ns1:BrutoPrihod>
-                            <ns1:OsnovicaZaPorez>{}</ns1:OsnovicaZaPorez>
-                            <ns1:ObracunatiPorez>{}</ns1:ObracunatiPorez>
-                            <ns1:PorezPlacenDrugojDrzavi>{}</ns1:PorezPlacenDrugojDrzavi>
-                            <ns1:PorezZaUplatu>{}</ns1:PorezZaUplatu>
-                        </ns1:PodaciOVrstamaPrihoda>
-                </ns1:DeklarisaniPodaciOVrstamaPrihoda>
-                <ns1:Ukupno>
-                    <ns1:FondSati>0.00</ns1:FondSati>
-                    <ns1:BrutoPrihod>{}</ns1:BrutoPrihod>
-                    <ns1:OsnovicaZaPorez>{}</ns1:OsnovicaZaPorez>
-                    <ns1:ObracunatiPorez>{}</ns1:ObracunatiPorez>
-                    <ns1:PorezPlacenDrugojDrzavi>{}</ns1:PorezPlacenDrugojDrzavi>
-                    <ns1:PorezZaUplatu>{}</ns1:PorezZaUplatu>
-                    <ns1:OsnovicaZaDoprinose>0.00</ns1:OsnovicaZaDoprinose>
-                    <ns1:PIO>0.00</ns1:PIO>
-                    <ns1:ZDRAVSTVO>0.00</ns1:ZDRAVSTVO>
-                    <ns1:NEZAPOSLENOST>0.00</ns1:NEZAPOSLENOST>
-                </ns1:Ukupno>
-                <ns1:Kamata>
-                    <ns1:PorezZaUplatu>0</ns1:PorezZaUplatu>
-                    <ns1:OsnovicaZaDoprinose>0</ns1:OsnovicaZaDoprinose>
-                    <ns1:PIO>0</ns1:PIO>
-                    <ns1:ZDRAVSTVO>0</ns1:ZDRAVSTVO>
-                    <ns1:NEZAPOSLENOST>0</ns1:NEZAPOSLENOST>
-                </ns1:Kamata>
-                <ns1:PodaciODodatnojKamati>
-                </ns1:PodaciODodatnojKamati>
-            </ns1:PodaciPoreskeDeklaracije>
-    ",
-            self.filing_info.income_date.format("%Y-%m"),
-            format_iso(&self.filing_info.income_date),
-            format_iso(&self.filing_deadline),
-            self.jmbg,
-            self.full_name,
-            self.street_address,
-            self.opstina_code,
-            self.filer_jmbg,
-            self.phone_number,
-            self.email,
-            self.realization_method,
-            if self.filing_info._type == "dividend" {
-                SVP_DIVIDEND
-            } else {
-                SVP_INTEREST
+    pub fn get_income_date(&self) -> NaiveDate {
+        self.filing_infos[0].income_date
+    }
+    pub fn get_gross_income_rsdc(&self) -> i64 {
+        self.filing_infos
+            .iter()
+            .map(|fi| fi.gross_income_rsdc)
+            .sum()
+    }
+    pub fn get_wht_actual_rsdc(&self) -> i64 {
+        self.filing_infos.iter().map(|fi| fi.wht_actual_rsdc).sum()
+    }
+    pub fn get_wht_paid_rsdc(&self) -> i64 {
+        self.filing_infos.iter().map(|fi| fi.wht_paid_rsdc).sum()
+    }
+    pub fn get_gross_tax_payable_rsdc(&self) -> i64 {
+        self.filing_infos
+            .iter()
+            .map(|fi| fi.gross_tax_payable_rsdc)
+            .sum()
+    }
+    pub fn get_tax_payable_rsdc(&self) -> i64 {
+        self.filing_infos.iter().map(|fi| fi.tax_payable_rsdc).sum()
+    }
+    fn get_podaci_o_kamati(&self) -> Vec<PodaciOKamati> {
+        let Some(lii) = &self.late_interest_info else {
+            return vec![];
+        };
+        vec![PodaciOKamati {
+            redni_broj: 1,
+            sifra_vrste_prihoda: get_svp(&self.filing_infos[0]._type).into(),
+            osnovica_za_kamatu: format_rsd_amount(self.get_tax_payable_rsdc()),
+            datum_dospelosti: format_iso(&self.filing_deadline),
+            datum_uplate: format_iso(&lii.payment_date),
+            broj_dana: lii.days_late,
+            kamata: format_rsd_amount(lii.interest_rsdc),
+        }]
+    }
+    pub fn get_declaration(&self) -> PodaciPoreskeDeklaracije {
+        let income_date = self.get_income_date();
+        PodaciPoreskeDeklaracije {
+            podaci_o_prijavi: PodaciOPrijavi {
+                vrsta_prijave: if self.amended_filing_reference.is_some() {
+                    2
+                } else {
+                    1
+                },
+                obracunski_period: income_date.format("%Y-%m").to_string(),
+                datum_ostvarivanja_prihoda: format_iso(&income_date),
+                rok: 1,
+                datum_dospelosti_obaveze: format_iso(&self.filing_deadline),
+                broj_izvorne_prijave: self.amended_filing_reference.clone(),
+            },
+            podaci_o_poreskom_obvezniku: PodaciOPoreskomObvezniku {
+                poreski_identifikacioni_broj: self.jmbg.clone(),
+                ime_prezime_obveznika: self.full_name.clone(),
+                ulica_broj_poreskog_obveznika: self.street_address.clone(),
+                prebivaliste_opstina: self.opstina_code.clone(),
+                jmbg_podnosioca_prijave: self.filer_jmbg.clone(),
+                telefon_kontakt_osobe: self.phone_number.clone(),
+                elektronska_posta: self.email.clone(),
+            },
+            podaci_o_nacinu_ostvarivanja_prihoda: PodaciONacinuOstvarivanjaPrihoda {
+                nacin_isplate: 3,
+                ostalo: self.realization_method.clone(),
+            },
+            deklarisani_podaci_o_vrstama_prihoda: DeklarisaniPodaciOVrstamaPrihoda {
+                podaci_o_vrstama_prihoda: self
+                    .filing_infos
+                    .iter()
+                    .enumerate()
+                    .map(|(i, fi)| PodaciOVrstamaPrihoda {
+                        redni_broj: i as u32 + 1,
+                        sifra_vrste_prihoda: get_svp(&fi._type).into(),
+                        bruto_prihod: format_rsd_amount(fi.gross_income_rsdc),
+                        osnovica_za_porez: format_rsd_amount(fi.gross_income_rsdc),
+                        obracunati_porez: format_rsd_amount(fi.gross_tax_payable_rsdc),
+                        porez_placen_drugoj_drzavi: format_rsd_amount(fi.wht_paid_rsdc),
+                        porez_za_uplatu: format_rsd_amount(fi.tax_payable_rsdc),
+                    })
+                    .collect(),
+            },
+            ukupno: Ukupno {
+                fond_sati: format_rsd_amount(0),
+                bruto_prihod: format_rsd_amount(self.get_gross_income_rsdc()),
+                osnovica_za_porez: format_rsd_amount(self.get_gross_income_rsdc()),
+                obracunati_porez: format_rsd_amount(self.get_gross_tax_payable_rsdc()),
+                porez_placen_drugoj_drzavi: format_rsd_amount(self.get_wht_paid_rsdc()),
+                porez_za_uplatu: format_rsd_amount(self.get_tax_payable_rsdc()),
+                osnovica_za_doprinose: format_rsd_amount(0),
+                pio: format_rsd_amount(0),
+                zdravstvo: format_rsd_amount(0),
+                nezaposlenost: format_rsd_amount(0),
             },
-            format_rsd_amount(self.filing_info.gross_income_rsdc),
-            format_rsd_amount(self.filing_info.gross_income_rsdc),
-            format_rsd_amount(self.filing_info.gross_tax_payable_rsdc),
-            format_rsd_amount(self.filing_info.wht_paid_rsdc),
-            format_rsd_amount(self.filing_info.tax_payable_rsdc),
-            format_rsd_amount(self.filing_info.gross_income_rsdc),
-            format_rsd_amount(self.filing_info.gross_income_rsdc),
-            format_rsd_amount(self.filing_info.gross_tax_payable_rsdc),
-            format_rsd_amount(self.filing_info.wht_paid_rsdc),
-            format_rsd_amount(self.filing_info.tax_payable_rsdc),
-        )
+            kamata: Kamata {
+                porez_za_uplatu: format_rsd_amount(
+                    self.late_interest_info
+                        .as_ref()
+                        .map(|lii| lii.interest_rsdc)
+                        .unwrap_or(0),
+                ),
+                osnovica_za_doprinose: format_rsd_amount(0),
+                pio: format_rsd_amount(0),
+                zdravstvo: format_rsd_amount(0),
+                nezaposlenost: format_rsd_amount(0),
+            },
+            podaci_o_dodatnoj_kamati: PodaciODodatnojKamati {
+                podaci_o_kamati: self.get_podaci_o_kamati(),
+            },
+        }
+    }
+    /// Produces the PP-OPO XML, rejecting data which ePorezi would not accept
+    pub fn fill(&self) -> DkaResult<String> {
+        let declaration = self.get_declaration();
+        declaration.validate()?;
+        declaration.to_xml()
     }
 }
 
-// TODO: unit test
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::parse_iso;
+    use rust_decimal_macros::dec;
+
+    fn get_opo_data() -> OpoData {
+        OpoData {
+            jmbg: "0101990710008".into(),
+            full_name: "Petar Petrović".into(),
+            street_address: "Knez Mihailova 1".into(),
+            opstina_code: "013".into(),
+            filer_jmbg: "0101990710008".into(),
+            phone_number: "+381 60 1234567".into(),
+            email: "petar@example.com".into(),
+            realization_method: "Dividends & interest, IBKR".into(),
+            filing_deadline: parse_iso("2025-10-31").unwrap(),
+            filing_infos: vec![FilingInfo {
+                _type: "dividend".into(),
+                paying_entity: "BMW".into(),
+                income_date: parse_iso("2025-10-01").unwrap(),
+                income_currency_code: "EUR".into(),
+                income_currency_amount: dec!(100.0),
+                income_exchange_rate: dec!(117.1697),
+                wht_currency_code: "EUR".into(),
+                wht_currency_amount: dec!(10.0),
+                wht_exchange_rate: dec!(117.1697),
+                gross_income_rsdc: 1171697,
+                wht_actual_rsdc: 117170,
+                wht_credit_rate: None,
+                wht_paid_rsdc: 117170,
+                gross_tax_payable_rsdc: 175755,
+                tax_payable_rsdc: 58585,
+            }],
+            amended_filing_reference: None,
+            late_interest_info: None,
+        }
+    }
+
+    #[test]
+    fn test_opo_data_fill() {
+        let opo_data = get_opo_data();
+        let xml = opo_data.fill().unwrap();
+        assert!(xml.contains("<ns1:Ostalo>Dividends &amp; interest, IBKR</ns1:Ostalo>"));
+        assert!(xml.contains("xmlns:ns1=\"http://pid.purs.gov.rs\""));
+        let declaration: PodaciPoreskeDeklaracije = serde_xml_rs::from_str(&xml).unwrap();
+        assert_eq!(declaration, opo_data.get_declaration());
+        assert_eq!(declaration.podaci_o_prijavi.vrsta_prijave, 1);
+        assert_eq!(
+            declaration
+                .deklarisani_podaci_o_vrstama_prihoda
+                .podaci_o_vrstama_prihoda[0]
+                .porez_za_uplatu,
+            "585.85"
+        );
+    }
+
+    #[test]
+    fn test_opo_data_fill_grouped() {
+        let mut opo_data = get_opo_data();
+        let second_filing_info = FilingInfo {
+            paying_entity: "Siemens".into(),
+            income_currency_amount: dec!(50.0),
+            wht_currency_amount: dec!(0.0),
+            gross_income_rsdc: 585849,
+            wht_actual_rsdc: 0,
+            wht_credit_rate: None,
+            wht_paid_rsdc: 0,
+            gross_tax_payable_rsdc: 87877,
+            tax_payable_rsdc: 87877,
+            ..opo_data.filing_infos[0].clone()
+        };
+        opo_data.filing_infos.push(second_filing_info);
+        let declaration: PodaciPoreskeDeklaracije =
+            serde_xml_rs::from_str(&opo_data.fill().unwrap()).unwrap();
+        let lines = &declaration
+            .deklarisani_podaci_o_vrstama_prihoda
+            .podaci_o_vrstama_prihoda;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].redni_broj, 2);
+        assert_eq!(lines[1].porez_za_uplatu, "878.77");
+        assert_eq!(declaration.ukupno.bruto_prihod, "17575.46");
+        assert_eq!(declaration.ukupno.porez_za_uplatu, "1464.62");
+    }
+
+    #[test]
+    fn test_opo_data_fill_invalid() {
+        let bad_jmbg = OpoData {
+            jmbg: "0101990710007".into(),
+            ..get_opo_data()
+        };
+        assert!(matches!(bad_jmbg.fill(), Err(DkaError::User(_))));
+        let bad_email = OpoData {
+            email: "petar".into(),
+            ..get_opo_data()
+        };
+        assert!(matches!(bad_email.fill(), Err(DkaError::User(_))));
+        let bad_opstina = OpoData {
+            opstina_code: "13".into(),
+            ..get_opo_data()
+        };
+        assert!(matches!(bad_opstina.fill(), Err(DkaError::User(_))));
+        let amendment_without_reference = OpoData {
+            amended_filing_reference: Some(" ".into()),
+            ..get_opo_data()
+        };
+        assert!(matches!(
+            amendment_without_reference.fill(),
+            Err(DkaError::User(_))
+        ));
+    }
+
+    #[test]
+    fn test_opo_data_fill_amendment() {
+        let opo_data = OpoData {
+            amended_filing_reference: Some("97 1234567890".into()),
+            ..get_opo_data()
+        };
+        let xml = opo_data.fill().unwrap();
+        assert!(xml.contains("<ns1:BrojIzvornePrijave>97 1234567890</ns1:BrojIzvornePrijave>"));
+        let declaration: PodaciPoreskeDeklaracije = serde_xml_rs::from_str(&xml).unwrap();
+        assert_eq!(declaration.podaci_o_prijavi.vrsta_prijave, 2);
+        assert_eq!(declaration, opo_data.get_declaration());
+        assert!(!get_opo_data()
+            .fill()
+            .unwrap()
+            .contains("BrojIzvornePrijave"));
+    }
+}
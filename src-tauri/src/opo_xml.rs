@@ -0,0 +1,352 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    date::parse_iso,
+    error::{DkaError, DkaResult},
+};
+
+pub const NS_PID: &str = "http://pid.purs.gov.rs";
+pub const NS_XSI: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "ns1:PodaciPoreskeDeklaracije")]
+pub struct PodaciPoreskeDeklaracije {
+    #[serde(rename = "ns1:PodaciOPrijavi")]
+    pub podaci_o_prijavi: PodaciOPrijavi,
+    #[serde(rename = "ns1:PodaciOPoreskomObvezniku")]
+    pub podaci_o_poreskom_obvezniku: PodaciOPoreskomObvezniku,
+    #[serde(rename = "ns1:PodaciONacinuOstvarivanjaPrihoda")]
+    pub podaci_o_nacinu_ostvarivanja_prihoda: PodaciONacinuOstvarivanjaPrihoda,
+    #[serde(rename = "ns1:DeklarisaniPodaciOVrstamaPrihoda")]
+    pub deklarisani_podaci_o_vrstama_prihoda: DeklarisaniPodaciOVrstamaPrihoda,
+    #[serde(rename = "ns1:Ukupno")]
+    pub ukupno: Ukupno,
+    #[serde(rename = "ns1:Kamata")]
+    pub kamata: Kamata,
+    #[serde(rename = "ns1:PodaciODodatnojKamati")]
+    pub podaci_o_dodatnoj_kamati: PodaciODodatnojKamati,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodaciOPrijavi {
+    #[serde(rename = "ns1:VrstaPrijave")]
+    pub vrsta_prijave: u8,
+    #[serde(rename = "ns1:ObracunskiPeriod")]
+    pub obracunski_period: String,
+    #[serde(rename = "ns1:DatumOstvarivanjaPrihoda")]
+    pub datum_ostvarivanja_prihoda: String,
+    #[serde(rename = "ns1:Rok")]
+    pub rok: u8,
+    #[serde(rename = "ns1:DatumDospelostiObaveze")]
+    pub datum_dospelosti_obaveze: String,
+    // Reference of the submitted filing which an amended return corrects
+    #[serde(
+        rename = "ns1:BrojIzvornePrijave",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub broj_izvorne_prijave: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodaciOPoreskomObvezniku {
+    #[serde(rename = "ns1:PoreskiIdentifikacioniBroj")]
+    pub poreski_identifikacioni_broj: String,
+    #[serde(rename = "ns1:ImePrezimeObveznika")]
+    pub ime_prezime_obveznika: String,
+    #[serde(rename = "ns1:UlicaBrojPoreskogObveznika")]
+    pub ulica_broj_poreskog_obveznika: String,
+    #[serde(rename = "ns1:PrebivalisteOpstina")]
+    pub prebivaliste_opstina: String,
+    #[serde(rename = "ns1:JMBGPodnosiocaPrijave")]
+    pub jmbg_podnosioca_prijave: String,
+    #[serde(rename = "ns1:TelefonKontaktOsobe")]
+    pub telefon_kontakt_osobe: String,
+    #[serde(rename = "ns1:ElektronskaPosta")]
+    pub elektronska_posta: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodaciONacinuOstvarivanjaPrihoda {
+    #[serde(rename = "ns1:NacinIsplate")]
+    pub nacin_isplate: u8,
+    #[serde(rename = "ns1:Ostalo")]
+    pub ostalo: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeklarisaniPodaciOVrstamaPrihoda {
+    #[serde(rename = "ns1:PodaciOVrstamaPrihoda", default)]
+    pub podaci_o_vrstama_prihoda: Vec<PodaciOVrstamaPrihoda>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodaciOVrstamaPrihoda {
+    #[serde(rename = "ns1:RedniBroj")]
+    pub redni_broj: u32,
+    #[serde(rename = "ns1:SifraVrstePrihoda")]
+    pub sifra_vrste_prihoda: String,
+    #[serde(rename = "ns1:BrutoPrihod")]
+    pub bruto_prihod: String,
+    #[serde(rename = "ns1:OsnovicaZaPorez")]
+    pub osnovica_za_porez: String,
+    #[serde(rename = "ns1:ObracunatiPorez")]
+    pub obracunati_porez: String,
+    #[serde(rename = "ns1:PorezPlacenDrugojDrzavi")]
+    pub porez_placen_drugoj_drzavi: String,
+    #[serde(rename = "ns1:PorezZaUplatu")]
+    pub porez_za_uplatu: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ukupno {
+    #[serde(rename = "ns1:FondSati")]
+    pub fond_sati: String,
+    #[serde(rename = "ns1:BrutoPrihod")]
+    pub bruto_prihod: String,
+    #[serde(rename = "ns1:OsnovicaZaPorez")]
+    pub osnovica_za_porez: String,
+    #[serde(rename = "ns1:ObracunatiPorez")]
+    pub obracunati_porez: String,
+    #[serde(rename = "ns1:PorezPlacenDrugojDrzavi")]
+    pub porez_placen_drugoj_drzavi: String,
+    #[serde(rename = "ns1:PorezZaUplatu")]
+    pub porez_za_uplatu: String,
+    #[serde(rename = "ns1:OsnovicaZaDoprinose")]
+    pub osnovica_za_doprinose: String,
+    #[serde(rename = "ns1:PIO")]
+    pub pio: String,
+    #[serde(rename = "ns1:ZDRAVSTVO")]
+    pub zdravstvo: String,
+    #[serde(rename = "ns1:NEZAPOSLENOST")]
+    pub nezaposlenost: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Kamata {
+    #[serde(rename = "ns1:PorezZaUplatu")]
+    pub porez_za_uplatu: String,
+    #[serde(rename = "ns1:OsnovicaZaDoprinose")]
+    pub osnovica_za_doprinose: String,
+    #[serde(rename = "ns1:PIO")]
+    pub pio: String,
+    #[serde(rename = "ns1:ZDRAVSTVO")]
+    pub zdravstvo: String,
+    #[serde(rename = "ns1:NEZAPOSLENOST")]
+    pub nezaposlenost: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodaciODodatnojKamati {
+    #[serde(rename = "ns1:PodaciOKamati", default)]
+    pub podaci_o_kamati: Vec<PodaciOKamati>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodaciOKamati {
+    #[serde(rename = "ns1:RedniBroj")]
+    pub redni_broj: u32,
+    #[serde(rename = "ns1:SifraVrstePrihoda")]
+    pub sifra_vrste_prihoda: String,
+    #[serde(rename = "ns1:OsnovicaZaKamatu")]
+    pub osnovica_za_kamatu: String,
+    #[serde(rename = "ns1:DatumDospelosti")]
+    pub datum_dospelosti: String,
+    #[serde(rename = "ns1:DatumUplate")]
+    pub datum_uplate: String,
+    #[serde(rename = "ns1:BrojDana")]
+    pub broj_dana: i64,
+    #[serde(rename = "ns1:Kamata")]
+    pub kamata: String,
+}
+
+fn invalid(field: &str, reason: &str) -> DkaError {
+    DkaError::user(format!("Invalid PP-OPO field {field}: {reason}"))
+}
+
+/// The check digit of a JMBG is defined by the Law on the Unique Master Citizen Number (Zakon o
+/// jedinstvenom matičnom broju građana), as 11 minus the weighted digit sum modulo 11
+fn validate_jmbg(field: &str, jmbg: &str) -> DkaResult<()> {
+    let digits: Vec<u32> = jmbg.chars().filter_map(|c| c.to_digit(10)).collect();
+    if jmbg.len() != 13 || digits.len() != 13 {
+        return Err(invalid(field, "JMBG must have 13 digits"));
+    }
+    let weighted_sum: u32 = (0..6)
+        .map(|i| (7 - i as u32) * (digits[i] + digits[i + 6]))
+        .sum();
+    let checksum = match 11 - weighted_sum % 11 {
+        10 | 11 => 0,
+        m => m,
+    };
+    if checksum != digits[12] {
+        return Err(invalid(field, "JMBG check digit does not match"));
+    }
+    Ok(())
+}
+
+fn validate_date(field: &str, date: &str) -> DkaResult<()> {
+    parse_iso(date)
+        .map(|_| ())
+        .map_err(|_| invalid(field, "expected a YYYY-MM-DD date"))
+}
+
+fn validate_not_empty(field: &str, value: &str) -> DkaResult<()> {
+    if value.trim().is_empty() {
+        return Err(invalid(field, "must not be empty"));
+    }
+    Ok(())
+}
+
+fn parse_amount(field: &str, amount: &str) -> DkaResult<i64> {
+    let Some((whole, frac)) = amount.split_once('.') else {
+        return Err(invalid(field, "expected an amount with 2 decimal places"));
+    };
+    if whole.is_empty()
+        || frac.len() != 2
+        || !whole.chars().all(|c| c.is_ascii_digit())
+        || !frac.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid(field, "expected an amount with 2 decimal places"));
+    }
+    let whole: i64 = whole
+        .parse()
+        .map_err(|_| invalid(field, "amount is out of range"))?;
+    let frac: i64 = frac.parse().unwrap();
+    Ok(whole * 100 + frac)
+}
+
+impl PodaciPoreskeDeklaracije {
+    /// Rejects declarations with malformed identifiers, contact details, dates or amounts, or with
+    /// totals that do not add up. Field lengths are not checked, since no limits have been
+    /// confirmed against the published PP-OPO schema.
+    pub fn validate(&self) -> DkaResult<()> {
+        let pop = &self.podaci_o_prijavi;
+        match (pop.vrsta_prijave, &pop.broj_izvorne_prijave) {
+            (1, None) => {}
+            (1, Some(_)) => {
+                return Err(invalid(
+                    "BrojIzvornePrijave",
+                    "is only allowed on amended returns",
+                ))
+            }
+            (2, Some(broj_izvorne_prijave)) => {
+                validate_not_empty("BrojIzvornePrijave", broj_izvorne_prijave)?
+            }
+            (2, None) => {
+                return Err(invalid(
+                    "BrojIzvornePrijave",
+                    "is required on amended returns",
+                ))
+            }
+            _ => return Err(invalid("VrstaPrijave", "must be 1 or 2")),
+        }
+        if parse_iso(&format!("{}-01", pop.obracunski_period)).is_err() {
+            return Err(invalid("ObracunskiPeriod", "expected a YYYY-MM month"));
+        }
+        validate_date("DatumOstvarivanjaPrihoda", &pop.datum_ostvarivanja_prihoda)?;
+        validate_date("DatumDospelostiObaveze", &pop.datum_dospelosti_obaveze)?;
+
+        let popo = &self.podaci_o_poreskom_obvezniku;
+        validate_jmbg(
+            "PoreskiIdentifikacioniBroj",
+            &popo.poreski_identifikacioni_broj,
+        )?;
+        validate_not_empty("ImePrezimeObveznika", &popo.ime_prezime_obveznika)?;
+        validate_not_empty(
+            "UlicaBrojPoreskogObveznika",
+            &popo.ulica_broj_poreskog_obveznika,
+        )?;
+        if popo.prebivaliste_opstina.len() != 3
+            || !popo
+                .prebivaliste_opstina
+                .chars()
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid("PrebivalisteOpstina", "expected a 3 digit code"));
+        }
+        validate_jmbg("JMBGPodnosiocaPrijave", &popo.jmbg_podnosioca_prijave)?;
+        if !popo
+            .telefon_kontakt_osobe
+            .chars()
+            .all(|c| c.is_ascii_digit() || " +-/()".contains(c))
+        {
+            return Err(invalid(
+                "TelefonKontaktOsobe",
+                "may only contain digits, spaces and +-/()",
+            ));
+        }
+        let email_valid = match popo.elektronska_posta.split_once('@') {
+            Some((local, domain)) => {
+                !local.is_empty() && domain.contains('.') && !domain.contains('@')
+            }
+            None => false,
+        };
+        if !email_valid || popo.elektronska_posta.contains(char::is_whitespace) {
+            return Err(invalid("ElektronskaPosta", "expected an email address"));
+        }
+
+        let lines = &self
+            .deklarisani_podaci_o_vrstama_prihoda
+            .podaci_o_vrstama_prihoda;
+        if lines.is_empty() {
+            return Err(invalid(
+                "DeklarisaniPodaciOVrstamaPrihoda",
+                "at least one income line is required",
+            ));
+        }
+        let mut totals = [0i64; 5];
+        for (i, line) in lines.iter().enumerate() {
+            if line.redni_broj != i as u32 + 1 {
+                return Err(invalid("RedniBroj", "lines must be numbered from 1"));
+            }
+            if line.sifra_vrste_prihoda.len() != 9
+                || !line.sifra_vrste_prihoda.chars().all(|c| c.is_ascii_digit())
+            {
+                return Err(invalid("SifraVrstePrihoda", "expected a 9 digit code"));
+            }
+            let amounts = [
+                parse_amount("BrutoPrihod", &line.bruto_prihod)?,
+                parse_amount("OsnovicaZaPorez", &line.osnovica_za_porez)?,
+                parse_amount("ObracunatiPorez", &line.obracunati_porez)?,
+                parse_amount("PorezPlacenDrugojDrzavi", &line.porez_placen_drugoj_drzavi)?,
+                parse_amount("PorezZaUplatu", &line.porez_za_uplatu)?,
+            ];
+            for (total, amount) in totals.iter_mut().zip(amounts) {
+                *total += amount;
+            }
+        }
+        let ukupno = &self.ukupno;
+        let ukupno_amounts = [
+            ("BrutoPrihod", &ukupno.bruto_prihod),
+            ("OsnovicaZaPorez", &ukupno.osnovica_za_porez),
+            ("ObracunatiPorez", &ukupno.obracunati_porez),
+            (
+                "PorezPlacenDrugojDrzavi",
+                &ukupno.porez_placen_drugoj_drzavi,
+            ),
+            ("PorezZaUplatu", &ukupno.porez_za_uplatu),
+        ];
+        for ((field, amount), total) in ukupno_amounts.into_iter().zip(totals) {
+            if parse_amount(field, amount)? != total {
+                return Err(invalid(field, "total does not match the income lines"));
+            }
+        }
+
+        parse_amount("PorezZaUplatu", &self.kamata.porez_za_uplatu)?;
+        for pok in self.podaci_o_dodatnoj_kamati.podaci_o_kamati.iter() {
+            validate_date("DatumDospelosti", &pok.datum_dospelosti)?;
+            validate_date("DatumUplate", &pok.datum_uplate)?;
+            parse_amount("OsnovicaZaKamatu", &pok.osnovica_za_kamatu)?;
+            parse_amount("Kamata", &pok.kamata)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_xml(&self) -> DkaResult<String> {
+        serde_xml_rs::SerdeXml::new()
+            .namespace("ns1", NS_PID)
+            .namespace("xsi", NS_XSI)
+            .to_string(self)
+            .map_err(DkaError::generic)
+    }
+}
@@ -0,0 +1,138 @@
+use chrono::NaiveDate;
+
+use crate::{
+    capital_gains::CapitalGainsFilingInfo,
+    date::format_iso,
+    error::DkaResult,
+    income_tax::get_filing_deadline,
+    ipc_types::{HolidayConf, TaxpayerProfile},
+    opo_data::format_rsd_amount,
+    pdg_xml::{
+        DeklarisaniPodaciOPrenosima, PodaciOPoreskomObvezniku, PodaciOPrenosu, PodaciOPrijavi,
+        PodaciPoreskeDeklaracije, Ukupno,
+    },
+};
+
+const CAPITAL_GAINS_TAX_RATE_PERCENT: &str = "15.00";
+
+#[derive(Debug, Clone)]
+pub struct PdgData {
+    pub jmbg: String,
+    pub full_name: String,
+    pub street_address: String,
+    pub opstina_code: String,
+    pub phone_number: String,
+    pub email: String,
+    pub filing_deadline: NaiveDate,
+    pub filing_info: CapitalGainsFilingInfo,
+}
+impl PdgData {
+    pub fn new(
+        cgfi: &CapitalGainsFilingInfo,
+        taxpayer_profile: &TaxpayerProfile,
+        holiday_conf: &HolidayConf,
+    ) -> DkaResult<Self> {
+        // PP-PDG-1R is due 30 days after the end of the half year
+        let filing_deadline = get_filing_deadline(&cgfi.period_end, holiday_conf)?;
+        Ok(PdgData {
+            jmbg: taxpayer_profile.jmbg.clone(),
+            full_name: taxpayer_profile.full_name.clone(),
+            street_address: taxpayer_profile.street_address.clone(),
+            opstina_code: taxpayer_profile.opstina_code.clone(),
+            phone_number: taxpayer_profile.phone_number.clone(),
+            email: taxpayer_profile.email_address.clone(),
+            filing_deadline,
+            filing_info: cgfi.clone(),
+        })
+    }
+    pub fn get_declaration(&self) -> PodaciPoreskeDeklaracije {
+        PodaciPoreskeDeklaracije {
+            podaci_o_prijavi: PodaciOPrijavi {
+                vrsta_prijave: 1,
+                period_od: format_iso(&self.filing_info.period_start),
+                period_do: format_iso(&self.filing_info.period_end),
+                datum_dospelosti_obaveze: format_iso(&self.filing_deadline),
+            },
+            podaci_o_poreskom_obvezniku: PodaciOPoreskomObvezniku {
+                poreski_identifikacioni_broj: self.jmbg.clone(),
+                ime_prezime_obveznika: self.full_name.clone(),
+                ulica_broj_poreskog_obveznika: self.street_address.clone(),
+                prebivaliste_opstina: self.opstina_code.clone(),
+                telefon_kontakt_osobe: self.phone_number.clone(),
+                elektronska_posta: self.email.clone(),
+            },
+            deklarisani_podaci_o_prenosima: DeklarisaniPodaciOPrenosima {
+                podaci_o_prenosu: self
+                    .filing_info
+                    .capital_gain_infos
+                    .iter()
+                    .filter(|cgi| !cgi.tax_exempt)
+                    .enumerate()
+                    .map(|(i, cgi)| PodaciOPrenosu {
+                        redni_broj: i as u32 + 1,
+                        vrsta_imovine: 4,
+                        opis_imovine: format!("{} {}", cgi.isin, cgi.symbol),
+                        kolicina: cgi.quantity.to_string(),
+                        datum_sticanja: format_iso(&cgi.acquisition_date),
+                        nabavna_cena: format_rsd_amount(cgi.acquisition_value_rsdc),
+                        datum_prenosa: format_iso(&cgi.disposal_date),
+                        prodajna_cena: format_rsd_amount(cgi.disposal_value_rsdc),
+                        kapitalni_dobitak: format_rsd_amount(cgi.capital_gain_rsdc.max(0)),
+                        kapitalni_gubitak: format_rsd_amount((-cgi.capital_gain_rsdc).max(0)),
+                    })
+                    .collect(),
+            },
+            ukupno: Ukupno {
+                kapitalni_dobitak: format_rsd_amount(self.filing_info.capital_gain_rsdc),
+                kapitalni_gubitak: format_rsd_amount(self.filing_info.capital_loss_rsdc),
+                poreska_stopa: CAPITAL_GAINS_TAX_RATE_PERCENT.into(),
+                porez_za_uplatu: format_rsd_amount(self.filing_info.tax_payable_rsdc),
+            },
+        }
+    }
+    pub fn fill(&self) -> DkaResult<String> {
+        self.get_declaration().to_xml()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{capital_gains::CapitalGainInfo, date::parse_iso};
+
+    #[test]
+    fn test_pdg_data_fill() {
+        let cgfi = CapitalGainsFilingInfo::new(
+            parse_iso("2023-01-01").unwrap(),
+            parse_iso("2023-06-30").unwrap(),
+            vec![CapitalGainInfo {
+                isin: "US0378331005".into(),
+                symbol: "AT&T <T>".into(),
+                quantity: dec!(10),
+                acquisition_date: parse_iso("2023-01-10").unwrap(),
+                disposal_date: parse_iso("2023-03-01").unwrap(),
+                acquisition_value_rsdc: 10000000,
+                disposal_value_rsdc: 12000000,
+                capital_gain_rsdc: 2000000,
+                tax_exempt: false,
+            }],
+        );
+        let pdg_data = PdgData {
+            jmbg: "0101990710008".into(),
+            full_name: "Petar Petrović".into(),
+            street_address: "Knez Mihailova 1".into(),
+            opstina_code: "013".into(),
+            phone_number: "0601234567".into(),
+            email: "petar@example.com".into(),
+            filing_deadline: parse_iso("2023-07-31").unwrap(),
+            filing_info: cgfi,
+        };
+        let xml = pdg_data.fill().unwrap();
+        assert!(xml.contains("<ns1:OpisImovine>US0378331005 AT&amp;T &lt;T&gt;</ns1:OpisImovine>"));
+        let declaration: PodaciPoreskeDeklaracije = serde_xml_rs::from_str(&xml).unwrap();
+        assert_eq!(declaration, pdg_data.get_declaration());
+        assert_eq!(declaration.ukupno.porez_za_uplatu, "3000.00");
+    }
+}
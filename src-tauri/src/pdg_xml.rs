@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{DkaError, DkaResult},
+    opo_xml::{NS_PID, NS_XSI},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "ns1:PodaciPoreskeDeklaracije")]
+pub struct PodaciPoreskeDeklaracije {
+    #[serde(rename = "ns1:PodaciOPrijavi")]
+    pub podaci_o_prijavi: PodaciOPrijavi,
+    #[serde(rename = "ns1:PodaciOPoreskomObvezniku")]
+    pub podaci_o_poreskom_obvezniku: PodaciOPoreskomObvezniku,
+    #[serde(rename = "ns1:DeklarisaniPodaciOPrenosima")]
+    pub deklarisani_podaci_o_prenosima: DeklarisaniPodaciOPrenosima,
+    #[serde(rename = "ns1:Ukupno")]
+    pub ukupno: Ukupno,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodaciOPrijavi {
+    #[serde(rename = "ns1:VrstaPrijave")]
+    pub vrsta_prijave: u8,
+    #[serde(rename = "ns1:PeriodOd")]
+    pub period_od: String,
+    #[serde(rename = "ns1:PeriodDo")]
+    pub period_do: String,
+    #[serde(rename = "ns1:DatumDospelostiObaveze")]
+    pub datum_dospelosti_obaveze: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodaciOPoreskomObvezniku {
+    #[serde(rename = "ns1:PoreskiIdentifikacioniBroj")]
+    pub poreski_identifikacioni_broj: String,
+    #[serde(rename = "ns1:ImePrezimeObveznika")]
+    pub ime_prezime_obveznika: String,
+    #[serde(rename = "ns1:UlicaBrojPoreskogObveznika")]
+    pub ulica_broj_poreskog_obveznika: String,
+    #[serde(rename = "ns1:PrebivalisteOpstina")]
+    pub prebivaliste_opstina: String,
+    #[serde(rename = "ns1:TelefonKontaktOsobe")]
+    pub telefon_kontakt_osobe: String,
+    #[serde(rename = "ns1:ElektronskaPosta")]
+    pub elektronska_posta: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeklarisaniPodaciOPrenosima {
+    #[serde(rename = "ns1:PodaciOPrenosu", default)]
+    pub podaci_o_prenosu: Vec<PodaciOPrenosu>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodaciOPrenosu {
+    #[serde(rename = "ns1:RedniBroj")]
+    pub redni_broj: u32,
+    #[serde(rename = "ns1:VrstaImovine")]
+    pub vrsta_imovine: u8,
+    #[serde(rename = "ns1:OpisImovine")]
+    pub opis_imovine: String,
+    #[serde(rename = "ns1:Kolicina")]
+    pub kolicina: String,
+    #[serde(rename = "ns1:DatumSticanja")]
+    pub datum_sticanja: String,
+    #[serde(rename = "ns1:NabavnaCena")]
+    pub nabavna_cena: String,
+    #[serde(rename = "ns1:DatumPrenosa")]
+    pub datum_prenosa: String,
+    #[serde(rename = "ns1:ProdajnaCena")]
+    pub prodajna_cena: String,
+    #[serde(rename = "ns1:KapitalniDobitak")]
+    pub kapitalni_dobitak: String,
+    #[serde(rename = "ns1:KapitalniGubitak")]
+    pub kapitalni_gubitak: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ukupno {
+    #[serde(rename = "ns1:KapitalniDobitak")]
+    pub kapitalni_dobitak: String,
+    #[serde(rename = "ns1:KapitalniGubitak")]
+    pub kapitalni_gubitak: String,
+    #[serde(rename = "ns1:PoreskaStopa")]
+    pub poreska_stopa: String,
+    #[serde(rename = "ns1:PorezZaUplatu")]
+    pub porez_za_uplatu: String,
+}
+
+impl PodaciPoreskeDeklaracije {
+    pub fn to_xml(&self) -> DkaResult<String> {
+        serde_xml_rs::SerdeXml::new()
+            .namespace("ns1", NS_PID)
+            .namespace("xsi", NS_XSI)
+            .to_string(self)
+            .map_err(DkaError::generic)
+    }
+}
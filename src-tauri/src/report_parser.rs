@@ -1,40 +1,123 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 use chrono::NaiveDate;
+use rust_decimal::Decimal;
 
 use crate::{
+    date::format_iso,
+    degiro_report_parser::DegiroReportParser,
     error::DkaResult,
+    exchange_rate::ExchangeRateProvider,
+    filesystem::get_content_hash,
+    generic_csv_report_parser::GenericCsvReportParser,
+    ibkr_flex_report_parser::IbkrFlexReportParser,
+    ibkr_report_parser::IbkrReportParser,
     income_tax::{get_filing_info, FilingInfo},
+    ipc_types::{GenericCsvConf, WhtTreatyRate},
+    revolut_report_parser::RevolutReportParser,
+    trading212_report_parser::Trading212ReportParser,
+    trivial_report_parser::TrivialReportParser,
 };
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct IncomeInfo {
     pub _type: String,
     pub paying_entity: String,
+    // The first two letters give the source country, for treaty withholding tax limits
+    pub isin: Option<String>,
     pub income_date: NaiveDate,
     pub income_currency_code: String,
-    pub income_currency_amount: f64,
+    pub income_currency_amount: Decimal,
     pub wht_currency_code: String,
-    pub wht_currency_amount: f64,
+    pub wht_currency_amount: Decimal,
+}
+impl IncomeInfo {
+    /// Identifies the income regardless of the report it comes from. Brokers format the same
+    /// income differently between exports, hence the normalization.
+    pub fn get_income_hash(&self) -> String {
+        let normalized = [
+            self._type.clone(),
+            format_iso(&self.income_date),
+            self.paying_entity.trim().to_lowercase(),
+            self.isin.as_deref().unwrap_or("").trim().to_uppercase(),
+            self.income_currency_code.trim().to_uppercase(),
+            self.income_currency_amount.normalize().to_string(),
+        ];
+        get_content_hash(normalized.join("\n").as_bytes())
+    }
+}
+
+/// An income filed from a report, recognized by its income hash
+#[derive(Debug, Clone, PartialEq)]
+pub struct FiledIncome {
+    pub income_hash: String,
+    pub report_id: i32,
+    pub taxpayer_profile_id: i32,
+    pub importer_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeInfo {
+    pub isin: String,
+    pub symbol: String,
+    pub trade_date: NaiveDate,
+    pub currency_code: String,
+    pub quantity: Decimal, // Positive for acquisitions, negative for disposals
+    pub proceeds: Decimal, // Negative for acquisitions, positive for disposals
+    pub commission: Decimal, // Negative or zero
+}
+
+/// Identifies the trades regardless of the report they come from, like the income hash does for
+/// incomes. Identical trades within a report, e.g. two fills at the same price, are told apart
+/// by their order.
+pub fn get_trade_hashes(trade_infos: &[TradeInfo]) -> Vec<String> {
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    trade_infos
+        .iter()
+        .map(|ti| {
+            let normalized = [
+                ti.isin.trim().to_uppercase(),
+                format_iso(&ti.trade_date),
+                ti.quantity.normalize().to_string(),
+                ti.proceeds.normalize().to_string(),
+                ti.commission.normalize().to_string(),
+            ]
+            .join("\n");
+            let occurrence = occurrences.entry(normalized.clone()).or_default();
+            *occurrence += 1;
+            get_content_hash(format!("{normalized}\n{occurrence}").as_bytes())
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ExchangeRateInfo {
     pub date: NaiveDate,
     pub currency_code: String,
-    pub currency_to_base_currency_rate: f64,
+    pub currency_to_base_currency_rate: Decimal,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ParsedReport {
     pub income_infos: Vec<IncomeInfo>,
+    pub trade_infos: Vec<TradeInfo>,
     pub exchange_rate_infos: Vec<ExchangeRateInfo>,
 }
 impl ParsedReport {
-    pub async fn get_filing_infos(&self) -> DkaResult<Vec<FilingInfo>> {
+    pub async fn get_filing_infos(
+        &self,
+        wht_treaty_rates: &[WhtTreatyRate],
+        exchange_rate_provider: &mut impl ExchangeRateProvider,
+    ) -> DkaResult<Vec<FilingInfo>> {
         let mut filing_infos: Vec<FilingInfo> = vec![];
         for income_info in &self.income_infos {
-            let filing_info = get_filing_info(income_info, &self.exchange_rate_infos).await?;
+            let filing_info = get_filing_info(
+                income_info,
+                &self.exchange_rate_infos,
+                wht_treaty_rates,
+                exchange_rate_provider,
+            )
+            .await?;
             filing_infos.push(filing_info);
         }
         Ok(filing_infos)
@@ -44,3 +127,83 @@ impl ParsedReport {
 pub trait ReportParser {
     fn parse(&self, report_path: PathBuf) -> DkaResult<ParsedReport>;
 }
+
+/// The parser for a report type, none for unknown types. Generic CSV reports are parsed with the
+/// column mapping of their importer.
+pub fn get_report_parser(
+    report_type: &str,
+    generic_csv_conf: Option<GenericCsvConf>,
+) -> Option<Box<dyn ReportParser>> {
+    match report_type {
+        "NativeIncomeJson" => Some(Box::new(TrivialReportParser::new())),
+        "IbkrCsv" => Some(Box::new(IbkrReportParser::new())),
+        "IbkrFlexXml" => Some(Box::new(IbkrFlexReportParser::new())),
+        "Trading212Csv" => Some(Box::new(Trading212ReportParser::new())),
+        "RevolutCsv" => Some(Box::new(RevolutReportParser::new())),
+        "DegiroCsv" => Some(Box::new(DegiroReportParser::new())),
+        "GenericCsv" => generic_csv_conf
+            .map(|conf| Box::new(GenericCsvReportParser::new(conf)) as Box<dyn ReportParser>),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn test_get_income_hash() {
+        let income_info = IncomeInfo {
+            _type: "dividend".into(),
+            paying_entity: "Apple Inc".into(),
+            isin: Some("US0378331005".into()),
+            income_date: NaiveDate::from_ymd_opt(2020, 7, 16).unwrap(),
+            income_currency_code: "USD".into(),
+            income_currency_amount: dec!(100.00),
+            wht_currency_code: "USD".into(),
+            wht_currency_amount: dec!(15),
+        };
+        // Formatting differences and the withholding tax do not matter
+        let reformatted = IncomeInfo {
+            paying_entity: " APPLE INC".into(),
+            isin: Some("us0378331005".into()),
+            income_currency_amount: dec!(100),
+            wht_currency_amount: dec!(0),
+            ..income_info.clone()
+        };
+        assert_eq!(income_info.get_income_hash(), reformatted.get_income_hash());
+        let other_date = IncomeInfo {
+            income_date: NaiveDate::from_ymd_opt(2020, 10, 16).unwrap(),
+            ..income_info.clone()
+        };
+        assert_ne!(income_info.get_income_hash(), other_date.get_income_hash());
+    }
+
+    #[test]
+    fn test_get_trade_hashes() {
+        let trade_info = TradeInfo {
+            isin: "US0378331005".into(),
+            symbol: "AAPL".into(),
+            trade_date: NaiveDate::from_ymd_opt(2023, 1, 13).unwrap(),
+            currency_code: "USD".into(),
+            quantity: dec!(10),
+            proceeds: dec!(-1250.70),
+            commission: dec!(-1),
+        };
+        let reformatted = TradeInfo {
+            symbol: "AAPL.OLD".into(),
+            quantity: dec!(10.0),
+            proceeds: dec!(-1250.7),
+            ..trade_info.clone()
+        };
+        let trade_hashes = get_trade_hashes(&[trade_info.clone(), trade_info.clone()]);
+        // Two fills at the same price are two trades
+        assert_ne!(trade_hashes[0], trade_hashes[1]);
+        assert_eq!(
+            get_trade_hashes(&[reformatted.clone(), reformatted]),
+            trade_hashes
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use chrono::NaiveDate;
+use csv::StringRecord;
+use rust_decimal::Decimal;
+
+use crate::{
+    error::{DkaError, DkaResult},
+    report_parser::{IncomeInfo, ParsedReport, ReportParser},
+};
+
+pub struct RevolutReportParser {}
+impl RevolutReportParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+const DIVIDEND_TYPES: &[&str] = &["DIVIDEND"];
+const WHT_TYPES: &[&str] = &["DIVIDEND TAX", "DIVIDEND TAX (CORRECTION)"];
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+struct DividendKey {
+    date: NaiveDate,
+    entity_name: String,
+}
+
+struct Columns {
+    date: usize,
+    ticker: usize,
+    _type: usize,
+    total_amount: usize,
+    currency: usize,
+}
+impl Columns {
+    fn from_header(header: &StringRecord) -> DkaResult<Self> {
+        let require = |name: &str| {
+            header.iter().position(|h| h == name).ok_or_else(|| {
+                DkaError::Generic(format!("Revolut report is missing column: {}", name))
+            })
+        };
+        Ok(Columns {
+            date: require("Date")?,
+            ticker: require("Ticker")?,
+            _type: require("Type")?,
+            total_amount: require("Total Amount")?,
+            currency: require("Currency")?,
+        })
+    }
+}
+
+fn parse_date(s: &str) -> DkaResult<NaiveDate> {
+    // Dates look like "2023-01-12T14:31:03.789Z"
+    let date_str = s.split('T').next().unwrap_or("");
+    NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+        .map_err(|_| DkaError::Generic(format!("Failed to parse date: {}", s)))
+}
+
+fn parse_amount(s: &str) -> DkaResult<Decimal> {
+    // Amounts look like "USD 1,068.00", "-USD 0.35" or "$2.30"
+    let amount_str: String = s
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.' || *c == '-')
+        .collect();
+    amount_str
+        .parse()
+        .map_err(|e| DkaError::Generic(format!("Could not parse number: {}", e)))
+}
+
+fn get_dividend_incomes(
+    columns: &Columns,
+    string_records: &[StringRecord],
+) -> DkaResult<Vec<IncomeInfo>> {
+    let mut dividend_info_map: HashMap<DividendKey, IncomeInfo> = HashMap::new();
+    for row in string_records
+        .iter()
+        .filter(|row| DIVIDEND_TYPES.contains(&&row[columns._type]))
+    {
+        let income_date = parse_date(&row[columns.date])?;
+        let paying_entity = row[columns.ticker].to_string();
+        let dividend_currency_code = row[columns.currency].to_string();
+        let dividend_currency_amount = parse_amount(&row[columns.total_amount])?;
+        match dividend_info_map.get_mut(&DividendKey {
+            date: income_date,
+            entity_name: paying_entity.clone(),
+        }) {
+            None => {
+                dividend_info_map.insert(
+                    DividendKey {
+                        date: income_date,
+                        entity_name: paying_entity.clone(),
+                    },
+                    IncomeInfo {
+                        _type: "dividend".into(),
+                        paying_entity,
+                        isin: None,
+                        income_date,
+                        income_currency_code: dividend_currency_code.clone(),
+                        income_currency_amount: dividend_currency_amount,
+                        wht_currency_code: dividend_currency_code, // Default value, may be overwritten
+                        wht_currency_amount: Decimal::ZERO, // Default value, may be overwritten
+                    },
+                );
+            }
+            Some(entry) => {
+                if entry.income_currency_code != dividend_currency_code {
+                    return Err(DkaError::generic(
+                        "Duplicate dividends found with different currencies",
+                    ));
+                }
+                entry.income_currency_amount += dividend_currency_amount;
+            }
+        };
+    }
+
+    for row in string_records
+        .iter()
+        .filter(|row| WHT_TYPES.contains(&&row[columns._type]))
+    {
+        let wht_currency_code = row[columns.currency].to_string();
+        // Tax is withheld as a negative amount, while a correction refunds it with a positive one
+        let wht_currency_amount = -parse_amount(&row[columns.total_amount])?;
+        match dividend_info_map.get_mut(&DividendKey {
+            date: parse_date(&row[columns.date])?,
+            entity_name: row[columns.ticker].to_string(),
+        }) {
+            None => {
+                return Err(DkaError::generic("Could not match wht to dividend"));
+            }
+            Some(pii) => {
+                if pii.wht_currency_amount != Decimal::ZERO
+                    && pii.wht_currency_code != wht_currency_code
+                {
+                    return Err(DkaError::generic(
+                        "Two WHT payments for the same dividend payment have different currencies",
+                    ));
+                }
+                pii.wht_currency_code = wht_currency_code;
+                pii.wht_currency_amount += wht_currency_amount;
+            }
+        };
+    }
+
+    let mut dividend_infos: Vec<_> = dividend_info_map.into_values().collect();
+    dividend_infos.sort_by_key(|pii| (pii.income_date, pii.paying_entity.clone())); // Deterministic order
+    Ok(dividend_infos)
+}
+
+impl ReportParser for RevolutReportParser {
+    fn parse(&self, report_path: PathBuf) -> DkaResult<ParsedReport> {
+        let file = std::fs::File::open(report_path)?;
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(file);
+        let header = rdr
+            .headers()
+            .map_err(|e| DkaError::Generic(format!("CSV parse error {}", e)))?
+            .clone();
+        let columns = Columns::from_header(&header)?;
+        let mut string_records: Vec<StringRecord> = vec![];
+        for result in rdr.records() {
+            let string_record = match result {
+                Ok(string_record) => string_record,
+                Err(e) => {
+                    return Err(DkaError::Generic(format!("CSV parse error {}", e)));
+                }
+            };
+            string_records.push(string_record);
+        }
+        Ok(ParsedReport {
+            income_infos: get_dividend_incomes(&columns, &string_records)?,
+            trade_infos: vec![],
+            exchange_rate_infos: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use rust_decimal_macros::dec;
+
+    use crate::{
+        date::parse_iso,
+        report_parser::{IncomeInfo, ReportParser},
+        revolut_report_parser::RevolutReportParser,
+    };
+
+    #[test]
+    fn test_revolut_full() {
+        let csv_content = include_bytes!("../tests/data/revolut-1.csv");
+        let mut tempfile = tempfile::NamedTempFile::new().unwrap();
+        tempfile.write_all(csv_content).unwrap();
+        let report_parser = RevolutReportParser::new();
+        let parsed_report = report_parser.parse(tempfile.path().to_path_buf()).unwrap();
+        assert_eq!(
+            parsed_report.income_infos,
+            vec![
+                IncomeInfo {
+                    _type: "dividend".into(),
+                    income_currency_code: "USD".into(),
+                    paying_entity: "AAPL".into(),
+                    isin: None,
+                    income_currency_amount: dec!(2.3),
+                    wht_currency_code: "USD".into(),
+                    wht_currency_amount: dec!(0.35), // Withheld 0.45, 0.10 refunded by a correction
+                    income_date: parse_iso("2023-01-12").unwrap(),
+                },
+                IncomeInfo {
+                    _type: "dividend".into(),
+                    income_currency_code: "USD".into(),
+                    paying_entity: "MSFT".into(),
+                    isin: None,
+                    income_currency_amount: dec!(1068.0), // Thousands separator
+                    wht_currency_code: "USD".into(),
+                    wht_currency_amount: dec!(0.0),
+                    income_date: parse_iso("2023-01-12").unwrap(),
+                },
+            ],
+        );
+    }
+}
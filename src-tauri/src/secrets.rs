@@ -0,0 +1,387 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+};
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::error::{DkaError, DkaResult};
+
+/// When set, secrets are encrypted with a key derived from this passphrase instead of the
+/// generated key file
+pub const PASSPHRASE_ENV_VAR: &str = "DOBKAPAPP_PASSPHRASE";
+
+// Kept apart from the data directory, so that copies of it do not carry the key along
+const KEY_DIR_NAME: &str = "dobkapapp-keys";
+const KEY_FILE_NAME: &str = "secret.key";
+// The salt and check files are kept in the data directory, and mean that a passphrase is in use
+const SALT_FILE_NAME: &str = "secret.salt";
+const CHECK_FILE_NAME: &str = "secret.check";
+// Encrypted into the check file, to tell a wrong passphrase apart before any secret is decrypted
+const CHECK_PLAINTEXT: &str = "dobkapapp";
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+// Prefix of encrypted values, followed by the hex encoded nonce, ciphertext and tag
+const ENCRYPTED_PREFIX: &str = "enc1:";
+
+/// Directory of the key file, outside of the data directory
+pub fn get_default_key_dir() -> DkaResult<PathBuf> {
+    dirs::config_local_dir()
+        .map(|config_dir| config_dir.join(KEY_DIR_NAME))
+        .ok_or_else(|| DkaError::user("Cannot determine the directory of the secret key"))
+}
+
+/// Whether the secrets are encrypted with a key derived from a passphrase rather than the key file
+pub fn uses_passphrase(data_dir: &Path) -> bool {
+    data_dir.join(SALT_FILE_NAME).exists()
+}
+
+/// Key for encrypting secrets (mailbox passwords, OAuth tokens) stored in the database
+pub struct SecretKey {
+    key: LessSafeKey,
+}
+
+impl SecretKey {
+    /// Derives the key from the passphrase if one is in use, otherwise loads the key file,
+    /// generating it on first use. A passphrase can only be given once secrets are encrypted with
+    /// one, or before any key exists.
+    pub fn load(data_dir: &Path, key_dir: &Path, passphrase: Option<&str>) -> DkaResult<Self> {
+        move_key_file(data_dir, key_dir)?;
+        let key_file_path = key_dir.join(KEY_FILE_NAME);
+        match passphrase {
+            Some(passphrase) => {
+                if !uses_passphrase(data_dir) && key_file_path.exists() {
+                    return Err(DkaError::user(
+                        "Secrets are encrypted with the key file, switch to a passphrase first",
+                    ));
+                }
+                let salt = read_or_create_random_file(&data_dir.join(SALT_FILE_NAME), SALT_LEN)?;
+                let secret_key = Self::derive(passphrase, &salt)?;
+                let check_path = data_dir.join(CHECK_FILE_NAME);
+                if check_path.exists() {
+                    secret_key
+                        .decrypt(&fs::read_to_string(&check_path)?)
+                        .map_err(|_| DkaError::user("Wrong passphrase"))?;
+                } else {
+                    // A new passphrase, or one set before the check file was introduced
+                    write_secret_file(
+                        &check_path,
+                        secret_key.encrypt(CHECK_PLAINTEXT)?.as_bytes(),
+                    )?;
+                }
+                Ok(secret_key)
+            }
+            None => {
+                if uses_passphrase(data_dir) {
+                    return Err(DkaError::user(
+                        "Secrets are protected by a passphrase, enter it to unlock them",
+                    ));
+                }
+                Self::from_bytes(&read_or_create_random_file(&key_file_path, KEY_LEN)?)
+            }
+        }
+    }
+
+    /// Generates a new key, derived from the passphrase if there is one and otherwise kept in the
+    /// key file. The key is only stored once `save_secrets` has re-encrypted the secrets with it.
+    pub fn replace(
+        data_dir: &Path,
+        key_dir: &Path,
+        passphrase: Option<&str>,
+        save_secrets: impl FnOnce(&SecretKey) -> DkaResult<()>,
+    ) -> DkaResult<Self> {
+        match passphrase {
+            Some(passphrase) => {
+                let salt = random_bytes(SALT_LEN)?;
+                let secret_key = Self::derive(passphrase, &salt)?;
+                save_secrets(&secret_key)?;
+                write_secret_file(&data_dir.join(SALT_FILE_NAME), &salt)?;
+                write_secret_file(
+                    &data_dir.join(CHECK_FILE_NAME),
+                    secret_key.encrypt(CHECK_PLAINTEXT)?.as_bytes(),
+                )?;
+                remove_file_if_exists(&key_dir.join(KEY_FILE_NAME))?;
+                Ok(secret_key)
+            }
+            None => {
+                let key_bytes = random_bytes(KEY_LEN)?;
+                let secret_key = Self::from_bytes(&key_bytes)?;
+                save_secrets(&secret_key)?;
+                write_secret_file(&key_dir.join(KEY_FILE_NAME), &key_bytes)?;
+                remove_file_if_exists(&data_dir.join(CHECK_FILE_NAME))?;
+                remove_file_if_exists(&data_dir.join(SALT_FILE_NAME))?;
+                Ok(secret_key)
+            }
+        }
+    }
+
+    fn derive(passphrase: &str, salt: &[u8]) -> DkaResult<Self> {
+        let mut key_bytes = vec![0; KEY_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+            salt,
+            passphrase.as_bytes(),
+            &mut key_bytes,
+        );
+        Self::from_bytes(&key_bytes)
+    }
+
+    fn from_bytes(key_bytes: &[u8]) -> DkaResult<Self> {
+        let unbound_key = UnboundKey::new(&AES_256_GCM, key_bytes)
+            .map_err(|_| DkaError::generic("Invalid secret key"))?;
+        Ok(Self {
+            key: LessSafeKey::new(unbound_key),
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> DkaResult<String> {
+        let mut nonce_bytes = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce_bytes)
+            .map_err(|_| DkaError::generic("Could not generate a nonce"))?;
+        let mut in_out = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce_bytes),
+                Aad::empty(),
+                &mut in_out,
+            )
+            .map_err(|_| DkaError::generic("Could not encrypt secret"))?;
+        Ok(format!(
+            "{ENCRYPTED_PREFIX}{}{}",
+            to_hex(&nonce_bytes),
+            to_hex(&in_out)
+        ))
+    }
+
+    pub fn decrypt(&self, encrypted: &str) -> DkaResult<String> {
+        let Some(bytes) = encrypted
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .and_then(from_hex)
+            .filter(|b| b.len() >= NONCE_LEN)
+        else {
+            return Err(DkaError::generic("Invalid encrypted secret"));
+        };
+        let (nonce_bytes, ciphertext) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce_bytes)
+            .map_err(|_| DkaError::generic("Invalid encrypted secret"))?;
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| {
+                DkaError::user("Could not decrypt secret, is the passphrase or key file correct?")
+            })?;
+        String::from_utf8(plaintext.to_vec())
+            .map_err(|_| DkaError::generic("Decrypted secret is not UTF-8"))
+    }
+}
+
+/// Moves the key file out of the data directory, where it was kept before
+fn move_key_file(data_dir: &Path, key_dir: &Path) -> DkaResult<()> {
+    let old_path = data_dir.join(KEY_FILE_NAME);
+    let new_path = key_dir.join(KEY_FILE_NAME);
+    if old_path.exists() && !new_path.exists() {
+        write_secret_file(&new_path, &fs::read(&old_path)?)?;
+        fs::remove_file(old_path)?;
+    }
+    Ok(())
+}
+
+fn read_or_create_random_file(path: &Path, len: usize) -> DkaResult<Vec<u8>> {
+    if path.exists() {
+        let bytes = fs::read(path)?;
+        if bytes.len() != len {
+            return Err(DkaError::Generic(format!(
+                "{} should be {len} bytes long",
+                path.display()
+            )));
+        }
+        return Ok(bytes);
+    }
+    let bytes = random_bytes(len)?;
+    write_secret_file(path, &bytes)?;
+    Ok(bytes)
+}
+
+fn random_bytes(len: usize) -> DkaResult<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| DkaError::generic("Could not generate random bytes"))?;
+    Ok(bytes)
+}
+
+/// Writes a file only readable by the user, replacing any previous one atomically
+fn write_secret_file(path: &Path, bytes: &[u8]) -> DkaResult<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    remove_file_if_exists(&tmp_path)?;
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+fn remove_file_if_exists(path: &Path) -> DkaResult<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = SecretKey::from_bytes(&[7; KEY_LEN]).unwrap();
+        let encrypted = key.encrypt("hunter2").unwrap();
+        assert!(encrypted.starts_with(ENCRYPTED_PREFIX));
+        assert!(!encrypted.contains("hunter2"));
+        // A fresh nonce every time
+        assert_ne!(key.encrypt("hunter2").unwrap(), encrypted);
+        assert_eq!(key.decrypt(&encrypted).unwrap(), "hunter2");
+        assert_eq!(key.decrypt(&key.encrypt("").unwrap()).unwrap(), "");
+    }
+
+    #[test]
+    fn test_decrypt_errors() {
+        let key = SecretKey::from_bytes(&[7; KEY_LEN]).unwrap();
+        let other_key = SecretKey::from_bytes(&[8; KEY_LEN]).unwrap();
+        let encrypted = key.encrypt("hunter2").unwrap();
+        assert!(matches!(
+            other_key.decrypt(&encrypted),
+            Err(DkaError::User(_))
+        ));
+        assert!(key.decrypt("hunter2").is_err());
+        assert!(key.decrypt("enc1:zz").is_err());
+        assert!(key.decrypt("enc1:00").is_err());
+    }
+
+    #[test]
+    fn test_load() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let key_dir = tempfile::tempdir().unwrap();
+        let encrypted = SecretKey::load(data_dir.path(), key_dir.path(), None)
+            .unwrap()
+            .encrypt("hunter2")
+            .unwrap();
+        assert_eq!(
+            fs::read(key_dir.path().join(KEY_FILE_NAME)).unwrap().len(),
+            KEY_LEN
+        );
+        // The key file is reused
+        let key = SecretKey::load(data_dir.path(), key_dir.path(), None).unwrap();
+        assert_eq!(key.decrypt(&encrypted).unwrap(), "hunter2");
+        // A passphrase cannot silently replace the key file
+        assert!(matches!(
+            SecretKey::load(data_dir.path(), key_dir.path(), Some("correct horse")),
+            Err(DkaError::User(_))
+        ));
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let key_dir = tempfile::tempdir().unwrap();
+        let encrypted = SecretKey::load(data_dir.path(), key_dir.path(), Some("correct horse"))
+            .unwrap()
+            .encrypt("hunter2")
+            .unwrap();
+        assert!(uses_passphrase(data_dir.path()));
+        let key = SecretKey::load(data_dir.path(), key_dir.path(), Some("correct horse")).unwrap();
+        assert_eq!(key.decrypt(&encrypted).unwrap(), "hunter2");
+        assert!(matches!(
+            SecretKey::load(data_dir.path(), key_dir.path(), Some("battery staple")),
+            Err(DkaError::User(_))
+        ));
+        assert!(matches!(
+            SecretKey::load(data_dir.path(), key_dir.path(), None),
+            Err(DkaError::User(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_moves_key_file() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let key_dir = tempfile::tempdir().unwrap();
+        fs::write(data_dir.path().join(KEY_FILE_NAME), [7; KEY_LEN]).unwrap();
+        let encrypted = SecretKey::from_bytes(&[7; KEY_LEN])
+            .unwrap()
+            .encrypt("hunter2")
+            .unwrap();
+        let key = SecretKey::load(data_dir.path(), key_dir.path(), None).unwrap();
+        assert_eq!(key.decrypt(&encrypted).unwrap(), "hunter2");
+        assert!(!data_dir.path().join(KEY_FILE_NAME).exists());
+        assert!(key_dir.path().join(KEY_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn test_replace() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let key_dir = tempfile::tempdir().unwrap();
+        SecretKey::load(data_dir.path(), key_dir.path(), None).unwrap();
+
+        // Nothing changes if the secrets could not be saved
+        assert!(SecretKey::replace(
+            data_dir.path(),
+            key_dir.path(),
+            Some("correct horse"),
+            |_| Err(DkaError::generic("Disk full")),
+        )
+        .is_err());
+        assert!(!uses_passphrase(data_dir.path()));
+
+        let encrypted = SecretKey::replace(
+            data_dir.path(),
+            key_dir.path(),
+            Some("correct horse"),
+            |_| Ok(()),
+        )
+        .unwrap()
+        .encrypt("hunter2")
+        .unwrap();
+        assert!(uses_passphrase(data_dir.path()));
+        assert!(!key_dir.path().join(KEY_FILE_NAME).exists());
+        let key = SecretKey::load(data_dir.path(), key_dir.path(), Some("correct horse")).unwrap();
+        assert_eq!(key.decrypt(&encrypted).unwrap(), "hunter2");
+
+        let encrypted = SecretKey::replace(data_dir.path(), key_dir.path(), None, |_| Ok(()))
+            .unwrap()
+            .encrypt("hunter2")
+            .unwrap();
+        assert!(!uses_passphrase(data_dir.path()));
+        let key = SecretKey::load(data_dir.path(), key_dir.path(), None).unwrap();
+        assert_eq!(key.decrypt(&encrypted).unwrap(), "hunter2");
+    }
+}
@@ -0,0 +1,324 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use chrono::NaiveDate;
+use csv::StringRecord;
+use rust_decimal::{Decimal, RoundingStrategy};
+use rust_decimal_macros::dec;
+
+use crate::{
+    error::{DkaError, DkaResult},
+    report_parser::{IncomeInfo, ParsedReport, ReportParser},
+};
+
+pub struct Trading212ReportParser {}
+impl Trading212ReportParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+const DIVIDEND_ACTIONS: &[&str] = &[
+    "Dividend (Ordinary)",
+    "Dividend (Dividends paid by us corporations)",
+    "Dividend (Dividend)",
+];
+const INTEREST_ACTIONS: &[&str] = &["Interest on cash"];
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+struct DividendKey {
+    date: NaiveDate,
+    entity_name: String,
+    entity_isin: String,
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+struct InterestKey {
+    date: NaiveDate,
+    currency_code: String,
+}
+
+struct Columns {
+    action: usize,
+    time: usize,
+    isin: usize,
+    ticker: usize,
+    shares: usize,
+    price_per_share: usize,
+    price_per_share_currency: usize,
+    total: usize,
+    total_currency: usize,
+    wht: Option<usize>,
+    wht_currency: Option<usize>,
+}
+impl Columns {
+    fn from_header(header: &StringRecord) -> DkaResult<Self> {
+        let find = |name: &str| header.iter().position(|h| h == name);
+        let require = |name: &str| {
+            find(name).ok_or_else(|| {
+                DkaError::Generic(format!("Trading 212 report is missing column: {}", name))
+            })
+        };
+        Ok(Columns {
+            action: require("Action")?,
+            time: require("Time")?,
+            isin: require("ISIN")?,
+            ticker: require("Ticker")?,
+            shares: require("No. of shares")?,
+            price_per_share: require("Price / share")?,
+            price_per_share_currency: require("Currency (Price / share)")?,
+            total: require("Total")?,
+            total_currency: require("Currency (Total)")?,
+            wht: find("Withholding tax"),
+            wht_currency: find("Currency (Withholding tax)"),
+        })
+    }
+}
+
+fn parse_time(s: &str) -> DkaResult<NaiveDate> {
+    // Times look like "2023-01-12 10:01:05", optionally with fractional seconds
+    let date_str = s.split(' ').next().unwrap_or("");
+    NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+        .map_err(|_| DkaError::Generic(format!("Failed to parse date: {}", s)))
+}
+
+fn parse_amount(s: &str) -> DkaResult<Decimal> {
+    if s.is_empty() {
+        return Ok(Decimal::ZERO);
+    }
+    s.parse()
+        .map_err(|e| DkaError::Generic(format!("Could not parse number: {}", e)))
+}
+
+fn round_cents(amount: Decimal) -> Decimal {
+    amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+}
+
+/// Trading 212 quotes LSE instruments in pence, which NBS does not publish a rate for
+fn normalize_currency(currency_code: &str, amount: Decimal) -> (String, Decimal) {
+    match currency_code {
+        "GBX" => ("GBP".into(), amount / dec!(100)),
+        _ => (currency_code.into(), amount),
+    }
+}
+
+fn get_dividend_incomes(
+    columns: &Columns,
+    string_records: &[StringRecord],
+) -> DkaResult<Vec<IncomeInfo>> {
+    let mut dividend_info_map: HashMap<DividendKey, IncomeInfo> = HashMap::new();
+    for row in string_records
+        .iter()
+        .filter(|row| DIVIDEND_ACTIONS.contains(&&row[columns.action]))
+    {
+        let income_date = parse_time(&row[columns.time])?;
+        let (dividend_currency_code, dividend_currency_amount) = normalize_currency(
+            &row[columns.price_per_share_currency],
+            parse_amount(&row[columns.shares])? * parse_amount(&row[columns.price_per_share])?,
+        );
+        let (wht_currency_code, wht_currency_amount) = match (columns.wht, columns.wht_currency) {
+            (Some(wht), Some(wht_currency)) if !row[wht_currency].is_empty() => {
+                normalize_currency(&row[wht_currency], parse_amount(&row[wht])?)
+            }
+            _ => (dividend_currency_code.clone(), Decimal::ZERO),
+        };
+        let paying_entity = row[columns.ticker].to_string();
+        match dividend_info_map.get_mut(&DividendKey {
+            date: income_date,
+            entity_name: paying_entity.clone(),
+            entity_isin: row[columns.isin].to_string(),
+        }) {
+            None => {
+                dividend_info_map.insert(
+                    DividendKey {
+                        date: income_date,
+                        entity_name: paying_entity.clone(),
+                        entity_isin: row[columns.isin].to_string(),
+                    },
+                    IncomeInfo {
+                        _type: "dividend".into(),
+                        paying_entity,
+                        isin: Some(row[columns.isin].to_string()),
+                        income_date,
+                        income_currency_code: dividend_currency_code,
+                        income_currency_amount: dividend_currency_amount,
+                        wht_currency_code,
+                        wht_currency_amount,
+                    },
+                );
+            }
+            Some(entry) => {
+                if entry.income_currency_code != dividend_currency_code {
+                    return Err(DkaError::generic(
+                        "Duplicate dividends found with different currencies",
+                    ));
+                }
+                if wht_currency_amount != Decimal::ZERO {
+                    if entry.wht_currency_amount != Decimal::ZERO
+                        && entry.wht_currency_code != wht_currency_code
+                    {
+                        return Err(DkaError::generic("Two WHT payments for the same dividend payment have different currencies"));
+                    }
+                    entry.wht_currency_code = wht_currency_code;
+                }
+                entry.income_currency_amount += dividend_currency_amount;
+                entry.wht_currency_amount += wht_currency_amount;
+            }
+        };
+    }
+
+    let mut dividend_infos: Vec<_> = dividend_info_map
+        .into_values()
+        .map(|mut pii| {
+            pii.income_currency_amount = round_cents(pii.income_currency_amount);
+            pii.wht_currency_amount = round_cents(pii.wht_currency_amount);
+            pii
+        })
+        .collect();
+    dividend_infos.sort_by_key(|pii| (pii.income_date, pii.paying_entity.clone())); // Deterministic order
+    Ok(dividend_infos)
+}
+
+fn get_interest_incomes(
+    columns: &Columns,
+    string_records: &[StringRecord],
+) -> DkaResult<Vec<IncomeInfo>> {
+    let mut interest_info_map: HashMap<InterestKey, IncomeInfo> = HashMap::new();
+    for row in string_records
+        .iter()
+        .filter(|row| INTEREST_ACTIONS.contains(&&row[columns.action]))
+    {
+        let income_date = parse_time(&row[columns.time])?;
+        let (interest_currency_code, interest_currency_amount) = normalize_currency(
+            &row[columns.total_currency],
+            parse_amount(&row[columns.total])?,
+        );
+        interest_info_map
+            .entry(InterestKey {
+                date: income_date,
+                currency_code: interest_currency_code.clone(),
+            })
+            .and_modify(|e| e.income_currency_amount += interest_currency_amount)
+            .or_insert(IncomeInfo {
+                _type: "interest".into(),
+                paying_entity: "Trading 212".into(),
+                isin: None,
+                income_date,
+                income_currency_code: interest_currency_code.clone(),
+                income_currency_amount: interest_currency_amount,
+                wht_currency_code: interest_currency_code,
+                wht_currency_amount: Decimal::ZERO,
+            });
+    }
+
+    let mut interest_infos: Vec<_> = interest_info_map
+        .into_values()
+        .map(|mut pii| {
+            pii.income_currency_amount = round_cents(pii.income_currency_amount);
+            pii
+        })
+        .filter(|pii| pii.income_currency_amount > Decimal::ZERO)
+        .collect();
+    interest_infos.sort_by_key(|pii| (pii.income_date, pii.income_currency_code.clone())); // Deterministic order
+    Ok(interest_infos)
+}
+
+impl ReportParser for Trading212ReportParser {
+    fn parse(&self, report_path: PathBuf) -> DkaResult<ParsedReport> {
+        let file = std::fs::File::open(report_path)?;
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(file);
+        let header = rdr
+            .headers()
+            .map_err(|e| DkaError::Generic(format!("CSV parse error {}", e)))?
+            .clone();
+        let columns = Columns::from_header(&header)?;
+        let mut string_records: Vec<StringRecord> = vec![];
+        for result in rdr.records() {
+            let string_record = match result {
+                Ok(string_record) => string_record,
+                Err(e) => {
+                    return Err(DkaError::Generic(format!("CSV parse error {}", e)));
+                }
+            };
+            string_records.push(string_record);
+        }
+        let mut income_infos: Vec<IncomeInfo> = vec![];
+        income_infos.append(&mut get_dividend_incomes(&columns, &string_records)?);
+        income_infos.append(&mut get_interest_incomes(&columns, &string_records)?);
+        Ok(ParsedReport {
+            income_infos,
+            trade_infos: vec![],
+            exchange_rate_infos: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use rust_decimal_macros::dec;
+
+    use crate::{
+        date::parse_iso,
+        report_parser::{IncomeInfo, ReportParser},
+        trading212_report_parser::Trading212ReportParser,
+    };
+
+    #[test]
+    fn test_trading212_full() {
+        let csv_content = include_bytes!("../tests/data/trading212-1.csv");
+        let mut tempfile = tempfile::NamedTempFile::new().unwrap();
+        tempfile.write_all(csv_content).unwrap();
+        let report_parser = Trading212ReportParser::new();
+        let parsed_report = report_parser.parse(tempfile.path().to_path_buf()).unwrap();
+        assert_eq!(
+            parsed_report.income_infos,
+            vec![
+                // Two dividends from AAPL in USD, merged together
+                IncomeInfo {
+                    _type: "dividend".into(),
+                    income_currency_code: "USD".into(),
+                    paying_entity: "AAPL".into(),
+                    isin: Some("US0378331005".into()),
+                    income_currency_amount: dec!(3.45), // 10 * 0.23 + 5 * 0.23
+                    wht_currency_code: "USD".into(),
+                    wht_currency_amount: dec!(0.52),
+                    income_date: parse_iso("2023-01-12").unwrap(),
+                },
+                // Price per share in pence
+                IncomeInfo {
+                    _type: "dividend".into(),
+                    income_currency_code: "GBP".into(),
+                    paying_entity: "BP".into(),
+                    isin: Some("GB0007980591".into()),
+                    income_currency_amount: dec!(5.5),
+                    wht_currency_code: "GBP".into(),
+                    wht_currency_amount: dec!(0.0),
+                    income_date: parse_iso("2023-01-12").unwrap(),
+                },
+                IncomeInfo {
+                    _type: "interest".into(),
+                    income_currency_code: "EUR".into(),
+                    paying_entity: "Trading 212".into(),
+                    isin: None,
+                    income_currency_amount: dec!(0.84),
+                    wht_currency_code: "EUR".into(),
+                    wht_currency_amount: dec!(0.0),
+                    income_date: parse_iso("2023-01-13").unwrap(),
+                },
+                IncomeInfo {
+                    _type: "interest".into(),
+                    income_currency_code: "EUR".into(),
+                    paying_entity: "Trading 212".into(),
+                    isin: None,
+                    income_currency_amount: dec!(0.86),
+                    wht_currency_code: "EUR".into(),
+                    wht_currency_amount: dec!(0.0),
+                    income_date: parse_iso("2023-01-14").unwrap(),
+                },
+            ],
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use std::{fs::File, io::Read, path::PathBuf};
 
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -19,11 +20,11 @@ struct TrivialReport {
     #[serde(rename = "incomeCurrencyCode")]
     income_currency_code: String,
     #[serde(rename = "incomeCurrencyAmount")]
-    income_currency_amount: f64,
+    income_currency_amount: Decimal,
     #[serde(rename = "whtCurrencyCode")]
     wht_currency_code: String,
     #[serde(rename = "whtCurrencyAmount")]
-    wht_currency_amount: f64,
+    wht_currency_amount: Decimal,
 }
 
 pub struct TrivialReportParser {}
@@ -43,6 +44,7 @@ impl ReportParser for TrivialReportParser {
         let income_info = IncomeInfo {
             _type: trivial_report._type,
             paying_entity: trivial_report.paying_entity,
+            isin: None,
             income_date: parse_iso(&trivial_report.income_date)?,
             income_currency_code: trivial_report.income_currency_code,
             income_currency_amount: trivial_report.income_currency_amount,
@@ -51,6 +53,7 @@ impl ReportParser for TrivialReportParser {
         };
         Ok(ParsedReport {
             income_infos: vec![income_info],
+            trade_infos: vec![],
             exchange_rate_infos: vec![],
         })
     }
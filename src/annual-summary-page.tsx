@@ -0,0 +1,97 @@
+import { formatRsdcAmount } from './helpers'
+import { AnnualSummary, AnnualSummaryLine, Filing } from './ipc-types'
+import {
+  Alert,
+  Container,
+  Stack,
+  Table,
+  TableBody,
+  TableCell,
+  TableHead,
+  TableRow,
+  TextField,
+} from '@mui/material'
+import { useState } from 'react'
+import { useQuery } from '@tanstack/react-query'
+import { invoke } from '@tauri-apps/api/core'
+
+interface AnnualSummaryPageProps {
+  // Refetch the summary whenever filings change
+  filings: Array<Filing>
+  taxpayerProfileId: number
+}
+
+const isValidYear = (input: string) => {
+  return /^[0-9]{4}$/.test(input)
+}
+
+const AnnualSummaryRow = (props: { line: AnnualSummaryLine, bold?: boolean }) => {
+  const style = props.bold ? { fontWeight: 'bold' } : undefined
+  return <TableRow>
+    <TableCell style={style}>{props.line.type}</TableCell>
+    <TableCell style={style} align="right">{props.line.filingCount}</TableCell>
+    <TableCell style={style} align="right">{formatRsdcAmount(props.line.grossIncome)}</TableCell>
+    <TableCell style={style} align="right">{formatRsdcAmount(props.line.whtPaid)}</TableCell>
+    <TableCell style={style} align="right">{formatRsdcAmount(props.line.whtReclaimable)}</TableCell>
+    <TableCell style={style} align="right">{formatRsdcAmount(props.line.taxPayable)}</TableCell>
+    <TableCell style={style} align="right">{formatRsdcAmount(props.line.taxPaid)}</TableCell>
+  </TableRow>
+}
+
+export const AnnualSummaryPage = (props: AnnualSummaryPageProps) => {
+  const [year, setYear] = useState(String(new Date().getFullYear() - 1))
+  const annualSummaryQuery = useQuery({
+    queryKey: ['annual-summary', props.taxpayerProfileId, year, props.filings],
+    queryFn: () => invoke('get_annual_summary', {
+      taxpayerProfileId: props.taxpayerProfileId,
+      year: parseInt(year),
+    }) as Promise<AnnualSummary>,
+    enabled: isValidYear(year),
+    networkMode: 'always',
+  })
+  const annualSummary = annualSummaryQuery.data
+  return <Container>
+    <h2 style={{ marginBottom: 0}}>Annual Summary</h2>
+    <Stack gap={1}>
+      <TextField
+        size="small"
+        style={{ width: 200 }}
+        label="Year"
+        value={year}
+        error={!isValidYear(year)}
+        onChange={e => setYear(e.target.value)}
+      />
+      {annualSummary && annualSummary.incompleteFilingCount > 0 &&
+        <Alert severity="warning">
+          {annualSummary.incompleteFilingCount} filings were created before income details were
+          recorded and are not included
+        </Alert>
+      }
+      {annualSummary && annualSummary.suspectedDuplicateFilingCount > 0 &&
+        <Alert severity="warning">
+          {annualSummary.suspectedDuplicateFilingCount} filings may be duplicates and are not
+          included until they are confirmed on the filings page
+        </Alert>
+      }
+      <Table>
+        <TableHead>
+          <TableRow>
+            <TableCell><b>Type</b></TableCell>
+            <TableCell align="right"><b>Filings</b></TableCell>
+            <TableCell align="right"><b>Gross Income</b></TableCell>
+            <TableCell align="right"><b>WHT Credited</b></TableCell>
+            <TableCell align="right"><b>WHT Reclaimable</b></TableCell>
+            <TableCell align="right"><b>Tax Payable</b></TableCell>
+            <TableCell align="right"><b>Tax Paid</b></TableCell>
+          </TableRow>
+        </TableHead>
+        <TableBody>
+          {annualSummary?.lines.map(line =>
+            <AnnualSummaryRow key={line.type} line={line} />
+          )}
+          {annualSummary && <AnnualSummaryRow line={annualSummary.total} bold />}
+        </TableBody>
+      </Table>
+    </Stack>
+  </Container>
+}
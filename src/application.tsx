@@ -1,6 +1,6 @@
 import { useMemo, useState } from "react";
 import "./application.css";
-import { AppBar, Button, CssBaseline, ListItemIcon, ListItemText, Menu, MenuItem, Toolbar } from "@mui/material";
+import { AppBar, Button, CssBaseline, ListItemIcon, ListItemText, Menu, MenuItem, Select, Toolbar } from "@mui/material";
 import SettingsIcon from '@mui/icons-material/Settings'
 import BuildIcon from '@mui/icons-material/Build'
 import WorkIcon from '@mui/icons-material/Work'
@@ -10,17 +10,20 @@ import SyncIcon from '@mui/icons-material/Sync'
 import EngineeringIcon from '@mui/icons-material/Engineering'
 import SummarizeIcon from '@mui/icons-material/Summarize'
 import CableIcon from '@mui/icons-material/Cable'
+import CalendarMonthIcon from '@mui/icons-material/CalendarMonth'
 import { DevPage } from "./dev-page";
 import { QueryClient, QueryClientProvider, useQuery } from "@tanstack/react-query";
 import { invoke } from "@tauri-apps/api/core";
-import { Importer, Mailbox, TaxpayerProfile, TechnicalConf, Report, Filing } from "./ipc-types";
+import { Importer, Mailbox, TaxpayerProfile, TechnicalConf, Report, Filing, SecretsStatus } from "./ipc-types";
 import { TechnicalPage } from "./technical-page";
-import { MailboxPage } from "./mailbox-page";
-import { TaxpayerProfilePage } from "./taxpayer-profile-page";
+import { MailboxesPage } from "./mailbox-page";
+import { TaxpayerProfilesPage } from "./taxpayer-profile-page";
 import { ImportersPage } from "./importer-page";
 import { ReportsPage } from "./reports-page";
 import { FilingsPage } from "./filings-page";
 import { SyncPage } from "./sync-page";
+import { AnnualSummaryPage } from "./annual-summary-page";
+import { UnlockPage } from "./unlock-page";
 
 type NavigationPage =
   | 'settings.taxpayer'
@@ -30,6 +33,7 @@ type NavigationPage =
   | 'sync'
   | 'reports'
   | 'filings'
+  | 'annual'
   | 'dev'
 
 const Application = () => {
@@ -42,18 +46,31 @@ const Application = () => {
     networkMode: 'always',
   })
 
-  const mailboxQuery = useQuery({
-    queryKey: ['mailbox'],
-    queryFn: () => invoke("get_mailbox") as Promise<Mailbox>,
+  const secretsStatusQuery = useQuery({
+    queryKey: ['secrets-status'],
+    queryFn: () => invoke("get_secrets_status") as Promise<SecretsStatus>,
     networkMode: 'always',
   })
 
-  const taxpayerProfileQuery = useQuery({
-    queryKey: ['taxpayer-profile'],
-    queryFn: () => invoke("get_taxpayer_profile") as Promise<TaxpayerProfile>,
+  // Mailbox secrets cannot be decrypted until the passphrase is entered
+  const mailboxesQuery = useQuery({
+    queryKey: ['mailboxes'],
+    queryFn: () => invoke("get_mailboxes") as Promise<Array<Mailbox>>,
+    enabled: secretsStatusQuery.data?.locked === false,
     networkMode: 'always',
   })
 
+  const taxpayerProfilesQuery = useQuery({
+    queryKey: ['taxpayer-profiles'],
+    queryFn: () => invoke("get_taxpayer_profiles") as Promise<Array<TaxpayerProfile>>,
+    networkMode: 'always',
+  })
+  // Falls back to the first profile until one is picked, or if the picked one was deleted
+  const [selectedTaxpayerProfileId, setSelectedTaxpayerProfileId] = useState<number | null>(null)
+  const taxpayerProfileId =
+    taxpayerProfilesQuery.data?.find(tp => tp.id === selectedTaxpayerProfileId)?.id ??
+    taxpayerProfilesQuery.data?.[0]?.id
+
   const importersQuery = useQuery({
     queryKey: ['importers'],
     queryFn: () => invoke("get_importers") as Promise<Array<Importer>>,
@@ -72,16 +89,32 @@ const Application = () => {
     networkMode: 'always',
   })
 
+  const taxpayerFilingsQuery = useQuery({
+    queryKey: ['filings', taxpayerProfileId],
+    queryFn: () => invoke("get_filings", { taxpayerProfileId }) as Promise<Array<Filing>>,
+    enabled: taxpayerProfileId !== undefined,
+    networkMode: 'always',
+  })
+
   const [menuAnchorEl, setMenuAnchorEl] = useState<HTMLElement | null>(null)
   const menuOpen = Boolean(menuAnchorEl)
 
+  if (secretsStatusQuery.data?.locked) {
+    return <>
+      <CssBaseline />
+      <UnlockPage />
+    </>
+  }
+
   if (
     !technicalConfQuery.isSuccess ||
-    !mailboxQuery.isSuccess ||
-    !taxpayerProfileQuery.data ||
+    !mailboxesQuery.isSuccess ||
+    !taxpayerProfilesQuery.data ||
+    taxpayerProfileId === undefined ||
     !importersQuery.data ||
     !reportsQuery.data ||
-    !filingsQuery.data
+    !filingsQuery.data ||
+    !taxpayerFilingsQuery.data
   ) {
     return <>
       <CssBaseline />
@@ -112,7 +145,7 @@ const Application = () => {
             <ListItemIcon>
               <PersonIcon />
             </ListItemIcon>
-            <ListItemText>Taxpayer</ListItemText>
+            <ListItemText>Taxpayers</ListItemText>
           </MenuItem>
           <MenuItem
             disabled={navigationPage === 'settings.mailbox'}
@@ -124,7 +157,7 @@ const Application = () => {
             <ListItemIcon>
               <InboxIcon />
             </ListItemIcon>
-            <ListItemText>Mailbox</ListItemText>
+            <ListItemText>Mailboxes</ListItemText>
           </MenuItem>
           <MenuItem
             disabled={navigationPage === 'settings.importers'}
@@ -175,6 +208,14 @@ const Application = () => {
           <WorkIcon />
           Filings
         </Button>
+        <Button
+          color="inherit"
+          disabled={!navigationEnabled || navigationPage === 'annual'}
+          onClick={() => setNavigationPage('annual')}
+        >
+          <CalendarMonthIcon />
+          Annual
+        </Button>
         <Button
           color="inherit"
           disabled={!navigationEnabled || navigationPage === 'dev'}
@@ -183,6 +224,20 @@ const Application = () => {
           <EngineeringIcon />
           Dev
         </Button>
+        {taxpayerProfilesQuery.data.length > 1 &&
+          <Select
+            size="small"
+            variant="standard"
+            style={{ marginLeft: 'auto', color: 'inherit' }}
+            disabled={!navigationEnabled}
+            value={taxpayerProfileId}
+            onChange={e => setSelectedTaxpayerProfileId(e.target.value as number)}
+          >
+            {taxpayerProfilesQuery.data.map(tp =>
+              <MenuItem key={tp.id} value={tp.id}>{tp.fullName || `Taxpayer ${tp.id}`}</MenuItem>
+            )}
+          </Select>
+        }
         </Toolbar>
       </AppBar>
       {(() => {
@@ -190,17 +245,27 @@ const Application = () => {
           case 'settings.technical':
             return <TechnicalPage technicalConf={technicalConfQuery.data} />
           case 'settings.mailbox':
-            return <MailboxPage mailbox={mailboxQuery.data} />
+            return <MailboxesPage mailboxes={mailboxesQuery.data} />
           case 'settings.taxpayer':
-            return <TaxpayerProfilePage taxpayerProfile={taxpayerProfileQuery.data} />
+            return <TaxpayerProfilesPage taxpayerProfiles={taxpayerProfilesQuery.data} />
           case 'settings.importers':
-            return <ImportersPage importers={importersQuery.data} />
+            return <ImportersPage
+              importers={importersQuery.data}
+              mailboxes={mailboxesQuery.data}
+              taxpayerProfiles={taxpayerProfilesQuery.data}
+            />
           case 'sync':
             return <SyncPage setNavigationEnabled={setNavigationEnabled} />
           case 'reports':
             return <ReportsPage reports={reportsQuery.data} filings={filingsQuery.data} />
           case 'filings':
-            return <FilingsPage reports={reportsQuery.data} filings={filingsQuery.data} />
+            return <FilingsPage
+              reports={reportsQuery.data}
+              filings={taxpayerFilingsQuery.data}
+              taxpayerProfileId={taxpayerProfileId}
+            />
+          case 'annual':
+            return <AnnualSummaryPage filings={taxpayerFilingsQuery.data} taxpayerProfileId={taxpayerProfileId} />
           case 'dev':
             return <DevPage/>
           default:
@@ -0,0 +1,89 @@
+import {
+  Alert,
+  Button,
+  ButtonGroup,
+  Dialog,
+  DialogActions,
+  DialogContent,
+  DialogTitle,
+  FormControl,
+  MenuItem,
+  Select,
+  Stack,
+  TextField,
+} from '@mui/material'
+import { useState } from 'react'
+import { useQueryClient } from '@tanstack/react-query'
+import { invoke } from '@tauri-apps/api/core'
+
+type CapitalGainsFilingDialogProps = {
+  taxpayerProfileId: number
+  onClose: () => void
+}
+
+const isValidYear = (input: string) => {
+  return /^[0-9]{4}$/.test(input)
+}
+
+export const CapitalGainsFilingDialog = (props: CapitalGainsFilingDialogProps) => {
+  const queryClient = useQueryClient()
+  const [year, setYear] = useState(String(new Date().getFullYear()))
+  const [half, setHalf] = useState<1 | 2>(1)
+  const [errorMessage, setErrorMessage] = useState<string | null>(null)
+  return <Dialog
+      open
+      onClose={props.onClose}
+    >
+    <DialogTitle>
+      Create Capital Gains Filing (PP-PDG-1R)
+    </DialogTitle>
+    <DialogContent>
+      <p style={{ marginBottom: 0}}></p>
+      <Stack gap={1}>
+        <TextField
+          style={{ width: 400 }}
+          label="Year"
+          value={year}
+          error={!isValidYear(year)}
+          onChange={e => setYear(e.target.value)}
+        />
+        <FormControl size="small">
+          <Select
+            value={half}
+            onChange={e => setHalf(e.target.value as 1 | 2)}
+          >
+            <MenuItem value={1}>January - June</MenuItem>
+            <MenuItem value={2}>July - December</MenuItem>
+          </Select>
+        </FormControl>
+        {errorMessage && <Alert severity="error">{errorMessage}</Alert>}
+      </Stack>
+    </DialogContent>
+    <DialogActions>
+      <ButtonGroup>
+      <Button onClick={props.onClose}>Cancel</Button>
+      <Button
+        variant="contained"
+        disabled={!isValidYear(year)}
+        onClick={async () => {
+          try {
+            await invoke('create_capital_gains_filing', {
+              taxpayerProfileId: props.taxpayerProfileId,
+              year: parseInt(year),
+              half,
+            })
+          } catch (e) {
+            setErrorMessage(String(e))
+            return
+          }
+          queryClient.invalidateQueries({ queryKey: ['filings'] })
+          props.onClose()
+        }}
+        autoFocus
+      >
+        Create
+      </Button>
+      </ButtonGroup>
+    </DialogActions>
+  </Dialog>
+}
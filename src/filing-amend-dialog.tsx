@@ -0,0 +1,107 @@
+import {
+  Alert,
+  Button,
+  ButtonGroup,
+  Dialog,
+  DialogActions,
+  DialogContent,
+  DialogTitle,
+  Stack,
+  TextField,
+} from '@mui/material'
+import { useEffect, useState } from 'react'
+import { useQuery, useQueryClient } from '@tanstack/react-query'
+import { invoke } from '@tauri-apps/api/core'
+import { Filing, FilingIncome } from './ipc-types'
+
+type FilingAmendDialogProps = {
+  filing: Filing
+  onClose: () => void
+}
+
+const isValidAmount = (input: string) => {
+  return /^[0-9]+(\.[0-9]+)?$/.test(input)
+}
+
+export const FilingAmendDialog = (props: FilingAmendDialogProps) => {
+  const queryClient = useQueryClient()
+  // Grouped filings declare several incomes, each of which can be corrected
+  const filingIncomesQuery = useQuery({
+    queryKey: ['filing-incomes', props.filing.id],
+    queryFn: () => invoke('get_filing_incomes', {
+      filingId: props.filing.id,
+    }) as Promise<Array<FilingIncome>>,
+    networkMode: 'always',
+  })
+  const filingIncomes = filingIncomesQuery.data ?? []
+  const [incomeAmounts, setIncomeAmounts] = useState<Array<string>>([])
+  const [whtAmounts, setWhtAmounts] = useState<Array<string>>([])
+  useEffect(() => {
+    setIncomeAmounts(filingIncomes.map(fi => String(fi.incomeCurrencyAmount)))
+    setWhtAmounts(filingIncomes.map(fi => String(fi.whtCurrencyAmount)))
+  }, [filingIncomesQuery.data])
+  const [errorMessage, setErrorMessage] = useState<string | null>(null)
+  const valid = incomeAmounts.length > 0
+    && incomeAmounts.every(isValidAmount)
+    && whtAmounts.every(isValidAmount)
+  return <Dialog
+      open
+      onClose={props.onClose}
+    >
+    <DialogTitle>
+      Amend Filing #{props.filing.id}
+    </DialogTitle>
+    <DialogContent>
+      <p style={{ marginBottom: 0}}>
+        Creates an amended PP-OPO with the corrected amounts. The exchange rates of the original filing are kept.
+      </p>
+      <Stack gap={1}>
+        {filingIncomes.map((fi, i) => <Stack key={i} gap={1}>
+          {filingIncomes.length > 1 && <b>{fi.payingEntity}</b>}
+          <TextField
+            style={{ width: 400 }}
+            label={`Gross income (${fi.incomeCurrencyCode})`}
+            value={incomeAmounts[i] ?? ''}
+            error={!isValidAmount(incomeAmounts[i] ?? '')}
+            onChange={e => setIncomeAmounts(incomeAmounts.map((a, j) => j === i ? e.target.value : a))}
+          />
+          <TextField
+            style={{ width: 400 }}
+            label={`Withholding tax (${fi.whtCurrencyCode})`}
+            value={whtAmounts[i] ?? ''}
+            error={!isValidAmount(whtAmounts[i] ?? '')}
+            onChange={e => setWhtAmounts(whtAmounts.map((a, j) => j === i ? e.target.value : a))}
+          />
+        </Stack>)}
+        {filingIncomesQuery.error && <Alert severity="error">{String(filingIncomesQuery.error)}</Alert>}
+        {errorMessage && <Alert severity="error">{errorMessage}</Alert>}
+      </Stack>
+    </DialogContent>
+    <DialogActions>
+      <ButtonGroup>
+      <Button onClick={props.onClose}>Cancel</Button>
+      <Button
+        variant="contained"
+        disabled={!valid}
+        onClick={async () => {
+          try {
+            await invoke('create_amended_filing', {
+              filingId: props.filing.id,
+              incomeCurrencyAmounts: incomeAmounts.map(parseFloat),
+              whtCurrencyAmounts: whtAmounts.map(parseFloat),
+            })
+          } catch (e) {
+            setErrorMessage(String(e))
+            return
+          }
+          queryClient.invalidateQueries({ queryKey: ['filings'] })
+          props.onClose()
+        }}
+        autoFocus
+      >
+        Create
+      </Button>
+      </ButtonGroup>
+    </DialogActions>
+  </Dialog>
+}
@@ -1,6 +1,7 @@
 import { formatRsdcAmount, getPaymentQrUri } from './helpers'
 import { Filing, FilingStatus } from './ipc-types'
 import {
+  Alert,
   Button,
   ButtonGroup,
   Dialog,
@@ -28,6 +29,14 @@ export const FilingEditDialog = (props: FilingEditDialogProps) => {
   const [filingStatus, setFilingStatus] = useState(props.filing.status)
   const [paymentReference, setPaymentReference] = useState(props.filing.taxPaymentReference)
   const [paymentQrUri, setPaymentQrUri] = useState<string | null>(null)
+  // Late interest is recomputed up to the actual payment date once the tax is paid
+  const [latePaymentDate, setLatePaymentDate] = useState(
+    props.filing.latePaymentDate ?? new Date().toISOString().slice(0, 10)
+  )
+  const [errorMessage, setErrorMessage] = useState<string | null>(null)
+  const askLatePaymentDate = props.filing.latePaymentDate !== null
+    && props.filing.status !== 'paid'
+    && filingStatus === 'paid'
   return <Dialog
       open
       onClose={props.onClose}
@@ -52,7 +61,10 @@ export const FilingEditDialog = (props: FilingEditDialogProps) => {
             value={filingStatus}
             sx={{ minWidth: 90 }}
             onChange={e => setFilingStatus(e.target.value as FilingStatus)}>
-            <MenuItem value='init'>Initial</MenuItem>
+            {props.filing.amendsFilingId === null
+              ? <MenuItem value='init'>Initial</MenuItem>
+              : <MenuItem value='amendment'>Amendment</MenuItem>
+            }
             <MenuItem value='filed'>Filed</MenuItem>
             <MenuItem value='paid'>Paid</MenuItem>
           </Select>
@@ -65,6 +77,25 @@ export const FilingEditDialog = (props: FilingEditDialogProps) => {
           value={formatRsdcAmount(props.filing.taxPayable)}
           onChange={e => setPaymentReference(e.target.value)}
         />
+        {props.filing.whtActual !== null && props.filing.whtPaid !== null &&
+          props.filing.whtActual > props.filing.whtPaid &&
+          <TextField
+            size="small"
+            style={{ width: 400 }}
+            disabled
+            label="WHT Above Treaty Rate (reclaimable abroad)"
+            value={formatRsdcAmount(props.filing.whtActual - props.filing.whtPaid)}
+          />
+        }
+        {props.filing.lateInterest !== null &&
+          <TextField
+            size="small"
+            style={{ width: 400 }}
+            disabled
+            label={`Late Interest (paid ${props.filing.latePaymentDate})`}
+            value={formatRsdcAmount(props.filing.lateInterest)}
+          />
+        }
         <TextField
           size="small"
           style={{ width: 400 }}
@@ -72,11 +103,22 @@ export const FilingEditDialog = (props: FilingEditDialogProps) => {
           value={paymentReference}
           onChange={e => {
             setPaymentReference(e.target.value)
-            if (filingStatus == 'init' && e.target.value != '') {
+            if ((filingStatus == 'init' || filingStatus == 'amendment') && e.target.value != '') {
               setFilingStatus('filed')
             }
           }}
         />
+        {askLatePaymentDate &&
+          <TextField
+            size="small"
+            style={{ width: 400 }}
+            label="Payment Date"
+            value={latePaymentDate}
+            error={!/^[0-9]{4}-[0-9]{2}-[0-9]{2}$/.test(latePaymentDate)}
+            onChange={e => setLatePaymentDate(e.target.value)}
+          />
+        }
+        {errorMessage && <Alert severity="error">{errorMessage}</Alert>}
       </Stack>
     </DialogContent>
     <DialogActions>
@@ -85,17 +127,28 @@ export const FilingEditDialog = (props: FilingEditDialogProps) => {
         if (paymentQrUri !== null) {
           setPaymentQrUri(null)
         } else {
-          const paymentQrUri = await getPaymentQrUri(props.filing.taxPayable, paymentReference)
+          const paymentQrUri = await getPaymentQrUri(
+            props.filing.taxPayable + (props.filing.lateInterest ?? 0),
+            paymentReference,
+          )
           setPaymentQrUri(paymentQrUri)
         }
       }}>Payment QR</Button>
       <Button onClick={props.onClose}>Cancel</Button>
       <Button variant="contained" onClick={async () => {
-        await invoke('update_filing', { filing: {
-          ...props.filing,
-          status: filingStatus,
-          taxPaymentReference: paymentReference,
-        }})
+        try {
+          await invoke('update_filing', {
+            filing: {
+              ...props.filing,
+              status: filingStatus,
+              taxPaymentReference: paymentReference,
+            },
+            latePaymentDate: askLatePaymentDate ? latePaymentDate : null,
+          })
+        } catch (e) {
+          setErrorMessage(String(e))
+          return
+        }
         queryClient.invalidateQueries({ queryKey: ['filings'] })
         props.onClose()
       }} autoFocus>
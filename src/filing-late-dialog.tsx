@@ -0,0 +1,81 @@
+import {
+  Alert,
+  Button,
+  ButtonGroup,
+  Dialog,
+  DialogActions,
+  DialogContent,
+  DialogTitle,
+  Stack,
+  TextField,
+} from '@mui/material'
+import { useState } from 'react'
+import { useQueryClient } from '@tanstack/react-query'
+import { invoke } from '@tauri-apps/api/core'
+import { Filing } from './ipc-types'
+
+type FilingLateDialogProps = {
+  filing: Filing
+  onClose: () => void
+}
+
+const isValidDate = (input: string) => {
+  return /^[0-9]{4}-[0-9]{2}-[0-9]{2}$/.test(input)
+}
+
+export const FilingLateDialog = (props: FilingLateDialogProps) => {
+  const queryClient = useQueryClient()
+  const [paymentDate, setPaymentDate] = useState(
+    props.filing.latePaymentDate ?? new Date().toISOString().slice(0, 10)
+  )
+  const [errorMessage, setErrorMessage] = useState<string | null>(null)
+  return <Dialog
+      open
+      onClose={props.onClose}
+    >
+    <DialogTitle>
+      File Late
+    </DialogTitle>
+    <DialogContent>
+      <p style={{ marginBottom: 0}}>
+        The filing deadline was {props.filing.filingDeadline}. Interest is calculated up to the date the
+        tax will be paid.
+      </p>
+      <Stack gap={1}>
+        <TextField
+          style={{ width: 400 }}
+          label="Payment date"
+          value={paymentDate}
+          error={!isValidDate(paymentDate)}
+          onChange={e => setPaymentDate(e.target.value)}
+        />
+        {errorMessage && <Alert severity="error">{errorMessage}</Alert>}
+      </Stack>
+    </DialogContent>
+    <DialogActions>
+      <ButtonGroup>
+      <Button onClick={props.onClose}>Cancel</Button>
+      <Button
+        variant="contained"
+        disabled={!isValidDate(paymentDate)}
+        onClick={async () => {
+          try {
+            await invoke('regenerate_filing', {
+              filingId: props.filing.id,
+              latePaymentDate: paymentDate,
+            })
+          } catch (e) {
+            setErrorMessage(String(e))
+            return
+          }
+          queryClient.invalidateQueries({ queryKey: ['filings'] })
+          props.onClose()
+        }}
+        autoFocus
+      >
+        Regenerate
+      </Button>
+      </ButtonGroup>
+    </DialogActions>
+  </Dialog>
+}
@@ -2,6 +2,7 @@ import { formatRsdcAmount } from './helpers'
 import { Filing, FilingStatus, Report } from './ipc-types'
 import {
   Button,
+  ButtonGroup,
   Container,
   FormControl,
   ListItemIcon,
@@ -23,13 +24,21 @@ import DoDisturbAltIcon from '@mui/icons-material/DoDisturbAlt'
 import DownloadIcon from '@mui/icons-material/Download'
 import TrashIcon from '@mui/icons-material/Delete'
 import EditIcon from '@mui/icons-material/Edit'
+import RefreshIcon from '@mui/icons-material/Refresh'
+import EditNoteIcon from '@mui/icons-material/EditNote'
+import EventBusyIcon from '@mui/icons-material/EventBusy'
+import DoneIcon from '@mui/icons-material/Done'
 import { FilingEditDialog } from './filing-edit-dialog'
+import { CapitalGainsFilingDialog } from './capital-gains-filing-dialog'
+import { FilingAmendDialog } from './filing-amend-dialog'
+import { FilingLateDialog } from './filing-late-dialog'
 import { useQueryClient } from '@tanstack/react-query'
 import { invoke } from '@tauri-apps/api/core'
 
 interface FilingsPageProps {
   reports: Array<Report>
   filings: Array<Filing>
+  taxpayerProfileId: number
 }
 
 type FilingFilter = 'all' | 'unpaid'
@@ -39,6 +48,8 @@ const PAGE_SIZE = 20
 type FilingsRowProps = {
   filing: Filing
   openFilingEditDialog: () => void
+  openFilingAmendDialog: () => void
+  openFilingLateDialog: () => void
 }
 
 const FilingsRow = (props: FilingsRowProps) => {
@@ -56,6 +67,11 @@ const FilingsRow = (props: FilingsRowProps) => {
           onClick={e => { e.stopPropagation() }}
           onChange={async e => {
             const newStatus = e.target.value as FilingStatus
+            if (newStatus === 'paid' && props.filing.latePaymentDate !== null) {
+              // The edit dialog asks for the payment date to recompute the late interest
+              props.openFilingEditDialog()
+              return
+            }
             await invoke('update_filing', {
               filing: {
                 ...props.filing,
@@ -65,16 +81,35 @@ const FilingsRow = (props: FilingsRowProps) => {
             queryClient.invalidateQueries({ queryKey: ['filings'] })
           }
         }>
-          <MenuItem value='init'>Initial</MenuItem>
+          {props.filing.amendsFilingId === null
+            ? <MenuItem value='init'>Initial</MenuItem>
+            : <MenuItem value='amendment'>Amendment</MenuItem>
+          }
           <MenuItem value='filed'>Filed</MenuItem>
           <MenuItem value='paid'>Paid</MenuItem>
         </Select>
       </FormControl>
     </TableCell>
     <TableCell>{props.filing.type}</TableCell>
-    <TableCell>{props.filing.payingEntity}</TableCell>
+    <TableCell>
+      {props.filing.payingEntity}
+      {props.filing.amendsFilingId !== null && ` (amends #${props.filing.amendsFilingId})`}
+      {props.filing.suspectedDuplicateOfReportId !== null &&
+        <><br /><span style={{ color: theme.palette.warning.dark }}>
+          May duplicate an income from report #{props.filing.suspectedDuplicateOfReportId}
+        </span></>
+      }
+    </TableCell>
     <TableCell align="right">{props.filing.filingDeadline}</TableCell>
-    <TableCell align="right">{formatRsdcAmount(props.filing.taxPayable)}</TableCell>
+    <TableCell align="right">
+      {formatRsdcAmount(props.filing.taxPayable)}
+      {props.filing.taxPayableDelta !== null &&
+        <><br />Δ {formatRsdcAmount(props.filing.taxPayableDelta)}</>
+      }
+      {props.filing.lateInterest !== null &&
+        <><br />+ {formatRsdcAmount(props.filing.lateInterest)} interest</>
+      }
+    </TableCell>
     <TableCell align="right">{props.filing.taxPaymentReference || '-'}</TableCell>
     <TableCell align="right">
         <Button onClick={e => {
@@ -119,6 +154,60 @@ const FilingsRow = (props: FilingsRowProps) => {
             </ListItemIcon>
             <ListItemText>Edit</ListItemText>
           </MenuItem>
+          {props.filing.suspectedDuplicateOfReportId !== null &&
+            <MenuItem
+              onClick={async e => {
+                e.stopPropagation()
+                await invoke('confirm_filing_not_duplicate', { filingId: props.filing.id })
+                queryClient.invalidateQueries({ queryKey: ['filings'] })
+                setMenuAnchorEl(null)
+              }}
+            >
+              <ListItemIcon>
+                <DoneIcon />
+              </ListItemIcon>
+              <ListItemText>Not a Duplicate</ListItemText>
+            </MenuItem>
+          }
+          <MenuItem
+            disabled={props.filing.incomeDate === null || props.filing.type === 'capital-gain'}
+            onClick={async e => {
+              e.stopPropagation()
+              await invoke('regenerate_filing', { filingId: props.filing.id, latePaymentDate: null })
+              setMenuAnchorEl(null)
+            }}
+          >
+            <ListItemIcon>
+              <RefreshIcon />
+            </ListItemIcon>
+            <ListItemText>Regenerate</ListItemText>
+          </MenuItem>
+          <MenuItem
+            disabled={props.filing.incomeDate === null || props.filing.type === 'capital-gain' || props.filing.status === 'init' || props.filing.status === 'amendment'}
+            onClick={e => {
+              e.stopPropagation()
+              props.openFilingAmendDialog()
+              setMenuAnchorEl(null)
+            }}
+          >
+            <ListItemIcon>
+              <EditNoteIcon />
+            </ListItemIcon>
+            <ListItemText>Amend</ListItemText>
+          </MenuItem>
+          <MenuItem
+            disabled={props.filing.incomeDate === null || props.filing.type === 'capital-gain' || props.filing.amendsFilingId !== null}
+            onClick={e => {
+              e.stopPropagation()
+              props.openFilingLateDialog()
+              setMenuAnchorEl(null)
+            }}
+          >
+            <ListItemIcon>
+              <EventBusyIcon />
+            </ListItemIcon>
+            <ListItemText>File Late...</ListItemText>
+          </MenuItem>
           <MenuItem
             onClick={async e => {
               e.stopPropagation()
@@ -159,6 +248,9 @@ export const FilingsPage = (props: FilingsPageProps) => {
   )
   const [filingEditDialogState, setFilingEditDialogState] =
     useState<FilingEditDialogState>({ visible: false })
+  const [capitalGainsDialogVisible, setCapitalGainsDialogVisible] = useState(false)
+  const [amendedFiling, setAmendedFiling] = useState<Filing | null>(null)
+  const [lateFiling, setLateFiling] = useState<Filing | null>(null)
 
   useEffect(() => {
     if (page > numPages){
@@ -176,6 +268,24 @@ export const FilingsPage = (props: FilingsPageProps) => {
         onClose={() => setFilingEditDialogState({ visible: false })}
       />
     }
+    {amendedFiling &&
+      <FilingAmendDialog
+        filing={amendedFiling}
+        onClose={() => setAmendedFiling(null)}
+      />
+    }
+    {lateFiling &&
+      <FilingLateDialog
+        filing={lateFiling}
+        onClose={() => setLateFiling(null)}
+      />
+    }
+    {capitalGainsDialogVisible &&
+      <CapitalGainsFilingDialog
+        taxpayerProfileId={props.taxpayerProfileId}
+        onClose={() => setCapitalGainsDialogVisible(false)}
+      />
+    }
     <FormControl size="small">
       <Select value={filter} onChange={e => setFilter(e.target.value as FilingFilter)}>
         <MenuItem value='unpaid'>Unpaid</MenuItem>
@@ -204,6 +314,8 @@ export const FilingsPage = (props: FilingsPageProps) => {
               visible: true,
               filing: f,
             })}
+            openFilingAmendDialog={() => setAmendedFiling(f)}
+            openFilingLateDialog={() => setLateFiling(f)}
           />
         )}
         { filteredFilings.length === 0 &&
@@ -221,5 +333,10 @@ export const FilingsPage = (props: FilingsPageProps) => {
     { numPages > 1 &&
       <Pagination count={numPages} onChange={(_e, value) => setPage(value)} />
     }
+    <ButtonGroup>
+      <Button onClick={() => setCapitalGainsDialogVisible(true)}>
+        Create capital gains filing
+      </Button>
+    </ButtonGroup>
   </Container>
 }
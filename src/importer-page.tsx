@@ -1,5 +1,5 @@
-import { Importer, ReportType } from './ipc-types'
-import { Button, ButtonGroup, Container, Dialog, DialogActions, DialogContent, DialogTitle, FormControl, IconButton, InputAdornment, InputLabel, MenuItem, OutlinedInput, Pagination, Select, Stack, Table, TableBody, TableCell, TableHead, TableRow, TextField, Tooltip } from '@mui/material'
+import { GenericCsvConf, Importer, Mailbox, ReportType, TaxpayerProfile } from './ipc-types'
+import { Button, ButtonGroup, Checkbox, Container, Dialog, DialogActions, DialogContent, DialogTitle, FormControl, FormControlLabel, IconButton, InputAdornment, InputLabel, MenuItem, OutlinedInput, Pagination, Select, Stack, Table, TableBody, TableCell, TableHead, TableRow, TextField, Tooltip } from '@mui/material'
 import { useEffect, useMemo, useState } from 'react'
 import DoDisturbAltIcon from '@mui/icons-material/DoDisturbAlt'
 import HelpIcon from '@mui/icons-material/Help'
@@ -8,10 +8,14 @@ import { invoke } from '@tauri-apps/api/core'
 
 interface ImportersPageProps {
   importers: Array<Importer>
+  mailboxes: Array<Mailbox>
+  taxpayerProfiles: Array<TaxpayerProfile>
 }
 
 type UpsertDialogProps = {
   initialImporter: Importer
+  mailboxes: Array<Mailbox>
+  taxpayerProfiles: Array<TaxpayerProfile>
   onClose: () => void
 }
 
@@ -19,9 +23,79 @@ const PAGE_SIZE = 20
 
 const ReportTypeDescriptions: { [k in ReportType]: string} = {
   'IbkrCsv': 'InteractiveBrokers CSV',
+  'IbkrFlexXml': 'InteractiveBrokers Flex XML',
+  'Trading212Csv': 'Trading 212 CSV',
+  'RevolutCsv': 'Revolut account statement CSV',
+  'DegiroCsv': 'Degiro Account.csv',
+  'GenericCsv': 'Generic CSV (custom column mapping)',
   'NativeIncomeJson': 'Dobkapapp JSON',
 }
 
+const DEFAULT_GENERIC_CSV_CONF: GenericCsvConf = {
+  delimiter: ',',
+  dateColumn: 'Date',
+  dateFormat: '%Y-%m-%d',
+  typeColumn: 'Type',
+  dividendTypeRegex: '^Dividend',
+  interestTypeRegex: '^Interest',
+  payerColumn: 'Payer',
+  currencyColumn: 'Currency',
+  amountColumn: 'Amount',
+  whtColumn: '',
+  decimalSeparator: '.',
+  rowFilters: [],
+}
+
+// Row filters are edited as one "column: regex" pair per line
+const formatRowFilters = (conf: GenericCsvConf) =>
+  conf.rowFilters.map(rf => `${rf.column}: ${rf.regex}`).join('\n')
+
+const parseRowFilters = (text: string) => text
+  .split('\n')
+  .filter(line => line.includes(':'))
+  .map(line => {
+    const separatorIndex = line.indexOf(':')
+    return {
+      column: line.slice(0, separatorIndex).trim(),
+      regex: line.slice(separatorIndex + 1).trim(),
+    }
+  })
+
+type GenericCsvConfFieldsProps = {
+  conf: GenericCsvConf
+  onChange: (conf: GenericCsvConf) => void
+}
+
+const GenericCsvConfFields = (props: GenericCsvConfFieldsProps) => {
+  const [rowFiltersText, setRowFiltersText] = useState(formatRowFilters(props.conf))
+  const textField = (label: string, key: Exclude<keyof GenericCsvConf, 'rowFilters'>) =>
+    <TextField label={label} size="small" value={props.conf[key]} onChange={e => props.onChange({ ...props.conf, [key]: e.target.value })} />
+  return <Stack gap={1}>
+    {textField('Delimiter', 'delimiter')}
+    {textField('Date column', 'dateColumn')}
+    {textField('Date format', 'dateFormat')}
+    {textField('Type column', 'typeColumn')}
+    {textField('Dividend type regex', 'dividendTypeRegex')}
+    {textField('Interest type regex', 'interestTypeRegex')}
+    {textField('Payer column', 'payerColumn')}
+    {textField('Currency column', 'currencyColumn')}
+    {textField('Amount column', 'amountColumn')}
+    {textField('WHT column', 'whtColumn')}
+    {textField('Decimal separator', 'decimalSeparator')}
+    <TextField
+      label="Row filters (column: regex, one per line)"
+      size="small"
+      multiline
+      minRows={2}
+      value={rowFiltersText}
+      onChange={e => {
+        setRowFiltersText(e.target.value)
+        props.onChange({ ...props.conf, rowFilters: parseRowFilters(e.target.value) })
+      }}
+    />
+  </Stack>
+}
+
 const UpsertImporterDialog = (props: UpsertDialogProps) => {
   const queryClient = useQueryClient()
   const [name, setName] = useState(props.initialImporter.name)
@@ -29,6 +103,11 @@ const UpsertImporterDialog = (props: UpsertDialogProps) => {
   const [subjectFilter, setSubjectFilter] = useState(props.initialImporter.subjectFilter)
   const [attachmentRegex, setAttachmentRegex] = useState(props.initialImporter.attachmentRegex)
   const [paymentNotes, setPaymentNotes] = useState(props.initialImporter.paymentNotes)
+  const [reportType, setReportType] = useState(props.initialImporter.reportType)
+  const [mailboxId, setMailboxId] = useState(props.initialImporter.mailboxId)
+  const [taxpayerProfileId, setTaxpayerProfileId] = useState(props.initialImporter.taxpayerProfileId)
+  const [genericCsvConf, setGenericCsvConf] = useState(props.initialImporter.genericCsvConf ?? DEFAULT_GENERIC_CSV_CONF)
+  const [groupFilingsByDate, setGroupFilingsByDate] = useState(props.initialImporter.groupFilingsByDate)
   return <div>
     <Dialog open={true} onClose={props.onClose} fullWidth>
       <DialogTitle>
@@ -38,6 +117,22 @@ const UpsertImporterDialog = (props: UpsertDialogProps) => {
         <h2 style={{ marginBottom: 0}}></h2>
         <Stack gap={1}>
           <TextField label="Name" size="small" value={name} onChange={e => setName(e.target.value)} />
+          <FormControl size="small">
+            <InputLabel>Mailbox</InputLabel>
+            <Select value={mailboxId} label="Mailbox" onChange={e => setMailboxId(e.target.value as number)}>
+              {props.mailboxes.map(mb =>
+                <MenuItem key={mb.id} value={mb.id}>{mb.emailAddress || `Mailbox ${mb.id}`}</MenuItem>
+              )}
+            </Select>
+          </FormControl>
+          <FormControl size="small">
+            <InputLabel>Taxpayer</InputLabel>
+            <Select value={taxpayerProfileId} label="Taxpayer" onChange={e => setTaxpayerProfileId(e.target.value as number)}>
+              {props.taxpayerProfiles.map(tp =>
+                <MenuItem key={tp.id} value={tp.id}>{tp.fullName || `Taxpayer ${tp.id}`}</MenuItem>
+              )}
+            </Select>
+          </FormControl>
           <TextField label="From filter" size="small" value={fromFilter} onChange={e => setFromFilter(e.target.value)} />
           <TextField label="Subject filter" size="small" value={subjectFilter} onChange={e => setSubjectFilter(e.target.value)} />
           <TextField label="Attachment regex" size="small" value={attachmentRegex} onChange={e => setAttachmentRegex(e.target.value)} />
@@ -61,10 +156,27 @@ const UpsertImporterDialog = (props: UpsertDialogProps) => {
           </FormControl>
           <FormControl size="small">
             <InputLabel>Import format</InputLabel>
-            <Select value="IbkrCsv" label="Import format">
+            <Select value={reportType} label="Import format" onChange={e => setReportType(e.target.value as ReportType)}>
               <MenuItem value='IbkrCsv'>{ReportTypeDescriptions['IbkrCsv']}</MenuItem>
+              <MenuItem value='IbkrFlexXml'>{ReportTypeDescriptions['IbkrFlexXml']}</MenuItem>
+              <MenuItem value='Trading212Csv'>{ReportTypeDescriptions['Trading212Csv']}</MenuItem>
+              <MenuItem value='RevolutCsv'>{ReportTypeDescriptions['RevolutCsv']}</MenuItem>
+              <MenuItem value='DegiroCsv'>{ReportTypeDescriptions['DegiroCsv']}</MenuItem>
+              <MenuItem value='GenericCsv'>{ReportTypeDescriptions['GenericCsv']}</MenuItem>
             </Select>
           </FormControl>
+          {reportType === 'GenericCsv' &&
+            <GenericCsvConfFields conf={genericCsvConf} onChange={setGenericCsvConf} />
+          }
+          <FormControlLabel
+            control={
+              <Checkbox
+                checked={groupFilingsByDate}
+                onChange={e => setGroupFilingsByDate(e.target.checked)}
+              />
+            }
+            label="Combine incomes of the same type and date into one filing"
+          />
         </Stack>
       </DialogContent>
       <DialogActions>
@@ -79,6 +191,11 @@ const UpsertImporterDialog = (props: UpsertDialogProps) => {
                         subjectFilter,
                         attachmentRegex,
                         paymentNotes,
+                        reportType,
+                        mailboxId,
+                        taxpayerProfileId,
+                        genericCsvConf: reportType === 'GenericCsv' ? genericCsvConf : null,
+                        groupFilingsByDate,
                     }
                 })
             } else {
@@ -90,6 +207,11 @@ const UpsertImporterDialog = (props: UpsertDialogProps) => {
                         subjectFilter,
                         attachmentRegex,
                         paymentNotes,
+                        reportType,
+                        mailboxId,
+                        taxpayerProfileId,
+                        genericCsvConf: reportType === 'GenericCsv' ? genericCsvConf : null,
+                        groupFilingsByDate,
                     }
                 })
             }
@@ -122,6 +244,8 @@ export const ImportersPage = (props: ImportersPageProps) => {
     <h2 style={{ marginBottom: 0}}>Importers</h2>
     {upsertImporter && <UpsertImporterDialog
       initialImporter={upsertImporter}
+      mailboxes={props.mailboxes}
+      taxpayerProfiles={props.taxpayerProfiles}
       onClose={() => setUpsertImporter(null)}
     />}
     <Table>
@@ -175,12 +299,14 @@ export const ImportersPage = (props: ImportersPageProps) => {
           id: 0,
           name: 'Untitled importer',
           reportType: 'IbkrCsv',
-          mailboxId: 1,
-          taxpayerProfileId: 1,
+          mailboxId: props.mailboxes[0]?.id ?? 1,
+          taxpayerProfileId: props.taxpayerProfiles[0]?.id ?? 1,
           fromFilter: '',
           subjectFilter: '',
           paymentNotes: 'Isplata na brokerski racun',
           attachmentRegex: '',
+          genericCsvConf: null,
+          groupFilingsByDate: false,
         })
       }}>Add importer</Button>
     </ButtonGroup>
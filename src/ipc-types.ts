@@ -6,8 +6,21 @@ export type HolidayConf = {
   holidays: DateString[]
 }
 
+export type ReferenceRate = {
+  validFrom: DateString
+  rate: number
+}
+
+export type WhtTreatyRate = {
+  countryCode: string
+  rate: number
+}
+
 export type TechnicalConf = {
   holidayConf: HolidayConf
+  referenceRates: ReferenceRate[]
+  whtTreatyRates: WhtTreatyRate[]
+  dropFolder: string | null
 }
 
 export type MailboxCursor = {
@@ -26,6 +39,25 @@ export type Mailbox = {
   imapHost: string,
   imapPort: number,
   cursor: MailboxCursor
+  folders: string[]
+  authMethod: MailboxAuthMethod
+  oauthConf: MailboxOAuthConf | null
+}
+
+export type MailboxAuthMethod = 'password' | 'oauth2'
+
+export type SecretsStatus = {
+  usesPassphrase: boolean
+  locked: boolean
+}
+
+export type MailboxOAuthConf = {
+  tokenUrl: string
+  clientId: string
+  clientSecret: string
+  refreshToken: string
+  accessToken: string
+  accessTokenExpiry: number
 }
 
 export type TaxpayerProfile = {
@@ -38,7 +70,27 @@ export type TaxpayerProfile = {
   emailAddress: string,
 }
 
-export type ReportType = 'IbkrCsv' | 'NativeIncomeJson'
+export type ReportType = 'IbkrCsv' | 'IbkrFlexXml' | 'Trading212Csv' | 'RevolutCsv' | 'DegiroCsv' | 'GenericCsv' | 'NativeIncomeJson'
+
+export type GenericCsvRowFilter = {
+  column: string
+  regex: string
+}
+
+export type GenericCsvConf = {
+  delimiter: string
+  dateColumn: string
+  dateFormat: string
+  typeColumn: string
+  dividendTypeRegex: string
+  interestTypeRegex: string
+  payerColumn: string
+  currencyColumn: string
+  amountColumn: string
+  whtColumn: string
+  decimalSeparator: string
+  rowFilters: GenericCsvRowFilter[]
+}
 
 export type Importer = {
   id: number
@@ -50,6 +102,8 @@ export type Importer = {
   subjectFilter: string
   paymentNotes: string
   attachmentRegex: string
+  genericCsvConf: GenericCsvConf | null
+  groupFilingsByDate: boolean
 }
 
 export type ReportStatus = 'init' | 'processed'
@@ -58,24 +112,74 @@ export type Report = {
   id: number,
   type: ReportType,
   importerId: number | null,
-  mailboxId: number,
-  mailboxMessageId: number,
+  // Only set for reports fetched over IMAP
+  mailboxId: number | null,
+  mailboxMessageId: number | null,
   reportName: string,
   status: ReportStatus,
+  // Taken from the importer when the report is imported
+  taxpayerProfileId: number,
 }
 
-export type FilingStatus = 'init' | 'filed' | 'paid'
-export declare type PassiveIncomeType = 'dividend' | 'interest'
+// Amendments start out as 'amendment' instead of 'init'
+export type FilingStatus = 'init' | 'amendment' | 'filed' | 'paid'
+export declare type PassiveIncomeType = 'dividend' | 'interest' | 'capital-gain'
 
 export type Filing = {
   id: number,
-  reportId: number,
+  reportId: number | null,
   type: PassiveIncomeType,
   status: FilingStatus,
   payingEntity: string,
   filingDeadline: DateString,
   taxPayable: number
   taxPaymentReference: string,
+  incomeDate: DateString | null,
+  grossIncome: number | null,
+  whtPaid: number | null,
+  grossTaxPayable: number | null,
+  incomeCurrencyCode: string | null,
+  incomeCurrencyAmount: number | null,
+  incomeExchangeRate: number | null,
+  whtCurrencyCode: string | null,
+  whtCurrencyAmount: number | null,
+  whtExchangeRate: number | null,
+  amendsFilingId: number | null,
+  taxPayableDelta: number | null,
+  latePaymentDate: DateString | null,
+  lateInterest: number | null,
+  whtActual: number | null,
+  whtCreditRate: number | null,
+  taxpayerProfileId: number,
+  period: string | null,
+  // Set until the user confirms that the income was not filed from that report already
+  suspectedDuplicateOfReportId: number | null,
+}
+
+export type FilingIncome = {
+  payingEntity: string,
+  incomeCurrencyCode: string,
+  incomeCurrencyAmount: number,
+  whtCurrencyCode: string,
+  whtCurrencyAmount: number,
+}
+
+export type AnnualSummaryLine = {
+  type: PassiveIncomeType | 'total'
+  filingCount: number
+  grossIncome: number
+  whtPaid: number
+  whtReclaimable: number
+  taxPayable: number
+  taxPaid: number
+}
+
+export type AnnualSummary = {
+  year: number
+  lines: AnnualSummaryLine[]
+  total: AnnualSummaryLine
+  incompleteFilingCount: number
+  suspectedDuplicateFilingCount: number
 }
 
 export type ReportJobMessage = {
@@ -95,7 +199,12 @@ export type SuccessJobMessage = {
   message: string
 }
 
-export type JobMessage = ReportJobMessage | ErrorJobMessage | SuccessJobMessage
+export type WarningJobMessage = {
+  type: 'warning',
+  message: string
+}
+
+export type JobMessage = ReportJobMessage | ErrorJobMessage | SuccessJobMessage | WarningJobMessage
 
 export type Job = {
   id: number
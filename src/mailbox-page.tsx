@@ -1,6 +1,6 @@
-import { Mailbox } from './ipc-types'
+import { Mailbox, MailboxAuthMethod } from './ipc-types'
 import { Visibility, VisibilityOff } from '@mui/icons-material'
-import { Alert, Button, ButtonGroup, Container, IconButton, InputAdornment, Stack, TextField } from '@mui/material'
+import { Alert, Button, ButtonGroup, Container, IconButton, InputAdornment, MenuItem, Stack, Tab, Tabs, TextField } from '@mui/material'
 import React, { useState } from 'react'
 import { openPath } from '@tauri-apps/plugin-opener'
 import { useQueryClient } from '@tanstack/react-query'
@@ -11,28 +11,57 @@ type Props = {
   mailbox: Mailbox
 }
 
-export const MailboxPage = (props: Props) => {
+const getSecretHelperText = (secret: string | null) => {
+  if (secret === null) {
+    return 'Saved secrets are not shown, type to replace the saved one'
+  }
+  return secret === '' ? 'The saved one is cleared on save' : undefined
+}
+
+const MailboxPage = (props: Props) => {
   const queryClient = useQueryClient()
   const [imapEmailAddress, setImapEmailAddress] = useState(props.mailbox.emailAddress)
-  const [imapEmailPassword, setImapEmailPassword] = useState(props.mailbox.emailPassword)
+  // Saved secrets are not sent to the frontend. null keeps the saved one, '' clears it
+  const [imapEmailPassword, setImapEmailPassword] = useState<string | null>(null)
   const [showPassword, setShowPassword] = useState(false)
   const [imapEmailHost, setImapEmailHost] = useState(props.mailbox.imapHost)
   const [imapEmailPort, setImapEmailPort] = useState(props.mailbox.imapPort.toString())
   const [cursor, setCursor] = useState(props.mailbox.cursor)
+  const [folders, setFolders] = useState(props.mailbox.folders.join('\n'))
+  const [errorMessage, setErrorMessage] = useState<string | null>(null)
+  const [authMethod, setAuthMethod] = useState(props.mailbox.authMethod)
+  const [oauthTokenUrl, setOauthTokenUrl] = useState(props.mailbox.oauthConf?.tokenUrl ?? 'https://oauth2.googleapis.com/token')
+  const [oauthClientId, setOauthClientId] = useState(props.mailbox.oauthConf?.clientId ?? '')
+  const [oauthClientSecret, setOauthClientSecret] = useState<string | null>(null)
+  const [oauthRefreshToken, setOauthRefreshToken] = useState<string | null>(null)
 
   const handleClickShowPassword = () => setShowPassword(show => !show)
   const handleMouseDownPassword = (event: React.MouseEvent<HTMLButtonElement>) => {
     event.preventDefault()
   }
-  return <Container>
-    <Stack spacing={1} style={{ paddingTop: '8px'}}>
-      <h2 style={{ marginBottom: 0}}>Mailbox</h2>
+  return <Stack spacing={1} style={{ paddingTop: '8px'}}>
       <TextField label="Email Address" size="small" value={imapEmailAddress} onChange={e => setImapEmailAddress(e.target.value)} />
+      <TextField select label="Authentication" size="small" value={authMethod} onChange={e => setAuthMethod(e.target.value as MailboxAuthMethod)}>
+        <MenuItem value="password">Password</MenuItem>
+        <MenuItem value="oauth2">OAuth2 (XOAUTH2)</MenuItem>
+      </TextField>
+      {authMethod === 'oauth2' ? <>
+      <TextField label="OAuth Token URL" size="small" value={oauthTokenUrl} onChange={e => setOauthTokenUrl(e.target.value)} />
+      <TextField label="OAuth Client ID" size="small" value={oauthClientId} onChange={e => setOauthClientId(e.target.value)} />
+      <TextField label="OAuth Client Secret" size="small" type="password" value={oauthClientSecret ?? ''} onChange={e => setOauthClientSecret(e.target.value)}
+        helperText={getSecretHelperText(oauthClientSecret)} />
+      <TextField label="OAuth Refresh Token" size="small" type="password" value={oauthRefreshToken ?? ''} onChange={e => setOauthRefreshToken(e.target.value)}
+        helperText={getSecretHelperText(oauthRefreshToken)} />
+      <Alert severity="info">Gmail uses https://oauth2.googleapis.com/token and Microsoft 365 uses
+        https://login.microsoftonline.com/common/oauth2/v2.0/token. The refresh token needs the IMAP scope
+      </Alert>
+      </> : <>
       <TextField
         type={showPassword ? 'text' : 'password'}
         label="Email Password"
         size="small"
-        value={imapEmailPassword}
+        helperText={getSecretHelperText(imapEmailPassword)}
+        value={imapEmailPassword ?? ''}
         onChange={e => setImapEmailPassword(e.target.value)}
         InputProps={{
           endAdornment: <InputAdornment position="end">
@@ -52,8 +81,11 @@ export const MailboxPage = (props: Props) => {
           openPath('https://support.google.com/mail/answer/185833?hl=en-GB')
         }}>Application Password</a> instead of your usual password
       </Alert>	
+      </>}
       <TextField label="IMAP host" size="small" value={imapEmailHost} onChange={e => setImapEmailHost(e.target.value)} />
       <TextField label="IMAP port" size="small" value={imapEmailPort} onChange={e => setImapEmailPort(e.target.value)} />
+      <TextField label="Folders" size="small" multiline minRows={2} value={folders} onChange={e => setFolders(e.target.value)}
+        helperText="One per line. Gmail labels are folders too, e.g. Statements/IBKR" />
       <ButtonGroup>
         <TextField label="From Date Filter" size="small" value={formatMailboxCursor(cursor)} disabled />
         <Button variant="contained" onClick={() => {
@@ -78,23 +110,98 @@ export const MailboxPage = (props: Props) => {
       <ButtonGroup>
         <Button variant="contained" onClick={async () => {
           const imapPort = parseInt(imapEmailPort)
-          await invoke(
-            'update_mailbox', {
-              mailbox: {
-                id: props.mailbox.id,
-                emailAddress: imapEmailAddress,
-                emailPassword: imapEmailPassword,
-                imapHost: imapEmailHost,
-                imapPort: Number.isNaN(imapPort) ? props.mailbox.imapPort : imapPort,
-                cursor,
+          setErrorMessage(null)
+          try {
+            await invoke(
+              'update_mailbox', {
+                mailbox: {
+                  id: props.mailbox.id,
+                  emailAddress: imapEmailAddress,
+                  emailPassword: '',
+                  imapHost: imapEmailHost,
+                  imapPort: Number.isNaN(imapPort) ? props.mailbox.imapPort : imapPort,
+                  cursor,
+                  folders: folders.split('\n').map(f => f.trim()).filter(f => f !== ''),
+                  authMethod,
+                  oauthConf: authMethod === 'oauth2' ? {
+                    tokenUrl: oauthTokenUrl,
+                    clientId: oauthClientId,
+                    clientSecret: '',
+                    refreshToken: '',
+                    accessToken: '',
+                    accessTokenExpiry: 0,
+                  } : props.mailbox.oauthConf,
+                },
+                secrets: {
+                  emailPassword: imapEmailPassword,
+                  clientSecret: oauthClientSecret,
+                  refreshToken: oauthRefreshToken,
+                },
               }
-            }
-          )
-          queryClient.invalidateQueries({ queryKey: ['mailbox'] })
+            )
+          } catch (e) {
+            setErrorMessage(String(e))
+            return
+          }
+          queryClient.invalidateQueries({ queryKey: ['mailboxes'] })
         }}>
           Save
         </Button>
+        <Button onClick={() => {
+          setImapEmailPassword('')
+          setOauthClientSecret('')
+          setOauthRefreshToken('')
+        }}>
+          Clear Secrets
+        </Button>
+        <Button color="error" onClick={async () => {
+          await invoke('delete_mailbox', { mailboxId: props.mailbox.id })
+          queryClient.invalidateQueries({ queryKey: ['mailboxes'] })
+        }}>
+          Delete
+        </Button>
       </ButtonGroup>
+      {errorMessage && <Alert severity='error'>{errorMessage}</Alert>}
+    </Stack>
+}
+
+type MailboxesPageProps = {
+  mailboxes: Array<Mailbox>
+}
+
+export const MailboxesPage = (props: MailboxesPageProps) => {
+  const queryClient = useQueryClient()
+  const [selectedMailboxId, setSelectedMailboxId] = useState(props.mailboxes[0]?.id)
+  const selectedMailbox = props.mailboxes.find(mb => mb.id === selectedMailboxId) ?? props.mailboxes[0]
+  return <Container>
+    <h2 style={{ marginBottom: 0}}>Mailboxes</h2>
+    <Stack direction="row" alignItems="center" gap={1}>
+      <Tabs value={selectedMailbox?.id ?? false} onChange={(_e, value) => setSelectedMailboxId(value)}>
+        {props.mailboxes.map(mb =>
+          <Tab key={mb.id} value={mb.id} label={mb.emailAddress || `Mailbox ${mb.id}`} />
+        )}
+      </Tabs>
+      <Button onClick={async () => {
+        const mailboxId = await invoke('create_mailbox', {
+          mailbox: {
+            id: 0,
+            emailAddress: '',
+            emailPassword: '',
+            imapHost: 'imap.gmail.com',
+            imapPort: 993,
+            cursor: {
+              type: 'date',
+              dateString: new Date().toISOString().slice(0, 10),
+            },
+            folders: ['INBOX'],
+            authMethod: 'password',
+            oauthConf: null,
+          }
+        }) as number
+        await queryClient.invalidateQueries({ queryKey: ['mailboxes'] })
+        setSelectedMailboxId(mailboxId)
+      }}>Add mailbox</Button>
     </Stack>
+    {selectedMailbox && <MailboxPage key={selectedMailbox.id} mailbox={selectedMailbox} />}
   </Container>
 }
@@ -11,6 +11,40 @@ type Props = {
 export const SyncPage = (props: Props) => {
   const queryClient = useQueryClient()
   const [job, setJob] = useState<Job | null>(null)
+  const [errorMessage, setErrorMessage] = useState<string | null>(null)
+
+  const runJob = async (createJob: () => Promise<number>) => {
+    props.setNavigationEnabled(false)
+    setErrorMessage(null)
+    try {
+      const id = await createJob()
+      // First job poll immediately
+      const j = await invoke('get_job', { jobId: id }) as Job
+      setJob(j)
+      // Continue job poll every 0.5s
+      while (true) {
+        await new Promise (resolve => setTimeout(resolve, 500))
+        const job = await invoke('get_job', { jobId: id }) as Job
+        setJob(job)
+        if (!job) {
+          // should never happen
+          break
+        }
+        if (job.completed) {
+          break
+        }
+      }
+    } catch (e) {
+      // E.g. no mail archive chosen
+      setErrorMessage(String(e))
+    } finally {
+      props.setNavigationEnabled(true)
+      queryClient.invalidateQueries({ queryKey: ['reports'] })
+      queryClient.invalidateQueries({ queryKey: ['filings'] })
+      queryClient.invalidateQueries({ queryKey: ['mailboxes'] })
+    }
+  }
+
   return <Container>
     <Stack spacing={1}>
       <h2 style={{ marginBottom: 0}}>Sync</h2>
@@ -25,36 +59,20 @@ export const SyncPage = (props: Props) => {
           Cancel Sync
         </Button>
         <Button variant="contained" color="success" disabled={!!(job && !job.completed)}
-        onClick={async () => {
-          props.setNavigationEnabled(false)
-          try {
-            const id = await invoke('create_job')
-            // First job poll immediately
-            const j = await invoke('get_job', { jobId: id }) as Job
-            setJob(j)
-            // Continue job poll every 0.5s
-            while (true) {
-              await new Promise (resolve => setTimeout(resolve, 500))
-              const job = await invoke('get_job', { jobId: id }) as Job
-              setJob(job)
-              if (!job) {
-                // should never happen
-                break
-              }
-              if (job.completed) {
-                break
-              }
-            }
-          } finally {
-            props.setNavigationEnabled(true)
-            queryClient.invalidateQueries({ queryKey: ['reports'] })
-            queryClient.invalidateQueries({ queryKey: ['filings'] })
-            queryClient.invalidateQueries({ queryKey: ['mailbox'] })
-          }
-        }}>
+          onClick={() => runJob(() => invoke('create_job') as Promise<number>)}
+        >
           Run Sync
         </Button>
       </ButtonGroup>
+      <ButtonGroup disabled={!!(job && !job.completed)}>
+        <Button onClick={() => runJob(() => invoke('create_mail_archive_job', { pickFolder: false }) as Promise<number>)}>
+          Import .eml or mbox file
+        </Button>
+        <Button onClick={() => runJob(() => invoke('create_mail_archive_job', { pickFolder: true }) as Promise<number>)}>
+          Import Maildir or .eml folder
+        </Button>
+      </ButtonGroup>
+      {errorMessage && <Alert severity='error'>{errorMessage}</Alert>}
       {job && job.messages.map((s, sIdx) => {
         if (s.type === 'success') {
           return <Alert key={sIdx} severity='success'>{s.message}</Alert>
@@ -62,6 +80,9 @@ export const SyncPage = (props: Props) => {
         if (s.type === 'error') {
           return <Alert key={sIdx} severity='error'>{s.message}</Alert>
         }
+        if (s.type === 'warning') {
+          return <Alert key={sIdx} severity='warning'>{s.message}</Alert>
+        }
         return <Alert key={sIdx} severity='info'>
           From: {s.from}<br />
           Subject: {s.subject}<br />
@@ -1,5 +1,5 @@
 import { TaxpayerProfile } from './ipc-types'
-import { Alert, Button, ButtonGroup, Container, FormControl, IconButton, InputAdornment, InputLabel, OutlinedInput, Stack, TextField, Tooltip } from '@mui/material'
+import { Alert, Button, ButtonGroup, Container, FormControl, IconButton, InputAdornment, InputLabel, OutlinedInput, Stack, Tab, Tabs, TextField, Tooltip } from '@mui/material'
 import { useState } from 'react'
 import HelpIcon from '@mui/icons-material/Help'
 import { useQueryClient } from '@tanstack/react-query'
@@ -9,7 +9,7 @@ type Props = {
   taxpayerProfile: TaxpayerProfile
 }
 
-export const TaxpayerProfilePage = (props: Props) => {
+const TaxpayerProfilePage = (props: Props) => {
   const queryClient = useQueryClient()
   const [jmbg, setJmbg] = useState(props.taxpayerProfile.jmbg)
   const [fullName, setFullName] = useState(props.taxpayerProfile.fullName)
@@ -18,10 +18,8 @@ export const TaxpayerProfilePage = (props: Props) => {
   const [phoneNumber, setPhoneNumber] = useState(props.taxpayerProfile.phoneNumber)
   const [filingEmailAddress, setFilingEmailAddress] = useState(props.taxpayerProfile.emailAddress)
 
-  return <Container>
-    <Stack spacing={1} style={{ paddingTop: '8px'}}>
-      <h2 style={{ marginBottom: 0}}>Taxpayer</h2>
-      <Alert severity="warning">These details will be inserted into each filing</Alert>
+  return <Stack spacing={1} style={{ paddingTop: '8px'}}>
+      <Alert severity="warning">These details will be inserted into each filing of this taxpayer</Alert>
       <TextField label="JMBG" size="small" value={jmbg} onChange={e => setJmbg(e.target.value)} />
       <TextField label="Full Name" size="small" value={fullName} onChange={e => setFullName(e.target.value)} />
       <FormControl variant="outlined" size="small">
@@ -66,7 +64,7 @@ export const TaxpayerProfilePage = (props: Props) => {
         <Button variant="contained" onClick={async () => {
           await invoke('update_taxpayer_profile', {
             taxpayerProfile: {
-              id: 1,
+              id: props.taxpayerProfile.id,
               jmbg,
               fullName,
               streetAddress,
@@ -75,11 +73,52 @@ export const TaxpayerProfilePage = (props: Props) => {
               emailAddress: filingEmailAddress,              
             }
           })
-          queryClient.invalidateQueries({ queryKey: ['taxpayer-profile'] })
+          queryClient.invalidateQueries({ queryKey: ['taxpayer-profiles'] })
         }}>
           Save
         </Button>
+        <Button color="error" onClick={async () => {
+          await invoke('delete_taxpayer_profile', { taxpayerProfileId: props.taxpayerProfile.id })
+          queryClient.invalidateQueries({ queryKey: ['taxpayer-profiles'] })
+        }}>
+          Delete
+        </Button>
       </ButtonGroup>
     </Stack>
+}
+
+type TaxpayerProfilesPageProps = {
+  taxpayerProfiles: Array<TaxpayerProfile>
+}
+
+export const TaxpayerProfilesPage = (props: TaxpayerProfilesPageProps) => {
+  const queryClient = useQueryClient()
+  const [selectedTaxpayerProfileId, setSelectedTaxpayerProfileId] = useState(props.taxpayerProfiles[0]?.id)
+  const selectedTaxpayerProfile = props.taxpayerProfiles.find(tp => tp.id === selectedTaxpayerProfileId) ?? props.taxpayerProfiles[0]
+  return <Container>
+    <h2 style={{ marginBottom: 0}}>Taxpayers</h2>
+    <Stack direction="row" alignItems="center" gap={1}>
+      <Tabs value={selectedTaxpayerProfile?.id ?? false} onChange={(_e, value) => setSelectedTaxpayerProfileId(value)}>
+        {props.taxpayerProfiles.map(tp =>
+          <Tab key={tp.id} value={tp.id} label={tp.fullName || `Taxpayer ${tp.id}`} />
+        )}
+      </Tabs>
+      <Button onClick={async () => {
+        const taxpayerProfileId = await invoke('create_taxpayer_profile', {
+          taxpayerProfile: {
+            id: 0,
+            jmbg: '',
+            fullName: '',
+            streetAddress: '',
+            opstinaCode: '',
+            phoneNumber: '',
+            emailAddress: '',
+          }
+        }) as number
+        await queryClient.invalidateQueries({ queryKey: ['taxpayer-profiles'] })
+        setSelectedTaxpayerProfileId(taxpayerProfileId)
+      }}>Add taxpayer</Button>
+    </Stack>
+    {selectedTaxpayerProfile && <TaxpayerProfilePage key={selectedTaxpayerProfile.id} taxpayerProfile={selectedTaxpayerProfile} />}
   </Container>
 }
@@ -1,7 +1,7 @@
 import { Alert, Button, ButtonGroup, Container, Stack, TextField } from '@mui/material'
 import { useState } from 'react'
-import { HolidayConf, TechnicalConf } from './ipc-types'
-import { useQueryClient } from '@tanstack/react-query'
+import { HolidayConf, ReferenceRate, SecretsStatus, TechnicalConf, WhtTreatyRate } from './ipc-types'
+import { useQuery, useQueryClient } from '@tanstack/react-query'
 import { invoke } from '@tauri-apps/api/core'
 
 type Props = {
@@ -11,6 +11,36 @@ type Props = {
 export const TechnicalPage = (props: Props) => {
   const queryClient = useQueryClient()
   const [holidayConf, setHolidayConf] = useState(props.technicalConf.holidayConf)
+  const [referenceRatesJson, setReferenceRatesJson] = useState(
+    JSON.stringify(props.technicalConf.referenceRates, null, 2)
+  )
+  const [referenceRatesError, setReferenceRatesError] = useState<string | null>(null)
+  const [whtTreatyRatesJson, setWhtTreatyRatesJson] = useState(
+    JSON.stringify(props.technicalConf.whtTreatyRates, null, 2)
+  )
+  const [whtTreatyRatesError, setWhtTreatyRatesError] = useState<string | null>(null)
+  const [dropFolder, setDropFolder] = useState(props.technicalConf.dropFolder ?? '')
+  const secretsStatusQuery = useQuery({
+    queryKey: ['secrets-status'],
+    queryFn: () => invoke('get_secrets_status') as Promise<SecretsStatus>,
+    networkMode: 'always',
+  })
+  const [newPassphrase, setNewPassphrase] = useState('')
+  const [repeatedPassphrase, setRepeatedPassphrase] = useState('')
+  const [passphraseError, setPassphraseError] = useState<string | null>(null)
+  // Re-encrypts the saved secrets, with the key file if there is no passphrase
+  const changePassphrase = async (passphrase: string | null) => {
+    setPassphraseError(null)
+    try {
+      await invoke('change_secrets_passphrase', { passphrase })
+    } catch (e) {
+      setPassphraseError(String(e))
+      return
+    }
+    setNewPassphrase('')
+    setRepeatedPassphrase('')
+    queryClient.invalidateQueries({ queryKey: ['secrets-status'] })
+  }
 
   return <Container>
     <Stack spacing={1} style={{ paddingTop: '8px'}}>
@@ -28,12 +58,104 @@ export const TechnicalPage = (props: Props) => {
         Serbia public holiday data is needed to correctly calculate filing deadlines. If the holiday
         data range is insufficient, you can import a larger range from a JSON file
       </Alert>
+      <TextField
+        label="NBS reference rates"
+        size="small"
+        multiline
+        maxRows={12}
+        value={referenceRatesJson}
+        error={referenceRatesError !== null}
+        helperText={referenceRatesError}
+        onChange={e => setReferenceRatesJson(e.target.value)}
+      />
+      <Alert severity="info">
+        Interest on late filings accrues at the NBS reference rate plus 10 percentage points. Each
+        entry applies from its validFrom date until the next entry
+      </Alert>
+      <TextField
+        label="Withholding tax treaty rates"
+        size="small"
+        multiline
+        maxRows={12}
+        value={whtTreatyRatesJson}
+        error={whtTreatyRatesError !== null}
+        helperText={whtTreatyRatesError}
+        onChange={e => setWhtTreatyRatesJson(e.target.value)}
+      />
+      <Alert severity="info">
+        Foreign withholding tax on dividends is only credited up to the treaty rate (in percent) of
+        the country in the security ISIN. Countries without an entry are credited in full
+      </Alert>
+      <TextField
+        label="Drop folder"
+        size="small"
+        placeholder="/Users/petar/Downloads/statements"
+        value={dropFolder}
+        onChange={e => setDropFolder(e.target.value)}
+      />
+      <Alert severity="info">
+        Report files placed in the drop folder are imported automatically while the app is running,
+        and on every sync. A file is matched to importers by their attachment name regex, so only
+        importers with one pick up dropped files
+      </Alert>
+      <TextField
+        type="password"
+        label="New master passphrase"
+        size="small"
+        value={newPassphrase}
+        onChange={e => setNewPassphrase(e.target.value)}
+      />
+      <TextField
+        type="password"
+        label="Repeat master passphrase"
+        size="small"
+        value={repeatedPassphrase}
+        error={repeatedPassphrase !== '' && repeatedPassphrase !== newPassphrase}
+        onChange={e => setRepeatedPassphrase(e.target.value)}
+      />
+      <ButtonGroup>
+        <Button
+          variant="contained"
+          disabled={!newPassphrase || newPassphrase !== repeatedPassphrase}
+          onClick={() => changePassphrase(newPassphrase)}
+        >
+          {secretsStatusQuery.data?.usesPassphrase ? 'Change Passphrase' : 'Set Passphrase'}
+        </Button>
+        {secretsStatusQuery.data?.usesPassphrase &&
+          <Button onClick={() => changePassphrase(null)}>Use Key File</Button>
+        }
+      </ButtonGroup>
+      {passphraseError && <Alert severity="error">{passphraseError}</Alert>}
+      <Alert severity="info">
+        Mailbox passwords and OAuth tokens are encrypted with a key file kept outside the data
+        directory, or with a key derived from a master passphrase that is asked for on every start.
+        Changing either re-encrypts the saved secrets
+      </Alert>
       <ButtonGroup>
         <Button variant="contained" onClick={async () => {
+          let referenceRates: ReferenceRate[]
+          try {
+            referenceRates = JSON.parse(referenceRatesJson)
+          } catch (e) {
+            setReferenceRatesError(String(e))
+            return
+          }
+          setReferenceRatesError(null)
+          let whtTreatyRates: WhtTreatyRate[]
+          try {
+            whtTreatyRates = JSON.parse(whtTreatyRatesJson)
+          } catch (e) {
+            setWhtTreatyRatesError(String(e))
+            return
+          }
+          setWhtTreatyRatesError(null)
           await invoke('update_technical_conf', {
             technicalConf: {
                 ...props.technicalConf,
                 holidayConf,
+                referenceRates,
+                whtTreatyRates,
+                dropFolder: dropFolder.trim() || null,
             }
           })
           queryClient.invalidateQueries({ queryKey: ['technical-conf'] })
@@ -0,0 +1,41 @@
+import { Alert, Button, Container, Stack, TextField } from '@mui/material'
+import { useState } from 'react'
+import { useQueryClient } from '@tanstack/react-query'
+import { invoke } from '@tauri-apps/api/core'
+
+// Shown on start when the saved secrets are protected by a master passphrase
+export const UnlockPage = () => {
+  const queryClient = useQueryClient()
+  const [passphrase, setPassphrase] = useState('')
+  const [errorMessage, setErrorMessage] = useState<string | null>(null)
+  const unlock = async () => {
+    setErrorMessage(null)
+    try {
+      await invoke('unlock_secrets', { passphrase })
+    } catch (e) {
+      setErrorMessage(String(e))
+      return
+    }
+    queryClient.invalidateQueries({ queryKey: ['secrets-status'] })
+  }
+  return <Container>
+    <Stack spacing={1} style={{ paddingTop: '8px', maxWidth: 400 }}>
+      <h2>Unlock</h2>
+      <TextField
+        type="password"
+        label="Master passphrase"
+        size="small"
+        autoFocus
+        value={passphrase}
+        onChange={e => setPassphrase(e.target.value)}
+        onKeyDown={e => {
+          if (e.key === 'Enter' && passphrase) {
+            unlock()
+          }
+        }}
+      />
+      <Button variant="contained" disabled={!passphrase} onClick={unlock}>Unlock</Button>
+      {errorMessage && <Alert severity="error">{errorMessage}</Alert>}
+    </Stack>
+  </Container>
+}